@@ -0,0 +1,1105 @@
+//! Command-line tool for operating a Synchronizer
+
+mod output;
+
+use clap::{crate_version, App, AppSettings, Arg, ArgMatches, SubCommand};
+use output::OutputFormat;
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+use solana_sdk::{signature::{read_keypair_file, Keypair, Signer}, signer::null_signer::NullSigner, transaction::Transaction};
+use std::{collections::HashMap, convert::TryFrom, error::Error, fs, process::exit, str::FromStr};
+use synchronizer::{instruction, processor::id, state::{FeeTier, RateBounds, SynchronizerData, TradingHours}};
+use synchronizer_client::{offline, reconcile, start_tcp_client, SynchronizerClient, Trade};
+use synchronizer_oracle::source::FileSource;
+
+type CliResult<T> = Result<T, Box<dyn Error>>;
+
+/// Options shared by every subcommand
+struct Config {
+    output: OutputFormat,
+    dry_run: bool,
+    /// Durable nonce account and its authority, the fee payer when `None`
+    nonce: Option<(Pubkey, Option<Box<dyn Signer>>)>,
+    /// File the partially signed transaction is written to
+    export: Option<String>,
+}
+
+fn app<'a, 'b>() -> App<'a, 'b> {
+    let amount = |name: &'static str, help: &'static str| Arg::with_name(name)
+        .value_name("AMOUNT")
+        .takes_value(true)
+        .required(true)
+        .help(help);
+    let recipient = Arg::with_name("recipient")
+        .long("recipient")
+        .value_name("PUBKEY")
+        .takes_value(true)
+        .required(true)
+        .help("Recipient collateral token account");
+    let collateral_mint = |required: bool, help: &'static str| Arg::with_name("collateral_mint")
+        .long("collateral-mint")
+        .value_name("PUBKEY")
+        .takes_value(true)
+        .required(required)
+        .help(help);
+    let oracles = |required: bool| Arg::with_name("oracle")
+        .long("oracle")
+        .value_name("PUBKEY")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .required(required)
+        .help("Oracle pubkey, repeat for every oracle");
+    let trade_args = vec![
+        Arg::with_name("user")
+            .long("user")
+            .value_name("KEYPAIR")
+            .takes_value(true)
+            .required(true)
+            .help("User keypair file, a pubkey with --export"),
+        Arg::with_name("fiat_mint")
+            .long("fiat-mint")
+            .value_name("PUBKEY")
+            .takes_value(true)
+            .required(true)
+            .help("Fiat asset mint"),
+        Arg::with_name("amount")
+            .long("amount")
+            .value_name("AMOUNT")
+            .takes_value(true)
+            .required(true)
+            .help("Fiat asset amount in base units"),
+        Arg::with_name("fee")
+            .long("fee")
+            .value_name("FEE")
+            .takes_value(true)
+            .required(true)
+            .help("Fee rate with 9 decimals"),
+        Arg::with_name("multiplier")
+            .long("multiplier")
+            .value_name("MULTIPLIER")
+            .takes_value(true)
+            .required(true)
+            .help("Dollar cap multiplier"),
+        Arg::with_name("price")
+            .long("price")
+            .value_name("PRICE")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .required(true)
+            .help("Oracle price in base units, repeat in the order of --oracle-keypair"),
+        Arg::with_name("oracle_keypair")
+            .long("oracle-keypair")
+            .value_name("KEYPAIR")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .required(true)
+            .help("Oracle keypair file or a pubkey with --export, repeat for every oracle"),
+        Arg::with_name("referrer")
+            .long("referrer")
+            .value_name("PUBKEY")
+            .takes_value(true)
+            .help("Referrer paid a share of the fee on its collateral associated token account"),
+        collateral_mint(false, "Accepted collateral settling the trade [default: the collateral token]"),
+    ];
+    let transaction_file = Arg::with_name("file")
+        .value_name("FILE")
+        .takes_value(true)
+        .required(true)
+        .help("Exported transaction file");
+
+    App::new("synchronizer-cli")
+        .version(crate_version!())
+        .about("Operate a Synchronizer")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(Arg::with_name("url")
+            .long("url")
+            .value_name("HOST:PORT")
+            .takes_value(true)
+            .global(true)
+            .help("Validator banks server address [default: 127.0.0.1:8901]"))
+        .arg(Arg::with_name("keypair")
+            .long("keypair")
+            .value_name("KEYPAIR")
+            .takes_value(true)
+            .global(true)
+            .help("Fee payer keypair file or a pubkey with --export [default: ~/.config/solana/id.json]"))
+        .arg(Arg::with_name("synchronizer")
+            .long("synchronizer")
+            .value_name("KEYPAIR_OR_PUBKEY")
+            .takes_value(true)
+            .global(true)
+            .help("Synchronizer account keypair file, a pubkey is enough for `show`, the position commands and --export"))
+        .arg(Arg::with_name("output")
+            .long("output")
+            .value_name("FORMAT")
+            .takes_value(true)
+            .possible_values(&["human", "json"])
+            .default_value("human")
+            .global(true)
+            .help("Output format"))
+        .arg(Arg::with_name("dry_run")
+            .long("dry-run")
+            .global(true)
+            .help("Print the signed transaction instead of sending it"))
+        .arg(Arg::with_name("nonce")
+            .long("nonce")
+            .value_name("PUBKEY")
+            .takes_value(true)
+            .global(true)
+            .help("Durable nonce account used instead of a recent blockhash"))
+        .arg(Arg::with_name("nonce_authority")
+            .long("nonce-authority")
+            .value_name("KEYPAIR")
+            .takes_value(true)
+            .global(true)
+            .requires("nonce")
+            .help("Nonce authority keypair file or a pubkey with --export [default: fee payer]"))
+        .arg(Arg::with_name("export")
+            .long("export")
+            .value_name("FILE")
+            .takes_value(true)
+            .global(true)
+            .requires("nonce")
+            .help("Write the partially signed transaction to FILE instead of sending it"))
+        .subcommand(SubCommand::with_name("init")
+            .about("Create and initialize the Synchronizer account")
+            .arg(Arg::with_name("collateral_mint")
+                .long("collateral-mint")
+                .value_name("PUBKEY")
+                .takes_value(true)
+                .required(true)
+                .help("Collateral token mint"))
+            .arg(Arg::with_name("cap")
+                .long("cap")
+                .value_name("AMOUNT")
+                .takes_value(true)
+                .required(true)
+                .help("Remaining dollar cap in base units"))
+            .arg(Arg::with_name("fee_amount")
+                .long("fee-amount")
+                .value_name("AMOUNT")
+                .takes_value(true)
+                .default_value("0")
+                .help("Initial withdrawable fee amount in base units"))
+            .arg(Arg::with_name("min_signatures")
+                .long("min-signatures")
+                .value_name("N")
+                .takes_value(true)
+                .required(true)
+                .help("Minimum required oracle signatures"))
+            .arg(oracles(false)))
+        .subcommand(SubCommand::with_name("set-oracles")
+            .about("Set the list of known oracles")
+            .arg(oracles(true)))
+        .subcommand(SubCommand::with_name("set-cap")
+            .about("Set the remaining dollar cap")
+            .arg(amount("cap", "Remaining dollar cap in base units")))
+        .subcommand(SubCommand::with_name("set-asset-caps")
+            .about("Set the caps of a fiat asset, in collateral base units")
+            .arg(Arg::with_name("fiat_mint")
+                .long("fiat-mint")
+                .value_name("PUBKEY")
+                .takes_value(true)
+                .required(true)
+                .help("Fiat asset mint"))
+            .arg(amount("max_outstanding", "Maximum outstanding notional").long("max-outstanding"))
+            .arg(amount("max_transaction", "Maximum notional of a buy").long("max-transaction")))
+        .subcommand(SubCommand::with_name("set-asset-spreads")
+            .about("Set the spreads of a fiat asset, in basis points of the oracle price")
+            .arg(Arg::with_name("fiat_mint")
+                .long("fiat-mint")
+                .value_name("PUBKEY")
+                .takes_value(true)
+                .required(true)
+                .help("Fiat asset mint"))
+            .arg(amount("ask_bps", "Spread added to the price of buys").long("ask-bps"))
+            .arg(amount("bid_bps", "Spread taken from the price of sells").long("bid-bps")))
+        .subcommand(SubCommand::with_name("set-market-schedule")
+            .about("Set the trading hours and holidays of a fiat asset, in UTC")
+            .arg(Arg::with_name("fiat_mint")
+                .long("fiat-mint")
+                .value_name("PUBKEY")
+                .takes_value(true)
+                .required(true)
+                .help("Fiat asset mint"))
+            .arg(Arg::with_name("hours")
+                .long("hours")
+                .value_name("DAYS=HH:MM-HH:MM")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Trading hours of a day or a range of days like `mon-fri=14:30-21:00`, days without hours are closed"))
+            .arg(Arg::with_name("holiday")
+                .long("holiday")
+                .value_name("YYYY-MM-DD")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Day the market is closed, repeat for every holiday")))
+        .subcommand(SubCommand::with_name("set-fee-tiers")
+            .about("Replace the fee tiers by rolling user volume, without tiers clears them")
+            .arg(Arg::with_name("tier")
+                .long("tier")
+                .value_name("VOLUME=FEE")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Fee rate in base units from a volume in collateral base units, by increasing volume"))
+            .arg(Arg::with_name("volume_window_slots")
+                .long("volume-window-slots")
+                .value_name("SLOTS")
+                .takes_value(true)
+                .default_value("0")
+                .help("Length of the rolling user volume window in slots, zero counts no volume")))
+        .subcommand(SubCommand::with_name("set-fee-override")
+            .about("Set the fee rate of a whitelisted user, used instead of the tiers")
+            .arg(Arg::with_name("user")
+                .long("user")
+                .value_name("PUBKEY")
+                .takes_value(true)
+                .required(true)
+                .help("User authority"))
+            .arg(amount("fee", "Fee rate in base units").long("fee").required_unless("remove"))
+            .arg(Arg::with_name("remove")
+                .long("remove")
+                .conflicts_with("fee")
+                .help("Remove the user from the whitelist")))
+        .subcommand(SubCommand::with_name("set-referral-share")
+            .about("Set the share of the trade fees paid to referrers, in basis points")
+            .arg(Arg::with_name("bps")
+                .long("bps")
+                .value_name("BPS")
+                .takes_value(true)
+                .required(true)
+                .help("Referral share, at most 10000")))
+        .subcommand(SubCommand::with_name("set-rate-limits")
+            .about("Set the rolling volume limits, in collateral base units, zero means no limit")
+            .arg(Arg::with_name("window_slots")
+                .long("window-slots")
+                .value_name("SLOTS")
+                .takes_value(true)
+                .required(true)
+                .help("Length of the rolling window in slots"))
+            .arg(amount("max_synchronizer", "Maximum notional traded through the Synchronizer per window").long("max-synchronizer"))
+            .arg(amount("max_user", "Maximum notional traded by a user per window").long("max-user")))
+        .subcommand(SubCommand::with_name("set-min-signatures")
+            .about("Set the minimum required oracle signatures")
+            .arg(Arg::with_name("min_signatures")
+                .value_name("N")
+                .takes_value(true)
+                .required(true)
+                .help("Minimum required oracle signatures")))
+        .subcommand(SubCommand::with_name("set-collateral-token")
+            .about("Set the collateral token mint")
+            .arg(Arg::with_name("collateral_mint")
+                .value_name("PUBKEY")
+                .takes_value(true)
+                .required(true)
+                .help("Collateral token mint")))
+        .subcommand(SubCommand::with_name("add-collateral")
+            .about("Accept a collateral token in trades besides the collateral token")
+            .arg(collateral_mint(true, "Accepted collateral token mint"))
+            .arg(Arg::with_name("vault")
+                .long("vault")
+                .value_name("PUBKEY")
+                .takes_value(true)
+                .required(true)
+                .help("Synchronizer token account of the accepted collateral"))
+            .arg(amount("rate", "Price of one accepted collateral token in collateral base units").long("rate"))
+            .arg(Arg::with_name("max_rate_age")
+                .long("max-rate-age")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .required(true)
+                .help("Age after which trades fail until the rate is updated"))
+            .arg(Arg::with_name("max_rate_deviation_bps")
+                .long("max-rate-deviation-bps")
+                .value_name("BPS")
+                .takes_value(true)
+                .required(true)
+                .help("Maximum change by the oracles of the rate set by the admin, at most 10000")))
+        .subcommand(SubCommand::with_name("remove-collateral")
+            .about("Stop accepting a collateral token, its fees and vault must have been withdrawn")
+            .arg(collateral_mint(true, "Accepted collateral token mint")))
+        .subcommand(SubCommand::with_name("set-collateral-rate")
+            .about("Set the rate of an accepted collateral token")
+            .arg(collateral_mint(true, "Accepted collateral token mint"))
+            .arg(amount("rate", "Price of one accepted collateral token in collateral base units").long("rate"))
+            .arg(Arg::with_name("oracle_keypair")
+                .long("oracle-keypair")
+                .value_name("KEYPAIR")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Oracle keypair file or a pubkey with --export, repeat for every attesting oracle [default: the Synchronizer keypair]")))
+        .subcommand(SubCommand::with_name("withdraw-fee")
+            .about("Withdraw fee from the Synchronizer vault")
+            .arg(amount("amount", "Collateral amount in base units"))
+            .arg(recipient.clone())
+            .arg(collateral_mint(false, "Accepted collateral of the vault [default: the collateral token]")))
+        .subcommand(SubCommand::with_name("withdraw-collateral")
+            .about("Withdraw collateral from the Synchronizer vault")
+            .arg(amount("amount", "Collateral amount in base units"))
+            .arg(recipient)
+            .arg(collateral_mint(false, "Accepted collateral of the vault [default: the collateral token]")))
+        .subcommand(SubCommand::with_name("buy")
+            .about("Buy fiat asset for collateral")
+            .args(&trade_args))
+        .subcommand(SubCommand::with_name("sell")
+            .about("Sell fiat asset for collateral")
+            .args(&trade_args))
+        .subcommand(SubCommand::with_name("create-position")
+            .about("Create the position account of a user in a fiat asset, trades then record their totals in it")
+            .arg(trade_args[0].clone())
+            .arg(trade_args[1].clone()))
+        .subcommand(SubCommand::with_name("close-position")
+            .about("Close the position account of a user in a fiat asset, returning its rent")
+            .arg(trade_args[0].clone())
+            .arg(trade_args[1].clone())
+            .arg(Arg::with_name("rent_recipient")
+                .long("rent-recipient")
+                .value_name("PUBKEY")
+                .takes_value(true)
+                .help("Recipient of the position account rent [default: the user]")))
+        .subcommand(SubCommand::with_name("show")
+            .about("Show the Synchronizer account state"))
+        .subcommand(SubCommand::with_name("reconcile")
+            .about("Check that the vault covers outstanding fiat assets and fees, fails when under-collateralized")
+            .arg(Arg::with_name("prices")
+                .long("prices")
+                .value_name("FILE")
+                .takes_value(true)
+                .required(true)
+                .help("Price snapshot, a JSON or CSV price file like the oracle's"))
+            .arg(Arg::with_name("fiat_mint")
+                .long("fiat-mint")
+                .value_name("PUBKEY")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Fiat asset mint to check, repeat for every asset [default: every asset of the snapshot]")))
+        .subcommand(SubCommand::with_name("sign")
+            .about("Add signatures to an exported transaction, works offline")
+            .arg(transaction_file.clone())
+            .arg(Arg::with_name("signer")
+                .long("signer")
+                .value_name("KEYPAIR")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .required(true)
+                .help("Signer keypair file, repeat for every signer")))
+        .subcommand(SubCommand::with_name("inspect")
+            .about("Show an exported transaction and its missing signatures, works offline")
+            .arg(transaction_file.clone()))
+        .subcommand(SubCommand::with_name("broadcast")
+            .about("Send a fully signed exported transaction")
+            .arg(transaction_file))
+}
+
+fn parse<T: FromStr>(matches: &ArgMatches, name: &str) -> CliResult<T>
+where
+    T::Err: Error + 'static,
+{
+    let value = matches.value_of(name).ok_or_else(|| format!("missing --{}", name))?;
+    value.parse().map_err(|error| format!("invalid {} `{}`: {}", name, value, error).into())
+}
+
+fn parse_pubkeys(matches: &ArgMatches, name: &str) -> CliResult<Vec<Pubkey>> {
+    matches.values_of(name).into_iter().flatten()
+        .map(|value| Pubkey::from_str(value).map_err(|error| format!("invalid pubkey `{}`: {}", value, error).into()))
+        .collect()
+}
+
+const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// Seconds since midnight of `HH:MM`, up to `24:00`
+fn parse_time_of_day(value: &str) -> Option<u32> {
+    let (hours, minutes) = value.split_once(':')?;
+    let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?);
+    if minutes >= 60 || hours * 60 + minutes > 24 * 60 {
+        return None;
+    }
+    Some(hours * 3600 + minutes * 60)
+}
+
+/// Weekly trading hours of the `--hours` values, Monday first
+fn parse_weekly_hours(matches: &ArgMatches) -> CliResult<[TradingHours; 7]> {
+    let weekday = |name: &str| WEEKDAYS.iter().position(|weekday| weekday.eq_ignore_ascii_case(name));
+    let mut weekly_hours = [TradingHours::default(); 7];
+    for value in matches.values_of("hours").into_iter().flatten() {
+        let invalid = || format!("invalid hours `{}`, expected DAYS=HH:MM-HH:MM", value);
+        let (days, hours) = value.split_once('=').ok_or_else(invalid)?;
+        let (first, last) = days.split_once('-').unwrap_or((days, days));
+        let (first, last) = (weekday(first).ok_or_else(invalid)?, weekday(last).ok_or_else(invalid)?);
+        let (open, close) = hours.split_once('-').ok_or_else(invalid)?;
+        let hours = TradingHours {
+            open: parse_time_of_day(open).ok_or_else(invalid)?,
+            close: parse_time_of_day(close).ok_or_else(invalid)?,
+        };
+        if first > last || !hours.is_valid() {
+            return Err(invalid().into());
+        }
+        for day_hours in weekly_hours[first..=last].iter_mut() {
+            *day_hours = hours;
+        }
+    }
+    Ok(weekly_hours)
+}
+
+/// Days since 1970-01-01 of a `YYYY-MM-DD` date
+fn parse_date(value: &str) -> Option<u32> {
+    let mut parts = value.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let month_days = [31, if leap { 29 } else { 28 }, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+    if !(1..=12).contains(&month) || day < 1 || day > month_days[month as usize - 1] {
+        return None;
+    }
+
+    // Days from civil, years starting in March
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    u32::try_from(era * 146_097 + day_of_era - 719_468).ok().filter(|days| *days > 0)
+}
+
+fn parse_holidays(matches: &ArgMatches) -> CliResult<Vec<u32>> {
+    matches.values_of("holiday").into_iter().flatten()
+        .map(|value| parse_date(value).ok_or_else(|| format!("invalid holiday `{}`, expected YYYY-MM-DD after 1970-01-01", value).into()))
+        .collect()
+}
+
+fn parse_fee_tiers(matches: &ArgMatches) -> CliResult<Vec<FeeTier>> {
+    matches.values_of("tier").into_iter().flatten()
+        .map(|value| {
+            let tier = value.split_once('=').and_then(|(min_volume, fee)| Some(FeeTier {
+                min_volume: min_volume.parse().ok()?,
+                fee: fee.parse().ok()?,
+            }));
+            tier.ok_or_else(|| format!("invalid tier `{}`, expected VOLUME=FEE", value).into())
+        })
+        .collect()
+}
+
+fn read_keypair(path: &str) -> CliResult<Keypair> {
+    read_keypair_file(path).map_err(|error| format!("can't read keypair {}: {}", path, error).into())
+}
+
+/// Reads a keypair file. A pubkey is accepted when the signature can be added later,
+/// the signer then doesn't sign.
+fn read_signer(value: &str, pubkey_allowed: bool) -> CliResult<Box<dyn Signer>> {
+    match Pubkey::from_str(value) {
+        Ok(pubkey) if pubkey_allowed => Ok(Box::new(NullSigner::new(&pubkey))),
+        Ok(_) => Err(format!("`{}` is a pubkey, a keypair file is required without --export", value).into()),
+        Err(_) => Ok(Box::new(read_keypair(value)?)),
+    }
+}
+
+fn read_transaction(path: &str) -> CliResult<Transaction> {
+    let encoded = fs::read_to_string(path).map_err(|error| format!("can't read transaction {}: {}", path, error))?;
+    Ok(offline::decode(&encoded)?)
+}
+
+fn write_transaction(path: &str, transaction: &Transaction) -> CliResult<()> {
+    fs::write(path, offline::encode(transaction) + "\n").map_err(|error| format!("can't write transaction {}: {}", path, error).into())
+}
+
+async fn process(
+    client: &mut SynchronizerClient,
+    config: &Config,
+    instructions: &[Instruction],
+    signers: &[&dyn Signer],
+) -> CliResult<()> {
+    let transaction = match &config.nonce {
+        Some((nonce_account, nonce_authority)) => {
+            let nonce_data = offline::get_nonce_data(client.banks_client(), nonce_account).await?;
+            let nonce_authority = nonce_authority.as_deref().unwrap_or_else(|| client.payer());
+            let mut transaction = offline::unsigned_transaction(
+                instructions,
+                &client.payer().pubkey(),
+                nonce_account,
+                &nonce_authority.pubkey(),
+                nonce_data.blockhash,
+            );
+            let mut all_signers = vec![client.payer(), nonce_authority];
+            all_signers.extend_from_slice(signers);
+            offline::sign(&mut transaction, &all_signers)?;
+            transaction
+        }
+        None => client.sign_transaction(instructions, signers).await?,
+    };
+
+    if let Some(path) = &config.export {
+        write_transaction(path, &transaction)?;
+        output::print_exported(config.output, path, &transaction);
+        return Ok(());
+    }
+    if config.dry_run {
+        output::print_transaction(config.output, &transaction);
+        return Ok(());
+    }
+    let signature = offline::broadcast(client.banks_client(), transaction).await?;
+    output::print_signature(config.output, &signature);
+    Ok(())
+}
+
+/// Runs the subcommands working on an exported transaction, `None` for other subcommands
+async fn run_transaction_file(command: &str, matches: &ArgMatches<'_>, output: OutputFormat) -> Option<CliResult<()>> {
+    let run = async {
+        let path = matches.value_of("file").ok_or("missing transaction file")?;
+        let mut transaction = read_transaction(path)?;
+        match command {
+            "sign" => {
+                let signers = matches.values_of("signer").into_iter().flatten()
+                    .map(read_keypair)
+                    .collect::<CliResult<Vec<Keypair>>>()?;
+                let signers: Vec<&dyn Signer> = signers.iter().map(|signer| signer as &dyn Signer).collect();
+                offline::sign(&mut transaction, &signers)?;
+                write_transaction(path, &transaction)?;
+                output::print_exported(output, path, &transaction);
+            }
+            "inspect" => output::print_transaction(output, &transaction),
+            _ => {
+                let url = matches.value_of("url").unwrap_or("127.0.0.1:8901");
+                let mut banks_client = start_tcp_client(url).await?;
+                let signature = offline::broadcast(&mut banks_client, transaction).await?;
+                output::print_signature(output, &signature);
+            }
+        }
+        Ok(())
+    };
+    match command {
+        "sign" | "inspect" | "broadcast" => Some(run.await),
+        _ => None,
+    }
+}
+
+/// Prices of the `--fiat-mint` assets, or of every asset, from the `--prices` snapshot
+fn read_price_snapshot(matches: &ArgMatches) -> CliResult<HashMap<Pubkey, u64>> {
+    let path = matches.value_of("prices").ok_or("missing --prices")?;
+    let prices = FileSource::new(path).prices().map_err(|error| format!("can't read prices {}: {}", path, error))?;
+    let fiat_mints = parse_pubkeys(matches, "fiat_mint")?;
+    if fiat_mints.is_empty() {
+        return Ok(prices);
+    }
+    fiat_mints.into_iter()
+        .map(|fiat_mint| match prices.get(&fiat_mint) {
+            Some(price) => Ok((fiat_mint, *price)),
+            None => Err(format!("no price for {} in {}", fiat_mint, path).into()),
+        })
+        .collect()
+}
+
+fn parse_trade(matches: &ArgMatches, oracles: &[Pubkey]) -> CliResult<Trade> {
+    let prices = matches.values_of("price").into_iter().flatten()
+        .map(|value| value.parse().map_err(|error| format!("invalid price `{}`: {}", value, error)))
+        .collect::<Result<Vec<u64>, _>>()?;
+    if prices.len() != oracles.len() {
+        return Err("every --oracle-keypair needs a --price".into());
+    }
+    Ok(Trade {
+        fiat_asset_mint: parse(matches, "fiat_mint")?,
+        multiplier: parse(matches, "multiplier")?,
+        amount: parse(matches, "amount")?,
+        fee: parse(matches, "fee")?,
+        prices,
+        oracles: oracles.to_vec(),
+        referrer: if matches.is_present("referrer") { Some(parse(matches, "referrer")?) } else { None },
+        collateral_mint: if matches.is_present("collateral_mint") { Some(parse(matches, "collateral_mint")?) } else { None },
+    })
+}
+
+async fn run(matches: ArgMatches<'_>) -> CliResult<()> {
+    let (command, command_matches) = matches.subcommand();
+    let command_matches = command_matches.ok_or("missing subcommand")?;
+    let output = OutputFormat::from_name(command_matches.value_of("output").unwrap_or("human")).ok_or("invalid output format")?;
+    if let Some(result) = run_transaction_file(command, command_matches, output).await {
+        return result;
+    }
+
+    // Signers of an exported transaction sign it later with `sign`
+    let export = command_matches.value_of("export").map(String::from);
+    let offline = export.is_some();
+    let nonce = match command_matches.value_of("nonce") {
+        Some(nonce) => {
+            let nonce_account = Pubkey::from_str(nonce).map_err(|error| format!("invalid nonce `{}`: {}", nonce, error))?;
+            let nonce_authority = command_matches.value_of("nonce_authority")
+                .map(|value| read_signer(value, offline))
+                .transpose()?;
+            Some((nonce_account, nonce_authority))
+        }
+        None => None,
+    };
+    let config = Config {
+        output,
+        dry_run: command_matches.is_present("dry_run"),
+        nonce,
+        export,
+    };
+
+    // `show` and `reconcile` send no transactions, so they don't need a fee payer
+    let read_only = command == "show" || command == "reconcile";
+    let default_keypair = format!("{}/.config/solana/id.json", std::env::var("HOME").unwrap_or_default());
+    let payer = match command_matches.value_of("keypair") {
+        None if read_only => Box::new(Keypair::new()),
+        keypair => read_signer(keypair.unwrap_or(&default_keypair), offline)?,
+    };
+    // Position commands are signed by the user alone, collateral rates by the oracles when given
+    let user_signed = command == "create-position"
+        || command == "close-position"
+        || (command == "set-collateral-rate" && command_matches.is_present("oracle_keypair"));
+    let synchronizer_signer = read_signer(
+        command_matches.value_of("synchronizer").ok_or("missing --synchronizer")?,
+        offline || read_only || user_signed,
+    )?;
+    let synchronizer_key = synchronizer_signer.pubkey();
+    let url = command_matches.value_of("url").unwrap_or("127.0.0.1:8901");
+    let banks_client = start_tcp_client(url).await?;
+    let mut client = SynchronizerClient::new(banks_client, payer, synchronizer_key);
+
+    if command == "show" {
+        let account = client.banks_client().get_account(synchronizer_key).await?
+            .ok_or_else(|| format!("Synchronizer account {} not found", synchronizer_key))?;
+        if account.owner != id() {
+            return Err(format!("account {} is not owned by the Synchronizer program", synchronizer_key).into());
+        }
+        let (version, synchronizer) = SynchronizerData::unpack_versioned(&account.data)?;
+        output::print_synchronizer(config.output, &synchronizer_key, version, &synchronizer);
+        return Ok(());
+    }
+    if command == "reconcile" {
+        let prices = read_price_snapshot(command_matches)?;
+        let reconciliation = reconcile::fetch_reconciliation(client.banks_client(), &synchronizer_key, &prices).await?;
+        output::print_reconciliation(config.output, &synchronizer_key, &reconciliation);
+        if reconciliation.is_under_collateralized() {
+            return Err(format!("Synchronizer {} is under-collateralized by {}", synchronizer_key, -reconciliation.surplus).into());
+        }
+        return Ok(());
+    }
+
+    let synchronizer_keypair = synchronizer_signer.as_ref();
+    let m = command_matches;
+    match command {
+        "init" => {
+            let instructions = client.initialize_instructions(
+                &parse(m, "collateral_mint")?,
+                parse(m, "cap")?,
+                parse(m, "fee_amount")?,
+                parse(m, "min_signatures")?,
+                &parse_pubkeys(m, "oracle")?,
+            ).await?;
+            process(&mut client, &config, &instructions, &[synchronizer_keypair]).await
+        }
+        "set-oracles" => {
+            let instruction = instruction::set_oracles(&id(), &parse_pubkeys(m, "oracle")?, &synchronizer_key)?;
+            process(&mut client, &config, &[instruction], &[synchronizer_keypair]).await
+        }
+        "set-cap" => {
+            let instruction = instruction::set_remaining_dollar_cap(&id(), parse(m, "cap")?, &synchronizer_key)?;
+            process(&mut client, &config, &[instruction], &[synchronizer_keypair]).await
+        }
+        "set-asset-caps" => {
+            let instruction = instruction::set_asset_caps(
+                &id(),
+                &parse(m, "fiat_mint")?,
+                parse(m, "max_outstanding")?,
+                parse(m, "max_transaction")?,
+                &synchronizer_key,
+                &client.payer().pubkey(),
+            )?;
+            process(&mut client, &config, &[instruction], &[synchronizer_keypair]).await
+        }
+        "set-asset-spreads" => {
+            let instruction = instruction::set_asset_spreads(
+                &id(),
+                &parse(m, "fiat_mint")?,
+                parse(m, "ask_bps")?,
+                parse(m, "bid_bps")?,
+                &synchronizer_key,
+                &client.payer().pubkey(),
+            )?;
+            process(&mut client, &config, &[instruction], &[synchronizer_keypair]).await
+        }
+        "set-fee-tiers" => {
+            let instruction = instruction::set_fee_tiers(&id(), &parse_fee_tiers(m)?, parse(m, "volume_window_slots")?, &synchronizer_key, &client.payer().pubkey())?;
+            process(&mut client, &config, &[instruction], &[synchronizer_keypair]).await
+        }
+        "set-fee-override" => {
+            let fee = if m.is_present("remove") { None } else { Some(parse(m, "fee")?) };
+            let instruction = instruction::set_fee_override(&id(), &parse(m, "user")?, fee, &synchronizer_key, &client.payer().pubkey())?;
+            process(&mut client, &config, &[instruction], &[synchronizer_keypair]).await
+        }
+        "set-referral-share" => {
+            let instruction = instruction::set_referral_share(&id(), parse(m, "bps")?, &synchronizer_key, &client.payer().pubkey())?;
+            process(&mut client, &config, &[instruction], &[synchronizer_keypair]).await
+        }
+        "set-market-schedule" => {
+            let instruction = instruction::set_market_schedule(
+                &id(),
+                &parse(m, "fiat_mint")?,
+                parse_weekly_hours(m)?,
+                &parse_holidays(m)?,
+                &synchronizer_key,
+                &client.payer().pubkey(),
+            )?;
+            process(&mut client, &config, &[instruction], &[synchronizer_keypair]).await
+        }
+        "set-rate-limits" => {
+            let instruction = instruction::set_rate_limits(
+                &id(),
+                parse(m, "window_slots")?,
+                parse(m, "max_synchronizer")?,
+                parse(m, "max_user")?,
+                &synchronizer_key,
+                &client.payer().pubkey(),
+            )?;
+            process(&mut client, &config, &[instruction], &[synchronizer_keypair]).await
+        }
+        "set-min-signatures" => {
+            let instruction = instruction::set_minimum_required_signature(&id(), parse(m, "min_signatures")?, &synchronizer_key)?;
+            process(&mut client, &config, &[instruction], &[synchronizer_keypair]).await
+        }
+        "set-collateral-token" => {
+            let instruction = instruction::set_collateral_token(&id(), &parse(m, "collateral_mint")?, &synchronizer_key)?;
+            process(&mut client, &config, &[instruction], &[synchronizer_keypair]).await
+        }
+        "add-collateral" => {
+            let instruction = instruction::add_collateral(
+                &id(),
+                parse(m, "rate")?,
+                RateBounds { max_age: parse(m, "max_rate_age")?, max_deviation_bps: parse(m, "max_rate_deviation_bps")? },
+                &parse(m, "collateral_mint")?,
+                &parse(m, "vault")?,
+                &synchronizer_key,
+                &client.payer().pubkey(),
+            )?;
+            process(&mut client, &config, &[instruction], &[synchronizer_keypair]).await
+        }
+        "remove-collateral" => {
+            let collateral_mint = parse(m, "collateral_mint")?;
+            let (_, vault) = client.get_collateral_vault(Some(&collateral_mint)).await?;
+            let instruction = instruction::remove_collateral(&id(), &collateral_mint, &vault, &synchronizer_key)?;
+            process(&mut client, &config, &[instruction], &[synchronizer_keypair]).await
+        }
+        "set-collateral-rate" => {
+            let oracles = m.values_of("oracle_keypair").into_iter().flatten()
+                .map(|value| read_signer(value, offline))
+                .collect::<CliResult<Vec<Box<dyn Signer>>>>()?;
+            let oracle_keys: Vec<Pubkey> = oracles.iter().map(|oracle| oracle.pubkey()).collect();
+            let instruction = instruction::update_collateral_rate(
+                &id(),
+                &parse(m, "collateral_mint")?,
+                parse(m, "rate")?,
+                &synchronizer_key,
+                &oracle_keys,
+            )?;
+            let signers: Vec<&dyn Signer> = if oracles.is_empty() {
+                vec![synchronizer_keypair]
+            } else {
+                oracles.iter().map(|oracle| oracle.as_ref()).collect()
+            };
+            process(&mut client, &config, &[instruction], &signers).await
+        }
+        "withdraw-fee" | "withdraw-collateral" => {
+            let collateral_mint = if m.is_present("collateral_mint") { Some(parse(m, "collateral_mint")?) } else { None };
+            let (_, vault) = client.get_collateral_vault(collateral_mint.as_ref()).await?;
+            let amount = parse(m, "amount")?;
+            let recipient = parse(m, "recipient")?;
+            let instruction = if command == "withdraw-fee" {
+                instruction::withdraw_fee(&id(), amount, &vault, &recipient, &synchronizer_key, collateral_mint.is_some())?
+            } else {
+                instruction::withdraw_collateral(&id(), amount, &vault, &recipient, &synchronizer_key, collateral_mint.is_some())?
+            };
+            process(&mut client, &config, &[instruction], &[synchronizer_keypair]).await
+        }
+        "buy" | "sell" => {
+            let user = read_signer(m.value_of("user").ok_or("missing --user")?, offline)?;
+            let oracles = m.values_of("oracle_keypair").into_iter().flatten()
+                .map(|value| read_signer(value, offline))
+                .collect::<CliResult<Vec<Box<dyn Signer>>>>()?;
+            let oracle_keys: Vec<Pubkey> = oracles.iter().map(|oracle| oracle.pubkey()).collect();
+            let trade = parse_trade(m, &oracle_keys)?;
+            let instructions = if command == "buy" {
+                client.buy_for_instructions(&user.pubkey(), &trade).await?
+            } else {
+                vec![client.sell_for_instruction(&user.pubkey(), &trade).await?]
+            };
+
+            let mut signers: Vec<&dyn Signer> = vec![user.as_ref(), synchronizer_keypair];
+            for oracle in oracles.iter() {
+                signers.push(oracle.as_ref());
+            }
+            process(&mut client, &config, &instructions, &signers).await
+        }
+        "create-position" | "close-position" => {
+            let user = read_signer(m.value_of("user").ok_or("missing --user")?, offline)?;
+            let fiat_asset_mint = parse(m, "fiat_mint")?;
+            let instruction = if command == "create-position" {
+                instruction::create_user_position(&id(), &fiat_asset_mint, &user.pubkey(), &synchronizer_key)?
+            } else {
+                let recipient = match m.value_of("rent_recipient") {
+                    Some(_) => parse(m, "rent_recipient")?,
+                    None => user.pubkey(),
+                };
+                instruction::close_user_position(&id(), &fiat_asset_mint, &recipient, &user.pubkey(), &synchronizer_key)?
+            };
+            process(&mut client, &config, &[instruction], &[user.as_ref()]).await
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let matches = app().get_matches();
+    if let Err(error) = run(matches).await {
+        eprintln!("Error: {}", error);
+        exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_trade_arguments() {
+        let oracles = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        let fiat_mint = Pubkey::new_unique();
+        let fiat_mint_arg = fiat_mint.to_string();
+        let args = vec![
+            "synchronizer-cli", "buy", "--dry-run", "--output", "json",
+            "--user", "user.json", "--fiat-mint", &fiat_mint_arg,
+            "--amount", "10", "--fee", "1", "--multiplier", "5",
+            "--price", "20", "--oracle-keypair", "oracle1.json",
+            "--price", "21", "--oracle-keypair", "oracle2.json",
+        ];
+        let matches = app().get_matches_from_safe(args.clone()).unwrap();
+        let (command, command_matches) = matches.subcommand();
+        assert_eq!(command, "buy");
+        let command_matches = command_matches.unwrap();
+        assert!(command_matches.is_present("dry_run"));
+        assert_eq!(command_matches.value_of("output"), Some("json"));
+
+        let trade = parse_trade(command_matches, &oracles).unwrap();
+        assert_eq!(trade, Trade {
+            fiat_asset_mint: fiat_mint,
+            multiplier: 5,
+            amount: 10,
+            fee: 1,
+            prices: vec![20, 21],
+            oracles: oracles.clone(),
+            referrer: None,
+            collateral_mint: None,
+        });
+
+        let referrer = Pubkey::new_unique().to_string();
+        let matches = app().get_matches_from_safe([&args[..], &["--referrer", &referrer]].concat()).unwrap();
+        let trade = parse_trade(matches.subcommand_matches("buy").unwrap(), &oracles).unwrap();
+        assert_eq!(trade.referrer.map(|referrer| referrer.to_string()), Some(referrer));
+
+        let usdt = Pubkey::new_unique();
+        let usdt_arg = usdt.to_string();
+        let matches = app().get_matches_from_safe([&args[..], &["--collateral-mint", &usdt_arg]].concat()).unwrap();
+        let trade = parse_trade(matches.subcommand_matches("buy").unwrap(), &oracles).unwrap();
+        assert_eq!(trade.collateral_mint, Some(usdt));
+
+        // BadCase: price without oracle keypair
+        let trade_error = parse_trade(command_matches, &oracles[..1]);
+        assert!(trade_error.is_err());
+
+        // BadCase: missing required argument
+        assert!(app().get_matches_from_safe(args[..4].to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_export_arguments() {
+        let nonce = Pubkey::new_unique().to_string();
+        let synchronizer = Pubkey::new_unique();
+        let synchronizer_arg = synchronizer.to_string();
+        let args = vec![
+            "synchronizer-cli", "set-cap", "1000", "--synchronizer", &synchronizer_arg,
+            "--nonce", &nonce, "--export", "set-cap.tx",
+        ];
+        let matches = app().get_matches_from_safe(args.clone()).unwrap();
+        let command_matches = matches.subcommand_matches("set-cap").unwrap();
+        assert_eq!(command_matches.value_of("export"), Some("set-cap.tx"));
+        assert_eq!(command_matches.value_of("nonce"), Some(nonce.as_str()));
+
+        // Offline parties are given by pubkey
+        let signer = read_signer(&synchronizer_arg, true).unwrap();
+        assert_eq!(signer.pubkey(), synchronizer);
+        assert!(read_signer(&synchronizer_arg, false).is_err());
+
+        // BadCase: export without durable nonce
+        let mut args = args;
+        args.drain(5..7);
+        assert!(app().get_matches_from_safe(args).is_err());
+
+        let matches = app().get_matches_from_safe(vec![
+            "synchronizer-cli", "sign", "set-cap.tx", "--signer", "admin.json", "--signer", "oracle.json",
+        ]).unwrap();
+        let command_matches = matches.subcommand_matches("sign").unwrap();
+        assert_eq!(command_matches.value_of("file"), Some("set-cap.tx"));
+        assert_eq!(command_matches.values_of("signer").unwrap().collect::<Vec<_>>(), vec!["admin.json", "oracle.json"]);
+    }
+
+    #[test]
+    fn test_transaction_file() {
+        let path = std::env::temp_dir().join(format!("synchronizer-cli-{}.tx", Pubkey::new_unique()));
+        let path = path.to_str().unwrap();
+        let payer = Keypair::new();
+        let synchronizer = Keypair::new();
+        let instruction = instruction::set_remaining_dollar_cap(&id(), 100, &synchronizer.pubkey()).unwrap();
+        let mut transaction = offline::unsigned_transaction(
+            &[instruction],
+            &payer.pubkey(),
+            &Pubkey::new_unique(),
+            &payer.pubkey(),
+            solana_program::hash::Hash::new_unique(),
+        );
+        offline::sign(&mut transaction, &[&payer, &NullSigner::new(&synchronizer.pubkey())]).unwrap();
+
+        write_transaction(path, &transaction).unwrap();
+        assert_eq!(read_transaction(path).unwrap(), transaction);
+        fs::remove_file(path).unwrap();
+
+        // BadCase: missing file
+        assert!(read_transaction(path).is_err());
+    }
+
+    #[test]
+    fn test_price_snapshot() {
+        let path = std::env::temp_dir().join(format!("synchronizer-cli-{}.csv", Pubkey::new_unique()));
+        let path_arg = path.to_str().unwrap();
+        let (aapl, tsla) = (Pubkey::new_unique(), Pubkey::new_unique());
+        fs::write(&path, format!("{},150\n{},700\n", aapl, tsla)).unwrap();
+
+        let matches = app().get_matches_from_safe(vec!["synchronizer-cli", "reconcile", "--prices", path_arg]).unwrap();
+        let prices = read_price_snapshot(matches.subcommand_matches("reconcile").unwrap()).unwrap();
+        assert_eq!(prices, vec![(aapl, 150), (tsla, 700)].into_iter().collect());
+
+        let aapl_arg = aapl.to_string();
+        let matches = app().get_matches_from_safe(vec!["synchronizer-cli", "reconcile", "--prices", path_arg, "--fiat-mint", &aapl_arg]).unwrap();
+        let prices = read_price_snapshot(matches.subcommand_matches("reconcile").unwrap()).unwrap();
+        assert_eq!(prices, vec![(aapl, 150)].into_iter().collect());
+
+        // BadCase: asset missing from the snapshot
+        let unknown = Pubkey::new_unique().to_string();
+        let matches = app().get_matches_from_safe(vec!["synchronizer-cli", "reconcile", "--prices", path_arg, "--fiat-mint", &unknown]).unwrap();
+        assert!(read_price_snapshot(matches.subcommand_matches("reconcile").unwrap()).is_err());
+        fs::remove_file(&path).unwrap();
+
+        // BadCase: snapshot is required
+        assert!(app().get_matches_from_safe(vec!["synchronizer-cli", "reconcile"]).is_err());
+    }
+
+    #[test]
+    fn test_market_schedule_arguments() {
+        let fiat_mint = Pubkey::new_unique().to_string();
+        let args = vec![
+            "synchronizer-cli", "set-market-schedule", "--fiat-mint", &fiat_mint,
+            "--hours", "mon-fri=14:30-21:00", "--hours", "Sat=00:00-24:00",
+            "--holiday", "2021-12-24", "--holiday", "2024-02-29",
+        ];
+        let matches = app().get_matches_from_safe(args).unwrap();
+        let matches = matches.subcommand_matches("set-market-schedule").unwrap();
+        let session = TradingHours { open: 52_200, close: 75_600 };
+        assert_eq!(
+            parse_weekly_hours(matches).unwrap(),
+            [session, session, session, session, session, TradingHours::ALL_DAY, TradingHours::default()]
+        );
+        assert_eq!(parse_holidays(matches).unwrap(), vec![18_985, 19_782]);
+        assert_eq!(parse_date("1970-01-02"), Some(1));
+
+        // BadCase: invalid hours or dates
+        for hours in ["mon=21:00-14:30", "fri-mon=14:30-21:00", "mon=14:60-21:00", "mon=00:00-24:01", "monday=14:30-21:00", "mon"].iter() {
+            let matches = app().get_matches_from_safe(vec!["synchronizer-cli", "set-market-schedule", "--fiat-mint", &fiat_mint, "--hours", hours]).unwrap();
+            assert!(parse_weekly_hours(matches.subcommand_matches("set-market-schedule").unwrap()).is_err(), "{}", hours);
+        }
+        for date in ["2021-02-29", "2021-13-01", "2021-12", "1970-01-01", "1969-12-31"].iter() {
+            assert_eq!(parse_date(date), None, "{}", date);
+        }
+    }
+
+    #[test]
+    fn test_asset_spreads_arguments() {
+        // Spreads take one value per flag
+        let mint = Pubkey::new_unique().to_string();
+        assert!(app().get_matches_from_safe(vec!["synchronizer-cli", "set-asset-spreads", "--fiat-mint", &mint, "--ask-bps", "150", "--bid-bps", "50"]).is_ok());
+        assert!(app().get_matches_from_safe(vec!["synchronizer-cli", "set-asset-spreads", "--fiat-mint", &mint, "--ask-bps", "150"]).is_err());
+    }
+
+    #[test]
+    fn test_fee_arguments() {
+        let matches = app().get_matches_from_safe(vec!["synchronizer-cli", "set-fee-tiers", "--tier", "1000=8", "--tier", "5000=5"]).unwrap();
+        assert_eq!(
+            parse_fee_tiers(matches.subcommand_matches("set-fee-tiers").unwrap()).unwrap(),
+            vec![FeeTier { min_volume: 1000, fee: 8 }, FeeTier { min_volume: 5000, fee: 5 }]
+        );
+        let matches = app().get_matches_from_safe(vec!["synchronizer-cli", "set-fee-tiers"]).unwrap();
+        assert!(parse_fee_tiers(matches.subcommand_matches("set-fee-tiers").unwrap()).unwrap().is_empty());
+        assert_eq!(matches.subcommand_matches("set-fee-tiers").unwrap().value_of("volume_window_slots"), Some("0"));
+        for tier in ["1000", "1000=", "=8", "1000=-1"].iter() {
+            let matches = app().get_matches_from_safe(vec!["synchronizer-cli", "set-fee-tiers", "--tier", tier]).unwrap();
+            assert!(parse_fee_tiers(matches.subcommand_matches("set-fee-tiers").unwrap()).is_err(), "{}", tier);
+        }
+
+        // Either a fee or --remove
+        let user = Pubkey::new_unique().to_string();
+        assert!(app().get_matches_from_safe(vec!["synchronizer-cli", "set-fee-override", "--user", &user, "--fee", "0"]).is_ok());
+        assert!(app().get_matches_from_safe(vec!["synchronizer-cli", "set-fee-override", "--user", &user, "--remove"]).is_ok());
+        assert!(app().get_matches_from_safe(vec!["synchronizer-cli", "set-fee-override", "--user", &user]).is_err());
+        assert!(app().get_matches_from_safe(vec!["synchronizer-cli", "set-fee-override", "--user", &user, "--fee", "0", "--remove"]).is_err());
+        assert!(app().get_matches_from_safe(vec!["synchronizer-cli", "set-referral-share", "--bps", "2500"]).is_ok());
+        assert!(app().get_matches_from_safe(vec!["synchronizer-cli", "set-referral-share"]).is_err());
+    }
+
+    #[test]
+    fn test_collateral_arguments() {
+        let mint = Pubkey::new_unique().to_string();
+        let vault = Pubkey::new_unique().to_string();
+        let matches = app().get_matches_from_safe(vec![
+            "synchronizer-cli", "add-collateral", "--collateral-mint", &mint, "--vault", &vault, "--rate", "998000",
+            "--max-rate-age", "3600", "--max-rate-deviation-bps", "100",
+        ]).unwrap();
+        let matches = matches.subcommand_matches("add-collateral").unwrap();
+        assert_eq!(parse::<u64>(matches, "rate").unwrap(), 998_000);
+        assert_eq!(matches.value_of("vault"), Some(vault.as_str()));
+        assert_eq!(parse::<i64>(matches, "max_rate_age").unwrap(), 3_600);
+        assert_eq!(parse::<u16>(matches, "max_rate_deviation_bps").unwrap(), 100);
+
+        // The rate is set by the Synchronizer authority or attested by the oracles
+        let matches = app().get_matches_from_safe(vec![
+            "synchronizer-cli", "set-collateral-rate", "--collateral-mint", &mint, "--rate", "1001000",
+            "--oracle-keypair", "oracle1.json", "--oracle-keypair", "oracle2.json",
+        ]).unwrap();
+        let oracles: Vec<&str> = matches.subcommand_matches("set-collateral-rate").unwrap().values_of("oracle_keypair").unwrap().collect();
+        assert_eq!(oracles, vec!["oracle1.json", "oracle2.json"]);
+        assert!(app().get_matches_from_safe(vec!["synchronizer-cli", "set-collateral-rate", "--collateral-mint", &mint, "--rate", "1001000"]).is_ok());
+
+        // Withdrawals default to the collateral token vault
+        let recipient = Pubkey::new_unique().to_string();
+        let matches = app().get_matches_from_safe(vec!["synchronizer-cli", "withdraw-fee", "10", "--recipient", &recipient]).unwrap();
+        assert!(!matches.subcommand_matches("withdraw-fee").unwrap().is_present("collateral_mint"));
+        let matches = app().get_matches_from_safe(vec![
+            "synchronizer-cli", "withdraw-collateral", "10", "--recipient", &recipient, "--collateral-mint", &mint,
+        ]).unwrap();
+        assert_eq!(matches.subcommand_matches("withdraw-collateral").unwrap().value_of("collateral_mint"), Some(mint.as_str()));
+
+        // BadCase: missing vault, rate or rate bounds
+        assert!(app().get_matches_from_safe(vec![
+            "synchronizer-cli", "add-collateral", "--collateral-mint", &mint, "--rate", "998000", "--max-rate-age", "3600", "--max-rate-deviation-bps", "100",
+        ]).is_err());
+        assert!(app().get_matches_from_safe(vec!["synchronizer-cli", "add-collateral", "--collateral-mint", &mint, "--vault", &vault, "--rate", "998000"]).is_err());
+        assert!(app().get_matches_from_safe(vec!["synchronizer-cli", "set-collateral-rate", "--collateral-mint", &mint]).is_err());
+        assert!(app().get_matches_from_safe(vec!["synchronizer-cli", "remove-collateral"]).is_err());
+    }
+}
@@ -0,0 +1,331 @@
+//! Human and JSON output
+
+use serde_json::{json, Value};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::{signature::Signature, transaction::Transaction};
+use synchronizer::{instruction::SynchronizerInstruction, processor::id, state::SynchronizerData};
+use synchronizer_client::{offline, reconcile::Reconciliation};
+
+/// Output format
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    Human,
+    Json,
+}
+
+impl OutputFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "human" => Some(OutputFormat::Human),
+            "json" => Some(OutputFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+pub fn synchronizer_json(key: &Pubkey, version: u8, synchronizer: &SynchronizerData) -> Value {
+    let oracles: Vec<String> = synchronizer.oracles.iter()
+        .filter(|oracle| **oracle != Pubkey::default())
+        .map(|oracle| oracle.to_string())
+        .collect();
+    json!({
+        "synchronizer": key.to_string(),
+        "version": version,
+        "isInitialized": synchronizer.is_initialized,
+        "collateralTokenKey": synchronizer.collateral_token_key.to_string(),
+        "collateralDecimals": synchronizer.collateral_token_decimals(),
+        "remainingDollarCap": synchronizer.remaining_dollar_cap,
+        "withdrawableFeeAmount": synchronizer.withdrawable_fee_amount,
+        "minimumRequiredSignature": synchronizer.minimum_required_signature,
+        "oracles": oracles,
+    })
+}
+
+pub fn synchronizer_human(key: &Pubkey, version: u8, synchronizer: &SynchronizerData) -> String {
+    let mut text = format!("Synchronizer: {}\n", key);
+    text += &format!("Layout version: {}\n", version);
+    text += &format!("Initialized: {}\n", synchronizer.is_initialized);
+    text += &format!("Collateral token: {}\n", synchronizer.collateral_token_key);
+    text += &format!("Collateral decimals: {}\n", synchronizer.collateral_token_decimals());
+    text += &format!("Remaining dollar cap: {}\n", synchronizer.remaining_dollar_cap);
+    text += &format!("Withdrawable fee amount: {}\n", synchronizer.withdrawable_fee_amount);
+    text += &format!("Minimum required signatures: {}\n", synchronizer.minimum_required_signature);
+    text += "Oracles:\n";
+    for oracle in synchronizer.oracles.iter().filter(|oracle| **oracle != Pubkey::default()) {
+        text += &format!("  {}\n", oracle);
+    }
+    text
+}
+
+pub fn transaction_json(transaction: &Transaction) -> Value {
+    let message = &transaction.message;
+    let instructions: Vec<Value> = message.instructions.iter().map(|instruction| {
+        let program_id = message.account_keys[instruction.program_id_index as usize];
+        let accounts: Vec<Value> = instruction.accounts.iter().map(|index| {
+            let index = *index as usize;
+            json!({
+                "pubkey": message.account_keys[index].to_string(),
+                "isSigner": message.is_signer(index),
+                "isWritable": message.is_writable(index, true),
+            })
+        }).collect();
+        let decoded = if program_id == id() {
+            SynchronizerInstruction::unpack(&instruction.data).ok().map(|decoded| format!("{:?}", decoded))
+        } else {
+            None
+        };
+        json!({
+            "programId": program_id.to_string(),
+            "accounts": accounts,
+            "data": base64::encode(&instruction.data),
+            "decoded": decoded,
+        })
+    }).collect();
+    let signatures: Vec<String> = transaction.signatures.iter().map(|signature| signature.to_string()).collect();
+    let missing_signers: Vec<String> = offline::missing_signers(transaction).iter().map(|signer| signer.to_string()).collect();
+
+    json!({
+        "recentBlockhash": message.recent_blockhash.to_string(),
+        "signatures": signatures,
+        "missingSigners": missing_signers,
+        "instructions": instructions,
+        "transaction": offline::encode(transaction),
+    })
+}
+
+pub fn transaction_human(transaction: &Transaction) -> String {
+    let json = transaction_json(transaction);
+    let mut text = format!("Recent blockhash: {}\n", json["recentBlockhash"].as_str().unwrap());
+    text += "Signatures:\n";
+    for signature in json["signatures"].as_array().unwrap() {
+        text += &format!("  {}\n", signature.as_str().unwrap());
+    }
+    for (i, instruction) in json["instructions"].as_array().unwrap().iter().enumerate() {
+        text += &format!("Instruction {}: program {}\n", i, instruction["programId"].as_str().unwrap());
+        if let Some(decoded) = instruction["decoded"].as_str() {
+            text += &format!("  {}\n", decoded);
+        }
+        for account in instruction["accounts"].as_array().unwrap() {
+            let mut flags = Vec::new();
+            if account["isWritable"].as_bool().unwrap() {
+                flags.push("writable");
+            }
+            if account["isSigner"].as_bool().unwrap() {
+                flags.push("signer");
+            }
+            text += &format!("  {} [{}]\n", account["pubkey"].as_str().unwrap(), flags.join(", "));
+        }
+        text += &format!("  Data: {}\n", instruction["data"].as_str().unwrap());
+    }
+    let missing_signers = json["missingSigners"].as_array().unwrap();
+    if !missing_signers.is_empty() {
+        text += "Missing signers:\n";
+        for signer in missing_signers {
+            text += &format!("  {}\n", signer.as_str().unwrap());
+        }
+    }
+    text += &format!("Transaction: {}\n", json["transaction"].as_str().unwrap());
+    text
+}
+
+pub fn reconciliation_json(key: &Pubkey, reconciliation: &Reconciliation) -> Value {
+    let assets: Vec<Value> = reconciliation.assets.iter().map(|asset| json!({
+        "fiatAssetMint": asset.fiat_asset_mint.to_string(),
+        "supply": asset.supply,
+        "price": asset.price,
+        "liability": asset.liability,
+        "backing": asset.backing,
+        // i128 isn't a JSON number
+        "surplus": asset.surplus.to_string(),
+    })).collect();
+    let collaterals: Vec<Value> = reconciliation.collaterals.iter().map(|collateral| json!({
+        "mint": collateral.mint.to_string(),
+        "vaultBalance": collateral.vault_balance,
+        "withdrawableFeeAmount": collateral.withdrawable_fee_amount,
+        "rate": collateral.rate,
+        "availableCollateral": collateral.available_collateral.to_string(),
+    })).collect();
+    json!({
+        "synchronizer": key.to_string(),
+        "vaultBalance": reconciliation.vault_balance,
+        "withdrawableFeeAmount": reconciliation.withdrawable_fee_amount,
+        "availableCollateral": reconciliation.available_collateral.to_string(),
+        "totalLiability": reconciliation.total_liability,
+        "surplus": reconciliation.surplus.to_string(),
+        "underCollateralized": reconciliation.is_under_collateralized(),
+        "collaterals": collaterals,
+        "assets": assets,
+    })
+}
+
+pub fn reconciliation_human(key: &Pubkey, reconciliation: &Reconciliation) -> String {
+    let mut text = format!("Synchronizer: {}\n", key);
+    text += &format!("Vault balance: {}\n", reconciliation.vault_balance);
+    text += &format!("Withdrawable fee amount: {}\n", reconciliation.withdrawable_fee_amount);
+    text += &format!("Available collateral: {}\n", reconciliation.available_collateral);
+    text += &format!("Total liability: {}\n", reconciliation.total_liability);
+    text += &format!("Surplus: {}\n", reconciliation.surplus);
+    if !reconciliation.collaterals.is_empty() {
+        text += "Accepted collaterals:\n";
+        for collateral in &reconciliation.collaterals {
+            text += &format!(
+                "  {}: vault balance {}, withdrawable fee amount {}, rate {}, available {}\n",
+                collateral.mint, collateral.vault_balance, collateral.withdrawable_fee_amount, collateral.rate, collateral.available_collateral,
+            );
+        }
+    }
+    text += "Assets:\n";
+    for asset in &reconciliation.assets {
+        text += &format!(
+            "  {}: supply {} at {}, liability {}, backing {}, surplus {}\n",
+            asset.fiat_asset_mint, asset.supply, asset.price, asset.liability, asset.backing, asset.surplus,
+        );
+    }
+    text += if reconciliation.is_under_collateralized() { "Status: UNDER-COLLATERALIZED\n" } else { "Status: collateralized\n" };
+    text
+}
+
+pub fn print_synchronizer(format: OutputFormat, key: &Pubkey, version: u8, synchronizer: &SynchronizerData) {
+    match format {
+        OutputFormat::Human => print!("{}", synchronizer_human(key, version, synchronizer)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&synchronizer_json(key, version, synchronizer)).unwrap()),
+    }
+}
+
+pub fn print_transaction(format: OutputFormat, transaction: &Transaction) {
+    match format {
+        OutputFormat::Human => print!("{}", transaction_human(transaction)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&transaction_json(transaction)).unwrap()),
+    }
+}
+
+pub fn print_exported(format: OutputFormat, path: &str, transaction: &Transaction) {
+    match format {
+        OutputFormat::Human => print!("Transaction written to {}\n{}", path, transaction_human(transaction)),
+        OutputFormat::Json => {
+            let mut json = transaction_json(transaction);
+            json["file"] = json!(path);
+            println!("{}", serde_json::to_string_pretty(&json).unwrap());
+        }
+    }
+}
+
+pub fn print_reconciliation(format: OutputFormat, key: &Pubkey, reconciliation: &Reconciliation) {
+    match format {
+        OutputFormat::Human => print!("{}", reconciliation_human(key, reconciliation)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&reconciliation_json(key, reconciliation)).unwrap()),
+    }
+}
+
+pub fn print_signature(format: OutputFormat, signature: &Signature) {
+    match format {
+        OutputFormat::Human => println!("Signature: {}", signature),
+        OutputFormat::Json => println!("{}", json!({ "signature": signature.to_string() })),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use solana_program::hash::Hash;
+    use solana_sdk::{signature::Keypair, signer::Signer};
+    use super::*;
+
+    #[test]
+    fn test_synchronizer_output() {
+        let key = Pubkey::new_unique();
+        let oracle = Pubkey::new_unique();
+        let synchronizer = SynchronizerData {
+            is_initialized: true,
+            remaining_dollar_cap: 500,
+            minimum_required_signature: 1,
+            oracles: [oracle, Pubkey::default(), Pubkey::default()],
+            collateral_decimals: Some(6),
+            ..SynchronizerData::default()
+        };
+
+        let json = synchronizer_json(&key, 3, &synchronizer);
+        assert_eq!(json["synchronizer"], key.to_string());
+        assert_eq!(json["version"], 3);
+        assert_eq!(json["remainingDollarCap"], 500);
+        assert_eq!(json["collateralDecimals"], 6);
+        assert_eq!(json["oracles"], json!([oracle.to_string()]));
+
+        let human = synchronizer_human(&key, 3, &synchronizer);
+        assert!(human.contains("Remaining dollar cap: 500\n"));
+        assert!(human.contains("Collateral decimals: 6\n"));
+        assert!(human.ends_with(&format!("Oracles:\n  {}\n", oracle)));
+    }
+
+    #[test]
+    fn test_transaction_output() {
+        let payer = Keypair::new();
+        let synchronizer = Keypair::new();
+        let instruction = synchronizer::instruction::set_remaining_dollar_cap(&id(), 100, &synchronizer.pubkey()).unwrap();
+        let mut transaction = Transaction::new_with_payer(&[instruction], Some(&payer.pubkey()));
+        transaction.sign(&[&payer, &synchronizer], Hash::default());
+
+        let json = transaction_json(&transaction);
+        let instruction = &json["instructions"][0];
+        assert_eq!(instruction["programId"], id().to_string());
+        assert_eq!(instruction["decoded"], "SetRemainingDollarCap { remaining_dollar_cap: 100 }");
+        assert_eq!(instruction["accounts"], json!([{
+            "pubkey": synchronizer.pubkey().to_string(),
+            "isSigner": true,
+            "isWritable": true,
+        }]));
+        let decoded: Transaction = bincode::deserialize(&base64::decode(json["transaction"].as_str().unwrap()).unwrap()).unwrap();
+        assert_eq!(decoded, transaction);
+
+        let human = transaction_human(&transaction);
+        assert!(human.contains(&format!("  {} [writable, signer]\n", synchronizer.pubkey())));
+        assert!(!human.contains("Missing signers"));
+
+        // Partially signed transaction lists the parties still to sign
+        let instruction = synchronizer::instruction::set_remaining_dollar_cap(&id(), 100, &synchronizer.pubkey()).unwrap();
+        let mut transaction = Transaction::new_with_payer(&[instruction], Some(&payer.pubkey()));
+        transaction.partial_sign(&[&payer], Hash::default());
+        assert_eq!(transaction_json(&transaction)["missingSigners"], json!([synchronizer.pubkey().to_string()]));
+        assert!(transaction_human(&transaction).contains(&format!("Missing signers:\n  {}\n", synchronizer.pubkey())));
+    }
+
+    #[test]
+    fn test_reconciliation_output() {
+        let key = Pubkey::new_unique();
+        let fiat_asset_mint = Pubkey::new_unique();
+        let collateral_mint = Pubkey::new_unique();
+        let reconciliation = Reconciliation {
+            vault_balance: 90,
+            withdrawable_fee_amount: 10,
+            available_collateral: 80,
+            total_liability: 100,
+            surplus: -20,
+            collaterals: vec![synchronizer_client::reconcile::CollateralReconciliation {
+                mint: collateral_mint,
+                vault_balance: 3,
+                withdrawable_fee_amount: 1,
+                rate: 5,
+                available_collateral: 10,
+            }],
+            assets: vec![synchronizer_client::reconcile::AssetReconciliation {
+                fiat_asset_mint,
+                supply: 10,
+                price: 10,
+                liability: 100,
+                backing: 80,
+                surplus: -20,
+            }],
+        };
+
+        let json = reconciliation_json(&key, &reconciliation);
+        assert_eq!(json["surplus"], "-20");
+        assert_eq!(json["underCollateralized"], true);
+        assert_eq!(json["assets"][0]["fiatAssetMint"], fiat_asset_mint.to_string());
+        assert_eq!(json["collaterals"][0]["mint"], collateral_mint.to_string());
+        assert_eq!(json["collaterals"][0]["availableCollateral"], "10");
+
+        let human = reconciliation_human(&key, &reconciliation);
+        assert!(human.contains(&format!("  {}: supply 10 at 10, liability 100, backing 80, surplus -20\n", fiat_asset_mint)));
+        assert!(human.contains(&format!("  {}: vault balance 3, withdrawable fee amount 1, rate 5, available 10\n", collateral_mint)));
+        assert!(human.ends_with("Status: UNDER-COLLATERALIZED\n"));
+    }
+}
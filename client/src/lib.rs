@@ -0,0 +1,663 @@
+//! Client for the Synchronizer program
+
+pub mod offline;
+pub mod reconcile;
+
+use num_traits::FromPrimitive;
+use solana_program::{instruction::{Instruction, InstructionError}, program_error::ProgramError, program_pack::Pack, pubkey::Pubkey, system_instruction};
+use solana_sdk::{signature::Signature, signer::{Signer, SignerError}, transaction::{Transaction, TransactionError}, transport::TransportError};
+use spl_token::state::{Account, Mint};
+use std::io;
+use synchronizer::{error::SynchronizerError, instruction, processor::id, quote::{self, Quote, Settlement}, state::{AssetData, CollateralSetData, FeeScheduleData, FeeTier, MarketScheduleData, RateBounds, RateLimitData, SynchronizerData, TradingHours, UserPositionData, UserVolumeData}};
+use thiserror::Error;
+
+pub use solana_banks_client::{start_tcp_client, BanksClient};
+pub use spl_associated_token_account::get_associated_token_address;
+
+/// Errors returned by the Synchronizer client
+#[derive(Debug, Error)]
+pub enum ClientError {
+    /// Synchronizer program error
+    #[error("Synchronizer error: {0}")]
+    Synchronizer(#[from] SynchronizerError),
+    /// Transaction error
+    #[error("Transport error: {0}")]
+    Transport(TransportError),
+    /// Transaction signing error
+    #[error("Signer error: {0}")]
+    Signer(#[from] SignerError),
+    /// Banks client I/O error
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// Instruction building or account decoding error
+    #[error("Program error: {0}")]
+    Program(#[from] ProgramError),
+    /// Account doesn't exist
+    #[error("Account {0} not found")]
+    AccountNotFound(Pubkey),
+    /// Account is not an initialized nonce account
+    #[error("Invalid nonce account")]
+    InvalidNonceAccount,
+    /// Transaction can't be decoded or has invalid signatures
+    #[error("Invalid transaction")]
+    InvalidTransaction,
+}
+
+impl From<TransportError> for ClientError {
+    fn from(error: TransportError) -> Self {
+        ClientError::Transport(error)
+    }
+}
+
+/// Maps a failed transaction to the `SynchronizerError` returned by one of its Synchronizer instructions.
+/// Token program errors raised inside Synchronizer instructions use the same custom codes.
+pub fn synchronizer_error(transaction: &Transaction, error: &TransportError) -> Option<SynchronizerError> {
+    match error {
+        TransportError::TransactionError(TransactionError::InstructionError(index, InstructionError::Custom(code)))
+            if transaction.message.program_id(*index as usize) == Some(&id()) => SynchronizerError::from_u32(*code),
+        _ => None,
+    }
+}
+
+/// Processes a signed transaction, mapping Synchronizer program errors to `ClientError::Synchronizer`
+pub(crate) async fn process_signed_transaction(banks_client: &mut BanksClient, transaction: Transaction) -> Result<Signature, ClientError> {
+    let signature = transaction.signatures[0];
+    match banks_client.process_transaction(transaction.clone()).await {
+        Ok(()) => Ok(signature),
+        Err(error) => match synchronizer_error(&transaction, &error) {
+            Some(error) => Err(ClientError::Synchronizer(error)),
+            None => Err(error.into()),
+        },
+    }
+}
+
+/// Trade parameters of `buy_for` and `sell_for`, signed by the oracles
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trade {
+    /// Fiat asset mint
+    pub fiat_asset_mint: Pubkey,
+    /// Dollar cap multiplier
+    pub multiplier: u64,
+    /// Fiat asset amount
+    pub amount: u64,
+    /// Fee rate in base units of `quote::FEE_DECIMALS`
+    pub fee: u64,
+    /// Oracle prices, in the same order as `oracles`
+    pub prices: Vec<u64>,
+    /// Oracles which sign the transaction
+    pub oracles: Vec<Pubkey>,
+    /// Referrer paid a share of the fee on its collateral associated token account, which must exist
+    pub referrer: Option<Pubkey>,
+    /// Accepted collateral settling the trade, `None` for the Synchronizer collateral token.
+    /// The user and referrer collateral accounts are their associated token accounts of this mint.
+    pub collateral_mint: Option<Pubkey>,
+}
+
+/// Synchronizer client over a banks client connection
+pub struct SynchronizerClient {
+    banks_client: BanksClient,
+    payer: Box<dyn Signer>,
+    synchronizer_key: Pubkey,
+}
+
+impl SynchronizerClient {
+    /// Creates a client for the Synchronizer account, `payer` pays for every transaction
+    pub fn new<P: Into<Box<dyn Signer>>>(banks_client: BanksClient, payer: P, synchronizer_key: Pubkey) -> Self {
+        Self {
+            banks_client,
+            payer: payer.into(),
+            synchronizer_key,
+        }
+    }
+
+    /// Synchronizer account authority
+    pub fn synchronizer_key(&self) -> Pubkey {
+        self.synchronizer_key
+    }
+
+    /// Transaction fee payer
+    pub fn payer(&self) -> &dyn Signer {
+        self.payer.as_ref()
+    }
+
+    /// Underlying banks client
+    pub fn banks_client(&mut self) -> &mut BanksClient {
+        &mut self.banks_client
+    }
+
+    /// Fetches and decodes Synchronizer data of any known layout version
+    pub async fn get_synchronizer_data(&mut self) -> Result<SynchronizerData, ClientError> {
+        let account = self.banks_client.get_account(self.synchronizer_key).await?
+            .ok_or(ClientError::AccountNotFound(self.synchronizer_key))?;
+        let (_, synchronizer) = SynchronizerData::unpack_versioned(&account.data)?;
+        Ok(synchronizer)
+    }
+
+    /// Fetches and decodes the asset account of a fiat asset, `None` if the asset has no caps nor spreads
+    pub async fn get_asset_data(&mut self, fiat_asset_mint: &Pubkey) -> Result<Option<AssetData>, ClientError> {
+        let (address, _) = AssetData::find_address(&self.synchronizer_key, fiat_asset_mint);
+        match self.banks_client.get_account(address).await? {
+            Some(account) if account.owner == id() => Ok(Some(AssetData::unpack(&account.data)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Fetches and decodes the market schedule of a fiat asset, `None` if the asset trades at any time
+    pub async fn get_market_schedule_data(&mut self, fiat_asset_mint: &Pubkey) -> Result<Option<MarketScheduleData>, ClientError> {
+        let (address, _) = MarketScheduleData::find_address(&self.synchronizer_key, fiat_asset_mint);
+        match self.banks_client.get_account(address).await? {
+            Some(account) if account.owner == id() => Ok(Some(MarketScheduleData::unpack(&account.data)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Fetches and decodes the rolling volume limits, `None` if the Synchronizer has no limits
+    pub async fn get_rate_limit_data(&mut self) -> Result<Option<RateLimitData>, ClientError> {
+        let (address, _) = RateLimitData::find_address(&self.synchronizer_key);
+        match self.banks_client.get_account(address).await? {
+            Some(account) if account.owner == id() => Ok(Some(RateLimitData::unpack(&account.data)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Fetches and decodes the rolling volume of a user, `None` before the user's first limited trade
+    pub async fn get_user_volume_data(&mut self, user: &Pubkey) -> Result<Option<UserVolumeData>, ClientError> {
+        let (address, _) = UserVolumeData::find_address(&self.synchronizer_key, user);
+        match self.banks_client.get_account(address).await? {
+            Some(account) if account.owner == id() => Ok(Some(UserVolumeData::unpack(&account.data)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Fetches and decodes the fee schedule, `None` if the Synchronizer has no fee tiers nor overrides
+    pub async fn get_fee_schedule_data(&mut self) -> Result<Option<FeeScheduleData>, ClientError> {
+        let (address, _) = FeeScheduleData::find_address(&self.synchronizer_key);
+        match self.banks_client.get_account(address).await? {
+            Some(account) if account.owner == id() => Ok(Some(FeeScheduleData::unpack(&account.data)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Fetches and decodes the accepted collaterals, `None` if the Synchronizer accepts only its collateral token
+    pub async fn get_collateral_set_data(&mut self) -> Result<Option<CollateralSetData>, ClientError> {
+        let (address, _) = CollateralSetData::find_address(&self.synchronizer_key);
+        match self.banks_client.get_account(address).await? {
+            Some(account) if account.owner == id() => Ok(Some(CollateralSetData::unpack(&account.data)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Fee rate `BuyFor`/`SellFor` would charge the user for `trade` at the root slot, for quotes
+    pub async fn trade_fee(&mut self, user: &Pubkey, trade: &Trade) -> Result<u64, ClientError> {
+        let schedule = match self.get_fee_schedule_data().await? {
+            Some(schedule) => schedule,
+            None => return Ok(trade.fee),
+        };
+        let slot = self.banks_client.get_root_slot().await?;
+        let volume = self.get_user_volume_data(user).await?
+            .map_or(0, |user_volume| user_volume.window.volume(slot));
+        Ok(schedule.fee_rate(user, volume).map_or(trade.fee, |rate| rate.min(trade.fee)))
+    }
+
+    /// Fetches and decodes the position account of a user in a fiat asset, `None` if the user has none
+    pub async fn get_user_position_data(&mut self, user: &Pubkey, fiat_asset_mint: &Pubkey) -> Result<Option<UserPositionData>, ClientError> {
+        let (address, _) = UserPositionData::find_address(&self.synchronizer_key, user, fiat_asset_mint);
+        match self.banks_client.get_account(address).await? {
+            Some(account) if account.owner == id() => Ok(Some(UserPositionData::unpack(&account.data)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Fetches and decodes a token account
+    pub async fn get_token_account(&mut self, key: &Pubkey) -> Result<Account, ClientError> {
+        let account = self.banks_client.get_account(*key).await?
+            .ok_or(ClientError::AccountNotFound(*key))?;
+        Ok(Account::unpack(&account.data)?)
+    }
+
+    /// Synchronizer associated token account of the collateral token, the Synchronizer vault
+    pub async fn get_synchronizer_collateral_address(&mut self) -> Result<Pubkey, ClientError> {
+        let synchronizer = self.get_synchronizer_data().await?;
+        Ok(get_associated_token_address(&self.synchronizer_key, &synchronizer.collateral_token_key))
+    }
+
+    /// Mint and Synchronizer vault of an accepted collateral, or of the collateral token for `None`
+    pub async fn get_collateral_vault(&mut self, collateral_mint: Option<&Pubkey>) -> Result<(Pubkey, Pubkey), ClientError> {
+        match collateral_mint {
+            Some(collateral_mint) => {
+                let set = self.get_collateral_set_data().await?;
+                let collateral = set.as_ref()
+                    .and_then(|set| set.position_by_mint(collateral_mint).map(|index| set.collaterals[index]))
+                    .ok_or(SynchronizerError::InvalidCollateral)?;
+                Ok((collateral.mint, collateral.vault))
+            }
+            None => {
+                let synchronizer = self.get_synchronizer_data().await?;
+                let vault = get_associated_token_address(&self.synchronizer_key, &synchronizer.collateral_token_key);
+                Ok((synchronizer.collateral_token_key, vault))
+            }
+        }
+    }
+
+    /// `TRADE_*_ACCOUNT` flags of the optional accounts a trade of the user passes: the accounts required by the
+    /// Synchronizer, the user position account if it records trades in the collateral of `trade` and the accepted
+    /// collaterals of `trade.collateral_mint`
+    pub async fn trade_optional_accounts(&mut self, user: &Pubkey, trade: &Trade) -> Result<u8, ClientError> {
+        let synchronizer = self.get_synchronizer_data().await?;
+        let mut optional_accounts = synchronizer.required_trade_accounts;
+        let collateral_mint = trade.collateral_mint.unwrap_or(synchronizer.collateral_token_key);
+        if let Some(position) = self.get_user_position_data(user, &trade.fiat_asset_mint).await? {
+            if position.collateral_mint == Pubkey::default() || position.collateral_mint == collateral_mint {
+                optional_accounts |= instruction::TRADE_USER_POSITION_ACCOUNT;
+            }
+        }
+        if trade.collateral_mint.is_some() {
+            optional_accounts |= instruction::TRADE_COLLATERAL_SET_ACCOUNT;
+        }
+        Ok(optional_accounts)
+    }
+
+    /// Builds a transaction paid by the payer and signs it with the payer and `signers`
+    pub async fn sign_transaction(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&dyn Signer],
+    ) -> Result<Transaction, ClientError> {
+        let mut transaction = Transaction::new_with_payer(instructions, Some(&self.payer.pubkey()));
+        let mut all_signers: Vec<&dyn Signer> = vec![self.payer.as_ref()];
+        all_signers.extend_from_slice(signers);
+
+        let recent_blockhash = self.banks_client.get_recent_blockhash().await?;
+        transaction.try_sign(&all_signers, recent_blockhash)?;
+        Ok(transaction)
+    }
+
+    /// Processes a signed transaction
+    pub async fn send_transaction(&mut self, transaction: Transaction) -> Result<Signature, ClientError> {
+        process_signed_transaction(&mut self.banks_client, transaction).await
+    }
+
+    /// Signs the transaction with the payer and `signers` and processes it
+    pub async fn process_transaction(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&dyn Signer],
+    ) -> Result<Signature, ClientError> {
+        let transaction = self.sign_transaction(instructions, signers).await?;
+        self.send_transaction(transaction).await
+    }
+
+    /// Creates the associated token account of `wallet` for `mint` unless it already exists
+    pub async fn create_associated_token_account(&mut self, wallet: &Pubkey, mint: &Pubkey) -> Result<Pubkey, ClientError> {
+        let address = get_associated_token_address(wallet, mint);
+        if self.banks_client.get_account(address).await?.is_none() {
+            let instruction = spl_associated_token_account::create_associated_token_account(&self.payer.pubkey(), wallet, mint);
+            self.process_transaction(&[instruction], &[]).await?;
+        }
+        Ok(address)
+    }
+
+    /// Previews `trade` as a buy with the current Synchronizer settings, fiat and collateral decimals and spreads.
+    /// The fee rate is `trade.fee`, see `trade_fee` for the rate charged to a user.
+    pub async fn quote_buy(&mut self, trade: &Trade) -> Result<Quote, ClientError> {
+        let (prices, fiat_decimals, collateral_decimals, asset) = self.quote_inputs(trade).await?;
+        let ask_spread_bps = asset.map_or(0, |asset| asset.ask_spread_bps);
+        Ok(quote::quote_buy(&prices, trade.amount, trade.fee, trade.multiplier, fiat_decimals, collateral_decimals, ask_spread_bps)?)
+    }
+
+    /// Previews `trade` as a sell with the current Synchronizer settings, fiat and collateral decimals and spreads.
+    /// The fee rate is `trade.fee`, see `trade_fee` for the rate charged to a user.
+    pub async fn quote_sell(&mut self, trade: &Trade) -> Result<Quote, ClientError> {
+        let (prices, fiat_decimals, collateral_decimals, asset) = self.quote_inputs(trade).await?;
+        let bid_spread_bps = asset.map_or(0, |asset| asset.bid_spread_bps);
+        Ok(quote::quote_sell(&prices, trade.amount, trade.fee, trade.multiplier, fiat_decimals, collateral_decimals, bid_spread_bps)?)
+    }
+
+    /// Amounts of a buy or sell `quote` of `trade` settled in `trade.collateral_mint` at its current rate
+    pub async fn settle_quote(&mut self, trade: &Trade, quote: &Quote, buy: bool) -> Result<Settlement, ClientError> {
+        let collateral_mint = match trade.collateral_mint {
+            Some(collateral_mint) => collateral_mint,
+            None => return Ok(Settlement::from(quote)),
+        };
+        let set = self.get_collateral_set_data().await?;
+        let collateral = set.as_ref()
+            .and_then(|set| set.position_by_mint(&collateral_mint).map(|index| set.collaterals[index]))
+            .ok_or(SynchronizerError::InvalidCollateral)?;
+        let settlement = if buy {
+            quote::settle_buy(quote, collateral.rate, collateral.decimals)?
+        } else {
+            quote::settle_sell(quote, collateral.rate, collateral.decimals)?
+        };
+        Ok(settlement)
+    }
+
+    async fn quote_inputs(&mut self, trade: &Trade) -> Result<(Vec<u64>, u8, u8, Option<AssetData>), ClientError> {
+        let synchronizer = self.get_synchronizer_data().await?;
+        let mint = self.banks_client.get_account(trade.fiat_asset_mint).await?
+            .ok_or(ClientError::AccountNotFound(trade.fiat_asset_mint))?;
+        let fiat_decimals = Mint::unpack(&mint.data)?.decimals;
+        let asset = self.get_asset_data(&trade.fiat_asset_mint).await?;
+        let prices = quote::signed_prices(&trade.prices, synchronizer.minimum_required_signature).to_vec();
+        Ok((prices, fiat_decimals, synchronizer.collateral_token_decimals(), asset))
+    }
+
+    /// Builds a `buy_for` instruction for the user associated token accounts
+    pub async fn buy_for_instruction(&mut self, user: &Pubkey, trade: &Trade) -> Result<Instruction, ClientError> {
+        let (collateral_mint, vault) = self.get_collateral_vault(trade.collateral_mint.as_ref()).await?;
+        let referrer = trade.referrer.map(|referrer| get_associated_token_address(&referrer, &collateral_mint));
+        let optional_accounts = self.trade_optional_accounts(user, trade).await?;
+        Ok(instruction::buy_for(
+            &id(),
+            trade.multiplier,
+            trade.amount,
+            trade.fee,
+            &trade.prices,
+            &trade.oracles,
+            &trade.fiat_asset_mint,
+            &get_associated_token_address(user, &collateral_mint),
+            &get_associated_token_address(user, &trade.fiat_asset_mint),
+            &vault,
+            user,
+            &self.synchronizer_key,
+            optional_accounts,
+            referrer.as_ref(),
+        )?)
+    }
+
+    /// Builds a `sell_for` instruction for the user associated token accounts
+    pub async fn sell_for_instruction(&mut self, user: &Pubkey, trade: &Trade) -> Result<Instruction, ClientError> {
+        let (collateral_mint, vault) = self.get_collateral_vault(trade.collateral_mint.as_ref()).await?;
+        let referrer = trade.referrer.map(|referrer| get_associated_token_address(&referrer, &collateral_mint));
+        let optional_accounts = self.trade_optional_accounts(user, trade).await?;
+        Ok(instruction::sell_for(
+            &id(),
+            trade.multiplier,
+            trade.amount,
+            trade.fee,
+            &trade.prices,
+            &trade.oracles,
+            &trade.fiat_asset_mint,
+            &get_associated_token_address(user, &collateral_mint),
+            &get_associated_token_address(user, &trade.fiat_asset_mint),
+            &vault,
+            user,
+            &self.synchronizer_key,
+            optional_accounts,
+            referrer.as_ref(),
+        )?)
+    }
+
+    /// Builds `buy_for` instructions, creating the user fiat asset account if needed
+    pub async fn buy_for_instructions(&mut self, user: &Pubkey, trade: &Trade) -> Result<Vec<Instruction>, ClientError> {
+        let mut instructions = Vec::with_capacity(2);
+        let user_fiat_account = get_associated_token_address(user, &trade.fiat_asset_mint);
+        if self.banks_client.get_account(user_fiat_account).await?.is_none() {
+            instructions.push(spl_associated_token_account::create_associated_token_account(
+                &self.payer.pubkey(),
+                user,
+                &trade.fiat_asset_mint,
+            ));
+        }
+        instructions.push(self.buy_for_instruction(user, trade).await?);
+        Ok(instructions)
+    }
+
+    /// Buys fiat asset for the user, creating the user fiat asset account if needed.
+    /// `signers` are the Synchronizer authority and the trade oracles.
+    pub async fn buy_for(&mut self, trade: &Trade, user: &dyn Signer, signers: &[&dyn Signer]) -> Result<Signature, ClientError> {
+        let instructions = self.buy_for_instructions(&user.pubkey(), trade).await?;
+        let mut all_signers = vec![user];
+        all_signers.extend_from_slice(signers);
+        self.process_transaction(&instructions, &all_signers).await
+    }
+
+    /// Sells fiat asset of the user.
+    /// `signers` are the Synchronizer authority and the trade oracles.
+    pub async fn sell_for(&mut self, trade: &Trade, user: &dyn Signer, signers: &[&dyn Signer]) -> Result<Signature, ClientError> {
+        let instruction = self.sell_for_instruction(&user.pubkey(), trade).await?;
+        let mut all_signers = vec![user];
+        all_signers.extend_from_slice(signers);
+        self.process_transaction(&[instruction], &all_signers).await
+    }
+
+    /// Creates the position account of the user in a fiat asset, the user pays its rent
+    pub async fn create_user_position(&mut self, fiat_asset_mint: &Pubkey, user: &dyn Signer) -> Result<Signature, ClientError> {
+        let instruction = instruction::create_user_position(&id(), fiat_asset_mint, &user.pubkey(), &self.synchronizer_key)?;
+        self.process_transaction(&[instruction], &[user]).await
+    }
+
+    /// Closes the position account of the user in a fiat asset, its rent goes to `recipient`
+    pub async fn close_user_position(&mut self, fiat_asset_mint: &Pubkey, recipient: &Pubkey, user: &dyn Signer) -> Result<Signature, ClientError> {
+        let instruction = instruction::close_user_position(&id(), fiat_asset_mint, recipient, &user.pubkey(), &self.synchronizer_key)?;
+        self.process_transaction(&[instruction], &[user]).await
+    }
+
+    /// Builds instructions creating and initializing the Synchronizer account
+    pub async fn initialize_instructions(
+        &mut self,
+        collateral_token_key: &Pubkey,
+        remaining_dollar_cap: u64,
+        withdrawable_fee_amount: u64,
+        minimum_required_signature: u8,
+        oracles: &[Pubkey],
+    ) -> Result<Vec<Instruction>, ClientError> {
+        let rent = self.banks_client.get_rent().await?;
+        Ok(vec![
+            system_instruction::create_account(
+                &self.payer.pubkey(),
+                &self.synchronizer_key,
+                rent.minimum_balance(SynchronizerData::LEN),
+                SynchronizerData::LEN as u64,
+                &id(),
+            ),
+            instruction::initialize_synchronizer_account(
+                &id(),
+                collateral_token_key,
+                remaining_dollar_cap,
+                withdrawable_fee_amount,
+                minimum_required_signature,
+                &oracles.to_vec(),
+                &self.synchronizer_key,
+            )?,
+        ])
+    }
+
+    /// Creates and initializes the Synchronizer account
+    pub async fn initialize(
+        &mut self,
+        synchronizer_authority: &dyn Signer,
+        collateral_token_key: &Pubkey,
+        remaining_dollar_cap: u64,
+        withdrawable_fee_amount: u64,
+        minimum_required_signature: u8,
+        oracles: &[Pubkey],
+    ) -> Result<Signature, ClientError> {
+        let instructions = self.initialize_instructions(
+            collateral_token_key,
+            remaining_dollar_cap,
+            withdrawable_fee_amount,
+            minimum_required_signature,
+            oracles,
+        ).await?;
+        self.process_transaction(&instructions, &[synchronizer_authority]).await
+    }
+
+    /// Sets the list of known oracles
+    pub async fn set_oracles(&mut self, oracles: &[Pubkey], synchronizer_authority: &dyn Signer) -> Result<Signature, ClientError> {
+        let instruction = instruction::set_oracles(&id(), &oracles.to_vec(), &self.synchronizer_key)?;
+        self.process_transaction(&[instruction], &[synchronizer_authority]).await
+    }
+
+    /// Sets the minimum required oracle signatures
+    pub async fn set_minimum_required_signature(&mut self, minimum_required_signature: u8, synchronizer_authority: &dyn Signer) -> Result<Signature, ClientError> {
+        let instruction = instruction::set_minimum_required_signature(&id(), minimum_required_signature, &self.synchronizer_key)?;
+        self.process_transaction(&[instruction], &[synchronizer_authority]).await
+    }
+
+    /// Sets the collateral token mint
+    pub async fn set_collateral_token(&mut self, collateral_token_key: &Pubkey, synchronizer_authority: &dyn Signer) -> Result<Signature, ClientError> {
+        let instruction = instruction::set_collateral_token(&id(), collateral_token_key, &self.synchronizer_key)?;
+        self.process_transaction(&[instruction], &[synchronizer_authority]).await
+    }
+
+    /// Sets the remaining dollar cap
+    pub async fn set_remaining_dollar_cap(&mut self, remaining_dollar_cap: u64, synchronizer_authority: &dyn Signer) -> Result<Signature, ClientError> {
+        let instruction = instruction::set_remaining_dollar_cap(&id(), remaining_dollar_cap, &self.synchronizer_key)?;
+        self.process_transaction(&[instruction], &[synchronizer_authority]).await
+    }
+
+    /// Sets the caps of a fiat asset, the payer funds the asset account on first use
+    pub async fn set_asset_caps(
+        &mut self,
+        fiat_asset_mint: &Pubkey,
+        max_outstanding_notional: u64,
+        max_transaction_notional: u64,
+        synchronizer_authority: &dyn Signer,
+    ) -> Result<Signature, ClientError> {
+        let instruction = instruction::set_asset_caps(
+            &id(),
+            fiat_asset_mint,
+            max_outstanding_notional,
+            max_transaction_notional,
+            &self.synchronizer_key,
+            &self.payer.pubkey(),
+        )?;
+        self.process_transaction(&[instruction], &[synchronizer_authority]).await
+    }
+
+    /// Sets the spreads of a fiat asset in basis points, the payer funds the asset account on first use
+    pub async fn set_asset_spreads(
+        &mut self,
+        fiat_asset_mint: &Pubkey,
+        ask_spread_bps: u16,
+        bid_spread_bps: u16,
+        synchronizer_authority: &dyn Signer,
+    ) -> Result<Signature, ClientError> {
+        let instruction = instruction::set_asset_spreads(
+            &id(),
+            fiat_asset_mint,
+            ask_spread_bps,
+            bid_spread_bps,
+            &self.synchronizer_key,
+            &self.payer.pubkey(),
+        )?;
+        self.process_transaction(&[instruction], &[synchronizer_authority]).await
+    }
+
+    /// Replaces the fee tiers and their volume window, the payer funds the fee schedule account on first use
+    pub async fn set_fee_tiers(&mut self, tiers: &[FeeTier], volume_window_slots: u64, synchronizer_authority: &dyn Signer) -> Result<Signature, ClientError> {
+        let instruction = instruction::set_fee_tiers(&id(), tiers, volume_window_slots, &self.synchronizer_key, &self.payer.pubkey())?;
+        self.process_transaction(&[instruction], &[synchronizer_authority]).await
+    }
+
+    /// Sets or removes the fee rate of a whitelisted user, the payer funds the fee schedule account on first use
+    pub async fn set_fee_override(&mut self, user: &Pubkey, fee: Option<u64>, synchronizer_authority: &dyn Signer) -> Result<Signature, ClientError> {
+        let instruction = instruction::set_fee_override(&id(), user, fee, &self.synchronizer_key, &self.payer.pubkey())?;
+        self.process_transaction(&[instruction], &[synchronizer_authority]).await
+    }
+
+    /// Sets the share of the trade fees paid to referrers, the payer funds the fee schedule account on first use
+    pub async fn set_referral_share(&mut self, referral_share_bps: u16, synchronizer_authority: &dyn Signer) -> Result<Signature, ClientError> {
+        let instruction = instruction::set_referral_share(&id(), referral_share_bps, &self.synchronizer_key, &self.payer.pubkey())?;
+        self.process_transaction(&[instruction], &[synchronizer_authority]).await
+    }
+
+    /// Sets the rolling volume limits, the payer funds the rate limit account on first use
+    pub async fn set_rate_limits(
+        &mut self,
+        window_slots: u64,
+        max_synchronizer_notional: u64,
+        max_user_notional: u64,
+        synchronizer_authority: &dyn Signer,
+    ) -> Result<Signature, ClientError> {
+        let instruction = instruction::set_rate_limits(
+            &id(),
+            window_slots,
+            max_synchronizer_notional,
+            max_user_notional,
+            &self.synchronizer_key,
+            &self.payer.pubkey(),
+        )?;
+        self.process_transaction(&[instruction], &[synchronizer_authority]).await
+    }
+
+    /// Sets the trading hours and holidays of a fiat asset, the payer funds the market schedule account on first use
+    pub async fn set_market_schedule(
+        &mut self,
+        fiat_asset_mint: &Pubkey,
+        weekly_hours: [TradingHours; 7],
+        holidays: &[u32],
+        synchronizer_authority: &dyn Signer,
+    ) -> Result<Signature, ClientError> {
+        let instruction = instruction::set_market_schedule(
+            &id(),
+            fiat_asset_mint,
+            weekly_hours,
+            holidays,
+            &self.synchronizer_key,
+            &self.payer.pubkey(),
+        )?;
+        self.process_transaction(&[instruction], &[synchronizer_authority]).await
+    }
+
+    /// Accepts a collateral token at `rate` Synchronizer collateral base units per token, `vault` is its
+    /// Synchronizer token account. The payer funds the collateral set account on first use.
+    pub async fn add_collateral(
+        &mut self,
+        rate: u64,
+        rate_bounds: RateBounds,
+        collateral_mint: &Pubkey,
+        vault: &Pubkey,
+        synchronizer_authority: &dyn Signer,
+    ) -> Result<Signature, ClientError> {
+        let instruction = instruction::add_collateral(&id(), rate, rate_bounds, collateral_mint, vault, &self.synchronizer_key, &self.payer.pubkey())?;
+        self.process_transaction(&[instruction], &[synchronizer_authority]).await
+    }
+
+    /// Stops accepting a collateral token, its fees must have been withdrawn and its vault emptied
+    pub async fn remove_collateral(&mut self, collateral_mint: &Pubkey, synchronizer_authority: &dyn Signer) -> Result<Signature, ClientError> {
+        let (_, vault) = self.get_collateral_vault(Some(collateral_mint)).await?;
+        let instruction = instruction::remove_collateral(&id(), collateral_mint, &vault, &self.synchronizer_key)?;
+        self.process_transaction(&[instruction], &[synchronizer_authority]).await
+    }
+
+    /// Sets the rate of an accepted collateral, `signers` are the Synchronizer authority
+    /// or the oracles attesting the rate
+    pub async fn update_collateral_rate(&mut self, collateral_mint: &Pubkey, rate: u64, signers: &[&dyn Signer]) -> Result<Signature, ClientError> {
+        let oracles = if signers.iter().any(|signer| signer.pubkey() == self.synchronizer_key) {
+            Vec::new()
+        } else {
+            signers.iter().map(|signer| signer.pubkey()).collect()
+        };
+        let instruction = instruction::update_collateral_rate(&id(), collateral_mint, rate, &self.synchronizer_key, &oracles)?;
+        self.process_transaction(&[instruction], signers).await
+    }
+
+    /// Withdraws fee from the vault of `collateral_mint`, or of the collateral token for `None`,
+    /// to the recipient token account of the same mint
+    pub async fn withdraw_fee(
+        &mut self,
+        amount: u64,
+        collateral_mint: Option<&Pubkey>,
+        recipient: &Pubkey,
+        synchronizer_authority: &dyn Signer,
+    ) -> Result<Signature, ClientError> {
+        let (_, vault) = self.get_collateral_vault(collateral_mint).await?;
+        let instruction = instruction::withdraw_fee(&id(), amount, &vault, recipient, &self.synchronizer_key, collateral_mint.is_some())?;
+        self.process_transaction(&[instruction], &[synchronizer_authority]).await
+    }
+
+    /// Withdraws collateral from the vault of `collateral_mint`, or of the collateral token for `None`,
+    /// to the recipient token account of the same mint
+    pub async fn withdraw_collateral(
+        &mut self,
+        amount: u64,
+        collateral_mint: Option<&Pubkey>,
+        recipient: &Pubkey,
+        synchronizer_authority: &dyn Signer,
+    ) -> Result<Signature, ClientError> {
+        let (_, vault) = self.get_collateral_vault(collateral_mint).await?;
+        let instruction = instruction::withdraw_collateral(&id(), amount, &vault, recipient, &self.synchronizer_key, collateral_mint.is_some())?;
+        self.process_transaction(&[instruction], &[synchronizer_authority]).await
+    }
+}
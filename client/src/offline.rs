@@ -0,0 +1,140 @@
+//! Offline transactions signed by several parties.
+//!
+//! A transaction is built with a durable nonce instead of a recent blockhash, so it doesn't expire
+//! while it is carried between machines. Each party adds its signature with `sign` and the
+//! fully signed transaction is sent with `broadcast`.
+
+use crate::{process_signed_transaction, ClientError};
+use solana_banks_client::BanksClient;
+use solana_program::{hash::Hash, instruction::Instruction, nonce::state::{Data, State, Versions}, pubkey::Pubkey, system_instruction};
+use solana_sdk::{signature::Signature, signer::{Signer, SignerError}, transaction::Transaction};
+
+/// Decodes initialized nonce account data
+pub fn nonce_data(account_data: &[u8]) -> Result<Data, ClientError> {
+    let versions: Versions = bincode::deserialize(account_data).map_err(|_| ClientError::InvalidNonceAccount)?;
+    match versions.convert_to_current() {
+        State::Initialized(data) => Ok(data),
+        State::Uninitialized => Err(ClientError::InvalidNonceAccount),
+    }
+}
+
+/// Fetches initialized nonce account data
+pub async fn get_nonce_data(banks_client: &mut BanksClient, nonce_account: &Pubkey) -> Result<Data, ClientError> {
+    let account = banks_client.get_account(*nonce_account).await?
+        .ok_or(ClientError::AccountNotFound(*nonce_account))?;
+    if account.owner != solana_program::system_program::id() {
+        return Err(ClientError::InvalidNonceAccount);
+    }
+    nonce_data(&account.data)
+}
+
+/// Builds an unsigned transaction which advances the nonce account before `instructions`
+pub fn unsigned_transaction(
+    instructions: &[Instruction],
+    payer: &Pubkey,
+    nonce_account: &Pubkey,
+    nonce_authority: &Pubkey,
+    nonce_blockhash: Hash,
+) -> Transaction {
+    let mut all_instructions = Vec::with_capacity(instructions.len() + 1);
+    all_instructions.push(system_instruction::advance_nonce_account(nonce_account, nonce_authority));
+    all_instructions.extend_from_slice(instructions);
+
+    let mut transaction = Transaction::new_with_payer(&all_instructions, Some(payer));
+    transaction.message.recent_blockhash = nonce_blockhash;
+    transaction
+}
+
+/// Adds signatures of `signers`, keeping signatures of the other parties.
+/// Every signer must be required by the transaction.
+pub fn sign(transaction: &mut Transaction, signers: &[&dyn Signer]) -> Result<(), ClientError> {
+    let blockhash = transaction.message.recent_blockhash;
+    transaction.try_partial_sign(&signers.to_vec(), blockhash)?;
+    Ok(())
+}
+
+/// Required signers whose signature is still missing
+pub fn missing_signers(transaction: &Transaction) -> Vec<Pubkey> {
+    let signer_count = transaction.message.header.num_required_signatures as usize;
+    transaction.message.account_keys[..signer_count].iter()
+        .zip(transaction.signatures.iter())
+        .filter(|(_, signature)| **signature == Signature::default())
+        .map(|(key, _)| *key)
+        .collect()
+}
+
+/// Encodes a transaction for transfer between machines
+pub fn encode(transaction: &Transaction) -> String {
+    // Serialization into a Vec can't fail
+    base64::encode(bincode::serialize(transaction).unwrap())
+}
+
+/// Decodes a transaction encoded with `encode`
+pub fn decode(encoded: &str) -> Result<Transaction, ClientError> {
+    let bytes = base64::decode(encoded.trim()).map_err(|_| ClientError::InvalidTransaction)?;
+    bincode::deserialize(&bytes).map_err(|_| ClientError::InvalidTransaction)
+}
+
+/// Verifies that every party signed the transaction and processes it
+pub async fn broadcast(banks_client: &mut BanksClient, transaction: Transaction) -> Result<Signature, ClientError> {
+    if !missing_signers(&transaction).is_empty() {
+        return Err(SignerError::NotEnoughSigners.into());
+    }
+    transaction.verify().map_err(|_| ClientError::InvalidTransaction)?;
+    process_signed_transaction(banks_client, transaction).await
+}
+
+#[cfg(test)]
+mod test {
+    use solana_program::fee_calculator::FeeCalculator;
+    use solana_sdk::{signature::Keypair, signer::null_signer::NullSigner};
+    use super::*;
+
+    #[test]
+    fn test_nonce_data() {
+        let data = Data {
+            authority: Pubkey::new_unique(),
+            blockhash: Hash::new_unique(),
+            fee_calculator: FeeCalculator::default(),
+        };
+        let account_data = bincode::serialize(&Versions::new_current(State::Initialized(data.clone()))).unwrap();
+        assert_eq!(nonce_data(&account_data).unwrap(), data);
+
+        let account_data = bincode::serialize(&Versions::new_current(State::Uninitialized)).unwrap();
+        assert!(matches!(nonce_data(&account_data), Err(ClientError::InvalidNonceAccount)));
+        assert!(matches!(nonce_data(&[1, 2]), Err(ClientError::InvalidNonceAccount)));
+    }
+
+    #[test]
+    fn test_multi_party_signing() {
+        let payer = Keypair::new();
+        let nonce_account = Pubkey::new_unique();
+        let admin = Keypair::new();
+        let blockhash = Hash::new_unique();
+        let instruction = synchronizer::instruction::set_remaining_dollar_cap(&synchronizer::processor::id(), 100, &admin.pubkey()).unwrap();
+
+        let mut transaction = unsigned_transaction(&[instruction], &payer.pubkey(), &nonce_account, &payer.pubkey(), blockhash);
+        assert_eq!(transaction.message.recent_blockhash, blockhash);
+        assert_eq!(missing_signers(&transaction), vec![payer.pubkey(), admin.pubkey()]);
+
+        // Coordinator signs with the payer, the admin is only known by pubkey
+        sign(&mut transaction, &[&payer, &NullSigner::new(&admin.pubkey())]).unwrap();
+        assert_eq!(missing_signers(&transaction), vec![admin.pubkey()]);
+
+        // Transfer to the admin machine
+        let mut transaction = decode(&encode(&transaction)).unwrap();
+        sign(&mut transaction, &[&admin]).unwrap();
+        assert!(missing_signers(&transaction).is_empty());
+        transaction.verify().unwrap();
+        assert_eq!(transaction.message.recent_blockhash, blockhash);
+
+        // BadCase: signer not required by the transaction
+        assert!(matches!(
+            sign(&mut transaction, &[&Keypair::new()]),
+            Err(ClientError::Signer(SignerError::KeypairPubkeyMismatch))
+        ));
+
+        // BadCase: garbage input
+        assert!(matches!(decode("not a transaction"), Err(ClientError::InvalidTransaction)));
+    }
+}
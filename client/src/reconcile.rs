@@ -0,0 +1,321 @@
+//! Collateral reconciliation.
+//!
+//! The Synchronizer vault must cover the withdrawable fee amount plus the collateral value of every
+//! outstanding fiat asset at oracle prices. Fiat assets are valued like a sell without fee, so the
+//! liability is what the vault pays if every holder sells. The vaults of accepted collaterals count
+//! at their rates, net of their own withdrawable fees.
+
+use crate::ClientError;
+use solana_banks_client::BanksClient;
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use spl_associated_token_account::get_associated_token_address;
+use spl_token::state::{Account, Mint};
+use std::collections::HashMap;
+use synchronizer::{quote, state::{AcceptedCollateral, CollateralSetData, SynchronizerData}};
+
+/// Outstanding supply of a fiat asset and its price snapshot
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AssetSupply {
+    pub fiat_asset_mint: Pubkey,
+    pub supply: u64,
+    /// Decimals of the fiat asset mint
+    pub decimals: u8,
+    /// Price in collateral token base units
+    pub price: u64,
+}
+
+/// Vault balance of an accepted collateral
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CollateralVault {
+    pub collateral: AcceptedCollateral,
+    /// Balance in base units of the collateral
+    pub balance: u64,
+}
+
+/// Holdings of an accepted collateral
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CollateralReconciliation {
+    pub mint: Pubkey,
+    /// Vault balance in base units of the collateral
+    pub vault_balance: u64,
+    /// Withdrawable fee amount in base units of the collateral
+    pub withdrawable_fee_amount: u64,
+    pub rate: u64,
+    /// Vault balance minus withdrawable fee amount in Synchronizer collateral base units,
+    /// negative if fees aren't covered
+    pub available_collateral: i128,
+}
+
+/// Collateral position of a fiat asset
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AssetReconciliation {
+    pub fiat_asset_mint: Pubkey,
+    pub supply: u64,
+    pub price: u64,
+    /// Collateral value of the supply
+    pub liability: u64,
+    /// Share of the available collateral, pro rata to the liabilities
+    pub backing: u64,
+    /// Backing minus liability, negative for a deficit
+    pub surplus: i128,
+}
+
+/// Collateral position of a Synchronizer
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reconciliation {
+    pub vault_balance: u64,
+    pub withdrawable_fee_amount: u64,
+    /// Vault balance minus withdrawable fee amount plus the available collateral of accepted collaterals,
+    /// negative if fees aren't covered
+    pub available_collateral: i128,
+    pub total_liability: u64,
+    /// Available collateral minus total liability, negative for a deficit
+    pub surplus: i128,
+    pub collaterals: Vec<CollateralReconciliation>,
+    pub assets: Vec<AssetReconciliation>,
+}
+
+impl Reconciliation {
+    /// Whether the vault doesn't cover outstanding fiat assets and fees
+    pub fn is_under_collateralized(&self) -> bool {
+        self.surplus < 0
+    }
+}
+
+/// Values the vaults of accepted collaterals at their rates, net of their withdrawable fees
+fn reconcile_collateral(vault: &CollateralVault) -> Result<CollateralReconciliation, ClientError> {
+    let collateral = &vault.collateral;
+    let value = |amount| quote::collateral_value(amount, collateral.rate, collateral.decimals);
+    let available_collateral = if vault.balance >= collateral.withdrawable_fee_amount {
+        value(vault.balance - collateral.withdrawable_fee_amount)? as i128
+    } else {
+        -(value(collateral.withdrawable_fee_amount - vault.balance)? as i128)
+    };
+    Ok(CollateralReconciliation {
+        mint: collateral.mint,
+        vault_balance: vault.balance,
+        withdrawable_fee_amount: collateral.withdrawable_fee_amount,
+        rate: collateral.rate,
+        available_collateral,
+    })
+}
+
+/// Values `assets` against the vault balance and withdrawable fee amount, and the vaults of accepted collaterals
+pub fn reconcile(
+    synchronizer: &SynchronizerData,
+    vault_balance: u64,
+    collateral_vaults: &[CollateralVault],
+    assets: &[AssetSupply],
+) -> Result<Reconciliation, ClientError> {
+    let collateral_decimals = synchronizer.collateral_token_decimals();
+    let liabilities = assets.iter()
+        .map(|asset| Ok(quote::quote_sell(&[asset.price], asset.supply, 0, 1, asset.decimals, collateral_decimals, 0)?.collateral_amount))
+        .collect::<Result<Vec<u64>, ClientError>>()?;
+    let total_liability: u128 = liabilities.iter().map(|liability| *liability as u128).sum();
+    let collaterals = collateral_vaults.iter()
+        .map(reconcile_collateral)
+        .collect::<Result<Vec<CollateralReconciliation>, ClientError>>()?;
+    let available_collateral = vault_balance as i128 - synchronizer.withdrawable_fee_amount as i128
+        + collaterals.iter().map(|collateral| collateral.available_collateral).sum::<i128>();
+    let distributable = available_collateral.max(0) as u128;
+
+    let assets = assets.iter().zip(liabilities).map(|(asset, liability)| {
+        // Nothing to back without liabilities
+        let backing = (distributable * liability as u128).checked_div(total_liability).unwrap_or(0) as u64;
+        AssetReconciliation {
+            fiat_asset_mint: asset.fiat_asset_mint,
+            supply: asset.supply,
+            price: asset.price,
+            liability,
+            backing,
+            surplus: backing as i128 - liability as i128,
+        }
+    }).collect();
+
+    Ok(Reconciliation {
+        vault_balance,
+        withdrawable_fee_amount: synchronizer.withdrawable_fee_amount,
+        available_collateral,
+        // Each liability is a u64 and there are few assets
+        total_liability: total_liability as u64,
+        surplus: available_collateral - total_liability as i128,
+        collaterals,
+        assets,
+    })
+}
+
+/// Fetches the Synchronizer data, vault balances and fiat asset supplies, then values the assets of `prices`
+pub async fn fetch_reconciliation(
+    banks_client: &mut BanksClient,
+    synchronizer_key: &Pubkey,
+    prices: &HashMap<Pubkey, u64>,
+) -> Result<Reconciliation, ClientError> {
+    let account = banks_client.get_account(*synchronizer_key).await?
+        .ok_or(ClientError::AccountNotFound(*synchronizer_key))?;
+    let (_, synchronizer) = SynchronizerData::unpack_versioned(&account.data)?;
+
+    // The vault is created by the first buy
+    let vault = get_associated_token_address(synchronizer_key, &synchronizer.collateral_token_key);
+    let vault_balance = match banks_client.get_account(vault).await? {
+        Some(account) => Account::unpack(&account.data)?.amount,
+        None => 0,
+    };
+
+    let (collateral_set, _) = CollateralSetData::find_address(synchronizer_key);
+    let collaterals = match banks_client.get_account(collateral_set).await? {
+        Some(account) if account.owner == synchronizer::processor::id() => CollateralSetData::unpack(&account.data)?.collaterals().to_vec(),
+        _ => Vec::new(),
+    };
+    let mut collateral_vaults = Vec::with_capacity(collaterals.len());
+    for collateral in collaterals {
+        let account = banks_client.get_account(collateral.vault).await?
+            .ok_or(ClientError::AccountNotFound(collateral.vault))?;
+        collateral_vaults.push(CollateralVault { collateral, balance: Account::unpack(&account.data)?.amount });
+    }
+
+    let mut mints: Vec<&Pubkey> = prices.keys().collect();
+    mints.sort();
+    let mut assets = Vec::with_capacity(mints.len());
+    for fiat_asset_mint in mints {
+        let account = banks_client.get_account(*fiat_asset_mint).await?
+            .ok_or(ClientError::AccountNotFound(*fiat_asset_mint))?;
+        let mint = Mint::unpack(&account.data)?;
+        assets.push(AssetSupply {
+            fiat_asset_mint: *fiat_asset_mint,
+            supply: mint.supply,
+            decimals: mint.decimals,
+            price: prices[fiat_asset_mint],
+        });
+    }
+    reconcile(&synchronizer, vault_balance, &collateral_vaults, &assets)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ui(amount: f64) -> u64 {
+        spl_token::ui_amount_to_amount(amount, 9)
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let synchronizer = SynchronizerData {
+            withdrawable_fee_amount: ui(5.0),
+            ..SynchronizerData::default()
+        };
+        let (aapl, tsla) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let assets = [
+            AssetSupply { fiat_asset_mint: aapl, supply: ui(10.0), decimals: 9, price: ui(150.0) },
+            AssetSupply { fiat_asset_mint: tsla, supply: ui(1.0), decimals: 9, price: ui(500.0) },
+        ];
+
+        // Liabilities 1500 and 500, 2100 available after fees
+        let reconciliation = reconcile(&synchronizer, ui(2105.0), &[], &assets).unwrap();
+        assert_eq!(reconciliation.available_collateral, ui(2100.0) as i128);
+        assert_eq!(reconciliation.total_liability, ui(2000.0));
+        assert_eq!(reconciliation.surplus, ui(100.0) as i128);
+        assert!(!reconciliation.is_under_collateralized());
+        assert_eq!(reconciliation.assets[0], AssetReconciliation {
+            fiat_asset_mint: aapl,
+            supply: ui(10.0),
+            price: ui(150.0),
+            liability: ui(1500.0),
+            backing: ui(1575.0),
+            surplus: ui(75.0) as i128,
+        });
+        assert_eq!(reconciliation.assets[1].surplus, ui(25.0) as i128);
+
+        // BadCase: price rise
+        let mut assets = assets;
+        assets[1].price = ui(700.0);
+        let reconciliation = reconcile(&synchronizer, ui(2105.0), &[], &assets).unwrap();
+        assert_eq!(reconciliation.surplus, -(ui(100.0) as i128));
+        assert!(reconciliation.is_under_collateralized());
+        assert!(reconciliation.assets.iter().all(|asset| asset.surplus < 0));
+
+        // BadCase: vault doesn't cover the fees
+        let reconciliation = reconcile(&synchronizer, ui(1.0), &[], &[]).unwrap();
+        assert_eq!(reconciliation.available_collateral, -(ui(4.0) as i128));
+        assert!(reconciliation.is_under_collateralized());
+
+        // No supply, nothing to cover
+        assets[0].supply = 0;
+        assets[1].supply = 0;
+        let reconciliation = reconcile(&synchronizer, ui(5.0), &[], &assets).unwrap();
+        assert_eq!(reconciliation.surplus, 0);
+        assert!(!reconciliation.is_under_collateralized());
+        assert_eq!(reconciliation.assets[0].backing, 0);
+
+        // 6 decimals collateral, prices and balances in its base units
+        let synchronizer = SynchronizerData { collateral_decimals: Some(6), ..SynchronizerData::default() };
+        let usdc = |amount| spl_token::ui_amount_to_amount(amount, 6);
+        let assets = [AssetSupply { fiat_asset_mint: aapl, supply: ui(10.0), decimals: 9, price: usdc(150.0) }];
+        let reconciliation = reconcile(&synchronizer, usdc(1600.0), &[], &assets).unwrap();
+        assert_eq!(reconciliation.total_liability, usdc(1500.0));
+        assert_eq!(reconciliation.surplus, usdc(100.0) as i128);
+    }
+
+    #[test]
+    fn test_reconcile_collaterals() {
+        let synchronizer = SynchronizerData {
+            withdrawable_fee_amount: ui(5.0),
+            ..SynchronizerData::default()
+        };
+        let assets = [AssetSupply { fiat_asset_mint: Pubkey::new_unique(), supply: ui(10.0), decimals: 9, price: ui(150.0) }];
+        // 6 decimals collateral at par, 2 decimals collateral worth 0.5
+        let usdc = AcceptedCollateral {
+            mint: Pubkey::new_unique(),
+            vault: Pubkey::new_unique(),
+            decimals: 6,
+            rate: ui(1.0),
+            withdrawable_fee_amount: 3_000_000,
+            ..AcceptedCollateral::default()
+        };
+        let cent = AcceptedCollateral {
+            mint: Pubkey::new_unique(),
+            vault: Pubkey::new_unique(),
+            decimals: 2,
+            rate: ui(0.5),
+            withdrawable_fee_amount: 1_000,
+            ..AcceptedCollateral::default()
+        };
+        let vaults = [
+            CollateralVault { collateral: usdc, balance: 1_003_000_000 },
+            CollateralVault { collateral: cent, balance: 41_000 },
+        ];
+
+        // 1000 and 200 of accepted collaterals after their fees, 300 in the vault, liability 1500
+        let reconciliation = reconcile(&synchronizer, ui(305.0), &vaults, &assets).unwrap();
+        assert_eq!(reconciliation.collaterals, vec![
+            CollateralReconciliation {
+                mint: usdc.mint,
+                vault_balance: 1_003_000_000,
+                withdrawable_fee_amount: 3_000_000,
+                rate: ui(1.0),
+                available_collateral: ui(1000.0) as i128,
+            },
+            CollateralReconciliation {
+                mint: cent.mint,
+                vault_balance: 41_000,
+                withdrawable_fee_amount: 1_000,
+                rate: ui(0.5),
+                available_collateral: ui(200.0) as i128,
+            },
+        ]);
+        assert_eq!(reconciliation.available_collateral, ui(1500.0) as i128);
+        assert_eq!(reconciliation.surplus, 0);
+        assert!(!reconciliation.is_under_collateralized());
+
+        // Without the accepted collaterals the vault alone is short
+        let reconciliation = reconcile(&synchronizer, ui(305.0), &[], &assets).unwrap();
+        assert!(reconciliation.is_under_collateralized());
+
+        // BadCase: collateral vault doesn't cover its fees
+        let vaults = [CollateralVault { collateral: cent, balance: 200 }];
+        let reconciliation = reconcile(&synchronizer, ui(1505.0), &vaults, &assets).unwrap();
+        assert_eq!(reconciliation.collaterals[0].available_collateral, -(ui(4.0) as i128));
+        assert_eq!(reconciliation.surplus, -(ui(4.0) as i128));
+        assert!(reconciliation.is_under_collateralized());
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::HashMap;
+use solana_program::{nonce, program_pack::Pack, pubkey::Pubkey, system_instruction};
+use solana_program_test::*;
+use solana_sdk::{account::Account, signature::Keypair, signer::{null_signer::NullSigner, Signer, SignerError}};
+use synchronizer::{error::SynchronizerError, processor::{id, Processor}, state::{CollateralSetData, RateBounds}};
+use synchronizer_client::{get_associated_token_address, offline, reconcile, ClientError, SynchronizerClient, Trade};
+
+fn program_test() -> ProgramTest {
+    let mut program_test = ProgramTest::new(
+        "synchronizer",
+        id(),
+        processor!(Processor::process_instruction),
+    );
+    program_test.add_program(
+        "spl_associated_token_account",
+        spl_associated_token_account::id(),
+        processor!(spl_associated_token_account::processor::process_instruction),
+    );
+    program_test
+}
+
+// Native program tests can't run the token program, tests without token transfers start with an
+// initialized collateral mint.
+fn add_collateral_mint(program_test: &mut ProgramTest, decimals: u8) -> Pubkey {
+    let address = Pubkey::new_unique();
+    let mut data = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint { decimals, is_initialized: true, ..Default::default() }.pack_into_slice(&mut data);
+    program_test.add_account(address, Account {
+        lamports: 1_000_000_000,
+        data,
+        owner: spl_token::id(),
+        executable: false,
+        rent_epoch: 0,
+    });
+    address
+}
+
+fn add_token_account(program_test: &mut ProgramTest, mint: &Pubkey, owner: &Pubkey) -> Pubkey {
+    let address = Pubkey::new_unique();
+    let mut data = vec![0; spl_token::state::Account::LEN];
+    spl_token::state::Account {
+        mint: *mint,
+        owner: *owner,
+        state: spl_token::state::AccountState::Initialized,
+        ..Default::default()
+    }.pack_into_slice(&mut data);
+    program_test.add_account(address, Account {
+        lamports: 1_000_000_000,
+        data,
+        owner: spl_token::id(),
+        executable: false,
+        rent_epoch: 0,
+    });
+    address
+}
+
+async fn create_mint(client: &mut SynchronizerClient, mint: &Keypair, authority: &Pubkey) {
+    let rent = client.banks_client().get_rent().await.unwrap();
+    let instructions = [
+        system_instruction::create_account(
+            &client.payer().pubkey(),
+            &mint.pubkey(),
+            rent.minimum_balance(spl_token::state::Mint::LEN),
+            spl_token::state::Mint::LEN as u64,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::initialize_mint(&spl_token::id(), &mint.pubkey(), authority, None, Processor::DEFAULT_DECIMALS).unwrap(),
+    ];
+    client.process_transaction(&instructions, &[mint]).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_client_admin() {
+    let mut program_test = program_test();
+    let collateral_token_key = add_collateral_mint(&mut program_test, 6);
+    let new_collateral_token_key = add_collateral_mint(&mut program_test, 6);
+    let (banks_client, payer, _) = program_test.start().await;
+    let synchronizer_key = Keypair::new();
+    let mut client = SynchronizerClient::new(banks_client, payer, synchronizer_key.pubkey());
+    let oracles = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+
+    // BadCase: account doesn't exist
+    match client.get_synchronizer_data().await {
+        Err(ClientError::AccountNotFound(key)) => assert_eq!(key, synchronizer_key.pubkey()),
+        result => panic!("unexpected result {:?}", result),
+    }
+
+    client.initialize(&synchronizer_key, &collateral_token_key, 500, 0, 2, &oracles).await.unwrap();
+    let synchronizer = client.get_synchronizer_data().await.unwrap();
+    assert!(synchronizer.is_initialized);
+    assert_eq!(synchronizer.collateral_token_key, collateral_token_key);
+    assert_eq!(synchronizer.collateral_decimals, Some(6));
+    assert_eq!(synchronizer.remaining_dollar_cap, 500);
+    assert_eq!(synchronizer.minimum_required_signature, 2);
+    assert_eq!(synchronizer.oracles[..2], oracles[..]);
+    assert_eq!(
+        client.get_synchronizer_collateral_address().await.unwrap(),
+        get_associated_token_address(&synchronizer_key.pubkey(), &collateral_token_key)
+    );
+
+    client.set_remaining_dollar_cap(1000, &synchronizer_key).await.unwrap();
+    client.set_minimum_required_signature(1, &synchronizer_key).await.unwrap();
+    client.set_collateral_token(&new_collateral_token_key, &synchronizer_key).await.unwrap();
+    let new_oracles = vec![Pubkey::new_unique()];
+    client.set_oracles(&new_oracles, &synchronizer_key).await.unwrap();
+
+    let synchronizer = client.get_synchronizer_data().await.unwrap();
+    assert_eq!(synchronizer.remaining_dollar_cap, 1000);
+    assert_eq!(synchronizer.minimum_required_signature, 1);
+    assert_eq!(synchronizer.collateral_token_key, new_collateral_token_key);
+    assert_eq!(synchronizer.collateral_decimals, Some(6));
+    assert_eq!(synchronizer.oracles, [new_oracles[0], Pubkey::default(), Pubkey::default()]);
+
+    // BadCase: program errors are mapped to SynchronizerError
+    let too_many_oracles = vec![Pubkey::new_unique(); 4];
+    match client.set_oracles(&too_many_oracles, &synchronizer_key).await {
+        Err(ClientError::Synchronizer(error)) => assert_eq!(error, SynchronizerError::MaxOraclesExceed),
+        result => panic!("unexpected result {:?}", result),
+    }
+
+    // BadCase: wrong Synchronizer authority keypair
+    match client.set_remaining_dollar_cap(10, &Keypair::new()).await {
+        Err(ClientError::Signer(_)) => {}
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+#[tokio::test]
+async fn test_client_collaterals() {
+    let mut program_test = program_test();
+    let synchronizer_key = Keypair::new();
+    let oracle = Keypair::new();
+    let collateral_token_key = add_collateral_mint(&mut program_test, 9);
+    let usdt = add_collateral_mint(&mut program_test, 6);
+    let usdt_vault = add_token_account(&mut program_test, &usdt, &synchronizer_key.pubkey());
+    // Native program tests can't create it with the system program
+    program_test.add_account(CollateralSetData::find_address(&synchronizer_key.pubkey()).0, Account {
+        lamports: 1_000_000_000,
+        data: vec![0; CollateralSetData::LEN],
+        owner: id(),
+        executable: false,
+        rent_epoch: 0,
+    });
+    let (banks_client, payer, _) = program_test.start().await;
+    let mut client = SynchronizerClient::new(banks_client, payer, synchronizer_key.pubkey());
+    client.initialize(&synchronizer_key, &collateral_token_key, 500, 0, 1, &[oracle.pubkey()]).await.unwrap();
+
+    let rate_bounds = RateBounds { max_age: 3_600, max_deviation_bps: 100 };
+    client.add_collateral(998_000_000, rate_bounds, &usdt, &usdt_vault, &synchronizer_key).await.unwrap();
+    client.update_collateral_rate(&usdt, 1_001_000_000, &[&oracle]).await.unwrap();
+    let set = client.get_collateral_set_data().await.unwrap().unwrap();
+    assert_eq!(set.collaterals().len(), 1);
+    assert_eq!(set.collaterals[0].decimals, 6);
+    assert_eq!(set.collaterals[0].rate, 1_001_000_000);
+    assert_eq!(set.collaterals[0].rate_bounds, rate_bounds);
+    // The admin sets rates out of the oracle bounds
+    client.update_collateral_rate(&usdt, 900_000_000, &[&synchronizer_key]).await.unwrap();
+    assert_eq!(client.get_collateral_set_data().await.unwrap().unwrap().collaterals[0].rate, 900_000_000);
+    assert_eq!(client.get_collateral_vault(Some(&usdt)).await.unwrap(), (usdt, usdt_vault));
+    assert_eq!(
+        client.get_collateral_vault(None).await.unwrap(),
+        (collateral_token_key, get_associated_token_address(&synchronizer_key.pubkey(), &collateral_token_key))
+    );
+    let reconciliation = reconcile::fetch_reconciliation(client.banks_client(), &synchronizer_key.pubkey(), &HashMap::new()).await.unwrap();
+    assert_eq!(reconciliation.collaterals.len(), 1);
+    assert_eq!(reconciliation.collaterals[0].mint, usdt);
+    assert_eq!(reconciliation.collaterals[0].rate, 900_000_000);
+
+    // BadCase: the signer is neither the Synchronizer authority nor an oracle, the oracle moves the rate by 10%
+    match client.update_collateral_rate(&usdt, 1, &[&Keypair::new()]).await {
+        Err(ClientError::Synchronizer(error)) => assert_eq!(error, SynchronizerError::BadOracle),
+        result => panic!("unexpected result {:?}", result),
+    }
+    match client.update_collateral_rate(&usdt, 990_000_000, &[&oracle]).await {
+        Err(ClientError::Synchronizer(error)) => assert_eq!(error, SynchronizerError::CollateralRateDeviationExceeded),
+        result => panic!("unexpected result {:?}", result),
+    }
+
+    client.remove_collateral(&usdt, &synchronizer_key).await.unwrap();
+    assert!(client.get_collateral_set_data().await.unwrap().unwrap().collaterals().is_empty());
+
+    // BadCase: trades and withdrawals in a collateral which isn't accepted
+    match client.get_collateral_vault(Some(&usdt)).await {
+        Err(ClientError::Synchronizer(error)) => assert_eq!(error, SynchronizerError::InvalidCollateral),
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+#[tokio::test]
+async fn test_client_trade() {
+    let (banks_client, payer, _) = program_test().start().await;
+    let synchronizer_key = Keypair::new();
+    let mut client = SynchronizerClient::new(banks_client, payer, synchronizer_key.pubkey());
+    let collateral_owner = Keypair::new();
+    let user = Keypair::new();
+    let oracles = [Keypair::new(), Keypair::new()];
+    let oracle_keys: Vec<Pubkey> = oracles.iter().map(|oracle| oracle.pubkey()).collect();
+    let decimals = Processor::DEFAULT_DECIMALS;
+
+    // Infrastructure preparing
+    let collateral_token = Keypair::new();
+    create_mint(&mut client, &collateral_token, &collateral_owner.pubkey()).await;
+    let fiat_asset_mint = Keypair::new();
+    create_mint(&mut client, &fiat_asset_mint, &synchronizer_key.pubkey()).await;
+
+    client.initialize(
+        &synchronizer_key,
+        &collateral_token.pubkey(),
+        spl_token::ui_amount_to_amount(100_000.0, decimals),
+        0,
+        oracles.len() as u8,
+        &oracle_keys
+    ).await.unwrap();
+
+    let vault = client.create_associated_token_account(&synchronizer_key.pubkey(), &collateral_token.pubkey()).await.unwrap();
+    assert_eq!(vault, client.get_synchronizer_collateral_address().await.unwrap());
+    let user_collateral = client.create_associated_token_account(&user.pubkey(), &collateral_token.pubkey()).await.unwrap();
+    let mint_to = spl_token::instruction::mint_to(
+        &spl_token::id(),
+        &collateral_token.pubkey(),
+        &user_collateral,
+        &collateral_owner.pubkey(),
+        &[],
+        spl_token::ui_amount_to_amount(1_000.0, decimals),
+    ).unwrap();
+    client.process_transaction(&[mint_to], &[&collateral_owner]).await.unwrap();
+
+    // Buy creates the user fiat asset account
+    let trade = Trade {
+        fiat_asset_mint: fiat_asset_mint.pubkey(),
+        multiplier: 5,
+        amount: spl_token::ui_amount_to_amount(10.0, decimals),
+        fee: spl_token::ui_amount_to_amount(0.001, decimals),
+        prices: vec![spl_token::ui_amount_to_amount(5.0, decimals); 2],
+        oracles: oracle_keys.clone(),
+        referrer: None,
+        collateral_mint: None,
+    };
+    let quote = client.quote_buy(&trade).await.unwrap();
+    let remaining_dollar_cap = client.get_synchronizer_data().await.unwrap().remaining_dollar_cap;
+    client.buy_for(&trade, &user, &[&synchronizer_key, &oracles[0], &oracles[1]]).await.unwrap();
+    let user_fiat = get_associated_token_address(&user.pubkey(), &fiat_asset_mint.pubkey());
+    assert_eq!(client.get_token_account(&user_fiat).await.unwrap().amount, trade.amount);
+    assert_eq!(
+        client.get_token_account(&user_collateral).await.unwrap().amount,
+        spl_token::ui_amount_to_amount(1_000.0, decimals) - quote.user_collateral_amount
+    );
+    assert_eq!(client.get_synchronizer_data().await.unwrap().remaining_dollar_cap, remaining_dollar_cap - quote.dollar_cap_amount);
+
+    client.sell_for(&trade, &user, &[&synchronizer_key, &oracles[0], &oracles[1]]).await.unwrap();
+    assert_eq!(client.get_token_account(&user_fiat).await.unwrap().amount, 0);
+
+    // BadCase: not enough oracles
+    let trade = Trade {
+        prices: vec![trade.prices[0]],
+        oracles: vec![oracle_keys[0]],
+        ..trade
+    };
+    match client.buy_for(&trade, &user, &[&synchronizer_key, &oracles[0]]).await {
+        Err(ClientError::Synchronizer(error)) => assert_eq!(error, SynchronizerError::NotEnoughOracles),
+        result => panic!("unexpected result {:?}", result),
+    }
+
+    // Fee withdrawal to the admin collateral account
+    let withdrawable_fee_amount = client.get_synchronizer_data().await.unwrap().withdrawable_fee_amount;
+    let recipient = client.create_associated_token_account(&collateral_owner.pubkey(), &collateral_token.pubkey()).await.unwrap();
+    client.withdraw_fee(withdrawable_fee_amount, None, &recipient, &synchronizer_key).await.unwrap();
+    assert_eq!(client.get_token_account(&recipient).await.unwrap().amount, withdrawable_fee_amount);
+    assert_eq!(client.get_synchronizer_data().await.unwrap().withdrawable_fee_amount, 0);
+}
+
+#[tokio::test]
+async fn test_client_offline_signing() {
+    let mut program_test = program_test();
+    let collateral_token_key = add_collateral_mint(&mut program_test, 9);
+    let (banks_client, payer, _) = program_test.start().await;
+    let payer_key = payer.pubkey();
+    let synchronizer_key = Keypair::new();
+    let mut client = SynchronizerClient::new(banks_client, payer, synchronizer_key.pubkey());
+    client.initialize(&synchronizer_key, &collateral_token_key, 500, 0, 1, &[Pubkey::new_unique()]).await.unwrap();
+
+    // Durable nonce owned by the payer
+    let nonce_account = Keypair::new();
+    let rent = client.banks_client().get_rent().await.unwrap();
+    let instructions = system_instruction::create_nonce_account(
+        &payer_key,
+        &nonce_account.pubkey(),
+        &payer_key,
+        rent.minimum_balance(nonce::State::size()),
+    );
+    client.process_transaction(&instructions, &[&nonce_account]).await.unwrap();
+    let nonce_data = offline::get_nonce_data(client.banks_client(), &nonce_account.pubkey()).await.unwrap();
+    assert_eq!(nonce_data.authority, payer_key);
+
+    // Export, the Synchronizer admin key is offline
+    let instruction = synchronizer::instruction::set_remaining_dollar_cap(&id(), 1000, &synchronizer_key.pubkey()).unwrap();
+    let mut transaction = offline::unsigned_transaction(&[instruction], &payer_key, &nonce_account.pubkey(), &payer_key, nonce_data.blockhash);
+    offline::sign(&mut transaction, &[client.payer(), &NullSigner::new(&synchronizer_key.pubkey())]).unwrap();
+    let exported = offline::encode(&transaction);
+
+    // BadCase: broadcast before every party signed
+    match offline::broadcast(client.banks_client(), offline::decode(&exported).unwrap()).await {
+        Err(ClientError::Signer(SignerError::NotEnoughSigners)) => {}
+        result => panic!("unexpected result {:?}", result),
+    }
+
+    // Admin signs offline, then the transaction is broadcast once the nonce can be advanced
+    client.banks_client().get_new_blockhash(&nonce_data.blockhash).await.unwrap();
+    let mut transaction = offline::decode(&exported).unwrap();
+    offline::sign(&mut transaction, &[&synchronizer_key]).unwrap();
+    offline::broadcast(client.banks_client(), transaction.clone()).await.unwrap();
+    assert_eq!(client.get_synchronizer_data().await.unwrap().remaining_dollar_cap, 1000);
+
+    // BadCase: the nonce was advanced, its old value can't be used again
+    let new_nonce_data = offline::get_nonce_data(client.banks_client(), &nonce_account.pubkey()).await.unwrap();
+    assert_ne!(new_nonce_data.blockhash, nonce_data.blockhash);
+    let instruction = synchronizer::instruction::set_remaining_dollar_cap(&id(), 2000, &synchronizer_key.pubkey()).unwrap();
+    let mut transaction = offline::unsigned_transaction(&[instruction], &payer_key, &nonce_account.pubkey(), &payer_key, nonce_data.blockhash);
+    offline::sign(&mut transaction, &[client.payer(), &synchronizer_key]).unwrap();
+    assert!(offline::broadcast(client.banks_client(), transaction).await.is_err());
+    assert_eq!(client.get_synchronizer_data().await.unwrap().remaining_dollar_cap, 1000);
+}
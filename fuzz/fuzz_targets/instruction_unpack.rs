@@ -0,0 +1,10 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use synchronizer::instruction::SynchronizerInstruction;
+
+fuzz_target!(|data: &[u8]| {
+    // `unpack` must never panic, and everything it accepts must survive a pack/unpack round trip
+    if let Ok(instruction) = SynchronizerInstruction::unpack(data) {
+        assert_eq!(SynchronizerInstruction::unpack(&instruction.pack()), Ok(instruction));
+    }
+});
@@ -0,0 +1,748 @@
+//! SQLite ledger of Synchronizer history with running totals
+
+use crate::{IndexerError, RecordedTransaction};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use solana_program::pubkey::Pubkey;
+use std::{convert::TryFrom, path::Path, str::FromStr};
+use synchronizer::{event::{parse_logs, SynchronizerEvent}, instruction::SynchronizerInstruction};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS transactions (
+    signature TEXT PRIMARY KEY,
+    slot INTEGER NOT NULL,
+    block_time INTEGER,
+    failed INTEGER NOT NULL,
+    instructions TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS ledger_entries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    signature TEXT NOT NULL,
+    slot INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    synchronizer TEXT NOT NULL,
+    account TEXT NOT NULL,
+    fiat_asset_mint TEXT,
+    amount INTEGER NOT NULL,
+    collateral_amount INTEGER NOT NULL,
+    fee_amount INTEGER NOT NULL,
+    price INTEGER
+);
+CREATE TABLE IF NOT EXISTS user_totals (
+    synchronizer TEXT NOT NULL,
+    user TEXT NOT NULL,
+    buys INTEGER NOT NULL,
+    sells INTEGER NOT NULL,
+    collateral_paid INTEGER NOT NULL,
+    collateral_received INTEGER NOT NULL,
+    fees_paid INTEGER NOT NULL,
+    PRIMARY KEY (synchronizer, user)
+);
+CREATE TABLE IF NOT EXISTS asset_totals (
+    synchronizer TEXT NOT NULL,
+    fiat_asset_mint TEXT NOT NULL,
+    minted INTEGER NOT NULL,
+    burned INTEGER NOT NULL,
+    collateral_in INTEGER NOT NULL,
+    collateral_out INTEGER NOT NULL,
+    fees INTEGER NOT NULL,
+    PRIMARY KEY (synchronizer, fiat_asset_mint)
+);
+CREATE TABLE IF NOT EXISTS synchronizer_totals (
+    synchronizer TEXT PRIMARY KEY,
+    trades INTEGER NOT NULL,
+    collateral_in INTEGER NOT NULL,
+    collateral_out INTEGER NOT NULL,
+    fees_accrued INTEGER NOT NULL,
+    fees_withdrawn INTEGER NOT NULL,
+    collateral_withdrawn INTEGER NOT NULL,
+    remaining_dollar_cap INTEGER NOT NULL,
+    withdrawable_fee_amount INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS collateral_totals (
+    synchronizer TEXT NOT NULL,
+    collateral_mint TEXT NOT NULL,
+    trades INTEGER NOT NULL,
+    collateral_in INTEGER NOT NULL,
+    collateral_out INTEGER NOT NULL,
+    fees_accrued INTEGER NOT NULL,
+    fees_withdrawn INTEGER NOT NULL,
+    collateral_withdrawn INTEGER NOT NULL,
+    withdrawable_fee_amount INTEGER NOT NULL,
+    PRIMARY KEY (synchronizer, collateral_mint)
+);
+";
+
+/// Kind of a ledger entry
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EntryKind {
+    Buy,
+    Sell,
+    FeeAccrual,
+    FeeWithdrawal,
+    CollateralWithdrawal,
+    ReferralFee,
+}
+
+impl EntryKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EntryKind::Buy => "buy",
+            EntryKind::Sell => "sell",
+            EntryKind::FeeAccrual => "fee_accrual",
+            EntryKind::FeeWithdrawal => "fee_withdrawal",
+            EntryKind::CollateralWithdrawal => "collateral_withdrawal",
+            EntryKind::ReferralFee => "referral_fee",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "buy" => Some(EntryKind::Buy),
+            "sell" => Some(EntryKind::Sell),
+            "fee_accrual" => Some(EntryKind::FeeAccrual),
+            "fee_withdrawal" => Some(EntryKind::FeeWithdrawal),
+            "collateral_withdrawal" => Some(EntryKind::CollateralWithdrawal),
+            "referral_fee" => Some(EntryKind::ReferralFee),
+            _ => None,
+        }
+    }
+}
+
+/// Ledger line, amounts are in token base units
+#[derive(Clone, Debug, PartialEq)]
+pub struct LedgerEntry {
+    pub signature: String,
+    pub slot: u64,
+    pub kind: EntryKind,
+    pub synchronizer: Pubkey,
+    /// User of a trade or fee accrual, recipient of a withdrawal or referral fee
+    pub account: Pubkey,
+    pub fiat_asset_mint: Option<Pubkey>,
+    /// Fiat asset amount of a trade, collateral amount otherwise
+    pub amount: u64,
+    pub collateral_amount: u64,
+    pub fee_amount: u64,
+    pub price: Option<u64>,
+}
+
+/// Totals of a user of a Synchronizer
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UserTotals {
+    pub synchronizer: Pubkey,
+    pub user: Pubkey,
+    pub buys: u64,
+    pub sells: u64,
+    /// Collateral paid for buys, fees included
+    pub collateral_paid: u64,
+    /// Collateral received for sells, fees deducted
+    pub collateral_received: u64,
+    pub fees_paid: u64,
+}
+
+/// Totals of a fiat asset of a Synchronizer
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AssetTotals {
+    pub synchronizer: Pubkey,
+    pub fiat_asset_mint: Pubkey,
+    pub minted: u64,
+    pub burned: u64,
+    /// Collateral value of buys at the trade price
+    pub collateral_in: u64,
+    /// Collateral value of sells at the trade price
+    pub collateral_out: u64,
+    pub fees: u64,
+}
+
+impl AssetTotals {
+    /// Fiat asset supply minted by the Synchronizer
+    pub fn outstanding(&self) -> i128 {
+        self.minted as i128 - self.burned as i128
+    }
+}
+
+/// Totals of a Synchronizer. Trades settled in an accepted collateral are counted at their
+/// collateral value, see `CollateralTotals` for their token amounts.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SynchronizerTotals {
+    pub synchronizer: Pubkey,
+    pub trades: u64,
+    /// Collateral paid by users, fees included
+    pub collateral_in: u64,
+    /// Collateral paid to users for sells
+    pub collateral_out: u64,
+    pub fees_accrued: u64,
+    /// Fees withdrawn by the admin or paid to referrers
+    pub fees_withdrawn: u64,
+    pub collateral_withdrawn: u64,
+    /// Last known remaining dollar cap
+    pub remaining_dollar_cap: u64,
+    /// Last known withdrawable fee amount
+    pub withdrawable_fee_amount: u64,
+}
+
+/// Totals of a collateral accepted by a Synchronizer besides its collateral token,
+/// in base units of the accepted collateral
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CollateralTotals {
+    pub synchronizer: Pubkey,
+    pub collateral_mint: Pubkey,
+    pub trades: u64,
+    /// Collateral paid by users, fees included
+    pub collateral_in: u64,
+    /// Collateral paid to users for sells
+    pub collateral_out: u64,
+    pub fees_accrued: u64,
+    /// Fees withdrawn by the admin or paid to referrers
+    pub fees_withdrawn: u64,
+    pub collateral_withdrawn: u64,
+    /// Last known withdrawable fee amount
+    pub withdrawable_fee_amount: u64,
+}
+
+fn to_sql(value: u64) -> Result<i64, IndexerError> {
+    i64::try_from(value).map_err(|_| IndexerError::Overflow)
+}
+
+fn add(total: &mut u64, value: u64) -> Result<(), IndexerError> {
+    *total = total.checked_add(value).ok_or(IndexerError::Overflow)?;
+    Ok(())
+}
+
+fn get_u64(row: &Row, index: usize) -> rusqlite::Result<u64> {
+    Ok(row.get::<_, i64>(index)? as u64)
+}
+
+fn get_pubkey(row: &Row, index: usize) -> rusqlite::Result<Pubkey> {
+    let value: String = row.get(index)?;
+    Pubkey::from_str(&value).map_err(|error| rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(error)))
+}
+
+fn instruction_name(instruction: &SynchronizerInstruction) -> &'static str {
+    match instruction {
+        SynchronizerInstruction::BuyFor { .. } => "BuyFor",
+        SynchronizerInstruction::SellFor { .. } => "SellFor",
+        SynchronizerInstruction::InitializeSynchronizerAccount { .. } => "InitializeSynchronizerAccount",
+        SynchronizerInstruction::SetMinimumRequiredSignature { .. } => "SetMinimumRequiredSignature",
+        SynchronizerInstruction::SetCollateralToken { .. } => "SetCollateralToken",
+        SynchronizerInstruction::SetRemainingDollarCap { .. } => "SetRemainingDollarCap",
+        SynchronizerInstruction::WithdrawFee { .. } => "WithdrawFee",
+        SynchronizerInstruction::WithdrawCollateral { .. } => "WithdrawCollateral",
+        SynchronizerInstruction::SetOracles { .. } => "SetOracles",
+        SynchronizerInstruction::MigrateSynchronizerAccount => "MigrateSynchronizerAccount",
+        SynchronizerInstruction::SetAssetCaps { .. } => "SetAssetCaps",
+        SynchronizerInstruction::SetRateLimits { .. } => "SetRateLimits",
+        SynchronizerInstruction::CreateUserPosition { .. } => "CreateUserPosition",
+        SynchronizerInstruction::CloseUserPosition { .. } => "CloseUserPosition",
+        SynchronizerInstruction::SetMarketSchedule { .. } => "SetMarketSchedule",
+        SynchronizerInstruction::SetAssetSpreads { .. } => "SetAssetSpreads",
+        SynchronizerInstruction::SetFeeTiers { .. } => "SetFeeTiers",
+        SynchronizerInstruction::SetFeeOverride { .. } => "SetFeeOverride",
+        SynchronizerInstruction::SetReferralShare { .. } => "SetReferralShare",
+        SynchronizerInstruction::AddCollateral { .. } => "AddCollateral",
+        SynchronizerInstruction::RemoveCollateral { .. } => "RemoveCollateral",
+        SynchronizerInstruction::UpdateCollateralRate { .. } => "UpdateCollateralRate",
+    }
+}
+
+/// Names of the `program_id` instructions of a transaction, `?` for undecodable ones
+fn instruction_names(program_id: &Pubkey, recorded: &RecordedTransaction) -> Vec<&'static str> {
+    let message = &recorded.transaction.message;
+    message.instructions.iter()
+        .filter(|instruction| message.account_keys.get(instruction.program_id_index as usize) == Some(program_id))
+        .map(|instruction| SynchronizerInstruction::unpack(&instruction.data).map(|instruction| instruction_name(&instruction)).unwrap_or("?"))
+        .collect()
+}
+
+/// SQLite ledger
+pub struct Ledger {
+    connection: Connection,
+}
+
+impl Ledger {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, IndexerError> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, IndexerError> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(connection: Connection) -> Result<Self, IndexerError> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self { connection })
+    }
+
+    pub fn is_indexed(&self, signature: &str) -> Result<bool, IndexerError> {
+        Ok(self.connection
+            .query_row("SELECT 1 FROM transactions WHERE signature = ?1", params![signature], |_| Ok(()))
+            .optional()?
+            .is_some())
+    }
+
+    /// Indexes a transaction of the `program_id` Synchronizer program.
+    /// Returns `false` for already indexed transactions and transactions without Synchronizer instructions.
+    pub fn index(&mut self, program_id: &Pubkey, recorded: &RecordedTransaction) -> Result<bool, IndexerError> {
+        let signature = recorded.signature();
+        if self.is_indexed(&signature)? {
+            return Ok(false);
+        }
+        let instructions = instruction_names(program_id, recorded);
+        if instructions.is_empty() {
+            return Ok(false);
+        }
+        // Failed transactions are recorded without effects
+        let events = if recorded.failed {
+            Vec::new()
+        } else {
+            parse_logs(program_id, &recorded.logs).map_err(IndexerError::InvalidEvent)?
+        };
+
+        let transaction = self.connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO transactions (signature, slot, block_time, failed, instructions) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![signature, to_sql(recorded.slot)?, recorded.block_time, recorded.failed, instructions.join(",")],
+        )?;
+        let mut events = events.into_iter().peekable();
+        while let Some(event) = events.next() {
+            // Trades settled in an accepted collateral are followed by their settlement
+            let settled = matches!(events.peek(), Some(SynchronizerEvent::CollateralSettled { .. }));
+            apply_event(&transaction, &signature, recorded.slot, event, settled)?;
+        }
+        transaction.commit()?;
+        Ok(true)
+    }
+
+    /// Indexes transactions in order, returns the number of newly indexed transactions
+    pub fn index_all<'a, I: IntoIterator<Item = &'a RecordedTransaction>>(&mut self, program_id: &Pubkey, transactions: I) -> Result<usize, IndexerError> {
+        let mut indexed = 0;
+        for recorded in transactions {
+            indexed += self.index(program_id, recorded)? as usize;
+        }
+        Ok(indexed)
+    }
+
+    /// Ledger entries of a Synchronizer in processing order
+    pub fn entries(&self, synchronizer: &Pubkey) -> Result<Vec<LedgerEntry>, IndexerError> {
+        let mut statement = self.connection.prepare(
+            "SELECT signature, slot, kind, synchronizer, account, fiat_asset_mint, amount, collateral_amount, fee_amount, price
+             FROM ledger_entries WHERE synchronizer = ?1 ORDER BY id",
+        )?;
+        let entries = statement.query_map(params![synchronizer.to_string()], |row| {
+            let kind: String = row.get(2)?;
+            let fiat_asset_mint: Option<String> = row.get(5)?;
+            let price: Option<i64> = row.get(9)?;
+            Ok(LedgerEntry {
+                signature: row.get(0)?,
+                slot: get_u64(row, 1)?,
+                kind: EntryKind::from_name(&kind).ok_or_else(|| rusqlite::Error::InvalidColumnType(2, kind.clone(), rusqlite::types::Type::Text))?,
+                synchronizer: get_pubkey(row, 3)?,
+                account: get_pubkey(row, 4)?,
+                fiat_asset_mint: fiat_asset_mint.and_then(|mint| Pubkey::from_str(&mint).ok()),
+                amount: get_u64(row, 6)?,
+                collateral_amount: get_u64(row, 7)?,
+                fee_amount: get_u64(row, 8)?,
+                price: price.map(|price| price as u64),
+            })
+        })?;
+        Ok(entries.collect::<Result<_, _>>()?)
+    }
+
+    pub fn user_totals(&self, synchronizer: &Pubkey, user: &Pubkey) -> Result<Option<UserTotals>, IndexerError> {
+        Ok(load_user_totals(&self.connection, synchronizer, user)?)
+    }
+
+    pub fn asset_totals(&self, synchronizer: &Pubkey, fiat_asset_mint: &Pubkey) -> Result<Option<AssetTotals>, IndexerError> {
+        Ok(load_asset_totals(&self.connection, synchronizer, fiat_asset_mint)?)
+    }
+
+    pub fn synchronizer_totals(&self, synchronizer: &Pubkey) -> Result<Option<SynchronizerTotals>, IndexerError> {
+        Ok(load_synchronizer_totals(&self.connection, synchronizer)?)
+    }
+
+    /// Totals of every user of a Synchronizer
+    pub fn users(&self, synchronizer: &Pubkey) -> Result<Vec<UserTotals>, IndexerError> {
+        let mut statement = self.connection.prepare("SELECT * FROM user_totals WHERE synchronizer = ?1 ORDER BY user")?;
+        let totals = statement.query_map(params![synchronizer.to_string()], user_totals_from_row)?;
+        Ok(totals.collect::<Result<_, _>>()?)
+    }
+
+    pub fn collateral_totals(&self, synchronizer: &Pubkey, collateral_mint: &Pubkey) -> Result<Option<CollateralTotals>, IndexerError> {
+        Ok(load_collateral_totals(&self.connection, synchronizer, collateral_mint)?)
+    }
+
+    /// Totals of every accepted collateral of a Synchronizer
+    pub fn collaterals(&self, synchronizer: &Pubkey) -> Result<Vec<CollateralTotals>, IndexerError> {
+        let mut statement = self.connection.prepare("SELECT * FROM collateral_totals WHERE synchronizer = ?1 ORDER BY collateral_mint")?;
+        let totals = statement.query_map(params![synchronizer.to_string()], collateral_totals_from_row)?;
+        Ok(totals.collect::<Result<_, _>>()?)
+    }
+
+    /// Totals of every fiat asset of a Synchronizer
+    pub fn assets(&self, synchronizer: &Pubkey) -> Result<Vec<AssetTotals>, IndexerError> {
+        let mut statement = self.connection.prepare("SELECT * FROM asset_totals WHERE synchronizer = ?1 ORDER BY fiat_asset_mint")?;
+        let totals = statement.query_map(params![synchronizer.to_string()], asset_totals_from_row)?;
+        Ok(totals.collect::<Result<_, _>>()?)
+    }
+}
+
+fn user_totals_from_row(row: &Row) -> rusqlite::Result<UserTotals> {
+    Ok(UserTotals {
+        synchronizer: get_pubkey(row, 0)?,
+        user: get_pubkey(row, 1)?,
+        buys: get_u64(row, 2)?,
+        sells: get_u64(row, 3)?,
+        collateral_paid: get_u64(row, 4)?,
+        collateral_received: get_u64(row, 5)?,
+        fees_paid: get_u64(row, 6)?,
+    })
+}
+
+fn asset_totals_from_row(row: &Row) -> rusqlite::Result<AssetTotals> {
+    Ok(AssetTotals {
+        synchronizer: get_pubkey(row, 0)?,
+        fiat_asset_mint: get_pubkey(row, 1)?,
+        minted: get_u64(row, 2)?,
+        burned: get_u64(row, 3)?,
+        collateral_in: get_u64(row, 4)?,
+        collateral_out: get_u64(row, 5)?,
+        fees: get_u64(row, 6)?,
+    })
+}
+
+fn collateral_totals_from_row(row: &Row) -> rusqlite::Result<CollateralTotals> {
+    Ok(CollateralTotals {
+        synchronizer: get_pubkey(row, 0)?,
+        collateral_mint: get_pubkey(row, 1)?,
+        trades: get_u64(row, 2)?,
+        collateral_in: get_u64(row, 3)?,
+        collateral_out: get_u64(row, 4)?,
+        fees_accrued: get_u64(row, 5)?,
+        fees_withdrawn: get_u64(row, 6)?,
+        collateral_withdrawn: get_u64(row, 7)?,
+        withdrawable_fee_amount: get_u64(row, 8)?,
+    })
+}
+
+fn load_user_totals(connection: &Connection, synchronizer: &Pubkey, user: &Pubkey) -> rusqlite::Result<Option<UserTotals>> {
+    connection.query_row(
+        "SELECT * FROM user_totals WHERE synchronizer = ?1 AND user = ?2",
+        params![synchronizer.to_string(), user.to_string()],
+        user_totals_from_row,
+    ).optional()
+}
+
+fn load_asset_totals(connection: &Connection, synchronizer: &Pubkey, fiat_asset_mint: &Pubkey) -> rusqlite::Result<Option<AssetTotals>> {
+    connection.query_row(
+        "SELECT * FROM asset_totals WHERE synchronizer = ?1 AND fiat_asset_mint = ?2",
+        params![synchronizer.to_string(), fiat_asset_mint.to_string()],
+        asset_totals_from_row,
+    ).optional()
+}
+
+fn load_collateral_totals(connection: &Connection, synchronizer: &Pubkey, collateral_mint: &Pubkey) -> rusqlite::Result<Option<CollateralTotals>> {
+    connection.query_row(
+        "SELECT * FROM collateral_totals WHERE synchronizer = ?1 AND collateral_mint = ?2",
+        params![synchronizer.to_string(), collateral_mint.to_string()],
+        collateral_totals_from_row,
+    ).optional()
+}
+
+fn load_synchronizer_totals(connection: &Connection, synchronizer: &Pubkey) -> rusqlite::Result<Option<SynchronizerTotals>> {
+    connection.query_row(
+        "SELECT * FROM synchronizer_totals WHERE synchronizer = ?1",
+        params![synchronizer.to_string()],
+        |row| Ok(SynchronizerTotals {
+            synchronizer: get_pubkey(row, 0)?,
+            trades: get_u64(row, 1)?,
+            collateral_in: get_u64(row, 2)?,
+            collateral_out: get_u64(row, 3)?,
+            fees_accrued: get_u64(row, 4)?,
+            fees_withdrawn: get_u64(row, 5)?,
+            collateral_withdrawn: get_u64(row, 6)?,
+            remaining_dollar_cap: get_u64(row, 7)?,
+            withdrawable_fee_amount: get_u64(row, 8)?,
+        }),
+    ).optional()
+}
+
+fn save_user_totals(connection: &Connection, totals: &UserTotals) -> Result<(), IndexerError> {
+    connection.execute(
+        "INSERT OR REPLACE INTO user_totals VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            totals.synchronizer.to_string(),
+            totals.user.to_string(),
+            to_sql(totals.buys)?,
+            to_sql(totals.sells)?,
+            to_sql(totals.collateral_paid)?,
+            to_sql(totals.collateral_received)?,
+            to_sql(totals.fees_paid)?,
+        ],
+    )?;
+    Ok(())
+}
+
+fn save_asset_totals(connection: &Connection, totals: &AssetTotals) -> Result<(), IndexerError> {
+    connection.execute(
+        "INSERT OR REPLACE INTO asset_totals VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            totals.synchronizer.to_string(),
+            totals.fiat_asset_mint.to_string(),
+            to_sql(totals.minted)?,
+            to_sql(totals.burned)?,
+            to_sql(totals.collateral_in)?,
+            to_sql(totals.collateral_out)?,
+            to_sql(totals.fees)?,
+        ],
+    )?;
+    Ok(())
+}
+
+fn save_synchronizer_totals(connection: &Connection, totals: &SynchronizerTotals) -> Result<(), IndexerError> {
+    connection.execute(
+        "INSERT OR REPLACE INTO synchronizer_totals VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            totals.synchronizer.to_string(),
+            to_sql(totals.trades)?,
+            to_sql(totals.collateral_in)?,
+            to_sql(totals.collateral_out)?,
+            to_sql(totals.fees_accrued)?,
+            to_sql(totals.fees_withdrawn)?,
+            to_sql(totals.collateral_withdrawn)?,
+            to_sql(totals.remaining_dollar_cap)?,
+            to_sql(totals.withdrawable_fee_amount)?,
+        ],
+    )?;
+    Ok(())
+}
+
+fn save_collateral_totals(connection: &Connection, totals: &CollateralTotals) -> Result<(), IndexerError> {
+    connection.execute(
+        "INSERT OR REPLACE INTO collateral_totals VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            totals.synchronizer.to_string(),
+            totals.collateral_mint.to_string(),
+            to_sql(totals.trades)?,
+            to_sql(totals.collateral_in)?,
+            to_sql(totals.collateral_out)?,
+            to_sql(totals.fees_accrued)?,
+            to_sql(totals.fees_withdrawn)?,
+            to_sql(totals.collateral_withdrawn)?,
+            to_sql(totals.withdrawable_fee_amount)?,
+        ],
+    )?;
+    Ok(())
+}
+
+fn insert_entry(connection: &Connection, entry: &LedgerEntry) -> Result<(), IndexerError> {
+    connection.execute(
+        "INSERT INTO ledger_entries (signature, slot, kind, synchronizer, account, fiat_asset_mint, amount, collateral_amount, fee_amount, price)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            entry.signature,
+            to_sql(entry.slot)?,
+            entry.kind.name(),
+            entry.synchronizer.to_string(),
+            entry.account.to_string(),
+            entry.fiat_asset_mint.map(|mint| mint.to_string()),
+            to_sql(entry.amount)?,
+            to_sql(entry.collateral_amount)?,
+            to_sql(entry.fee_amount)?,
+            entry.price.map(to_sql).transpose()?,
+        ],
+    )?;
+    Ok(())
+}
+
+fn synchronizer_totals(connection: &Connection, synchronizer: &Pubkey) -> rusqlite::Result<SynchronizerTotals> {
+    Ok(load_synchronizer_totals(connection, synchronizer)?.unwrap_or(SynchronizerTotals {
+        synchronizer: *synchronizer,
+        ..SynchronizerTotals::default()
+    }))
+}
+
+fn collateral_totals(connection: &Connection, synchronizer: &Pubkey, collateral_mint: &Pubkey) -> rusqlite::Result<CollateralTotals> {
+    Ok(load_collateral_totals(connection, synchronizer, collateral_mint)?.unwrap_or(CollateralTotals {
+        synchronizer: *synchronizer,
+        collateral_mint: *collateral_mint,
+        ..CollateralTotals::default()
+    }))
+}
+
+/// Applies an event, `settled` for trade events followed by their settlement in an accepted collateral
+fn apply_event(connection: &Connection, signature: &str, slot: u64, event: SynchronizerEvent, settled: bool) -> Result<(), IndexerError> {
+    match event {
+        SynchronizerEvent::BuyExecuted { synchronizer, user, fiat_asset_mint, amount, price, collateral_amount, fee_amount, remaining_dollar_cap, .. }
+        | SynchronizerEvent::SellExecuted { synchronizer, user, fiat_asset_mint, amount, price, collateral_amount, fee_amount, remaining_dollar_cap, .. } => {
+            let buy = matches!(event, SynchronizerEvent::BuyExecuted { .. });
+            let entry = LedgerEntry {
+                signature: signature.to_string(),
+                slot,
+                kind: if buy { EntryKind::Buy } else { EntryKind::Sell },
+                synchronizer,
+                account: user,
+                fiat_asset_mint: Some(fiat_asset_mint),
+                amount,
+                collateral_amount,
+                fee_amount,
+                price: Some(price),
+            };
+            insert_entry(connection, &entry)?;
+            insert_entry(connection, &LedgerEntry {
+                kind: EntryKind::FeeAccrual,
+                amount: fee_amount,
+                collateral_amount: 0,
+                ..entry
+            })?;
+
+            let mut user_totals = load_user_totals(connection, &synchronizer, &user)?
+                .unwrap_or(UserTotals { synchronizer, user, ..UserTotals::default() });
+            let mut asset_totals = load_asset_totals(connection, &synchronizer, &fiat_asset_mint)?
+                .unwrap_or(AssetTotals { synchronizer, fiat_asset_mint, ..AssetTotals::default() });
+            let mut totals = synchronizer_totals(connection, &synchronizer)?;
+            if buy {
+                let paid = collateral_amount.checked_add(fee_amount).ok_or(IndexerError::Overflow)?;
+                add(&mut user_totals.buys, 1)?;
+                add(&mut user_totals.collateral_paid, paid)?;
+                add(&mut asset_totals.minted, amount)?;
+                add(&mut asset_totals.collateral_in, collateral_amount)?;
+                add(&mut totals.collateral_in, paid)?;
+            } else {
+                let received = collateral_amount.saturating_sub(fee_amount);
+                add(&mut user_totals.sells, 1)?;
+                add(&mut user_totals.collateral_received, received)?;
+                add(&mut asset_totals.burned, amount)?;
+                add(&mut asset_totals.collateral_out, collateral_amount)?;
+                add(&mut totals.collateral_out, received)?;
+            }
+            add(&mut user_totals.fees_paid, fee_amount)?;
+            add(&mut asset_totals.fees, fee_amount)?;
+            add(&mut totals.trades, 1)?;
+            add(&mut totals.fees_accrued, fee_amount)?;
+            // Fees of trades settled in an accepted collateral accrue in its vault
+            if !settled {
+                add(&mut totals.withdrawable_fee_amount, fee_amount)?;
+            }
+            totals.remaining_dollar_cap = remaining_dollar_cap;
+
+            save_user_totals(connection, &user_totals)?;
+            save_asset_totals(connection, &asset_totals)?;
+            save_synchronizer_totals(connection, &totals)?;
+        }
+
+        SynchronizerEvent::FeeWithdrawn { synchronizer, recipient, amount, withdrawable_fee_amount } => {
+            insert_entry(connection, &LedgerEntry {
+                signature: signature.to_string(),
+                slot,
+                kind: EntryKind::FeeWithdrawal,
+                synchronizer,
+                account: recipient,
+                fiat_asset_mint: None,
+                amount,
+                collateral_amount: amount,
+                fee_amount: 0,
+                price: None,
+            })?;
+            let mut totals = synchronizer_totals(connection, &synchronizer)?;
+            add(&mut totals.fees_withdrawn, amount)?;
+            totals.withdrawable_fee_amount = withdrawable_fee_amount;
+            save_synchronizer_totals(connection, &totals)?;
+        }
+
+        // Referral fees are paid from the accrued fees, after the trade event
+        SynchronizerEvent::ReferralFeePaid { synchronizer, referrer, fiat_asset_mint, amount, .. } => {
+            insert_entry(connection, &LedgerEntry {
+                signature: signature.to_string(),
+                slot,
+                kind: EntryKind::ReferralFee,
+                synchronizer,
+                account: referrer,
+                fiat_asset_mint: Some(fiat_asset_mint),
+                amount,
+                collateral_amount: amount,
+                fee_amount: 0,
+                price: None,
+            })?;
+            let mut totals = synchronizer_totals(connection, &synchronizer)?;
+            add(&mut totals.fees_withdrawn, amount)?;
+            totals.withdrawable_fee_amount = totals.withdrawable_fee_amount.saturating_sub(amount);
+            save_synchronizer_totals(connection, &totals)?;
+        }
+
+        SynchronizerEvent::CollateralWithdrawn { synchronizer, recipient, amount } => {
+            insert_entry(connection, &LedgerEntry {
+                signature: signature.to_string(),
+                slot,
+                kind: EntryKind::CollateralWithdrawal,
+                synchronizer,
+                account: recipient,
+                fiat_asset_mint: None,
+                amount,
+                collateral_amount: amount,
+                fee_amount: 0,
+                price: None,
+            })?;
+            let mut totals = synchronizer_totals(connection, &synchronizer)?;
+            add(&mut totals.collateral_withdrawn, amount)?;
+            save_synchronizer_totals(connection, &totals)?;
+        }
+
+        SynchronizerEvent::CollateralSettled { synchronizer, collateral_mint, buy, user_collateral_amount, fee_amount, referral_amount, withdrawable_fee_amount, .. } => {
+            let mut totals = collateral_totals(connection, &synchronizer, &collateral_mint)?;
+            add(&mut totals.trades, 1)?;
+            if buy {
+                add(&mut totals.collateral_in, user_collateral_amount)?;
+            } else {
+                add(&mut totals.collateral_out, user_collateral_amount)?;
+            }
+            add(&mut totals.fees_accrued, fee_amount)?;
+            add(&mut totals.fees_withdrawn, referral_amount)?;
+            totals.withdrawable_fee_amount = withdrawable_fee_amount;
+            save_collateral_totals(connection, &totals)?;
+        }
+
+        SynchronizerEvent::CollateralFeeWithdrawn { synchronizer, collateral_mint, amount, withdrawable_fee_amount, .. } => {
+            let mut totals = collateral_totals(connection, &synchronizer, &collateral_mint)?;
+            add(&mut totals.fees_withdrawn, amount)?;
+            totals.withdrawable_fee_amount = withdrawable_fee_amount;
+            save_collateral_totals(connection, &totals)?;
+        }
+
+        SynchronizerEvent::AcceptedCollateralWithdrawn { synchronizer, collateral_mint, amount, .. } => {
+            let mut totals = collateral_totals(connection, &synchronizer, &collateral_mint)?;
+            add(&mut totals.collateral_withdrawn, amount)?;
+            save_collateral_totals(connection, &totals)?;
+        }
+
+        SynchronizerEvent::SynchronizerInitialized { synchronizer, remaining_dollar_cap, withdrawable_fee_amount, .. } => {
+            let mut totals = synchronizer_totals(connection, &synchronizer)?;
+            totals.remaining_dollar_cap = remaining_dollar_cap;
+            totals.withdrawable_fee_amount = withdrawable_fee_amount;
+            save_synchronizer_totals(connection, &totals)?;
+        }
+
+        SynchronizerEvent::CapChanged { synchronizer, remaining_dollar_cap } => {
+            let mut totals = synchronizer_totals(connection, &synchronizer)?;
+            totals.remaining_dollar_cap = remaining_dollar_cap;
+            save_synchronizer_totals(connection, &totals)?;
+        }
+
+        // Totals are kept under the old account key, migration doesn't change them
+        SynchronizerEvent::SynchronizerMigrated { .. }
+        | SynchronizerEvent::MinimumRequiredSignatureChanged { .. }
+        | SynchronizerEvent::CollateralTokenChanged { .. }
+        | SynchronizerEvent::OraclesChanged { .. }
+        | SynchronizerEvent::AssetCapsChanged { .. }
+        | SynchronizerEvent::RateLimitsChanged { .. }
+        | SynchronizerEvent::UserPositionCreated { .. }
+        | SynchronizerEvent::UserPositionClosed { .. }
+        | SynchronizerEvent::MarketScheduleChanged { .. }
+        | SynchronizerEvent::AssetSpreadsChanged { .. }
+        | SynchronizerEvent::FeeTiersChanged { .. }
+        | SynchronizerEvent::FeeOverrideChanged { .. }
+        | SynchronizerEvent::ReferralShareChanged { .. }
+        | SynchronizerEvent::CollateralAdded { .. }
+        | SynchronizerEvent::CollateralRemoved { .. }
+        | SynchronizerEvent::CollateralRateUpdated { .. } => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,38 @@
+//! Indexer reconstructing Synchronizer history from transaction logs.
+//!
+//! Transactions are read from a dump of `getConfirmedTransaction` results or fetched from a
+//! validator JSON RPC. Synchronizer instructions and events are decoded and stored in an SQLite
+//! `Ledger` with running totals per user, per fiat asset and per Synchronizer.
+
+pub mod ledger;
+pub mod source;
+
+use solana_program::program_error::ProgramError;
+use std::io;
+use thiserror::Error;
+
+pub use ledger::Ledger;
+pub use source::RecordedTransaction;
+
+/// Errors returned by the indexer
+#[derive(Debug, Error)]
+pub enum IndexerError {
+    /// Database error
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    /// Dump file I/O error
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// Recorded transaction can't be decoded
+    #[error("Invalid transaction record: {0}")]
+    InvalidTransaction(String),
+    /// Synchronizer event in the logs can't be decoded
+    #[error("Invalid event: {0}")]
+    InvalidEvent(ProgramError),
+    /// JSON RPC request failed
+    #[error("RPC error: {0}")]
+    Rpc(String),
+    /// Total doesn't fit the database integer type
+    #[error("Total overflow")]
+    Overflow,
+}
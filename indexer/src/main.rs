@@ -0,0 +1,170 @@
+//! Indexer command line: fills the SQLite ledger and prints totals
+
+use clap::{crate_version, App, AppSettings, Arg, ArgGroup, ArgMatches, SubCommand};
+use serde_json::{json, Value};
+use solana_program::pubkey::Pubkey;
+use std::{process::exit, str::FromStr};
+use synchronizer_indexer::{
+    ledger::{AssetTotals, CollateralTotals, UserTotals},
+    source::{read_dump, RpcSource},
+    IndexerError, Ledger,
+};
+
+fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new("synchronizer-indexer")
+        .version(crate_version!())
+        .about("Index Synchronizer trades, fees and withdrawals into SQLite")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(Arg::with_name("db")
+            .long("db")
+            .value_name("FILE")
+            .takes_value(true)
+            .global(true)
+            .default_value("synchronizer.sqlite")
+            .help("SQLite database"))
+        .arg(Arg::with_name("program_id")
+            .long("program-id")
+            .value_name("PUBKEY")
+            .takes_value(true)
+            .global(true)
+            .help("Synchronizer program id [default: the deployed program]"))
+        .subcommand(SubCommand::with_name("index")
+            .about("Index transactions from a dump or a validator")
+            .arg(Arg::with_name("dump")
+                .long("dump")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("File with a base64 `getConfirmedTransaction` result per line"))
+            .arg(Arg::with_name("rpc")
+                .long("rpc")
+                .value_name("URL")
+                .takes_value(true)
+                .requires("synchronizer")
+                .help("Validator JSON RPC"))
+            .arg(Arg::with_name("synchronizer")
+                .long("synchronizer")
+                .value_name("PUBKEY")
+                .takes_value(true)
+                .help("Synchronizer account whose transactions are fetched"))
+            .group(ArgGroup::with_name("source")
+                .args(&["dump", "rpc"])
+                .required(true)))
+        .subcommand(SubCommand::with_name("totals")
+            .about("Print Synchronizer, fiat asset and user totals as JSON")
+            .arg(Arg::with_name("synchronizer")
+                .long("synchronizer")
+                .value_name("PUBKEY")
+                .takes_value(true)
+                .required(true)
+                .help("Synchronizer account")))
+}
+
+fn pubkey_of(matches: &ArgMatches, name: &str) -> Result<Option<Pubkey>, String> {
+    matches.value_of(name)
+        .map(|value| Pubkey::from_str(value).map_err(|error| format!("invalid {} `{}`: {}", name, value, error)))
+        .transpose()
+}
+
+fn index(ledger: &mut Ledger, program_id: &Pubkey, synchronizer: Option<Pubkey>, matches: &ArgMatches) -> Result<(), IndexerError> {
+    let (indexed, total) = match matches.value_of("dump") {
+        Some(path) => {
+            let transactions = read_dump(path)?;
+            (ledger.index_all(program_id, &transactions)?, transactions.len())
+        }
+        None => {
+            let rpc = RpcSource::new(matches.value_of("rpc").unwrap());
+            // `--rpc` requires `--synchronizer`
+            let synchronizer = synchronizer.unwrap();
+            let signatures = rpc.signatures(&synchronizer)?;
+            let mut indexed = 0;
+            for signature in &signatures {
+                if !ledger.is_indexed(signature)? {
+                    indexed += ledger.index(program_id, &rpc.transaction(signature)?)? as usize;
+                }
+            }
+            (indexed, signatures.len())
+        }
+    };
+    println!("Indexed {} of {} transactions", indexed, total);
+    Ok(())
+}
+
+fn user_json(totals: &UserTotals) -> Value {
+    json!({
+        "user": totals.user.to_string(),
+        "buys": totals.buys,
+        "sells": totals.sells,
+        "collateralPaid": totals.collateral_paid,
+        "collateralReceived": totals.collateral_received,
+        "feesPaid": totals.fees_paid,
+    })
+}
+
+fn asset_json(totals: &AssetTotals) -> Value {
+    json!({
+        "fiatAssetMint": totals.fiat_asset_mint.to_string(),
+        "minted": totals.minted,
+        "burned": totals.burned,
+        "collateralIn": totals.collateral_in,
+        "collateralOut": totals.collateral_out,
+        "fees": totals.fees,
+    })
+}
+
+fn collateral_json(totals: &CollateralTotals) -> Value {
+    json!({
+        "collateralMint": totals.collateral_mint.to_string(),
+        "trades": totals.trades,
+        "collateralIn": totals.collateral_in,
+        "collateralOut": totals.collateral_out,
+        "feesAccrued": totals.fees_accrued,
+        "feesWithdrawn": totals.fees_withdrawn,
+        "collateralWithdrawn": totals.collateral_withdrawn,
+        "withdrawableFeeAmount": totals.withdrawable_fee_amount,
+    })
+}
+
+fn totals(ledger: &Ledger, synchronizer: &Pubkey) -> Result<Value, IndexerError> {
+    let totals = ledger.synchronizer_totals(synchronizer)?.unwrap_or_default();
+    Ok(json!({
+        "synchronizer": synchronizer.to_string(),
+        "trades": totals.trades,
+        "collateralIn": totals.collateral_in,
+        "collateralOut": totals.collateral_out,
+        "feesAccrued": totals.fees_accrued,
+        "feesWithdrawn": totals.fees_withdrawn,
+        "collateralWithdrawn": totals.collateral_withdrawn,
+        "remainingDollarCap": totals.remaining_dollar_cap,
+        "withdrawableFeeAmount": totals.withdrawable_fee_amount,
+        "assets": ledger.assets(synchronizer)?.iter().map(asset_json).collect::<Vec<_>>(),
+        "users": ledger.users(synchronizer)?.iter().map(user_json).collect::<Vec<_>>(),
+        "collaterals": ledger.collaterals(synchronizer)?.iter().map(collateral_json).collect::<Vec<_>>(),
+    }))
+}
+
+fn run() -> Result<(), String> {
+    let matches = app().get_matches();
+    let program_id = pubkey_of(&matches, "program_id")?.unwrap_or_else(synchronizer::processor::id);
+    let mut ledger = Ledger::open(matches.value_of("db").unwrap()).map_err(|error| error.to_string())?;
+
+    match matches.subcommand() {
+        ("index", Some(matches)) => {
+            let synchronizer = pubkey_of(matches, "synchronizer")?;
+            index(&mut ledger, &program_id, synchronizer, matches).map_err(|error| error.to_string())
+        }
+        ("totals", Some(matches)) => {
+            let synchronizer = pubkey_of(matches, "synchronizer")?.unwrap();
+            let totals = totals(&ledger, &synchronizer).map_err(|error| error.to_string())?;
+            println!("{}", serde_json::to_string_pretty(&totals).unwrap());
+            Ok(())
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("Error: {}", error);
+        exit(1);
+    }
+}
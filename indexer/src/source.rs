@@ -0,0 +1,168 @@
+//! Transaction sources: dump files and validator JSON RPC
+
+use crate::IndexerError;
+use serde_json::{json, Value};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::transaction::Transaction;
+use std::{fs, path::Path};
+
+/// Confirmed transaction with its logs
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedTransaction {
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    /// Whether the transaction failed, its effects were rolled back
+    pub failed: bool,
+    pub logs: Vec<String>,
+    pub transaction: Transaction,
+}
+
+impl RecordedTransaction {
+    /// First signature of the transaction
+    pub fn signature(&self) -> String {
+        self.transaction.signatures.first().map(|signature| signature.to_string()).unwrap_or_default()
+    }
+
+    /// Parses a `getConfirmedTransaction` result with `base64` encoding
+    pub fn from_rpc_json(value: &Value) -> Result<Self, IndexerError> {
+        let invalid = |what: &str| IndexerError::InvalidTransaction(format!("missing {}", what));
+        let slot = value["slot"].as_u64().ok_or_else(|| invalid("slot"))?;
+        let meta = &value["meta"];
+        let logs = meta["logMessages"].as_array().ok_or_else(|| invalid("meta.logMessages"))?
+            .iter()
+            .map(|line| line.as_str().map(String::from).ok_or_else(|| invalid("log message")))
+            .collect::<Result<_, _>>()?;
+        let encoded = match &value["transaction"] {
+            Value::Array(encoded) if encoded.get(1) == Some(&json!("base64")) => encoded[0].as_str(),
+            _ => None,
+        }.ok_or_else(|| invalid("base64 transaction"))?;
+        let data = base64::decode(encoded).map_err(|error| IndexerError::InvalidTransaction(error.to_string()))?;
+        let transaction = bincode::deserialize(&data).map_err(|error| IndexerError::InvalidTransaction(error.to_string()))?;
+
+        Ok(Self {
+            slot,
+            block_time: value["blockTime"].as_i64(),
+            failed: !meta["err"].is_null(),
+            logs,
+            transaction,
+        })
+    }
+
+    /// Encodes the transaction like a `getConfirmedTransaction` result with `base64` encoding
+    pub fn to_rpc_json(&self) -> Value {
+        json!({
+            "slot": self.slot,
+            "blockTime": self.block_time,
+            "meta": {
+                "err": if self.failed { json!({ "InstructionError": [0, "InvalidArgument"] }) } else { Value::Null },
+                "logMessages": self.logs,
+            },
+            // Serialization into a Vec can't fail
+            "transaction": [base64::encode(bincode::serialize(&self.transaction).unwrap()), "base64"],
+        })
+    }
+}
+
+/// Reads a dump with a `getConfirmedTransaction` result per line, in the order they were processed
+pub fn read_dump<P: AsRef<Path>>(path: P) -> Result<Vec<RecordedTransaction>, IndexerError> {
+    fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let value: Value = serde_json::from_str(line).map_err(|error| IndexerError::InvalidTransaction(error.to_string()))?;
+            RecordedTransaction::from_rpc_json(&value)
+        })
+        .collect()
+}
+
+/// Validator JSON RPC
+pub struct RpcSource {
+    url: String,
+}
+
+impl RpcSource {
+    /// Number of signatures requested at once
+    const SIGNATURES_LIMIT: usize = 1000;
+
+    pub fn new(url: &str) -> Self {
+        Self { url: url.to_string() }
+    }
+
+    fn call(&self, method: &str, params: Value) -> Result<Value, IndexerError> {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let response = ureq::post(&self.url)
+            .set("Content-Type", "application/json")
+            .send_string(&request.to_string())
+            .map_err(|error| IndexerError::Rpc(error.to_string()))?;
+        let mut body: Value = serde_json::from_str(&response.into_string()?)
+            .map_err(|error| IndexerError::Rpc(error.to_string()))?;
+        if !body["error"].is_null() {
+            return Err(IndexerError::Rpc(body["error"].to_string()));
+        }
+        Ok(body["result"].take())
+    }
+
+    /// Signatures of confirmed transactions referencing `address`, oldest first
+    pub fn signatures(&self, address: &Pubkey) -> Result<Vec<String>, IndexerError> {
+        let mut signatures = Vec::new();
+        let mut before: Option<String> = None;
+        loop {
+            let result = self.call(
+                "getConfirmedSignaturesForAddress2",
+                json!([address.to_string(), { "before": before, "limit": Self::SIGNATURES_LIMIT }]),
+            )?;
+            let page: Vec<String> = result.as_array().ok_or_else(|| IndexerError::Rpc(format!("unexpected result {}", result)))?
+                .iter()
+                .filter_map(|entry| entry["signature"].as_str().map(String::from))
+                .collect();
+            let done = page.len() < Self::SIGNATURES_LIMIT;
+            before = page.last().cloned();
+            signatures.extend(page);
+            if done {
+                break;
+            }
+        }
+        // Newest first from the RPC
+        signatures.reverse();
+        Ok(signatures)
+    }
+
+    pub fn transaction(&self, signature: &str) -> Result<RecordedTransaction, IndexerError> {
+        let result = self.call("getConfirmedTransaction", json!([signature, "base64"]))?;
+        RecordedTransaction::from_rpc_json(&result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use solana_sdk::{hash::Hash, signature::Keypair, signer::Signer};
+
+    #[test]
+    fn test_rpc_json() {
+        let payer = Keypair::new();
+        let instruction = synchronizer::instruction::set_remaining_dollar_cap(&synchronizer::processor::id(), 100, &payer.pubkey()).unwrap();
+        let mut transaction = Transaction::new_with_payer(&[instruction], Some(&payer.pubkey()));
+        transaction.sign(&[&payer], Hash::new_unique());
+        let recorded = RecordedTransaction {
+            slot: 12,
+            block_time: Some(1_620_000_000),
+            failed: false,
+            logs: vec!["Program log: Instruction: SetRemainingDollarCap".to_string()],
+            transaction,
+        };
+
+        let json = recorded.to_rpc_json();
+        assert!(json["meta"]["err"].is_null());
+        assert_eq!(RecordedTransaction::from_rpc_json(&json).unwrap(), recorded);
+        assert_eq!(recorded.signature(), recorded.transaction.signatures[0].to_string());
+
+        let failed = RecordedTransaction { failed: true, block_time: None, ..recorded };
+        assert_eq!(RecordedTransaction::from_rpc_json(&failed.to_rpc_json()).unwrap(), failed);
+
+        // BadCase: JSON encoded transaction
+        let mut json = failed.to_rpc_json();
+        json["transaction"] = json!({ "signatures": [] });
+        assert!(matches!(RecordedTransaction::from_rpc_json(&json), Err(IndexerError::InvalidTransaction(_))));
+    }
+}
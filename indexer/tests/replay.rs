@@ -0,0 +1,113 @@
+use solana_program::pubkey::Pubkey;
+use solana_sdk::{signature::keypair_from_seed, signer::Signer};
+use synchronizer::processor::id;
+use synchronizer_indexer::{
+    ledger::{AssetTotals, EntryKind, SynchronizerTotals, UserTotals},
+    source::read_dump,
+    Ledger,
+};
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/transactions.jsonl");
+
+// Keys of the recorded transactions
+fn signer(seed: u8) -> Pubkey {
+    keypair_from_seed(&[seed; 32]).unwrap().pubkey()
+}
+
+fn ui(amount: f64) -> u64 {
+    spl_token::ui_amount_to_amount(amount, 9)
+}
+
+#[test]
+fn test_replay_fixture() {
+    // Initialization, buys of AAPL by A and TSLA by B, sell of AAPL by A, failed buy by B,
+    // fee and collateral withdrawals, cap change next to a spoofed event and a duplicate buy
+    let transactions = read_dump(FIXTURE).unwrap();
+    assert_eq!(transactions.len(), 9);
+    let synchronizer = signer(1);
+    let (user_a, user_b) = (signer(2), signer(3));
+    let (aapl, tsla) = (Pubkey::new(&[10; 32]), Pubkey::new(&[11; 32]));
+
+    let mut ledger = Ledger::open_in_memory().unwrap();
+    // The duplicate isn't indexed twice
+    assert_eq!(ledger.index_all(&id(), &transactions).unwrap(), 8);
+
+    assert_eq!(ledger.user_totals(&synchronizer, &user_a).unwrap().unwrap(), UserTotals {
+        synchronizer,
+        user: user_a,
+        buys: 1,
+        sells: 1,
+        collateral_paid: ui(1506.505),
+        collateral_received: ui(753.246),
+        fees_paid: ui(2.259),
+    });
+    // The failed buy isn't counted
+    assert_eq!(ledger.user_totals(&synchronizer, &user_b).unwrap().unwrap(), UserTotals {
+        synchronizer,
+        user: user_b,
+        buys: 1,
+        sells: 0,
+        collateral_paid: ui(2802.8),
+        collateral_received: 0,
+        fees_paid: ui(2.8),
+    });
+
+    let aapl_totals = ledger.asset_totals(&synchronizer, &aapl).unwrap().unwrap();
+    assert_eq!(aapl_totals, AssetTotals {
+        synchronizer,
+        fiat_asset_mint: aapl,
+        minted: ui(10.0),
+        burned: ui(5.0),
+        collateral_in: ui(1505.0),
+        collateral_out: ui(754.0),
+        fees: ui(2.259),
+    });
+    assert_eq!(aapl_totals.outstanding(), ui(5.0) as i128);
+    // The spoofed TSLA buy isn't counted
+    assert_eq!(ledger.asset_totals(&synchronizer, &tsla).unwrap().unwrap(), AssetTotals {
+        synchronizer,
+        fiat_asset_mint: tsla,
+        minted: ui(4.0),
+        burned: 0,
+        collateral_in: ui(2800.0),
+        collateral_out: 0,
+        fees: ui(2.8),
+    });
+    assert_eq!(ledger.assets(&synchronizer).unwrap().len(), 2);
+    assert_eq!(ledger.users(&synchronizer).unwrap().len(), 2);
+
+    assert_eq!(ledger.synchronizer_totals(&synchronizer).unwrap().unwrap(), SynchronizerTotals {
+        synchronizer,
+        trades: 3,
+        collateral_in: ui(4309.305),
+        collateral_out: ui(753.246),
+        fees_accrued: ui(5.059),
+        fees_withdrawn: ui(2.5295),
+        collateral_withdrawn: ui(100.0),
+        remaining_dollar_cap: ui(200_000.0),
+        withdrawable_fee_amount: ui(2.5295),
+    });
+
+    let kinds: Vec<_> = ledger.entries(&synchronizer).unwrap().iter().map(|entry| entry.kind).collect();
+    assert_eq!(kinds, vec![
+        EntryKind::Buy,
+        EntryKind::FeeAccrual,
+        EntryKind::Buy,
+        EntryKind::FeeAccrual,
+        EntryKind::Sell,
+        EntryKind::FeeAccrual,
+        EntryKind::FeeWithdrawal,
+        EntryKind::CollateralWithdrawal,
+    ]);
+
+    // Replaying doesn't double count
+    let totals = ledger.synchronizer_totals(&synchronizer).unwrap();
+    assert_eq!(ledger.index_all(&id(), &transactions).unwrap(), 0);
+    assert_eq!(ledger.synchronizer_totals(&synchronizer).unwrap(), totals);
+    assert_eq!(ledger.entries(&synchronizer).unwrap().len(), 8);
+
+    // Transactions of another program id aren't indexed
+    let mut ledger = Ledger::open_in_memory().unwrap();
+    assert_eq!(ledger.index_all(&Pubkey::new_unique(), &transactions).unwrap(), 0);
+    assert_eq!(ledger.synchronizer_totals(&synchronizer).unwrap(), None);
+}
@@ -0,0 +1,73 @@
+//! Client of the oracle HTTP API
+
+use crate::{Attestation, OracleError};
+use serde_json::{json, Value};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::{signature::Signature, transaction::Transaction};
+use std::str::FromStr;
+use synchronizer_client::offline;
+
+/// Client of one oracle server
+pub struct OracleClient {
+    url: String,
+}
+
+impl OracleClient {
+    pub fn new(url: &str) -> Self {
+        Self { url: url.trim_end_matches('/').to_string() }
+    }
+
+    fn response(result: Result<ureq::Response, ureq::Error>) -> Result<Value, OracleError> {
+        let (ok, response) = match result {
+            Ok(response) => (true, response),
+            Err(ureq::Error::Status(_, response)) => (false, response),
+            Err(error) => return Err(OracleError::Http(error.to_string())),
+        };
+        let body: Value = serde_json::from_str(&response.into_string()?)
+            .map_err(|error| OracleError::Http(error.to_string()))?;
+        if !ok {
+            return Err(OracleError::Rejected(body["error"].as_str().unwrap_or_default().to_string()));
+        }
+        Ok(body)
+    }
+
+    pub fn pubkey(&self) -> Result<Pubkey, OracleError> {
+        let body = Self::response(ureq::get(&format!("{}/oracle", self.url)).call())?;
+        body["oracle"].as_str()
+            .and_then(|oracle| Pubkey::from_str(oracle).ok())
+            .ok_or_else(|| OracleError::Http(format!("invalid response {}", body)))
+    }
+
+    /// Fetches and verifies a signed price of `fiat_mint`
+    pub fn price(&self, fiat_mint: &Pubkey) -> Result<Attestation, OracleError> {
+        let body = Self::response(ureq::get(&format!("{}/price/{}", self.url, fiat_mint)).call())?;
+        let attestation = Attestation::from_json(&body).ok_or_else(|| OracleError::Http(format!("invalid response {}", body)))?;
+        if attestation.fiat_mint != *fiat_mint || !attestation.verify() {
+            return Err(OracleError::InvalidSignature);
+        }
+        Ok(attestation)
+    }
+
+    /// Asks the oracle to co-sign a trade transaction and adds its signature
+    pub fn sign(&self, transaction: &mut Transaction, oracle: &Pubkey) -> Result<(), OracleError> {
+        let request = json!({ "transaction": offline::encode(transaction) });
+        let body = Self::response(ureq::post(&format!("{}/sign", self.url)).send_string(&request.to_string()))?;
+        let signature = body["signature"].as_str()
+            .and_then(|signature| Signature::from_str(signature).ok())
+            .ok_or_else(|| OracleError::Http(format!("invalid response {}", body)))?;
+        add_signature(transaction, oracle, signature)
+    }
+}
+
+/// Places the signature of `signer` at its position, after checking it signs the transaction message
+pub fn add_signature(transaction: &mut Transaction, signer: &Pubkey, signature: Signature) -> Result<(), OracleError> {
+    let signer_count = transaction.message.header.num_required_signatures as usize;
+    let position = transaction.message.account_keys[..signer_count].iter()
+        .position(|key| key == signer)
+        .ok_or(OracleError::InvalidSignature)?;
+    if !signature.verify(signer.as_ref(), &transaction.message_data()) {
+        return Err(OracleError::InvalidSignature);
+    }
+    transaction.signatures[position] = signature;
+    Ok(())
+}
@@ -0,0 +1,280 @@
+//! Reference oracle signer service for the Synchronizer program.
+//!
+//! An oracle reads fiat asset prices from a `PriceSource`, publishes signed price attestations and
+//! co-signs `BuyFor`/`SellFor` transactions whose prices agree with its own.
+
+pub mod client;
+pub mod server;
+pub mod source;
+
+use serde_json::{json, Value};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::{signature::{Keypair, Signature, Signer}, transaction::Transaction};
+use source::PriceSource;
+use std::{io, str::FromStr, time::{SystemTime, UNIX_EPOCH}};
+use synchronizer::{instruction::{first_trade_oracle_account, SynchronizerInstruction}, processor::id};
+use synchronizer_client::ClientError;
+use thiserror::Error;
+
+/// Default accepted difference between a transaction price and the oracle price
+pub const DEFAULT_MAX_DEVIATION_BPS: u64 = 50;
+
+
+/// Errors returned by the oracle service and its client
+#[derive(Debug, Error)]
+pub enum OracleError {
+    /// Price source or server I/O error
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// Price source content can't be parsed
+    #[error("Invalid prices: {0}")]
+    InvalidPrices(String),
+    /// Price source has no price for the fiat asset
+    #[error("No price for fiat asset {0}")]
+    UnknownAsset(Pubkey),
+    /// HTTP request failed
+    #[error("HTTP error: {0}")]
+    Http(String),
+    /// Transaction price differs from the oracle price by more than the allowed deviation
+    #[error("Price {actual} of fiat asset {fiat_mint} deviates from the oracle price {expected}")]
+    PriceMismatch { fiat_mint: Pubkey, expected: u64, actual: u64 },
+    /// Transaction has no trade signed by this oracle, or uses the oracle key elsewhere
+    #[error("Transaction is not a trade signed by the oracle")]
+    NothingToSign,
+    /// Oracle server returned an error
+    #[error("Oracle rejected the request: {0}")]
+    Rejected(String),
+    /// Attestation or co-signature doesn't verify
+    #[error("Invalid oracle signature")]
+    InvalidSignature,
+    /// Transaction encoding error
+    #[error("Client error: {0}")]
+    Client(#[from] ClientError),
+}
+
+/// Price of a fiat asset signed by an oracle
+#[derive(Clone, Debug, PartialEq)]
+pub struct Attestation {
+    pub oracle: Pubkey,
+    pub fiat_mint: Pubkey,
+    pub price: u64,
+    /// Unix timestamp of the price
+    pub timestamp: i64,
+    pub signature: Signature,
+}
+
+impl Attestation {
+    /// Signed message: domain tag, fiat mint, little-endian price and timestamp
+    pub fn message(fiat_mint: &Pubkey, price: u64, timestamp: i64) -> Vec<u8> {
+        let mut message = b"synchronizer-oracle-price".to_vec();
+        message.extend_from_slice(fiat_mint.as_ref());
+        message.extend_from_slice(&price.to_le_bytes());
+        message.extend_from_slice(&timestamp.to_le_bytes());
+        message
+    }
+
+    pub fn verify(&self) -> bool {
+        self.signature.verify(self.oracle.as_ref(), &Self::message(&self.fiat_mint, self.price, self.timestamp))
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "oracle": self.oracle.to_string(),
+            "fiatMint": self.fiat_mint.to_string(),
+            "price": self.price,
+            "timestamp": self.timestamp,
+            "signature": self.signature.to_string(),
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Option<Self> {
+        Some(Self {
+            oracle: Pubkey::from_str(value["oracle"].as_str()?).ok()?,
+            fiat_mint: Pubkey::from_str(value["fiatMint"].as_str()?).ok()?,
+            price: value["price"].as_u64()?,
+            timestamp: value["timestamp"].as_i64()?,
+            signature: Signature::from_str(value["signature"].as_str()?).ok()?,
+        })
+    }
+}
+
+/// Oracle signer
+pub struct Oracle {
+    keypair: Keypair,
+    source: Box<dyn PriceSource>,
+    max_deviation_bps: u64,
+}
+
+impl Oracle {
+    pub fn new<S: PriceSource + 'static>(keypair: Keypair, source: S) -> Self {
+        Self {
+            keypair,
+            source: Box::new(source),
+            max_deviation_bps: DEFAULT_MAX_DEVIATION_BPS,
+        }
+    }
+
+    /// Sets the accepted difference between a transaction price and the oracle price, in basis points
+    pub fn with_max_deviation_bps(mut self, max_deviation_bps: u64) -> Self {
+        self.max_deviation_bps = max_deviation_bps;
+        self
+    }
+
+    pub fn pubkey(&self) -> Pubkey {
+        self.keypair.pubkey()
+    }
+
+    /// Signs the current price of `fiat_mint`
+    pub fn attest(&self, fiat_mint: &Pubkey) -> Result<Attestation, OracleError> {
+        let price = self.source.price(fiat_mint)?;
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs() as i64).unwrap_or_default();
+        Ok(Attestation {
+            oracle: self.pubkey(),
+            fiat_mint: *fiat_mint,
+            price,
+            timestamp,
+            signature: self.keypair.sign_message(&Attestation::message(fiat_mint, price, timestamp)),
+        })
+    }
+
+    /// Checks that the oracle key only signs `BuyFor`/`SellFor` instructions
+    /// and that the prices given for this oracle agree with its own
+    pub fn check_transaction(&self, transaction: &Transaction) -> Result<(), OracleError> {
+        let message = &transaction.message;
+        let oracle = self.pubkey();
+        let oracle_index = match message.account_keys.iter().position(|key| *key == oracle) {
+            // The fee payer is always the first account
+            Some(index) if index > 0 && message.is_signer(index) => index as u8,
+            _ => return Err(OracleError::NothingToSign),
+        };
+
+        let mut trades = 0;
+        for instruction in message.instructions.iter() {
+            let oracle_position = instruction.accounts.iter().position(|index| *index == oracle_index);
+            let program_id = message.account_keys[instruction.program_id_index as usize];
+            if oracle_position.is_none() && program_id != oracle {
+                continue;
+            }
+
+            let (prices, first_oracle) = match SynchronizerInstruction::unpack(&instruction.data) {
+                Ok(SynchronizerInstruction::BuyFor { prices, optional_accounts, .. }) | Ok(SynchronizerInstruction::SellFor { prices, optional_accounts, .. })
+                    if program_id == id() => (prices, first_trade_oracle_account(optional_accounts)),
+                _ => return Err(OracleError::NothingToSign),
+            };
+            let price = oracle_position
+                .filter(|position| *position >= first_oracle)
+                .and_then(|position| prices.get(position - first_oracle))
+                .copied()
+                .ok_or(OracleError::NothingToSign)?;
+            let fiat_mint = instruction.accounts.first()
+                .map(|index| message.account_keys[*index as usize])
+                .ok_or(OracleError::NothingToSign)?;
+
+            let expected = self.source.price(&fiat_mint)?;
+            let deviation = (price as u128).max(expected as u128) - (price as u128).min(expected as u128);
+            if deviation * 10_000 > expected as u128 * self.max_deviation_bps as u128 {
+                return Err(OracleError::PriceMismatch { fiat_mint, expected, actual: price });
+            }
+            trades += 1;
+        }
+
+        if trades == 0 {
+            return Err(OracleError::NothingToSign);
+        }
+        Ok(())
+    }
+
+    /// Co-signs a trade transaction accepted by `check_transaction`
+    pub fn sign_transaction(&self, transaction: &Transaction) -> Result<Signature, OracleError> {
+        self.check_transaction(transaction)?;
+        Ok(self.keypair.sign_message(&transaction.message_data()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use solana_program::instruction::Instruction;
+    use synchronizer::instruction::TRADE_PROGRAM_ACCOUNTS;
+    use std::collections::HashMap;
+
+    fn trade(oracles: &[Pubkey], prices: &[u64], fiat_mint: &Pubkey, optional_accounts: u8) -> Instruction {
+        synchronizer::instruction::buy_for(
+            &id(),
+            5,
+            10,
+            1,
+            &prices.to_vec(),
+            &oracles.to_vec(),
+            fiat_mint,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            optional_accounts,
+            None,
+        ).unwrap()
+    }
+
+    #[test]
+    fn test_attestation() {
+        let fiat_mint = Pubkey::new_unique();
+        let oracle = Oracle::new(Keypair::new(), vec![(fiat_mint, 5_000)].into_iter().collect::<HashMap<_, _>>());
+
+        let attestation = oracle.attest(&fiat_mint).unwrap();
+        assert_eq!(attestation.oracle, oracle.pubkey());
+        assert_eq!(attestation.price, 5_000);
+        assert!(attestation.verify());
+        assert_eq!(Attestation::from_json(&attestation.to_json()).unwrap(), attestation);
+
+        // BadCase: tampered price
+        let tampered = Attestation { price: 6_000, ..attestation };
+        assert!(!tampered.verify());
+
+        // BadCase: unknown asset
+        assert!(matches!(oracle.attest(&Pubkey::new_unique()), Err(OracleError::UnknownAsset(_))));
+    }
+
+    #[test]
+    fn test_sign_transaction() {
+        let fiat_mint = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let other_oracle = Pubkey::new_unique();
+        let oracle = Oracle::new(Keypair::new(), vec![(fiat_mint, 10_000)].into_iter().collect::<HashMap<_, _>>());
+        let oracles = [other_oracle, oracle.pubkey()];
+
+        // Good case: price within 50 bps
+        let transaction = Transaction::new_with_payer(&[trade(&oracles, &[9_000, 10_050], &fiat_mint, TRADE_PROGRAM_ACCOUNTS)], Some(&payer));
+        let signature = oracle.sign_transaction(&transaction).unwrap();
+        assert!(signature.verify(oracle.pubkey().as_ref(), &transaction.message_data()));
+
+        // Good case: trade without optional accounts
+        let transaction = Transaction::new_with_payer(&[trade(&oracles, &[9_000, 10_050], &fiat_mint, 0)], Some(&payer));
+        oracle.sign_transaction(&transaction).unwrap();
+
+        // BadCase: price deviates more than 50 bps
+        let transaction = Transaction::new_with_payer(&[trade(&oracles, &[10_000, 9_949], &fiat_mint, TRADE_PROGRAM_ACCOUNTS)], Some(&payer));
+        assert!(matches!(
+            oracle.sign_transaction(&transaction),
+            Err(OracleError::PriceMismatch { expected: 10_000, actual: 9_949, .. })
+        ));
+
+        // BadCase: no price for the oracle
+        let transaction = Transaction::new_with_payer(&[trade(&oracles, &[10_000], &fiat_mint, TRADE_PROGRAM_ACCOUNTS)], Some(&payer));
+        assert!(matches!(oracle.sign_transaction(&transaction), Err(OracleError::NothingToSign)));
+
+        // BadCase: the oracle key is used by another instruction
+        let transfer = solana_program::system_instruction::transfer(&oracle.pubkey(), &payer, 1);
+        let transaction = Transaction::new_with_payer(&[trade(&oracles, &[10_000, 10_000], &fiat_mint, TRADE_PROGRAM_ACCOUNTS), transfer], Some(&payer));
+        assert!(matches!(oracle.sign_transaction(&transaction), Err(OracleError::NothingToSign)));
+
+        // BadCase: the oracle pays the fee
+        let transaction = Transaction::new_with_payer(&[trade(&oracles, &[10_000, 10_000], &fiat_mint, TRADE_PROGRAM_ACCOUNTS)], Some(&oracle.pubkey()));
+        assert!(matches!(oracle.sign_transaction(&transaction), Err(OracleError::NothingToSign)));
+
+        // BadCase: the oracle isn't part of the trade
+        let transaction = Transaction::new_with_payer(&[trade(&[other_oracle], &[10_000], &fiat_mint, TRADE_PROGRAM_ACCOUNTS)], Some(&payer));
+        assert!(matches!(oracle.sign_transaction(&transaction), Err(OracleError::NothingToSign)));
+    }
+}
@@ -0,0 +1,69 @@
+//! Reference oracle node serving signed prices
+
+use clap::{crate_version, App, Arg, ArgGroup};
+use solana_sdk::signature::read_keypair_file;
+use std::process::exit;
+use synchronizer_oracle::{server::OracleServer, source::{FileSource, HttpSource}, Oracle, DEFAULT_MAX_DEVIATION_BPS};
+
+fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new("synchronizer-oracle")
+        .version(crate_version!())
+        .about("Serve signed fiat asset prices and co-sign Synchronizer trades")
+        .arg(Arg::with_name("keypair")
+            .long("keypair")
+            .value_name("KEYPAIR")
+            .takes_value(true)
+            .required(true)
+            .help("Oracle keypair file"))
+        .arg(Arg::with_name("listen")
+            .long("listen")
+            .value_name("HOST:PORT")
+            .takes_value(true)
+            .default_value("127.0.0.1:8080")
+            .help("HTTP API address"))
+        .arg(Arg::with_name("prices")
+            .long("prices")
+            .value_name("FILE")
+            .takes_value(true)
+            .help("JSON or CSV price file"))
+        .arg(Arg::with_name("price_url")
+            .long("price-url")
+            .value_name("URL")
+            .takes_value(true)
+            .help("HTTP price feed"))
+        .group(ArgGroup::with_name("source")
+            .args(&["prices", "price_url"])
+            .required(true))
+        .arg(Arg::with_name("max_deviation_bps")
+            .long("max-deviation-bps")
+            .value_name("BPS")
+            .takes_value(true)
+            .help("Accepted difference between a trade price and the oracle price [default: 50]"))
+}
+
+fn run() -> Result<(), String> {
+    let matches = app().get_matches();
+    let keypair_path = matches.value_of("keypair").unwrap();
+    let keypair = read_keypair_file(keypair_path).map_err(|error| format!("can't read keypair {}: {}", keypair_path, error))?;
+    let max_deviation_bps = match matches.value_of("max_deviation_bps") {
+        Some(value) => value.parse().map_err(|error| format!("invalid max deviation `{}`: {}", value, error))?,
+        None => DEFAULT_MAX_DEVIATION_BPS,
+    };
+    let oracle = match matches.value_of("prices") {
+        Some(path) => Oracle::new(keypair, FileSource::new(path)),
+        None => Oracle::new(keypair, HttpSource::new(matches.value_of("price_url").unwrap())),
+    }.with_max_deviation_bps(max_deviation_bps);
+
+    let pubkey = oracle.pubkey();
+    let server = OracleServer::start(matches.value_of("listen").unwrap(), oracle).map_err(|error| error.to_string())?;
+    println!("Oracle {} listening on {}", pubkey, server.url());
+    server.join();
+    Ok(())
+}
+
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("Error: {}", error);
+        exit(1);
+    }
+}
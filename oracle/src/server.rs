@@ -0,0 +1,106 @@
+//! HTTP API of an oracle
+//!
+//! * `GET /oracle` returns `{"oracle": <pubkey>}`
+//! * `GET /price/<fiat mint>` returns a signed price `Attestation`
+//! * `POST /sign` with `{"transaction": <base64 transaction>}` returns `{"signature": <signature>}`
+//!   when the oracle accepts the trade
+//!
+//! Errors are returned as `{"error": <message>}` with a 4xx or 5xx status.
+
+use crate::{Oracle, OracleError};
+use serde_json::{json, Value};
+use solana_program::pubkey::Pubkey;
+use std::{net::SocketAddr, str::FromStr, sync::Arc, thread::{self, JoinHandle}};
+use synchronizer_client::offline;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// Oracle HTTP server running on its own thread, stopped when dropped
+pub struct OracleServer {
+    server: Arc<Server>,
+    address: SocketAddr,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl OracleServer {
+    /// Starts serving `oracle` on `address`, port 0 picks a free port
+    pub fn start(address: &str, oracle: Oracle) -> Result<Self, OracleError> {
+        let server = Server::http(address).map_err(|error| OracleError::Http(error.to_string()))?;
+        let address = server.server_addr().to_ip().ok_or_else(|| OracleError::Http("not an IP address".to_string()))?;
+        let server = Arc::new(server);
+        let thread_server = server.clone();
+        let thread = thread::spawn(move || {
+            for mut request in thread_server.incoming_requests() {
+                let (status, body) = handle(&oracle, &mut request);
+                let response = Response::from_string(body.to_string())
+                    .with_status_code(status)
+                    .with_header(Header::from_bytes("Content-Type", "application/json").unwrap());
+                // The client may be gone, nothing to do about it
+                let _ = request.respond(response);
+            }
+        });
+        Ok(Self { server, address, thread: Some(thread) })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    /// Serves until the process exits
+    pub fn join(mut self) {
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for OracleServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn error_status(error: &OracleError) -> u16 {
+    match error {
+        OracleError::UnknownAsset(_) => 404,
+        OracleError::PriceMismatch { .. } | OracleError::NothingToSign | OracleError::Client(_) => 400,
+        _ => 500,
+    }
+}
+
+fn handle(oracle: &Oracle, request: &mut Request) -> (u16, Value) {
+    let url = request.url().to_string();
+    let result = match (request.method(), url.as_str()) {
+        (Method::Get, "/oracle") => Ok(json!({ "oracle": oracle.pubkey().to_string() })),
+        (Method::Get, path) if path.starts_with("/price/") => match Pubkey::from_str(&path["/price/".len()..]) {
+            Ok(fiat_mint) => oracle.attest(&fiat_mint).map(|attestation| attestation.to_json()),
+            Err(_) => return (400, json!({ "error": "invalid fiat mint" })),
+        },
+        (Method::Post, "/sign") => {
+            let mut body = String::new();
+            if request.as_reader().read_to_string(&mut body).is_err() {
+                return (400, json!({ "error": "invalid request body" }));
+            }
+            let encoded = serde_json::from_str::<Value>(&body).ok()
+                .and_then(|body| body["transaction"].as_str().map(String::from));
+            match encoded {
+                Some(encoded) => offline::decode(&encoded)
+                    .map_err(OracleError::from)
+                    .and_then(|transaction| oracle.sign_transaction(&transaction))
+                    .map(|signature| json!({ "signature": signature.to_string() })),
+                None => return (400, json!({ "error": "missing transaction" })),
+            }
+        }
+        _ => return (404, json!({ "error": "not found" })),
+    };
+    match result {
+        Ok(body) => (200, body),
+        Err(error) => (error_status(&error), json!({ "error": error.to_string() })),
+    }
+}
@@ -0,0 +1,154 @@
+//! Pluggable price sources
+
+use crate::OracleError;
+use serde_json::Value;
+use solana_program::pubkey::Pubkey;
+use std::{collections::HashMap, fs, path::PathBuf, str::FromStr};
+
+/// Source of fiat asset prices
+pub trait PriceSource: Send + Sync {
+    /// Price of one fiat asset in collateral token base units
+    fn price(&self, fiat_mint: &Pubkey) -> Result<u64, OracleError>;
+}
+
+/// Fixed prices
+impl PriceSource for HashMap<Pubkey, u64> {
+    fn price(&self, fiat_mint: &Pubkey) -> Result<u64, OracleError> {
+        self.get(fiat_mint).copied().ok_or(OracleError::UnknownAsset(*fiat_mint))
+    }
+}
+
+/// Prices in a local file, read again for every request so it can be updated while the oracle runs.
+///
+/// A `.csv` file has a `<fiat mint>,<price>` line per asset, any other file is a JSON object
+/// `{"<fiat mint>": <price>}`.
+pub struct FileSource {
+    path: PathBuf,
+}
+
+impl FileSource {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Every price of the file
+    pub fn prices(&self) -> Result<HashMap<Pubkey, u64>, OracleError> {
+        let text = fs::read_to_string(&self.path)?;
+        match self.path.extension() {
+            Some(extension) if extension == "csv" => parse_csv_prices(&text),
+            _ => parse_json_prices(&text),
+        }
+    }
+}
+
+impl PriceSource for FileSource {
+    fn price(&self, fiat_mint: &Pubkey) -> Result<u64, OracleError> {
+        self.prices()?.price(fiat_mint)
+    }
+}
+
+/// Prices served over HTTP, `GET <url>/<fiat mint>` returns `{"price": <price>}`
+pub struct HttpSource {
+    url: String,
+}
+
+impl HttpSource {
+    pub fn new(url: &str) -> Self {
+        Self { url: url.trim_end_matches('/').to_string() }
+    }
+}
+
+impl PriceSource for HttpSource {
+    fn price(&self, fiat_mint: &Pubkey) -> Result<u64, OracleError> {
+        let response = match ureq::get(&format!("{}/{}", self.url, fiat_mint)).call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(404, _)) => return Err(OracleError::UnknownAsset(*fiat_mint)),
+            Err(error) => return Err(OracleError::Http(error.to_string())),
+        };
+        let body: Value = serde_json::from_str(&response.into_string()?)
+            .map_err(|error| OracleError::InvalidPrices(error.to_string()))?;
+        body["price"].as_u64().ok_or_else(|| OracleError::InvalidPrices(format!("no price in {}", body)))
+    }
+}
+
+fn parse_mint(value: &str) -> Result<Pubkey, OracleError> {
+    Pubkey::from_str(value.trim()).map_err(|_| OracleError::InvalidPrices(format!("invalid fiat mint `{}`", value)))
+}
+
+/// Parses a `{"<fiat mint>": <price>}` JSON object
+pub fn parse_json_prices(text: &str) -> Result<HashMap<Pubkey, u64>, OracleError> {
+    let value: Value = serde_json::from_str(text).map_err(|error| OracleError::InvalidPrices(error.to_string()))?;
+    let object = value.as_object().ok_or_else(|| OracleError::InvalidPrices("expected an object".to_string()))?;
+    object.iter()
+        .map(|(mint, price)| {
+            let price = price.as_u64().ok_or_else(|| OracleError::InvalidPrices(format!("invalid price {}", price)))?;
+            Ok((parse_mint(mint)?, price))
+        })
+        .collect()
+}
+
+/// Parses `<fiat mint>,<price>` lines, empty lines and `#` comments are skipped
+pub fn parse_csv_prices(text: &str) -> Result<HashMap<Pubkey, u64>, OracleError> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (mint, price) = line.split_once(',').ok_or_else(|| OracleError::InvalidPrices(format!("invalid line `{}`", line)))?;
+            let price = price.trim().parse().map_err(|_| OracleError::InvalidPrices(format!("invalid price `{}`", price)))?;
+            Ok((parse_mint(mint)?, price))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_prices() {
+        let mint1 = Pubkey::new_unique();
+        let mint2 = Pubkey::new_unique();
+
+        let prices = parse_json_prices(&format!(r#"{{"{}": 5000000000, "{}": 20}}"#, mint1, mint2)).unwrap();
+        assert_eq!(prices.price(&mint1).unwrap(), 5_000_000_000);
+        assert_eq!(prices.price(&mint2).unwrap(), 20);
+
+        let prices = parse_csv_prices(&format!("# fiat mint,price\n{},5000000000\n\n{}, 20\n", mint1, mint2)).unwrap();
+        assert_eq!(prices.price(&mint1).unwrap(), 5_000_000_000);
+        assert_eq!(prices.price(&mint2).unwrap(), 20);
+
+        // BadCase: unknown asset
+        let unknown = Pubkey::new_unique();
+        assert!(matches!(prices.price(&unknown), Err(OracleError::UnknownAsset(mint)) if mint == unknown));
+
+        // BadCase: malformed input
+        assert!(parse_json_prices("[1, 2]").is_err());
+        assert!(parse_json_prices(&format!(r#"{{"{}": -1}}"#, mint1)).is_err());
+        assert!(parse_json_prices(r#"{"not a mint": 1}"#).is_err());
+        assert!(parse_csv_prices(&format!("{}", mint1)).is_err());
+        assert!(parse_csv_prices(&format!("{},1.5", mint1)).is_err());
+    }
+
+    #[test]
+    fn test_file_source() {
+        let mint = Pubkey::new_unique();
+        let dir = std::env::temp_dir();
+        let json_path = dir.join(format!("synchronizer-oracle-{}.json", Pubkey::new_unique()));
+        let csv_path = dir.join(format!("synchronizer-oracle-{}.csv", Pubkey::new_unique()));
+        fs::write(&json_path, format!(r#"{{"{}": 7}}"#, mint)).unwrap();
+        fs::write(&csv_path, format!("{},8\n", mint)).unwrap();
+
+        assert_eq!(FileSource::new(&json_path).price(&mint).unwrap(), 7);
+        assert_eq!(FileSource::new(&csv_path).price(&mint).unwrap(), 8);
+
+        // Updated file is read again
+        fs::write(&csv_path, format!("{},9\n", mint)).unwrap();
+        assert_eq!(FileSource::new(&csv_path).price(&mint).unwrap(), 9);
+
+        fs::remove_file(&json_path).unwrap();
+        fs::remove_file(&csv_path).unwrap();
+
+        // BadCase: missing file
+        assert!(matches!(FileSource::new(&json_path).price(&mint), Err(OracleError::Io(_))));
+    }
+}
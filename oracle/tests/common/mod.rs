@@ -0,0 +1,80 @@
+use serde_json::json;
+use solana_program::pubkey::Pubkey;
+use solana_sdk::signature::Keypair;
+use std::{collections::HashMap, fs, path::PathBuf, str::FromStr, sync::Arc, thread};
+use synchronizer_oracle::{server::OracleServer, source::{FileSource, HttpSource}, Oracle};
+use tiny_http::{Response, Server};
+
+/// Stand-in HTTP price feed, `GET /<fiat mint>` returns `{"price": <price>}`
+pub struct MockPriceFeed {
+    server: Arc<Server>,
+    pub url: String,
+}
+
+impl MockPriceFeed {
+    pub fn start(prices: HashMap<Pubkey, u64>) -> Self {
+        let server = Arc::new(Server::http("127.0.0.1:0").unwrap());
+        let url = format!("http://{}", server.server_addr().to_ip().unwrap());
+        let thread_server = server.clone();
+        thread::spawn(move || {
+            for request in thread_server.incoming_requests() {
+                let price = Pubkey::from_str(request.url().trim_start_matches('/')).ok()
+                    .and_then(|fiat_mint| prices.get(&fiat_mint));
+                let response = match price {
+                    Some(price) => Response::from_string(json!({ "price": price }).to_string()),
+                    None => Response::from_string("{}").with_status_code(404),
+                };
+                let _ = request.respond(response);
+            }
+        });
+        Self { server, url }
+    }
+}
+
+impl Drop for MockPriceFeed {
+    fn drop(&mut self) {
+        self.server.unblock();
+    }
+}
+
+/// Three local oracles reading the same prices from a JSON file, a CSV file and an HTTP feed
+pub struct LocalOracles {
+    pub servers: Vec<OracleServer>,
+    pub pubkeys: Vec<Pubkey>,
+    _feed: MockPriceFeed,
+    files: Vec<PathBuf>,
+}
+
+impl LocalOracles {
+    pub fn start(prices: &HashMap<Pubkey, u64>) -> Self {
+        let dir = std::env::temp_dir();
+        let json_path = dir.join(format!("synchronizer-oracle-{}.json", Pubkey::new_unique()));
+        let csv_path = dir.join(format!("synchronizer-oracle-{}.csv", Pubkey::new_unique()));
+        let json_prices: serde_json::Map<String, serde_json::Value> = prices.iter()
+            .map(|(mint, price)| (mint.to_string(), json!(price)))
+            .collect();
+        fs::write(&json_path, serde_json::Value::Object(json_prices).to_string()).unwrap();
+        let csv_prices: String = prices.iter().map(|(mint, price)| format!("{},{}\n", mint, price)).collect();
+        fs::write(&csv_path, csv_prices).unwrap();
+        let feed = MockPriceFeed::start(prices.clone());
+
+        let oracles = vec![
+            Oracle::new(Keypair::new(), FileSource::new(&json_path)),
+            Oracle::new(Keypair::new(), FileSource::new(&csv_path)),
+            Oracle::new(Keypair::new(), HttpSource::new(&feed.url)),
+        ];
+        let pubkeys = oracles.iter().map(|oracle| oracle.pubkey()).collect();
+        let servers = oracles.into_iter()
+            .map(|oracle| OracleServer::start("127.0.0.1:0", oracle).unwrap())
+            .collect();
+        Self { servers, pubkeys, _feed: feed, files: vec![json_path, csv_path] }
+    }
+}
+
+impl Drop for LocalOracles {
+    fn drop(&mut self) {
+        for file in self.files.iter() {
+            let _ = fs::remove_file(file);
+        }
+    }
+}
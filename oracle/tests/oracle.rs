@@ -0,0 +1,69 @@
+mod common;
+
+use common::LocalOracles;
+use solana_program::pubkey::Pubkey;
+use solana_sdk::{hash::Hash, signature::Keypair, signer::Signer, transaction::Transaction};
+use synchronizer::{instruction::TRADE_PROGRAM_ACCOUNTS, processor::id};
+use synchronizer_oracle::{client::OracleClient, OracleError};
+
+#[test]
+fn test_oracle_api() {
+    let fiat_mint = Pubkey::new_unique();
+    let oracles = LocalOracles::start(&vec![(fiat_mint, 5_000_000_000)].into_iter().collect());
+    let clients: Vec<OracleClient> = oracles.servers.iter().map(|server| OracleClient::new(&server.url())).collect();
+
+    for (client, pubkey) in clients.iter().zip(oracles.pubkeys.iter()) {
+        assert_eq!(client.pubkey().unwrap(), *pubkey);
+        let attestation = client.price(&fiat_mint).unwrap();
+        assert_eq!(attestation.oracle, *pubkey);
+        assert_eq!(attestation.price, 5_000_000_000);
+
+        // BadCase: no price for the asset
+        assert!(matches!(client.price(&Pubkey::new_unique()), Err(OracleError::Rejected(_))));
+    }
+
+    // Every oracle co-signs a trade with its price
+    let user = Keypair::new();
+    let synchronizer = Keypair::new();
+    let trade = |prices: Vec<u64>| {
+        let instruction = synchronizer::instruction::buy_for(
+            &id(),
+            5,
+            10,
+            1,
+            &prices,
+            &oracles.pubkeys,
+            &fiat_mint,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &user.pubkey(),
+            &synchronizer.pubkey(),
+            TRADE_PROGRAM_ACCOUNTS,
+            None,
+        ).unwrap();
+        let mut transaction = Transaction::new_with_payer(&[instruction], Some(&user.pubkey()));
+        transaction.partial_sign(&[&user, &synchronizer], Hash::new_unique());
+        transaction
+    };
+    let mut transaction = trade(vec![5_000_000_000, 5_010_000_000, 4_990_000_000]);
+    for (client, pubkey) in clients.iter().zip(oracles.pubkeys.iter()) {
+        client.sign(&mut transaction, pubkey).unwrap();
+    }
+    transaction.verify().unwrap();
+
+    // BadCase: price too far from the oracle price
+    let mut transaction = trade(vec![5_000_000_000, 5_500_000_000, 5_000_000_000]);
+    client_rejects(&clients[1], &mut transaction, &oracles.pubkeys[1]);
+
+    // BadCase: signature placed for another oracle
+    let mut transaction = trade(vec![5_000_000_000; 3]);
+    assert!(matches!(clients[0].sign(&mut transaction, &oracles.pubkeys[1]), Err(OracleError::InvalidSignature)));
+}
+
+fn client_rejects(client: &OracleClient, transaction: &mut Transaction, oracle: &Pubkey) {
+    match client.sign(transaction, oracle) {
+        Err(OracleError::Rejected(error)) => assert!(error.contains("deviates"), "{}", error),
+        result => panic!("unexpected result {:?}", result),
+    }
+}
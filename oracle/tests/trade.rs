@@ -0,0 +1,99 @@
+mod common;
+
+use common::LocalOracles;
+use solana_program::{program_pack::Pack, pubkey::Pubkey, system_instruction};
+use solana_program_test::*;
+use solana_sdk::{signature::Keypair, signer::Signer, transaction::Transaction};
+use synchronizer::processor::{id, Processor};
+use synchronizer_client::{get_associated_token_address, SynchronizerClient, Trade};
+use synchronizer_oracle::client::OracleClient;
+
+async fn create_mint(client: &mut SynchronizerClient, mint: &Keypair, authority: &Pubkey) {
+    let rent = client.banks_client().get_rent().await.unwrap();
+    let instructions = [
+        system_instruction::create_account(
+            &client.payer().pubkey(),
+            &mint.pubkey(),
+            rent.minimum_balance(spl_token::state::Mint::LEN),
+            spl_token::state::Mint::LEN as u64,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::initialize_mint(&spl_token::id(), &mint.pubkey(), authority, None, Processor::DEFAULT_DECIMALS).unwrap(),
+    ];
+    client.process_transaction(&instructions, &[mint]).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_trade_with_local_oracles() {
+    let mut program_test = ProgramTest::new(
+        "synchronizer",
+        id(),
+        processor!(Processor::process_instruction),
+    );
+    program_test.add_program(
+        "spl_associated_token_account",
+        spl_associated_token_account::id(),
+        processor!(spl_associated_token_account::processor::process_instruction),
+    );
+    let (banks_client, payer, _) = program_test.start().await;
+    let synchronizer_key = Keypair::new();
+    let mut client = SynchronizerClient::new(banks_client, payer, synchronizer_key.pubkey());
+    let collateral_owner = Keypair::new();
+    let user = Keypair::new();
+    let decimals = Processor::DEFAULT_DECIMALS;
+
+    // Infrastructure preparing
+    let collateral_token = Keypair::new();
+    create_mint(&mut client, &collateral_token, &collateral_owner.pubkey()).await;
+    let fiat_asset_mint = Keypair::new();
+    create_mint(&mut client, &fiat_asset_mint, &synchronizer_key.pubkey()).await;
+
+    let oracles = LocalOracles::start(&vec![(fiat_asset_mint.pubkey(), spl_token::ui_amount_to_amount(5.0, decimals))].into_iter().collect());
+    let oracle_clients: Vec<OracleClient> = oracles.servers.iter().map(|server| OracleClient::new(&server.url())).collect();
+
+    client.initialize(
+        &synchronizer_key,
+        &collateral_token.pubkey(),
+        spl_token::ui_amount_to_amount(100_000.0, decimals),
+        0,
+        oracles.pubkeys.len() as u8,
+        &oracles.pubkeys,
+    ).await.unwrap();
+    client.create_associated_token_account(&synchronizer_key.pubkey(), &collateral_token.pubkey()).await.unwrap();
+    let user_collateral = client.create_associated_token_account(&user.pubkey(), &collateral_token.pubkey()).await.unwrap();
+    let mint_to = spl_token::instruction::mint_to(
+        &spl_token::id(),
+        &collateral_token.pubkey(),
+        &user_collateral,
+        &collateral_owner.pubkey(),
+        &[],
+        spl_token::ui_amount_to_amount(1_000.0, decimals),
+    ).unwrap();
+    client.process_transaction(&[mint_to], &[&collateral_owner]).await.unwrap();
+
+    // Prices are fetched from every oracle, then each oracle co-signs the trade
+    let prices = oracle_clients.iter()
+        .map(|oracle| oracle.price(&fiat_asset_mint.pubkey()).unwrap().price)
+        .collect();
+    let trade = Trade {
+        fiat_asset_mint: fiat_asset_mint.pubkey(),
+        multiplier: 5,
+        amount: spl_token::ui_amount_to_amount(10.0, decimals),
+        fee: spl_token::ui_amount_to_amount(0.001, decimals),
+        prices,
+        oracles: oracles.pubkeys.clone(),
+        referrer: None,
+        collateral_mint: None,
+    };
+    let instructions = client.buy_for_instructions(&user.pubkey(), &trade).await.unwrap();
+    let recent_blockhash = client.banks_client().get_recent_blockhash().await.unwrap();
+    let mut transaction = Transaction::new_with_payer(&instructions, Some(&client.payer().pubkey()));
+    transaction.try_partial_sign(&vec![client.payer(), &user, &synchronizer_key], recent_blockhash).unwrap();
+    for (oracle, pubkey) in oracle_clients.iter().zip(oracles.pubkeys.iter()) {
+        oracle.sign(&mut transaction, pubkey).unwrap();
+    }
+    client.send_transaction(transaction).await.unwrap();
+
+    let user_fiat = get_associated_token_address(&user.pubkey(), &fiat_asset_mint.pubkey());
+    assert_eq!(client.get_token_account(&user_fiat).await.unwrap().amount, trade.amount);
+}
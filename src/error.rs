@@ -49,6 +49,105 @@ pub enum SynchronizerError {
     /// Exceed limit of maximum signers
     #[error("Exceed limit of maximum signers")]
     MaxSignersExceed,
+    /// Account is not owned by the expected program
+    #[error("Account is not owned by the expected program")]
+    InvalidAccountOwner,
+    /// Invalid token account data
+    #[error("Invalid token account data")]
+    InvalidTokenAccount,
+    /// Token account is not initialized
+    #[error("Token account is not initialized")]
+    UninitializedTokenAccount,
+    /// Token account is frozen
+    #[error("Token account is frozen")]
+    FrozenTokenAccount,
+    /// Token account owner mismatch
+    #[error("Token account owner mismatch")]
+    OwnerMismatch,
+    /// Bad fiat asset mint
+    #[error("Bad fiat asset mint")]
+    BadFiatMint,
+    /// Invalid mint data
+    #[error("Invalid mint data")]
+    InvalidMint,
+    /// Mint is not initialized
+    #[error("Mint is not initialized")]
+    UninitializedMint,
+    /// Invalid token program
+    #[error("Invalid token program")]
+    InvalidTokenProgram,
+    /// Synchronizer account must be migrated to the current layout
+    #[error("Synchronizer account must be migrated to the current layout")]
+    MigrationRequired,
+    /// Synchronizer account already has the current layout
+    #[error("Synchronizer account already has the current layout")]
+    AlreadyMigrated,
+    /// Account type discriminator mismatch
+    #[error("Account type discriminator mismatch")]
+    InvalidAccountType,
+    /// Buy exceeds the maximum outstanding notional of the asset
+    #[error("Buy exceeds the maximum outstanding notional of the asset")]
+    AssetCapExceeded,
+    /// Buy exceeds the maximum transaction notional of the asset
+    #[error("Buy exceeds the maximum transaction notional of the asset")]
+    TransactionCapExceeded,
+    /// Asset account address doesn't match the Synchronizer and the fiat asset mint
+    #[error("Invalid asset account")]
+    InvalidAssetAccount,
+    /// Buy is above the remaining dollar cap of the Synchronizer
+    #[error("Dollar cap exceeded")]
+    DollarCapExceeded,
+    /// Trade exceeds the rolling volume limit of the Synchronizer or the user
+    #[error("Rolling volume limit exceeded")]
+    RateLimitExceeded,
+    /// Rate limit account address doesn't match the Synchronizer
+    #[error("Invalid rate limit account")]
+    InvalidRateLimitAccount,
+    /// User volume account address doesn't match the Synchronizer and the user
+    #[error("Invalid user volume account")]
+    InvalidUserVolumeAccount,
+    /// User position account address doesn't match the Synchronizer, the user and the fiat asset
+    #[error("Invalid user position account")]
+    InvalidUserPositionAccount,
+    /// Trade outside the trading hours of the fiat asset
+    #[error("Market is closed")]
+    MarketClosed,
+    /// Trading hours outside a day or invalid holidays
+    #[error("Invalid market schedule")]
+    InvalidMarketSchedule,
+    /// Market schedule account address doesn't match the Synchronizer and the fiat asset
+    #[error("Invalid market schedule account")]
+    InvalidMarketScheduleAccount,
+    /// Ask spread above the price or bid spread reaching it
+    #[error("Invalid spread")]
+    InvalidSpread,
+    /// Fee tiers out of order or too many tiers or overrides
+    #[error("Invalid fee schedule")]
+    InvalidFeeSchedule,
+    /// Fee schedule account address doesn't match the Synchronizer
+    #[error("Invalid fee schedule account")]
+    InvalidFeeScheduleAccount,
+    /// Referrer account is the Synchronizer collateral account
+    #[error("Invalid referrer account")]
+    InvalidReferrerAccount,
+    /// Trade doesn't pass an optional account required by the Synchronizer
+    #[error("Missing trade account")]
+    MissingTradeAccount,
+    /// Collateral not accepted, already accepted or with fees or a vault balance left to withdraw
+    #[error("Invalid collateral")]
+    InvalidCollateral,
+    /// Zero collateral conversion rate or invalid rate bounds
+    #[error("Invalid collateral rate")]
+    InvalidCollateralRate,
+    /// Collateral set account address doesn't match the Synchronizer
+    #[error("Invalid collateral set account")]
+    InvalidCollateralSetAccount,
+    /// Rate of the accepted collateral is older than its maximum age
+    #[error("Stale collateral rate")]
+    StaleCollateralRate,
+    /// Oracle rate update deviates from the reference rate by more than the collateral allows
+    #[error("Collateral rate deviation exceeded")]
+    CollateralRateDeviationExceeded,
 }
 
 impl From<SynchronizerError> for ProgramError {
@@ -0,0 +1,399 @@
+//! Structured events logged by the instruction handlers
+//!
+//! Every event is logged as `EVENT_LOG_PREFIX` followed by the base64 encoding of
+//! `EVENT_VERSION` and the Borsh encoded `SynchronizerEvent`. New variants are only appended,
+//! so decoders of the same version keep working for older events.
+
+use crate::state::{FeeTier, RateBounds, TradingHours};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{msg, program_error::ProgramError, pubkey::Pubkey};
+
+/// Prefix of event log messages
+pub const EVENT_LOG_PREFIX: &str = "SYNCHRONIZER_EVENT:";
+/// Version byte of the event encoding
+pub const EVENT_VERSION: u8 = 1;
+
+/// Prefix the runtime adds to messages logged by a program
+const PROGRAM_LOG_PREFIX: &str = "Program log: ";
+
+/// Events of the Synchronizer program
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+pub enum SynchronizerEvent {
+    /// User bought fiat assets
+    BuyExecuted {
+        synchronizer: Pubkey,
+        user: Pubkey,
+        fiat_asset_mint: Pubkey,
+        /// Fiat asset amount minted to the user
+        amount: u64,
+        price: u64,
+        collateral_amount: u64,
+        fee_amount: u64,
+        /// Decrease of the remaining dollar cap
+        dollar_cap_amount: u64,
+        remaining_dollar_cap: u64,
+    },
+
+    /// User sold fiat assets
+    SellExecuted {
+        synchronizer: Pubkey,
+        user: Pubkey,
+        fiat_asset_mint: Pubkey,
+        /// Fiat asset amount burned from the user
+        amount: u64,
+        price: u64,
+        collateral_amount: u64,
+        fee_amount: u64,
+        /// Increase of the remaining dollar cap
+        dollar_cap_amount: u64,
+        remaining_dollar_cap: u64,
+    },
+
+    /// Synchronizer account initialized
+    SynchronizerInitialized {
+        synchronizer: Pubkey,
+        collateral_token_key: Pubkey,
+        remaining_dollar_cap: u64,
+        withdrawable_fee_amount: u64,
+        minimum_required_signature: u8,
+        oracles: Vec<Pubkey>,
+    },
+
+    /// Minimum required oracle signatures changed
+    MinimumRequiredSignatureChanged {
+        synchronizer: Pubkey,
+        minimum_required_signature: u8,
+    },
+
+    /// Collateral token changed
+    CollateralTokenChanged {
+        synchronizer: Pubkey,
+        collateral_token_key: Pubkey,
+    },
+
+    /// Remaining dollar cap set by the admin
+    CapChanged {
+        synchronizer: Pubkey,
+        remaining_dollar_cap: u64,
+    },
+
+    /// Fee withdrawn from the vault
+    FeeWithdrawn {
+        synchronizer: Pubkey,
+        recipient: Pubkey,
+        amount: u64,
+        withdrawable_fee_amount: u64,
+    },
+
+    /// Collateral withdrawn from the vault
+    CollateralWithdrawn {
+        synchronizer: Pubkey,
+        recipient: Pubkey,
+        amount: u64,
+    },
+
+    /// Oracle list replaced
+    OraclesChanged {
+        synchronizer: Pubkey,
+        oracles: Vec<Pubkey>,
+    },
+
+    /// Synchronizer account migrated to the current layout
+    SynchronizerMigrated {
+        synchronizer: Pubkey,
+        new_synchronizer: Pubkey,
+        from_version: u8,
+    },
+
+    /// Per-asset caps set by the admin
+    AssetCapsChanged {
+        synchronizer: Pubkey,
+        fiat_asset_mint: Pubkey,
+        max_outstanding_notional: u64,
+        max_transaction_notional: u64,
+    },
+
+    /// Rolling volume limits set by the admin
+    RateLimitsChanged {
+        synchronizer: Pubkey,
+        window_slots: u64,
+        max_synchronizer_notional: u64,
+        max_user_notional: u64,
+    },
+
+    /// Position account created by a user
+    UserPositionCreated {
+        synchronizer: Pubkey,
+        user: Pubkey,
+        fiat_asset_mint: Pubkey,
+    },
+
+    /// Position account closed by a user, its rent returned to `recipient`
+    UserPositionClosed {
+        synchronizer: Pubkey,
+        user: Pubkey,
+        fiat_asset_mint: Pubkey,
+        recipient: Pubkey,
+    },
+
+    /// Trading hours and holidays of a fiat asset set by the admin
+    MarketScheduleChanged {
+        synchronizer: Pubkey,
+        fiat_asset_mint: Pubkey,
+        weekly_hours: [TradingHours; 7],
+        holidays: Vec<u32>,
+    },
+
+    /// Per-asset spreads set by the admin
+    AssetSpreadsChanged {
+        synchronizer: Pubkey,
+        fiat_asset_mint: Pubkey,
+        ask_spread_bps: u16,
+        bid_spread_bps: u16,
+    },
+
+    /// Fee tiers set by the admin
+    FeeTiersChanged {
+        synchronizer: Pubkey,
+        tiers: Vec<FeeTier>,
+        volume_window_slots: u64,
+    },
+
+    /// Fee rate of a whitelisted user set by the admin, `None` when removed
+    FeeOverrideChanged {
+        synchronizer: Pubkey,
+        user: Pubkey,
+        fee: Option<u64>,
+    },
+
+    /// Referral share of the trade fees set by the admin
+    ReferralShareChanged {
+        synchronizer: Pubkey,
+        referral_share_bps: u16,
+    },
+
+    /// Part of the fee of a trade paid to its referrer collateral account, emitted after the trade event
+    ReferralFeePaid {
+        synchronizer: Pubkey,
+        user: Pubkey,
+        referrer: Pubkey,
+        fiat_asset_mint: Pubkey,
+        amount: u64,
+    },
+
+    /// Collateral token accepted by the admin
+    CollateralAdded {
+        synchronizer: Pubkey,
+        collateral_mint: Pubkey,
+        vault: Pubkey,
+        decimals: u8,
+        rate: u64,
+        rate_bounds: RateBounds,
+    },
+
+    /// Collateral token no longer accepted
+    CollateralRemoved {
+        synchronizer: Pubkey,
+        collateral_mint: Pubkey,
+    },
+
+    /// Rate of an accepted collateral set by the admin or attested by the oracles
+    CollateralRateUpdated {
+        synchronizer: Pubkey,
+        collateral_mint: Pubkey,
+        rate: u64,
+        /// Oracles attesting the rate, empty when set by the admin
+        oracles: Vec<Pubkey>,
+    },
+
+    /// Trade settled in an accepted collateral, emitted after the trade event whose amounts are
+    /// in the Synchronizer collateral token. Amounts are in base units of the accepted collateral.
+    CollateralSettled {
+        synchronizer: Pubkey,
+        user: Pubkey,
+        fiat_asset_mint: Pubkey,
+        collateral_mint: Pubkey,
+        /// `true` for a buy, `false` for a sell
+        buy: bool,
+        /// Collateral paid by the user for a buy, or received by the user for a sell
+        user_collateral_amount: u64,
+        fee_amount: u64,
+        /// Part of the fee paid to the referrer collateral account
+        referral_amount: u64,
+        withdrawable_fee_amount: u64,
+    },
+
+    /// Fees of an accepted collateral withdrawn from its vault
+    CollateralFeeWithdrawn {
+        synchronizer: Pubkey,
+        collateral_mint: Pubkey,
+        recipient: Pubkey,
+        amount: u64,
+        withdrawable_fee_amount: u64,
+    },
+
+    /// Accepted collateral withdrawn from its vault
+    AcceptedCollateralWithdrawn {
+        synchronizer: Pubkey,
+        collateral_mint: Pubkey,
+        recipient: Pubkey,
+        amount: u64,
+    },
+}
+
+impl SynchronizerEvent {
+    /// Base64 encoded version and event
+    pub fn encode(&self) -> String {
+        let mut data = vec![EVENT_VERSION];
+        // Serialization into a Vec can't fail
+        self.serialize(&mut data).unwrap();
+        base64::encode(data)
+    }
+
+    /// Decodes an event encoded by `encode`
+    pub fn decode(encoded: &str) -> Result<Self, ProgramError> {
+        let data = base64::decode(encoded).map_err(|_| ProgramError::InvalidArgument)?;
+        let (&version, rest) = data.split_first().ok_or(ProgramError::InvalidArgument)?;
+        if version != EVENT_VERSION {
+            return Err(ProgramError::InvalidArgument);
+        }
+        Self::try_from_slice(rest).map_err(|_| ProgramError::InvalidArgument)
+    }
+
+    /// Message logged by `emit`
+    pub fn log_message(&self) -> String {
+        format!("{}{}", EVENT_LOG_PREFIX, self.encode())
+    }
+
+    /// Logs the event
+    pub fn emit(&self) {
+        msg!("{}", self.log_message());
+    }
+
+    /// Decodes an event from a message logged by `emit`, `None` for other messages
+    pub fn from_log_message(message: &str) -> Option<Result<Self, ProgramError>> {
+        message.strip_prefix(EVENT_LOG_PREFIX).map(Self::decode)
+    }
+}
+
+/// Decodes events logged by `program_id` from transaction logs.
+///
+/// Messages are attributed to the program on top of the invocation stack, so events logged by
+/// other programs, including programs invoked by the Synchronizer, are ignored.
+/// Logs of failed transactions should not be passed: their effects were rolled back.
+pub fn parse_logs<S: AsRef<str>>(program_id: &Pubkey, logs: &[S]) -> Result<Vec<SynchronizerEvent>, ProgramError> {
+    let program_id = program_id.to_string();
+    let mut stack: Vec<&str> = Vec::new();
+    let mut events = Vec::new();
+    for line in logs.iter().map(AsRef::as_ref) {
+        if let Some(message) = line.strip_prefix(PROGRAM_LOG_PREFIX) {
+            if stack.last() == Some(&program_id.as_str()) {
+                if let Some(event) = SynchronizerEvent::from_log_message(message) {
+                    events.push(event?);
+                }
+            }
+            continue;
+        }
+
+        let mut words = line.split(' ');
+        if let (Some("Program"), Some(program), Some(status)) = (words.next(), words.next(), words.next()) {
+            match status {
+                "invoke" => stack.push(program),
+                "success" | "failed:" if stack.last() == Some(&program) => {
+                    stack.pop();
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_event_encoding() {
+        let events = vec![
+            SynchronizerEvent::BuyExecuted {
+                synchronizer: Pubkey::new_unique(),
+                user: Pubkey::new_unique(),
+                fiat_asset_mint: Pubkey::new_unique(),
+                amount: 10,
+                price: 5,
+                collateral_amount: 50,
+                fee_amount: 1,
+                dollar_cap_amount: 250,
+                remaining_dollar_cap: 1000,
+            },
+            SynchronizerEvent::OraclesChanged {
+                synchronizer: Pubkey::new_unique(),
+                oracles: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+            },
+            SynchronizerEvent::SynchronizerMigrated {
+                synchronizer: Pubkey::new_unique(),
+                new_synchronizer: Pubkey::new_unique(),
+                from_version: 2,
+            },
+        ];
+        for event in events {
+            assert_eq!(SynchronizerEvent::decode(&event.encode()).unwrap(), event);
+            assert_eq!(SynchronizerEvent::from_log_message(&event.log_message()).unwrap().unwrap(), event);
+        }
+
+        let event = SynchronizerEvent::CapChanged { synchronizer: Pubkey::new_unique(), remaining_dollar_cap: 7 };
+        let mut data = base64::decode(event.encode()).unwrap();
+        assert_eq!(data[0], EVENT_VERSION);
+        // Variant index follows the version byte
+        assert_eq!(data[1], 5);
+
+        // BadCase: unknown version
+        data[0] = EVENT_VERSION + 1;
+        assert_eq!(SynchronizerEvent::decode(&base64::encode(&data)).unwrap_err(), ProgramError::InvalidArgument);
+
+        // BadCase: not base64 or truncated
+        assert_eq!(SynchronizerEvent::decode("!").unwrap_err(), ProgramError::InvalidArgument);
+        assert_eq!(SynchronizerEvent::decode(&base64::encode(&data[..10])).unwrap_err(), ProgramError::InvalidArgument);
+        assert_eq!(SynchronizerEvent::decode("").unwrap_err(), ProgramError::InvalidArgument);
+
+        // Other messages aren't events
+        assert!(SynchronizerEvent::from_log_message("Instruction: BuyFor").is_none());
+    }
+
+    #[test]
+    fn test_parse_logs() {
+        let program_id = Pubkey::new_unique();
+        let other_program = Pubkey::new_unique();
+        let first = SynchronizerEvent::CapChanged { synchronizer: Pubkey::new_unique(), remaining_dollar_cap: 7 };
+        let second = SynchronizerEvent::CollateralWithdrawn { synchronizer: Pubkey::new_unique(), recipient: Pubkey::new_unique(), amount: 3 };
+        let spoofed = SynchronizerEvent::CapChanged { synchronizer: Pubkey::new_unique(), remaining_dollar_cap: 0 };
+
+        let logs = vec![
+            format!("Program {} invoke [1]", program_id),
+            "Program log: Instruction: SetRemainingDollarCap".to_string(),
+            format!("Program log: {}", first.log_message()),
+            format!("Program {} consumed 2000 of 200000 compute units", program_id),
+            format!("Program {} success", program_id),
+            // Events logged by other programs are ignored
+            format!("Program {} invoke [1]", other_program),
+            format!("Program log: {}", spoofed.log_message()),
+            format!("Program {} success", other_program),
+            format!("Program {} invoke [1]", program_id),
+            format!("Program {} invoke [2]", other_program),
+            format!("Program log: {}", spoofed.log_message()),
+            format!("Program {} success", other_program),
+            format!("Program log: {}", second.log_message()),
+            format!("Program {} success", program_id),
+        ];
+        assert_eq!(parse_logs(&program_id, &logs).unwrap(), vec![first, second]);
+
+        // BadCase: malformed event of the program
+        let logs = vec![
+            format!("Program {} invoke [1]", program_id),
+            format!("Program log: {}AAAA", EVENT_LOG_PREFIX),
+            format!("Program {} success", program_id),
+        ];
+        assert_eq!(parse_logs(&program_id, &logs).unwrap_err(), ProgramError::InvalidArgument);
+    }
+}
@@ -1,52 +1,107 @@
 //! Instructions supported by the Synchronizer.
 
-use crate::{error::SynchronizerError, processor::check_program_account};
-use solana_program::{instruction::{AccountMeta, Instruction}, program_error::ProgramError, pubkey::Pubkey, sysvar};
-use std::{mem::size_of, convert::TryInto};
+use crate::{error::SynchronizerError, processor::check_program_account, state::{AssetData, CollateralSetData, FeeScheduleData, FeeTier, MarketScheduleData, RateBounds, RateLimitData, TradingHours, UserPositionData, UserVolumeData}};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{instruction::{AccountMeta, Instruction}, program_error::ProgramError, pubkey::Pubkey, system_program, sysvar};
+use std::convert::TryInto;
 
 /// Maximum known oracles authorities
 pub const MAX_ORACLES: usize = 3;
 /// Maximum oracles signs in transaction
 pub const MAX_SIGNERS: u8 = 3;
+/// Number of accounts of `BuyFor`/`SellFor` before their optional accounts
+pub const TRADE_FIXED_ACCOUNTS: usize = 7;
+/// Optional account flags of `BuyFor`/`SellFor`, see `SynchronizerInstruction::BuyFor`
+pub const TRADE_ASSET_ACCOUNT: u8 = 1;
+pub const TRADE_RATE_LIMIT_ACCOUNT: u8 = 1 << 1;
+/// The user volume account, followed by the system program creating it
+pub const TRADE_USER_VOLUME_ACCOUNT: u8 = 1 << 2;
+pub const TRADE_USER_POSITION_ACCOUNT: u8 = 1 << 3;
+pub const TRADE_MARKET_SCHEDULE_ACCOUNT: u8 = 1 << 4;
+pub const TRADE_FEE_SCHEDULE_ACCOUNT: u8 = 1 << 5;
+pub const TRADE_REFERRER_ACCOUNT: u8 = 1 << 6;
+pub const TRADE_COLLATERAL_SET_ACCOUNT: u8 = 1 << 7;
+/// Every optional program derived account of `BuyFor`/`SellFor`
+pub const TRADE_PROGRAM_ACCOUNTS: u8 = TRADE_ASSET_ACCOUNT | TRADE_RATE_LIMIT_ACCOUNT | TRADE_USER_VOLUME_ACCOUNT
+    | TRADE_USER_POSITION_ACCOUNT | TRADE_MARKET_SCHEDULE_ACCOUNT | TRADE_FEE_SCHEDULE_ACCOUNT | TRADE_COLLATERAL_SET_ACCOUNT;
+/// First byte of Borsh encoded instruction data.
+/// Instruction data starting with any other byte is decoded with the legacy layout (tags 0-8).
+pub const BORSH_INSTRUCTION_TAG: u8 = 0xFF;
 
 /// Instructions supported by the Synchronizer
 #[repr(C)]
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
 pub enum SynchronizerInstruction {
     /// User buys fiat asset for collateral tokens
     /// Accounts expected by this instruction:
     ///
     /// 0. `[writable]` The mint account of fiat asset
-    /// 1. `[writable]` The user collateral token associated account (user source)
+    /// 1. `[writable]` The user collateral token associated account of the settled collateral (user source)
     /// 2. `[writable]` The user fiat asset token associated account (user destination)
-    /// 3. `[writable]` The Synchronizer collateral token associated account (Synchronizer destination)
-    /// 4. `[signer]` The user pubkey authority
+    /// 3. `[writable]` The Synchronizer collateral token associated account, or the vault of the accepted
+    ///    collateral to settle in (Synchronizer destination)
+    /// 4. `[writable, signer]` The user pubkey authority, pays the user volume account rent
     /// 5. `[writable, signer]` The Synchronizer account authority
     /// 6. `[]` Token program
-    /// 7. `[]` N Oracles authority
+    /// 7. `[]` Clock sysvar, only when `optional_accounts` flags any account
+    ///
+    ///    Then the accounts flagged in `optional_accounts`, in this order:
+    ///    * `[writable]` The asset account of the Synchronizer and fiat asset, see `AssetData::find_address`
+    ///    * `[writable]` The rate limit account of the Synchronizer, see `RateLimitData::find_address`
+    ///    * `[writable]` The volume account of the user, see `UserVolumeData::find_address`
+    ///    * `[]` System program, with the user volume account
+    ///    * `[writable]` The position account of the user in the fiat asset, see `UserPositionData::find_address`
+    ///    * `[]` The market schedule account of the fiat asset, see `MarketScheduleData::find_address`
+    ///    * `[]` The fee schedule account of the Synchronizer, see `FeeScheduleData::find_address`
+    ///    * `[writable]` The referrer collateral token account
+    ///    * `[writable]` The collateral set account of the Synchronizer, see `CollateralSetData::find_address`
+    ///
+    ///    Then N Oracles authority `[]`
+    ///
+    /// Trades must flag the accounts in `SynchronizerData::required_trade_accounts`.
     BuyFor {
         multiplier: u64,
         amount: u64,
         fee: u64,
         prices: Vec<u64>,
+        /// `TRADE_*_ACCOUNT` flags of the optional accounts passed, zero in the legacy layout
+        optional_accounts: u8,
     },
 
     /// User sells fiat assets for collateral tokens
     ///
     /// Accounts expected by this instruction:
     /// 0. `[writable]` The mint account of fiat asset
-    /// 1. `[writable]` The user collateral token associated account (user destination)
+    /// 1. `[writable]` The user collateral token associated account of the settled collateral (user destination)
     /// 2. `[writable]` The user fiat asset token associated account (user source)
-    /// 3. `[writable]` The Synchronizer collateral token associated account (Synchronizer source)
-    /// 4. `[signer]` The user pubkey authority
+    /// 3. `[writable]` The Synchronizer collateral token associated account, or the vault of the accepted
+    ///    collateral to settle in (Synchronizer source)
+    /// 4. `[writable, signer]` The user pubkey authority, pays the user volume account rent
     /// 5. `[writable, signer]` The Synchronizer account authority
     /// 6. `[]` Token program
-    /// 7. `[]` N Oracles authority
+    /// 7. `[]` Clock sysvar, only when `optional_accounts` flags any account
+    ///
+    ///    Then the accounts flagged in `optional_accounts`, in this order:
+    ///    * `[writable]` The asset account of the Synchronizer and fiat asset, see `AssetData::find_address`
+    ///    * `[writable]` The rate limit account of the Synchronizer, see `RateLimitData::find_address`
+    ///    * `[writable]` The volume account of the user, see `UserVolumeData::find_address`
+    ///    * `[]` System program, with the user volume account
+    ///    * `[writable]` The position account of the user in the fiat asset, see `UserPositionData::find_address`
+    ///    * `[]` The market schedule account of the fiat asset, see `MarketScheduleData::find_address`
+    ///    * `[]` The fee schedule account of the Synchronizer, see `FeeScheduleData::find_address`
+    ///    * `[writable]` The referrer collateral token account
+    ///    * `[writable]` The collateral set account of the Synchronizer, see `CollateralSetData::find_address`
+    ///
+    ///    Then N Oracles authority `[]`
+    ///
+    /// Trades must flag the accounts in `SynchronizerData::required_trade_accounts`.
     SellFor {
         multiplier: u64,
         amount: u64,
         fee: u64,
         prices: Vec<u64>,
+        /// `TRADE_*_ACCOUNT` flags of the optional accounts passed, zero in the legacy layout
+        optional_accounts: u8,
     },
 
     /// Initialization of Synchronizer account
@@ -54,6 +109,8 @@ pub enum SynchronizerInstruction {
     ///
     /// 0. `[writable, signer]` The Synchronizer account authority
     /// 1. `[]` Rent sysvar
+    /// 2. `[]` Optional, the collateral token mint, its decimals are stored in the Synchronizer account.
+    ///    Accounts initialized without it assume 9 decimals until `SetCollateralToken`.
     InitializeSynchronizerAccount {
         collateral_token_key: Pubkey,
         remaining_dollar_cap: u64,
@@ -74,6 +131,8 @@ pub enum SynchronizerInstruction {
     ///
     /// Accounts expected by this instruction:
     /// 0. `[signer]` The Synchronizer account authority
+    /// 1. `[]` The new collateral token mint, its decimals are stored in the Synchronizer account. Changing the
+    ///    decimals requires the remaining dollar cap and the withdrawable fee amount to be zero
     SetCollateralToken {
         collateral_token_key: Pubkey
     },
@@ -86,13 +145,16 @@ pub enum SynchronizerInstruction {
         remaining_dollar_cap: u64
     },
 
-    /// Withdraw fee from Synchronizer account to recipient account
+    /// Withdraw fee from Synchronizer account to recipient account.
+    /// Fees of an accepted collateral are withdrawn from its vault.
     ///
     /// Accounts expected by this instruction:
-    /// 0. `[writable]` The Synchronizer collateral token associated account (source)
+    /// 0. `[writable]` The Synchronizer collateral token associated account, or the vault of an accepted collateral (source)
     /// 1. `[writable]` recipient collateral token associated account (detination)
     /// 2. `[writable, signer]` The Synchronizer account authority
     /// 3. `[]` Token program
+    /// 4. `[writable]` Optional, the collateral set account of the Synchronizer, see `CollateralSetData::find_address`.
+    ///    Withdrawals without it are from the Synchronizer collateral token associated account.
     WithdrawFee {
         amount: u64
     },
@@ -100,10 +162,12 @@ pub enum SynchronizerInstruction {
     /// Withdraw collateral from Synchronizer account to recipient account
     ///
     /// Accounts expected by this instruction:
-    /// 0. `[writable]` The Synchronizer collateral token associated account (source)
+    /// 0. `[writable]` The Synchronizer collateral token associated account, or the vault of an accepted collateral (source)
     /// 1. `[writable]` recipient collateral token associated account (detination)
     /// 2. `[writable, signer]` The Synchronizer account authority
     /// 3. `[]` Token program
+    /// 4. `[writable]` Optional, the collateral set account of the Synchronizer, see `CollateralSetData::find_address`.
+    ///    Withdrawals without it are from the Synchronizer collateral token associated account.
     WithdrawCollateral {
         amount: u64
     },
@@ -114,269 +178,307 @@ pub enum SynchronizerInstruction {
     /// 0. `[writable, signer]` The Synchronizer account authority
     SetOracles {
         oracles: Vec<Pubkey>,
-    }
+    },
+
+    /// Migrate Synchronizer account data to the current layout version.
+    /// Data is copied into the new account, authority over the given token accounts and mints
+    /// is moved to the new account and the old account is closed.
+    ///
+    /// Accounts expected by this instruction:
+    /// 0. `[writable, signer]` The Synchronizer account authority
+    /// 1. `[writable, signer]` The new Synchronizer account authority, rent exempt and not initialized
+    /// 2. `[]` Rent sysvar
+    /// 3. `[]` Token program
+    /// 4. `[writable]` N Synchronizer token accounts and fiat asset mints
+    MigrateSynchronizerAccount,
+
+    /// Set per-asset caps of a fiat asset, creating its asset account on first use.
+    /// Caps are collateral notional amounts, the outstanding notional is kept.
+    /// Trades must pass the asset account from then on.
+    ///
+    /// Accounts expected by this instruction:
+    /// 0. `[writable, signer]` The Synchronizer account authority
+    /// 1. `[writable]` The asset account of the Synchronizer and fiat asset, see `AssetData::find_address`
+    /// 2. `[writable, signer]` Payer of the asset account rent
+    /// 3. `[]` System program
+    /// 4. `[]` Rent sysvar
+    SetAssetCaps {
+        fiat_asset_mint: Pubkey,
+        max_outstanding_notional: u64,
+        max_transaction_notional: u64,
+    },
+
+    /// Set rolling volume limits of buys and sells, creating the rate limit account on first use.
+    /// Limits are collateral notional amounts traded in the last `window_slots` slots, on the
+    /// Synchronizer and by each user. A zero limit is no limit, a zero window disables both.
+    /// Trades must pass the rate limit and user volume accounts from then on.
+    ///
+    /// Accounts expected by this instruction:
+    /// 0. `[writable, signer]` The Synchronizer account authority
+    /// 1. `[writable]` The rate limit account of the Synchronizer, see `RateLimitData::find_address`
+    /// 2. `[writable, signer]` Payer of the rate limit account rent
+    /// 3. `[]` System program
+    /// 4. `[]` Rent sysvar
+    SetRateLimits {
+        window_slots: u64,
+        max_synchronizer_notional: u64,
+        max_user_notional: u64,
+    },
+
+    /// Create the position account of the user in a fiat asset. `BuyFor` and `SellFor` record
+    /// the trades of the user in the position account while it exists.
+    ///
+    /// Accounts expected by this instruction:
+    /// 0. `[writable, signer]` The user pubkey authority, pays the position account rent
+    /// 1. `[]` The Synchronizer account
+    /// 2. `[writable]` The position account of the user, see `UserPositionData::find_address`
+    /// 3. `[]` System program
+    /// 4. `[]` Rent sysvar
+    CreateUserPosition {
+        fiat_asset_mint: Pubkey,
+    },
+
+    /// Close the position account of the user in a fiat asset, returning its rent
+    ///
+    /// Accounts expected by this instruction:
+    /// 0. `[signer]` The user pubkey authority
+    /// 1. `[]` The Synchronizer account
+    /// 2. `[writable]` The position account of the user, see `UserPositionData::find_address`
+    /// 3. `[writable]` Recipient of the position account lamports
+    CloseUserPosition {
+        fiat_asset_mint: Pubkey,
+    },
+
+    /// Set the trading hours and holidays of a fiat asset, creating its market schedule account on
+    /// first use. `BuyFor` and `SellFor` fail outside the trading hours and on holidays.
+    /// Hours are seconds since UTC midnight from Monday to Sunday, holidays are days since 1970-01-01.
+    /// Trades must pass the market schedule account from then on.
+    ///
+    /// Accounts expected by this instruction:
+    /// 0. `[writable, signer]` The Synchronizer account authority
+    /// 1. `[writable]` The market schedule account of the fiat asset, see `MarketScheduleData::find_address`
+    /// 2. `[writable, signer]` Payer of the market schedule account rent
+    /// 3. `[]` System program
+    /// 4. `[]` Rent sysvar
+    SetMarketSchedule {
+        fiat_asset_mint: Pubkey,
+        weekly_hours: [TradingHours; 7],
+        holidays: Vec<u32>,
+    },
+
+    /// Set the spreads of a fiat asset in basis points of the price, creating its asset account
+    /// on first use without caps. `BuyFor` adds the ask spread to the aggregated oracle price and
+    /// `SellFor` takes the bid spread from it, before the fee. Trades must pass the asset account from then on.
+    ///
+    /// Accounts expected by this instruction:
+    /// 0. `[writable, signer]` The Synchronizer account authority
+    /// 1. `[writable]` The asset account of the Synchronizer and fiat asset, see `AssetData::find_address`
+    /// 2. `[writable, signer]` Payer of the asset account rent
+    /// 3. `[]` System program
+    /// 4. `[]` Rent sysvar
+    SetAssetSpreads {
+        fiat_asset_mint: Pubkey,
+        ask_spread_bps: u16,
+        bid_spread_bps: u16,
+    },
+
+    /// Replace the volume tiers of the fee schedule and their volume window, creating the fee schedule
+    /// account on first use. `BuyFor` and `SellFor` charge the fee rate of the highest tier reached by
+    /// the user volume in the last `volume_window_slots` slots when it's below the fee rate signed by the oracles.
+    /// Trades must pass the fee schedule and user volume accounts from then on.
+    ///
+    /// Accounts expected by this instruction:
+    /// 0. `[writable, signer]` The Synchronizer account authority
+    /// 1. `[writable]` The fee schedule account of the Synchronizer, see `FeeScheduleData::find_address`
+    /// 2. `[writable, signer]` Payer of the fee schedule account rent
+    /// 3. `[]` System program
+    /// 4. `[]` Rent sysvar
+    SetFeeTiers {
+        tiers: Vec<FeeTier>,
+        volume_window_slots: u64,
+    },
+
+    /// Set the fee rate of a whitelisted user, used instead of the tiers, or remove it with `None`.
+    /// Creates the fee schedule account on first use, trades must pass it and the user volume account from then on.
+    ///
+    /// Accounts expected by this instruction:
+    /// 0. `[writable, signer]` The Synchronizer account authority
+    /// 1. `[writable]` The fee schedule account of the Synchronizer, see `FeeScheduleData::find_address`
+    /// 2. `[writable, signer]` Payer of the fee schedule account rent
+    /// 3. `[]` System program
+    /// 4. `[]` Rent sysvar
+    SetFeeOverride {
+        user: Pubkey,
+        fee: Option<u64>,
+    },
+
+    /// Set the share of the trade fees paid to the referrer of `BuyFor` and `SellFor`, in basis points.
+    /// The Synchronizer collateral account pays the referral, the rest accrues to the withdrawable fees.
+    /// Creates the fee schedule account on first use, trades must pass it and the user volume account from then on.
+    ///
+    /// Accounts expected by this instruction:
+    /// 0. `[writable, signer]` The Synchronizer account authority
+    /// 1. `[writable]` The fee schedule account of the Synchronizer, see `FeeScheduleData::find_address`
+    /// 2. `[writable, signer]` Payer of the fee schedule account rent
+    /// 3. `[]` System program
+    /// 4. `[]` Rent sysvar
+    SetReferralShare {
+        referral_share_bps: u16,
+    },
+
+    /// Accept a collateral token in `BuyFor` and `SellFor`, creating the collateral set account on first use.
+    /// Trades passing its vault are settled in the collateral, converting amounts at `rate`, the price of
+    /// one collateral token in Synchronizer collateral token base units. Trades fail once the rate is older
+    /// than the maximum age of `rate_bounds`.
+    ///
+    /// Accounts expected by this instruction:
+    /// 0. `[signer]` The Synchronizer account authority
+    /// 1. `[writable]` The collateral set account of the Synchronizer, see `CollateralSetData::find_address`
+    /// 2. `[writable, signer]` Payer of the collateral set account rent
+    /// 3. `[]` System program
+    /// 4. `[]` Rent sysvar
+    /// 5. `[]` The collateral token mint
+    /// 6. `[]` The collateral token account of the Synchronizer receiving and paying the trades (vault)
+    /// 7. `[]` Clock sysvar
+    AddCollateral {
+        rate: u64,
+        rate_bounds: RateBounds,
+    },
+
+    /// Stop accepting a collateral token, its fees must have been withdrawn and its vault emptied
+    ///
+    /// Accounts expected by this instruction:
+    /// 0. `[signer]` The Synchronizer account authority
+    /// 1. `[writable]` The collateral set account of the Synchronizer, see `CollateralSetData::find_address`
+    /// 2. `[]` The vault of the collateral
+    RemoveCollateral {
+        collateral_mint: Pubkey,
+    },
+
+    /// Set the rate of an accepted collateral, signed by the Synchronizer account or attested by
+    /// `minimum_required_signature` distinct oracles, at least one. Oracle rates must be within the maximum
+    /// deviation of the collateral rate bounds from the rate last set by the Synchronizer account.
+    ///
+    /// Accounts expected by this instruction:
+    /// 0. `[signer]` The Synchronizer account authority, signer unless the oracles attest the rate
+    /// 1. `[writable]` The collateral set account of the Synchronizer, see `CollateralSetData::find_address`
+    /// 2. `[]` Clock sysvar
+    /// 3. ..3+N. `[signer]` N oracle authorities, none when the Synchronizer account signs
+    UpdateCollateralRate {
+        collateral_mint: Pubkey,
+        rate: u64,
+    },
 }
 
 impl SynchronizerInstruction {
     /// Unpacks a byte buffer into a SynchronizerInstruction.
     pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        let (&tag, rest) = input.split_first().ok_or(SynchronizerError::InvalidInstruction)?;
+        if tag == BORSH_INSTRUCTION_TAG {
+            return Self::try_from_slice(rest).map_err(|_| SynchronizerError::InvalidInstruction.into());
+        }
+        Self::unpack_legacy(input)
+    }
+
+    /// Unpacks a byte buffer encoded with the legacy layout into a SynchronizerInstruction.
+    fn unpack_legacy(input: &[u8]) -> Result<Self, ProgramError> {
         use SynchronizerError::InvalidInstruction;
 
         let (&tag, rest) = input.split_first().ok_or(InvalidInstruction)?;
-        Ok(match tag {
+        let (instruction, rest) = match tag {
             // Public Instructions
             0 | 1 => {
-                let (multiplier, rest) = rest.split_at(8);
-                let multiplier = multiplier
-                    .try_into()
-                    .ok()
-                    .map(u64::from_le_bytes)
-                    .ok_or(InvalidInstruction)?;
-
-                let (amount, rest) = rest.split_at(8);
-                let amount = amount
-                    .try_into()
-                    .ok()
-                    .map(u64::from_le_bytes)
-                    .ok_or(InvalidInstruction)?;
-
-                let (fee, rest) = rest.split_at(8);
-                let fee = fee
-                    .try_into()
-                    .ok()
-                    .map(u64::from_le_bytes)
-                    .ok_or(InvalidInstruction)?;
-
-                let (&prices_num, rest) = rest.split_first().ok_or(InvalidInstruction)?;
-                let mut prices = Vec::with_capacity(prices_num as usize);
-                let (price_slice, _rest) = rest.split_at(prices_num as usize * 8);
-                for i in 0..prices_num {
-                    let price = price_slice
-                        .get(i as usize * 8 .. i as usize * 8 + 8)
-                        .and_then(|slice| slice.try_into().ok())
-                        .map(u64::from_le_bytes)
-                        .ok_or(InvalidInstruction)?;
-                    prices.push(price);
-                }
-
-                match tag {
-                    0 => Self::BuyFor {multiplier, amount, fee, prices},
-                    1 => Self::SellFor {multiplier, amount, fee, prices},
-                    _ => unreachable!(),
-                }
+                let (multiplier, rest) = Self::unpack_u64(rest)?;
+                let (amount, rest) = Self::unpack_u64(rest)?;
+                let (fee, rest) = Self::unpack_u64(rest)?;
+                let (prices, rest) = Self::unpack_u64_vec(rest)?;
+
+                let instruction = match tag {
+                    0 => Self::BuyFor {multiplier, amount, fee, prices, optional_accounts: 0},
+                    _ => Self::SellFor {multiplier, amount, fee, prices, optional_accounts: 0},
+                };
+                (instruction, rest)
             }
 
             // Admin Instructions
             2 => {
-                let (collateral_token_key, rest) = Self::unpack_pubkey(rest).unwrap();
-                let (remaining_dollar_cap, rest) = rest.split_at(8);
-                let remaining_dollar_cap = remaining_dollar_cap
-                    .try_into()
-                    .ok()
-                    .map(u64::from_le_bytes)
-                    .ok_or(InvalidInstruction)?;
-                let (withdrawable_fee_amount, rest) = rest.split_at(8);
-                let withdrawable_fee_amount = withdrawable_fee_amount
-                    .try_into()
-                    .ok()
-                    .map(u64::from_le_bytes)
-                    .ok_or(InvalidInstruction)?;
-
+                let (collateral_token_key, rest) = Self::unpack_pubkey(rest)?;
+                let (remaining_dollar_cap, rest) = Self::unpack_u64(rest)?;
+                let (withdrawable_fee_amount, rest) = Self::unpack_u64(rest)?;
                 let (&minimum_required_signature, rest) = rest.split_first().ok_or(InvalidInstruction)?;
+                let (oracles, rest) = Self::unpack_pubkey_vec(rest)?;
 
-                let (&oracles_num, rest) = rest.split_first().ok_or(InvalidInstruction)?;
-                let mut oracles = Vec::with_capacity(oracles_num as usize);
-                let (oracles_slice, _rest) = rest.split_at(oracles_num as usize * 32);
-                for i in 0..oracles_num {
-                    let oracle = oracles_slice.get(i as usize * 32 .. i as usize * 32 + 32).unwrap();
-                    let (oracle, _) = Self::unpack_pubkey(oracle).unwrap();
-                    oracles.push(oracle);
-                }
-
-                Self::InitializeSynchronizerAccount {
+                (Self::InitializeSynchronizerAccount {
                     collateral_token_key,
                     remaining_dollar_cap,
                     withdrawable_fee_amount,
                     minimum_required_signature,
                     oracles
-                }
+                }, rest)
             }
 
             3 => {
-                let (&minimum_required_signature, _rest) = rest.split_first().ok_or(InvalidInstruction)?;
+                let (&minimum_required_signature, rest) = rest.split_first().ok_or(InvalidInstruction)?;
 
-                Self::SetMinimumRequiredSignature {
+                (Self::SetMinimumRequiredSignature {
                     minimum_required_signature
-                }
+                }, rest)
             }
 
             4 => {
-                let (collateral_token_key, _rest) = Self::unpack_pubkey(rest).unwrap();
+                let (collateral_token_key, rest) = Self::unpack_pubkey(rest)?;
 
-                Self::SetCollateralToken {
+                (Self::SetCollateralToken {
                     collateral_token_key
-                }
+                }, rest)
             }
 
             5 => {
-                let (remaining_dollar_cap, _rest) = rest.split_at(8);
-                let remaining_dollar_cap = remaining_dollar_cap
-                    .try_into()
-                    .ok()
-                    .map(u64::from_le_bytes)
-                    .ok_or(InvalidInstruction)?;
-
-                Self:: SetRemainingDollarCap {
+                let (remaining_dollar_cap, rest) = Self::unpack_u64(rest)?;
+
+                (Self::SetRemainingDollarCap {
                     remaining_dollar_cap
-                }
+                }, rest)
             }
 
             6 => {
-                let (amount, _rest) = rest.split_at(8);
-                let amount = amount
-                    .try_into()
-                    .ok()
-                    .map(u64::from_le_bytes)
-                    .ok_or(InvalidInstruction)?;
-
-                Self::WithdrawFee {
+                let (amount, rest) = Self::unpack_u64(rest)?;
+
+                (Self::WithdrawFee {
                     amount
-                }
+                }, rest)
             }
 
             7 => {
-                let (amount, _rest) = rest.split_at(8);
-                let amount = amount
-                    .try_into()
-                    .ok()
-                    .map(u64::from_le_bytes)
-                    .ok_or(InvalidInstruction)?;
-
-                Self::WithdrawCollateral {
+                let (amount, rest) = Self::unpack_u64(rest)?;
+
+                (Self::WithdrawCollateral {
                     amount
-                }
+                }, rest)
             }
 
             8 => {
-                let (&oracles_num, rest) = rest.split_first().ok_or(InvalidInstruction)?;
-                let mut oracles = Vec::with_capacity(oracles_num as usize);
-                let (oracles_slice, _rest) = rest.split_at(oracles_num as usize * 32);
-                for i in 0..oracles_num {
-                    let oracle = oracles_slice.get(i as usize * 32 .. i as usize * 32 + 32).unwrap();
-                    let (oracle, _) = Self::unpack_pubkey(oracle).unwrap();
-                    oracles.push(oracle);
-                }
+                let (oracles, rest) = Self::unpack_pubkey_vec(rest)?;
 
-                Self::SetOracles {
+                (Self::SetOracles {
                     oracles
-                }
+                }, rest)
             }
 
-            _ => return Err(SynchronizerError::InvalidInstruction.into()),
-        })
+            _ => return Err(InvalidInstruction.into()),
+        };
+
+        if !rest.is_empty() {
+            return Err(InvalidInstruction.into());
+        }
+        Ok(instruction)
     }
 
     /// Packs a SynchronizerInstruction into a byte buffer.
     pub fn pack(&self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity(size_of::<Self>());
-        match self {
-            // Public Instructions
-            Self::BuyFor {
-                multiplier,
-                amount,
-                fee,
-                ref prices,
-            } => {
-                buf.push(0);
-                buf.extend_from_slice(&multiplier.to_le_bytes());
-                buf.extend_from_slice(&amount.to_le_bytes());
-                buf.extend_from_slice(&fee.to_le_bytes());
-                buf.push(prices.len().try_into().unwrap());
-                for price in prices {
-                    buf.extend_from_slice(&price.to_le_bytes());
-                }
-            },
-
-            Self::SellFor {
-                multiplier,
-                amount,
-                fee,
-                ref prices,
-            } => {
-                buf.push(1);
-                buf.extend_from_slice(&multiplier.to_le_bytes());
-                buf.extend_from_slice(&amount.to_le_bytes());
-                buf.extend_from_slice(&fee.to_le_bytes());
-                buf.push(prices.len().try_into().unwrap());
-                for price in prices {
-                    buf.extend_from_slice(&price.to_le_bytes());
-                }
-            },
-
-            // Admin Instructions
-            Self::InitializeSynchronizerAccount {
-                collateral_token_key,
-                remaining_dollar_cap,
-                withdrawable_fee_amount,
-                minimum_required_signature,
-                oracles
-            } => {
-                buf.push(2);
-                buf.extend_from_slice(collateral_token_key.as_ref());
-                buf.extend_from_slice(&remaining_dollar_cap.to_le_bytes());
-                buf.extend_from_slice(&withdrawable_fee_amount.to_le_bytes());
-                buf.push(*minimum_required_signature);
-                buf.push(oracles.len().try_into().unwrap());
-                for oracle in oracles {
-                    buf.extend_from_slice(oracle.as_ref());
-                }
-            }
-
-            Self::SetMinimumRequiredSignature {
-                minimum_required_signature
-            } => {
-                buf.push(3);
-                buf.push(*minimum_required_signature);
-            },
-
-            Self::SetCollateralToken {
-                collateral_token_key
-            } => {
-                buf.push(4);
-                buf.extend_from_slice(&collateral_token_key.as_ref());
-            },
-
-            Self::SetRemainingDollarCap {
-                remaining_dollar_cap
-            } => {
-                buf.push(5);
-                buf.extend_from_slice(&remaining_dollar_cap.to_le_bytes());
-            },
-
-            Self::WithdrawFee {
-                amount
-            } => {
-                buf.push(6);
-                buf.extend_from_slice(&amount.to_le_bytes());
-            },
-
-            Self::WithdrawCollateral {
-                amount
-            } => {
-                buf.push(7);
-                buf.extend_from_slice(&amount.to_le_bytes());
-            },
-
-            Self::SetOracles {
-                oracles
-            } => {
-                buf.push(8);
-                buf.push(oracles.len().try_into().unwrap());
-                for oracle in oracles {
-                    buf.extend_from_slice(oracle.as_ref());
-                }
-            }
-        };
+        let mut buf = vec![BORSH_INSTRUCTION_TAG];
+        // Serialization into a Vec can't fail
+        self.serialize(&mut buf).unwrap();
         buf
     }
 
@@ -389,9 +491,105 @@ impl SynchronizerInstruction {
             Err(SynchronizerError::InvalidInstruction.into())
         }
     }
+
+    fn unpack_u64(input: &[u8]) -> Result<(u64, &[u8]), ProgramError> {
+        if input.len() >= 8 {
+            let (value, rest) = input.split_at(8);
+            let value = value
+                .try_into()
+                .ok()
+                .map(u64::from_le_bytes)
+                .ok_or(SynchronizerError::InvalidInstruction)?;
+            Ok((value, rest))
+        } else {
+            Err(SynchronizerError::InvalidInstruction.into())
+        }
+    }
+
+    /// Unpacks a `u8` length prefixed vector of `u64`.
+    /// The length must not exceed the number of values actually present in `input`.
+    fn unpack_u64_vec(input: &[u8]) -> Result<(Vec<u64>, &[u8]), ProgramError> {
+        let (&len, mut rest) = input.split_first().ok_or(SynchronizerError::InvalidInstruction)?;
+        if rest.len() < len as usize * 8 {
+            return Err(SynchronizerError::InvalidInstruction.into());
+        }
+
+        let mut values = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            let (value, tail) = Self::unpack_u64(rest)?;
+            values.push(value);
+            rest = tail;
+        }
+        Ok((values, rest))
+    }
+
+    /// Unpacks a `u8` length prefixed vector of `Pubkey`.
+    /// The length must not exceed the number of keys actually present in `input`.
+    fn unpack_pubkey_vec(input: &[u8]) -> Result<(Vec<Pubkey>, &[u8]), ProgramError> {
+        let (&len, mut rest) = input.split_first().ok_or(SynchronizerError::InvalidInstruction)?;
+        if rest.len() < len as usize * 32 {
+            return Err(SynchronizerError::InvalidInstruction.into());
+        }
+
+        let mut keys = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            let (key, tail) = Self::unpack_pubkey(rest)?;
+            keys.push(key);
+            rest = tail;
+        }
+        Ok((keys, rest))
+    }
 }
 
-/// Creates a `BuyFor` instruction
+/// Index of the first oracle account of `BuyFor`/`SellFor` passing the `optional_accounts` flags
+pub fn first_trade_oracle_account(optional_accounts: u8) -> usize {
+    let clock = (optional_accounts != 0) as usize;
+    let system_program = (optional_accounts & TRADE_USER_VOLUME_ACCOUNT != 0) as usize;
+    TRADE_FIXED_ACCOUNTS + clock + optional_accounts.count_ones() as usize + system_program
+}
+
+/// Pushes the `optional_accounts` flagged accounts of `BuyFor`/`SellFor`, the referrer account is flagged
+/// when it's given
+fn push_optional_trade_accounts(
+    accounts: &mut Vec<AccountMeta>,
+    optional_accounts: u8,
+    mint: &Pubkey,
+    user_authority: &Pubkey,
+    synchronizer_authority: &Pubkey,
+    referrer_collateral_token_account: Option<&Pubkey>,
+) {
+    if optional_accounts != 0 {
+        accounts.push(AccountMeta::new_readonly(sysvar::clock::id(), false));
+    }
+    if optional_accounts & TRADE_ASSET_ACCOUNT != 0 {
+        accounts.push(AccountMeta::new(AssetData::find_address(synchronizer_authority, mint).0, false));
+    }
+    if optional_accounts & TRADE_RATE_LIMIT_ACCOUNT != 0 {
+        accounts.push(AccountMeta::new(RateLimitData::find_address(synchronizer_authority).0, false));
+    }
+    if optional_accounts & TRADE_USER_VOLUME_ACCOUNT != 0 {
+        accounts.push(AccountMeta::new(UserVolumeData::find_address(synchronizer_authority, user_authority).0, false));
+        accounts.push(AccountMeta::new_readonly(system_program::id(), false));
+    }
+    if optional_accounts & TRADE_USER_POSITION_ACCOUNT != 0 {
+        accounts.push(AccountMeta::new(UserPositionData::find_address(synchronizer_authority, user_authority, mint).0, false));
+    }
+    if optional_accounts & TRADE_MARKET_SCHEDULE_ACCOUNT != 0 {
+        accounts.push(AccountMeta::new_readonly(MarketScheduleData::find_address(synchronizer_authority, mint).0, false));
+    }
+    if optional_accounts & TRADE_FEE_SCHEDULE_ACCOUNT != 0 {
+        accounts.push(AccountMeta::new_readonly(FeeScheduleData::find_address(synchronizer_authority).0, false));
+    }
+    if let Some(referrer) = referrer_collateral_token_account {
+        accounts.push(AccountMeta::new(*referrer, false));
+    }
+    if optional_accounts & TRADE_COLLATERAL_SET_ACCOUNT != 0 {
+        accounts.push(AccountMeta::new(CollateralSetData::find_address(synchronizer_authority).0, false));
+    }
+}
+
+/// Creates a `BuyFor` instruction passing the `optional_accounts` flagged accounts,
+/// paying the referral share of the fee to the referrer collateral account if any
 pub fn buy_for(
     program_id: &Pubkey,
     multiplier: u64,
@@ -404,24 +602,32 @@ pub fn buy_for(
     user_fiat_token_account: &Pubkey,
     synchronizer_collateral_token_account: &Pubkey,
     user_authority: &Pubkey,
-    synchronizer_authority: &Pubkey
+    synchronizer_authority: &Pubkey,
+    optional_accounts: u8,
+    referrer_collateral_token_account: Option<&Pubkey>,
 ) -> Result<Instruction, ProgramError> {
     check_program_account(program_id)?;
+    let optional_accounts = match referrer_collateral_token_account {
+        Some(_) => optional_accounts | TRADE_REFERRER_ACCOUNT,
+        None => optional_accounts & !TRADE_REFERRER_ACCOUNT,
+    };
     let data = SynchronizerInstruction::BuyFor {
         amount,
         fee,
         multiplier,
         prices: prices.iter().cloned().collect(),
+        optional_accounts,
     }.pack();
 
-    let mut accounts = Vec::with_capacity(7);
+    let mut accounts = Vec::with_capacity(first_trade_oracle_account(optional_accounts) + oracles.len());
     accounts.push(AccountMeta::new(*mint, false));
     accounts.push(AccountMeta::new(*user_collateral_token_account, false));
     accounts.push(AccountMeta::new(*user_fiat_token_account, false));
     accounts.push(AccountMeta::new(*synchronizer_collateral_token_account, false));
-    accounts.push(AccountMeta::new_readonly(*user_authority, true));
+    accounts.push(AccountMeta::new(*user_authority, true));
     accounts.push(AccountMeta::new(*synchronizer_authority, true));
     accounts.push(AccountMeta::new_readonly(spl_token::id(), false));
+    push_optional_trade_accounts(&mut accounts, optional_accounts, mint, user_authority, synchronizer_authority, referrer_collateral_token_account);
     for oracle in oracles {
         accounts.push(AccountMeta::new_readonly(*oracle, true));
     }
@@ -433,7 +639,8 @@ pub fn buy_for(
     })
 }
 
-/// Creates a `SellFor` instruction
+/// Creates a `SellFor` instruction passing the `optional_accounts` flagged accounts,
+/// paying the referral share of the fee to the referrer collateral account if any
 pub fn sell_for(
     program_id: &Pubkey,
     multiplier: u64,
@@ -446,24 +653,32 @@ pub fn sell_for(
     user_fiat_token_account: &Pubkey,
     synchronizer_collateral_token_account: &Pubkey,
     user_authority: &Pubkey,
-    synchronizer_authority: &Pubkey
+    synchronizer_authority: &Pubkey,
+    optional_accounts: u8,
+    referrer_collateral_token_account: Option<&Pubkey>,
 ) -> Result<Instruction, ProgramError> {
     check_program_account(program_id)?;
+    let optional_accounts = match referrer_collateral_token_account {
+        Some(_) => optional_accounts | TRADE_REFERRER_ACCOUNT,
+        None => optional_accounts & !TRADE_REFERRER_ACCOUNT,
+    };
     let data = SynchronizerInstruction::SellFor {
         amount,
         fee,
         multiplier,
         prices: prices.iter().cloned().collect(),
+        optional_accounts,
     }.pack();
 
-    let mut accounts = Vec::with_capacity(7);
+    let mut accounts = Vec::with_capacity(first_trade_oracle_account(optional_accounts) + oracles.len());
     accounts.push(AccountMeta::new(*mint, false));
     accounts.push(AccountMeta::new(*user_collateral_token_account, false));
     accounts.push(AccountMeta::new(*user_fiat_token_account, false));
     accounts.push(AccountMeta::new(*synchronizer_collateral_token_account, false));
-    accounts.push(AccountMeta::new_readonly(*user_authority, true));
+    accounts.push(AccountMeta::new(*user_authority, true));
     accounts.push(AccountMeta::new(*synchronizer_authority, true));
     accounts.push(AccountMeta::new_readonly(spl_token::id(), false));
+    push_optional_trade_accounts(&mut accounts, optional_accounts, mint, user_authority, synchronizer_authority, referrer_collateral_token_account);
     for oracle in oracles {
         accounts.push(AccountMeta::new_readonly(*oracle, true));
     }
@@ -494,9 +709,10 @@ pub fn initialize_synchronizer_account(
         oracles: oracles.iter().cloned().collect(),
     }.pack();
 
-    let mut accounts = Vec::with_capacity(2);
+    let mut accounts = Vec::with_capacity(3);
     accounts.push(AccountMeta::new(*synchronizer_authority, true));
     accounts.push(AccountMeta::new_readonly(sysvar::rent::id(), false));
+    accounts.push(AccountMeta::new_readonly(*collateral_token_key, false));
 
     Ok(Instruction {
         program_id: *program_id,
@@ -533,8 +749,9 @@ pub fn set_collateral_token(
     check_program_account(program_id)?;
     let data = SynchronizerInstruction::SetCollateralToken { collateral_token_key: *collateral_token }.pack();
 
-    let mut accounts = Vec::with_capacity(1);
+    let mut accounts = Vec::with_capacity(2);
     accounts.push(AccountMeta::new(*synchronizer_authority, true));
+    accounts.push(AccountMeta::new_readonly(*collateral_token, false));
 
     Ok(Instruction {
         program_id: *program_id,
@@ -562,22 +779,27 @@ pub fn set_remaining_dollar_cap(
     })
 }
 
-/// Creates a `WithdrawFee` instruction
+/// Creates a `WithdrawFee` instruction, `accepted_collateral` passes the collateral set account
+/// for the vault of an accepted collateral
 pub fn withdraw_fee(
     program_id: &Pubkey,
     amount: u64,
     synchronizer_collateral_token_account: &Pubkey,
     recipient_collateral_token_account: &Pubkey,
     synchronizer_authority: &Pubkey,
+    accepted_collateral: bool,
 ) -> Result<Instruction, ProgramError> {
     check_program_account(program_id)?;
     let data = SynchronizerInstruction::WithdrawFee { amount }.pack();
 
-    let mut accounts = Vec::with_capacity(4);
+    let mut accounts = Vec::with_capacity(5);
     accounts.push(AccountMeta::new(*synchronizer_collateral_token_account, false));
     accounts.push(AccountMeta::new(*recipient_collateral_token_account, false));
     accounts.push(AccountMeta::new(*synchronizer_authority, true));
     accounts.push(AccountMeta::new_readonly(spl_token::id(), false));
+    if accepted_collateral {
+        accounts.push(AccountMeta::new(CollateralSetData::find_address(synchronizer_authority).0, false));
+    }
 
     Ok(Instruction {
         program_id: *program_id,
@@ -586,22 +808,27 @@ pub fn withdraw_fee(
     })
 }
 
-/// Creates a `WithdrawCollateral` instruction
+/// Creates a `WithdrawCollateral` instruction, `accepted_collateral` passes the collateral set account
+/// for the vault of an accepted collateral
 pub fn withdraw_collateral(
     program_id: &Pubkey,
     amount: u64,
     synchronizer_collateral_token_account: &Pubkey,
     recipient_collateral_token_account: &Pubkey,
     synchronizer_authority: &Pubkey,
+    accepted_collateral: bool,
 ) -> Result<Instruction, ProgramError> {
     check_program_account(program_id)?;
     let data = SynchronizerInstruction::WithdrawCollateral { amount }.pack();
 
-    let mut accounts = Vec::with_capacity(4);
+    let mut accounts = Vec::with_capacity(5);
     accounts.push(AccountMeta::new(*synchronizer_collateral_token_account, false));
     accounts.push(AccountMeta::new(*recipient_collateral_token_account, false));
     accounts.push(AccountMeta::new(*synchronizer_authority, true));
     accounts.push(AccountMeta::new_readonly(spl_token::id(), false));
+    if accepted_collateral {
+        accounts.push(AccountMeta::new(CollateralSetData::find_address(synchronizer_authority).0, false));
+    }
 
     Ok(Instruction {
         program_id: *program_id,
@@ -629,6 +856,365 @@ pub fn set_oracles(
     })
 }
 
+/// Creates a `MigrateSynchronizerAccount` instruction
+pub fn migrate_synchronizer_account(
+    program_id: &Pubkey,
+    synchronizer_authority: &Pubkey,
+    new_synchronizer_authority: &Pubkey,
+    authority_accounts: &[Pubkey],
+) -> Result<Instruction, ProgramError> {
+    check_program_account(program_id)?;
+    let data = SynchronizerInstruction::MigrateSynchronizerAccount.pack();
+
+    let mut accounts = Vec::with_capacity(4 + authority_accounts.len());
+    accounts.push(AccountMeta::new(*synchronizer_authority, true));
+    accounts.push(AccountMeta::new(*new_synchronizer_authority, true));
+    accounts.push(AccountMeta::new_readonly(sysvar::rent::id(), false));
+    accounts.push(AccountMeta::new_readonly(spl_token::id(), false));
+    for authority_account in authority_accounts.iter() {
+        accounts.push(AccountMeta::new(*authority_account, false));
+    }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `SetAssetCaps` instruction
+pub fn set_asset_caps(
+    program_id: &Pubkey,
+    fiat_asset_mint: &Pubkey,
+    max_outstanding_notional: u64,
+    max_transaction_notional: u64,
+    synchronizer_authority: &Pubkey,
+    payer: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    check_program_account(program_id)?;
+    let data = SynchronizerInstruction::SetAssetCaps {
+        fiat_asset_mint: *fiat_asset_mint,
+        max_outstanding_notional,
+        max_transaction_notional,
+    }.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*synchronizer_authority, true),
+        AccountMeta::new(AssetData::find_address(synchronizer_authority, fiat_asset_mint).0, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `SetRateLimits` instruction
+pub fn set_rate_limits(
+    program_id: &Pubkey,
+    window_slots: u64,
+    max_synchronizer_notional: u64,
+    max_user_notional: u64,
+    synchronizer_authority: &Pubkey,
+    payer: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    check_program_account(program_id)?;
+    let data = SynchronizerInstruction::SetRateLimits {
+        window_slots,
+        max_synchronizer_notional,
+        max_user_notional,
+    }.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*synchronizer_authority, true),
+        AccountMeta::new(RateLimitData::find_address(synchronizer_authority).0, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `CreateUserPosition` instruction
+pub fn create_user_position(
+    program_id: &Pubkey,
+    fiat_asset_mint: &Pubkey,
+    user_authority: &Pubkey,
+    synchronizer_authority: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    check_program_account(program_id)?;
+    let data = SynchronizerInstruction::CreateUserPosition { fiat_asset_mint: *fiat_asset_mint }.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*user_authority, true),
+        AccountMeta::new_readonly(*synchronizer_authority, false),
+        AccountMeta::new(UserPositionData::find_address(synchronizer_authority, user_authority, fiat_asset_mint).0, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `CloseUserPosition` instruction
+pub fn close_user_position(
+    program_id: &Pubkey,
+    fiat_asset_mint: &Pubkey,
+    recipient: &Pubkey,
+    user_authority: &Pubkey,
+    synchronizer_authority: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    check_program_account(program_id)?;
+    let data = SynchronizerInstruction::CloseUserPosition { fiat_asset_mint: *fiat_asset_mint }.pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*user_authority, true),
+        AccountMeta::new_readonly(*synchronizer_authority, false),
+        AccountMeta::new(UserPositionData::find_address(synchronizer_authority, user_authority, fiat_asset_mint).0, false),
+        AccountMeta::new(*recipient, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `SetMarketSchedule` instruction
+pub fn set_market_schedule(
+    program_id: &Pubkey,
+    fiat_asset_mint: &Pubkey,
+    weekly_hours: [TradingHours; 7],
+    holidays: &[u32],
+    synchronizer_authority: &Pubkey,
+    payer: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    check_program_account(program_id)?;
+    let data = SynchronizerInstruction::SetMarketSchedule {
+        fiat_asset_mint: *fiat_asset_mint,
+        weekly_hours,
+        holidays: holidays.to_vec(),
+    }.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*synchronizer_authority, true),
+        AccountMeta::new(MarketScheduleData::find_address(synchronizer_authority, fiat_asset_mint).0, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `SetAssetSpreads` instruction
+pub fn set_asset_spreads(
+    program_id: &Pubkey,
+    fiat_asset_mint: &Pubkey,
+    ask_spread_bps: u16,
+    bid_spread_bps: u16,
+    synchronizer_authority: &Pubkey,
+    payer: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    check_program_account(program_id)?;
+    let data = SynchronizerInstruction::SetAssetSpreads {
+        fiat_asset_mint: *fiat_asset_mint,
+        ask_spread_bps,
+        bid_spread_bps,
+    }.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*synchronizer_authority, true),
+        AccountMeta::new(AssetData::find_address(synchronizer_authority, fiat_asset_mint).0, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `SetFeeTiers` instruction
+pub fn set_fee_tiers(
+    program_id: &Pubkey,
+    tiers: &[FeeTier],
+    volume_window_slots: u64,
+    synchronizer_authority: &Pubkey,
+    payer: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    check_program_account(program_id)?;
+    let data = SynchronizerInstruction::SetFeeTiers { tiers: tiers.to_vec(), volume_window_slots }.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*synchronizer_authority, true),
+        AccountMeta::new(FeeScheduleData::find_address(synchronizer_authority).0, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `SetFeeOverride` instruction
+pub fn set_fee_override(
+    program_id: &Pubkey,
+    user: &Pubkey,
+    fee: Option<u64>,
+    synchronizer_authority: &Pubkey,
+    payer: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    check_program_account(program_id)?;
+    let data = SynchronizerInstruction::SetFeeOverride { user: *user, fee }.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*synchronizer_authority, true),
+        AccountMeta::new(FeeScheduleData::find_address(synchronizer_authority).0, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `SetReferralShare` instruction
+pub fn set_referral_share(
+    program_id: &Pubkey,
+    referral_share_bps: u16,
+    synchronizer_authority: &Pubkey,
+    payer: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    check_program_account(program_id)?;
+    let data = SynchronizerInstruction::SetReferralShare { referral_share_bps }.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*synchronizer_authority, true),
+        AccountMeta::new(FeeScheduleData::find_address(synchronizer_authority).0, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an `AddCollateral` instruction
+pub fn add_collateral(
+    program_id: &Pubkey,
+    rate: u64,
+    rate_bounds: RateBounds,
+    collateral_mint: &Pubkey,
+    vault: &Pubkey,
+    synchronizer_authority: &Pubkey,
+    payer: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    check_program_account(program_id)?;
+    let data = SynchronizerInstruction::AddCollateral { rate, rate_bounds }.pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*synchronizer_authority, true),
+        AccountMeta::new(CollateralSetData::find_address(synchronizer_authority).0, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(*collateral_mint, false),
+        AccountMeta::new_readonly(*vault, false),
+        AccountMeta::new_readonly(sysvar::clock::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `RemoveCollateral` instruction
+pub fn remove_collateral(
+    program_id: &Pubkey,
+    collateral_mint: &Pubkey,
+    vault: &Pubkey,
+    synchronizer_authority: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    check_program_account(program_id)?;
+    let data = SynchronizerInstruction::RemoveCollateral { collateral_mint: *collateral_mint }.pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*synchronizer_authority, true),
+        AccountMeta::new(CollateralSetData::find_address(synchronizer_authority).0, false),
+        AccountMeta::new_readonly(*vault, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an `UpdateCollateralRate` instruction attested by `oracles`,
+/// signed by the Synchronizer account authority when there are no oracles
+pub fn update_collateral_rate(
+    program_id: &Pubkey,
+    collateral_mint: &Pubkey,
+    rate: u64,
+    synchronizer_authority: &Pubkey,
+    oracles: &[Pubkey],
+) -> Result<Instruction, ProgramError> {
+    check_program_account(program_id)?;
+    let data = SynchronizerInstruction::UpdateCollateralRate { collateral_mint: *collateral_mint, rate }.pack();
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*synchronizer_authority, oracles.is_empty()),
+        AccountMeta::new(CollateralSetData::find_address(synchronizer_authority).0, false),
+        AccountMeta::new_readonly(sysvar::clock::id(), false),
+    ];
+    for oracle in oracles.iter() {
+        accounts.push(AccountMeta::new_readonly(*oracle, true));
+    }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
 #[cfg(test)]
 mod test {
     use std::str::FromStr;
@@ -636,14 +1222,14 @@ mod test {
     use super::*;
 
     #[test]
-    fn test_instruction_packing() {
+    fn test_legacy_instruction_unpacking() {
         let check = SynchronizerInstruction::BuyFor {
             multiplier: 5,
             amount: 215,
             fee: 100,
             prices: vec![211, 123, 300],
+            optional_accounts: 0,
         };
-        let packed = check.pack();
         let mut expect = Vec::from([0u8]);
         expect.extend_from_slice(&[5, 0, 0, 0, 0, 0, 0, 0]);
         expect.extend_from_slice(&[215, 0, 0, 0, 0, 0, 0, 0]);
@@ -652,7 +1238,6 @@ mod test {
         expect.extend_from_slice(&[211, 0, 0, 0, 0, 0, 0, 0]);
         expect.extend_from_slice(&[123, 0, 0, 0, 0, 0, 0, 0]);
         expect.extend_from_slice(&[44, 1, 0, 0, 0, 0, 0, 0]);
-        assert_eq!(packed, expect);
         let unpacked = SynchronizerInstruction::unpack(&expect).unwrap();
         assert_eq!(unpacked, check);
 
@@ -661,8 +1246,8 @@ mod test {
             amount: 215,
             fee: 100,
             prices: vec![211, 123, 300],
+            optional_accounts: 0,
         };
-        let packed = check.pack();
         let mut expect = Vec::from([1u8]);
         expect.extend_from_slice(&[5, 0, 0, 0, 0, 0, 0, 0]);
         expect.extend_from_slice(&[215, 0, 0, 0, 0, 0, 0, 0]);
@@ -671,7 +1256,6 @@ mod test {
         expect.extend_from_slice(&[211, 0, 0, 0, 0, 0, 0, 0]);
         expect.extend_from_slice(&[123, 0, 0, 0, 0, 0, 0, 0]);
         expect.extend_from_slice(&[44, 1, 0, 0, 0, 0, 0, 0]);
-        assert_eq!(packed, expect);
         let unpacked = SynchronizerInstruction::unpack(&expect).unwrap();
         assert_eq!(unpacked, check);
 
@@ -685,7 +1269,6 @@ mod test {
                 Pubkey::from_str("EExYKmkDnS5HuUhb33e5ZeGHdZPCdQKJcQXDQTyWSb4X").unwrap()
             ],
         };
-        let packed = check.pack();
         let mut expect = Vec::from([2u8]);
         expect.extend_from_slice(&[178, 177, 51, 164, 92, 30, 126, 138, 210, 146, 214, 193, 145, 103, 57, 185, 60, 120, 46, 119, 37, 184, 251, 108, 93, 90, 88, 249, 49, 176, 59, 160]);
         expect.extend_from_slice(&[44, 1, 0, 0, 0, 0, 0, 0]);
@@ -694,57 +1277,46 @@ mod test {
         expect.extend_from_slice(&[2]);
         expect.extend_from_slice(&[178, 177, 51, 164, 92, 30, 126, 138, 210, 146, 214, 193, 145, 103, 57, 185, 60, 120, 46, 119, 37, 184, 251, 108, 93, 90, 88, 249, 49, 176, 59, 160]);
         expect.extend_from_slice(&[196, 187, 71, 168, 43, 226, 204, 130, 198, 182, 91, 6, 240, 228, 232, 228, 89, 217, 65, 173, 197, 180, 93, 22, 141, 243, 103, 79, 210, 0, 211, 76]);
-        assert_eq!(packed, expect);
         let unpacked = SynchronizerInstruction::unpack(&expect).unwrap();
         assert_eq!(unpacked, check);
 
         let check = SynchronizerInstruction::SetMinimumRequiredSignature {
             minimum_required_signature: 3,
         };
-        let packed = check.pack();
         let mut expect = Vec::from([3u8]);
         expect.extend_from_slice(&[3]);
-        assert_eq!(packed, expect);
         let unpacked = SynchronizerInstruction::unpack(&expect).unwrap();
         assert_eq!(unpacked, check);
 
         let check = SynchronizerInstruction::SetCollateralToken {
             collateral_token_key: Pubkey::from_str("D2YHis8gk2wRHkMEY7bULLsFUk277KdodWFR1nJ9SRgb").unwrap()
         };
-        let packed = check.pack();
         let mut expect = Vec::from([4u8]);
         expect.extend_from_slice(&[178, 177, 51, 164, 92, 30, 126, 138, 210, 146, 214, 193, 145, 103, 57, 185, 60, 120, 46, 119, 37, 184, 251, 108, 93, 90, 88, 249, 49, 176, 59, 160]);
-        assert_eq!(packed, expect);
         let unpacked = SynchronizerInstruction::unpack(&expect).unwrap();
         assert_eq!(unpacked, check);
 
         let check = SynchronizerInstruction::SetRemainingDollarCap {
             remaining_dollar_cap: 500_000_000_000
         };
-        let packed = check.pack();
         let mut expect = Vec::from([5u8]);
         expect.extend_from_slice(&[0, 136, 82, 106, 116, 0, 0, 0]);
-        assert_eq!(packed, expect);
         let unpacked = SynchronizerInstruction::unpack(&expect).unwrap();
         assert_eq!(unpacked, check);
 
         let check = SynchronizerInstruction::WithdrawFee {
             amount: 500_000_000_000
         };
-        let packed = check.pack();
         let mut expect = Vec::from([6u8]);
         expect.extend_from_slice(&[0, 136, 82, 106, 116, 0, 0, 0]);
-        assert_eq!(packed, expect);
         let unpacked = SynchronizerInstruction::unpack(&expect).unwrap();
         assert_eq!(unpacked, check);
 
         let check = SynchronizerInstruction::WithdrawCollateral {
             amount: 500_000_000_000
         };
-        let packed = check.pack();
         let mut expect = Vec::from([7u8]);
         expect.extend_from_slice(&[0, 136, 82, 106, 116, 0, 0, 0]);
-        assert_eq!(packed, expect);
         let unpacked = SynchronizerInstruction::unpack(&expect).unwrap();
         assert_eq!(unpacked, check);
 
@@ -757,7 +1329,6 @@ mod test {
                 Pubkey::from_str("7kVUKpM9Tz3H5aTPgfDFdWxgyCEWcx8orax7icVm629u").unwrap()
             ],
         };
-        let packed = check.pack();
         let mut expect = Vec::from([8u8]);
         expect.extend_from_slice(&[5]);
         expect.extend_from_slice(&[178, 177, 51, 164, 92, 30, 126, 138, 210, 146, 214, 193, 145, 103, 57, 185, 60, 120, 46, 119, 37, 184, 251, 108, 93, 90, 88, 249, 49, 176, 59, 160]);
@@ -765,8 +1336,235 @@ mod test {
         expect.extend_from_slice(&[46, 114, 255, 76, 55, 224, 86, 81, 80, 77, 238, 196, 54, 34, 220, 143, 51, 146, 94, 92, 180, 213, 127, 110, 44, 152, 35, 202, 56, 105, 199, 54, 185]);
         expect.extend_from_slice(&[106, 220, 194, 67, 146, 136, 46, 107, 170, 107, 183, 9, 110, 128, 95, 226, 235, 241, 127, 237, 200, 73, 88, 145, 62, 168, 192, 157, 143, 206, 100, 100]);
         expect.extend_from_slice(&[74, 73, 234, 151, 55, 189, 63, 169, 31, 58, 64, 4, 192, 129, 45, 231, 221, 183, 196, 119, 148, 203, 29, 109, 186, 145, 150, 226, 24, 95, 112]);
+        let unpacked = SynchronizerInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+    }
+
+    #[test]
+    fn test_instruction_packing() {
+        let check = SynchronizerInstruction::BuyFor {
+            multiplier: 5,
+            amount: 215,
+            fee: 100,
+            prices: vec![211, 123, 300],
+            optional_accounts: 0,
+        };
+        let packed = check.pack();
+        let mut expect = Vec::from([BORSH_INSTRUCTION_TAG, 0]);
+        expect.extend_from_slice(&[5, 0, 0, 0, 0, 0, 0, 0]);
+        expect.extend_from_slice(&[215, 0, 0, 0, 0, 0, 0, 0]);
+        expect.extend_from_slice(&[100, 0, 0, 0, 0, 0, 0, 0]);
+        expect.extend_from_slice(&[3, 0, 0, 0]);
+        expect.extend_from_slice(&[211, 0, 0, 0, 0, 0, 0, 0]);
+        expect.extend_from_slice(&[123, 0, 0, 0, 0, 0, 0, 0]);
+        expect.extend_from_slice(&[44, 1, 0, 0, 0, 0, 0, 0]);
+        expect.push(0);
+        assert_eq!(packed, expect);
+        let unpacked = SynchronizerInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let check = SynchronizerInstruction::SetOracles {
+            oracles: vec![
+                Pubkey::from_str("D2YHis8gk2wRHkMEY7bULLsFUk277KdodWFR1nJ9SRgb").unwrap(),
+            ],
+        };
+        let packed = check.pack();
+        let mut expect = Vec::from([BORSH_INSTRUCTION_TAG, 8]);
+        expect.extend_from_slice(&[1, 0, 0, 0]);
+        expect.extend_from_slice(&[178, 177, 51, 164, 92, 30, 126, 138, 210, 146, 214, 193, 145, 103, 57, 185, 60, 120, 46, 119, 37, 184, 251, 108, 93, 90, 88, 249, 49, 176, 59, 160]);
         assert_eq!(packed, expect);
         let unpacked = SynchronizerInstruction::unpack(&expect).unwrap();
         assert_eq!(unpacked, check);
+
+        let checks = vec![
+            SynchronizerInstruction::SellFor {
+                multiplier: 5,
+                amount: 215,
+                fee: 100,
+                prices: vec![211, 123, 300],
+                optional_accounts: TRADE_PROGRAM_ACCOUNTS,
+            },
+            SynchronizerInstruction::InitializeSynchronizerAccount {
+                collateral_token_key: Pubkey::new_unique(),
+                remaining_dollar_cap: 300,
+                withdrawable_fee_amount: 200,
+                minimum_required_signature: 3,
+                oracles: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+            },
+            SynchronizerInstruction::SetMinimumRequiredSignature { minimum_required_signature: 3 },
+            SynchronizerInstruction::SetCollateralToken { collateral_token_key: Pubkey::new_unique() },
+            SynchronizerInstruction::SetRemainingDollarCap { remaining_dollar_cap: 500_000_000_000 },
+            SynchronizerInstruction::WithdrawFee { amount: 500_000_000_000 },
+            SynchronizerInstruction::WithdrawCollateral { amount: 500_000_000_000 },
+            SynchronizerInstruction::MigrateSynchronizerAccount,
+            SynchronizerInstruction::SetAssetCaps {
+                fiat_asset_mint: Pubkey::new_unique(),
+                max_outstanding_notional: 1_000_000,
+                max_transaction_notional: 1000,
+            },
+            SynchronizerInstruction::SetRateLimits {
+                window_slots: 150,
+                max_synchronizer_notional: 1_000_000,
+                max_user_notional: 10_000,
+            },
+            SynchronizerInstruction::CreateUserPosition { fiat_asset_mint: Pubkey::new_unique() },
+            SynchronizerInstruction::CloseUserPosition { fiat_asset_mint: Pubkey::new_unique() },
+            SynchronizerInstruction::SetMarketSchedule {
+                fiat_asset_mint: Pubkey::new_unique(),
+                weekly_hours: [TradingHours { open: 52_200, close: 75_600 }; 7],
+                holidays: vec![18_985, 18_992],
+            },
+            SynchronizerInstruction::SetAssetSpreads {
+                fiat_asset_mint: Pubkey::new_unique(),
+                ask_spread_bps: 150,
+                bid_spread_bps: 50,
+            },
+            SynchronizerInstruction::SetFeeTiers {
+                tiers: vec![FeeTier { min_volume: 1000, fee: 8 }, FeeTier { min_volume: 5000, fee: 5 }],
+                volume_window_slots: 216_000,
+            },
+            SynchronizerInstruction::SetFeeOverride { user: Pubkey::new_unique(), fee: Some(0) },
+            SynchronizerInstruction::SetFeeOverride { user: Pubkey::new_unique(), fee: None },
+            SynchronizerInstruction::SetReferralShare { referral_share_bps: 2500 },
+            SynchronizerInstruction::AddCollateral { rate: 1_000_000, rate_bounds: RateBounds { max_age: 3_600, max_deviation_bps: 100 } },
+            SynchronizerInstruction::RemoveCollateral { collateral_mint: Pubkey::new_unique() },
+            SynchronizerInstruction::UpdateCollateralRate { collateral_mint: Pubkey::new_unique(), rate: 999_000 },
+        ];
+        for check in checks {
+            let unpacked = SynchronizerInstruction::unpack(&check.pack()).unwrap();
+            assert_eq!(unpacked, check);
+        }
+    }
+
+    #[test]
+    fn test_instruction_unpack_malformed() {
+        let check = SynchronizerInstruction::BuyFor {
+            multiplier: 5,
+            amount: 215,
+            fee: 100,
+            prices: vec![211, 123, 300],
+            optional_accounts: 0,
+        };
+        let packed = check.pack();
+        let mut legacy = Vec::from([0u8]);
+        legacy.extend_from_slice(&packed[2..26]);
+        legacy.push(3);
+        legacy.extend_from_slice(&packed[30..packed.len() - 1]);
+        assert_eq!(SynchronizerInstruction::unpack(&legacy).unwrap(), check);
+
+        // Truncated input
+        for len in 0..packed.len() {
+            assert_eq!(
+                SynchronizerInstruction::unpack(&packed[..len]),
+                Err(SynchronizerError::InvalidInstruction.into())
+            );
+        }
+        for len in 0..legacy.len() {
+            assert_eq!(
+                SynchronizerInstruction::unpack(&legacy[..len]),
+                Err(SynchronizerError::InvalidInstruction.into())
+            );
+        }
+
+        // Trailing bytes
+        for input in [&packed, &legacy].iter() {
+            let mut trailing = input.to_vec();
+            trailing.push(0);
+            assert_eq!(
+                SynchronizerInstruction::unpack(&trailing),
+                Err(SynchronizerError::InvalidInstruction.into())
+            );
+        }
+
+        // Vector length exceeds the data
+        let mut oversized = packed.clone();
+        oversized[26..30].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(
+            SynchronizerInstruction::unpack(&oversized),
+            Err(SynchronizerError::InvalidInstruction.into())
+        );
+        let mut oversized = legacy.clone();
+        oversized[25] = u8::MAX;
+        assert_eq!(
+            SynchronizerInstruction::unpack(&oversized),
+            Err(SynchronizerError::InvalidInstruction.into())
+        );
+
+        let mut oversized = Vec::from([8u8, 3]);
+        oversized.extend_from_slice(&[1; 64]);
+        assert_eq!(
+            SynchronizerInstruction::unpack(&oversized),
+            Err(SynchronizerError::InvalidInstruction.into())
+        );
+
+        // Unknown tag
+        assert_eq!(
+            SynchronizerInstruction::unpack(&[u8::MAX - 1]),
+            Err(SynchronizerError::InvalidInstruction.into())
+        );
+        assert_eq!(
+            SynchronizerInstruction::unpack(&[BORSH_INSTRUCTION_TAG, u8::MAX]),
+            Err(SynchronizerError::InvalidInstruction.into())
+        );
+    }
+
+    #[test]
+    fn test_instruction_unpack_never_panics() {
+        // Every tag with every short length and every byte value of the length prefix
+        for tag in 0..=21u8 {
+            for len in 0..=80usize {
+                for fill in [0u8, 1, 2, 3, 0x7f, u8::MAX].iter() {
+                    let mut input = vec![*fill; len + 1];
+                    input[0] = tag;
+                    let _ = SynchronizerInstruction::unpack(&input);
+
+                    let mut input = vec![*fill; len + 2];
+                    input[0] = BORSH_INSTRUCTION_TAG;
+                    input[1] = tag;
+                    let _ = SynchronizerInstruction::unpack(&input);
+                }
+            }
+        }
+    }
+    #[test]
+    fn test_trade_optional_accounts() {
+        let program_id = crate::processor::id();
+        let (mint, synchronizer, user, referrer) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let oracles = vec![Pubkey::new_unique()];
+        let sell = |optional_accounts, referrer| sell_for(
+            &program_id, 1, 100, 0, &vec![50], &oracles, &mint,
+            &Pubkey::new_unique(), &Pubkey::new_unique(), &Pubkey::new_unique(), &user, &synchronizer, optional_accounts, referrer,
+        ).unwrap();
+        let optional_accounts = |instruction: &Instruction| match SynchronizerInstruction::unpack(&instruction.data).unwrap() {
+            SynchronizerInstruction::SellFor { optional_accounts, .. } => optional_accounts,
+            instruction => panic!("unexpected instruction {:?}", instruction),
+        };
+
+        // Legacy accounts, the oracles follow the token program
+        let instruction = sell(0, None);
+        assert_eq!(optional_accounts(&instruction), 0);
+        assert_eq!(instruction.accounts.len(), TRADE_FIXED_ACCOUNTS + 1);
+        assert_eq!(instruction.accounts[TRADE_FIXED_ACCOUNTS], AccountMeta::new_readonly(oracles[0], true));
+
+        // The referrer is flagged when given
+        let instruction = sell(TRADE_REFERRER_ACCOUNT, None);
+        assert_eq!(optional_accounts(&instruction), 0);
+        let instruction = sell(0, Some(&referrer));
+        assert_eq!(optional_accounts(&instruction), TRADE_REFERRER_ACCOUNT);
+        assert_eq!(instruction.accounts[TRADE_FIXED_ACCOUNTS], AccountMeta::new_readonly(sysvar::clock::id(), false));
+        assert_eq!(instruction.accounts[TRADE_FIXED_ACCOUNTS + 1], AccountMeta::new(referrer, false));
+        assert_eq!(first_trade_oracle_account(TRADE_REFERRER_ACCOUNT), TRADE_FIXED_ACCOUNTS + 2);
+
+        let instruction = sell(TRADE_USER_VOLUME_ACCOUNT | TRADE_COLLATERAL_SET_ACCOUNT, None);
+        assert_eq!(instruction.accounts[TRADE_FIXED_ACCOUNTS + 1], AccountMeta::new(UserVolumeData::find_address(&synchronizer, &user).0, false));
+        assert_eq!(instruction.accounts[TRADE_FIXED_ACCOUNTS + 2], AccountMeta::new_readonly(system_program::id(), false));
+        assert_eq!(instruction.accounts[TRADE_FIXED_ACCOUNTS + 3], AccountMeta::new(CollateralSetData::find_address(&synchronizer).0, false));
+        assert_eq!(first_trade_oracle_account(TRADE_USER_VOLUME_ACCOUNT | TRADE_COLLATERAL_SET_ACCOUNT), TRADE_FIXED_ACCOUNTS + 4);
+
+        let instruction = sell(TRADE_PROGRAM_ACCOUNTS, Some(&referrer));
+        assert_eq!(optional_accounts(&instruction), u8::MAX);
+        assert_eq!(instruction.accounts.len(), first_trade_oracle_account(u8::MAX) + 1);
+        assert_eq!(instruction.accounts[first_trade_oracle_account(u8::MAX)], AccountMeta::new_readonly(oracles[0], true));
+        assert_eq!(instruction.accounts[first_trade_oracle_account(u8::MAX) - 2], AccountMeta::new(referrer, false));
     }
 }
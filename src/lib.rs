@@ -1,5 +1,9 @@
+#[cfg(not(feature = "no-entrypoint"))]
 mod entrypoint;
 pub mod error;
+pub mod event;
 pub mod instruction;
+pub mod quote;
 pub mod state;
 pub mod processor;
+pub mod validation;
@@ -1,9 +1,9 @@
 //! Program state processor
 
-use crate::{error::SynchronizerError, instruction::{MAX_ORACLES, MAX_SIGNERS, SynchronizerInstruction}, state::SynchronizerData};
+use crate::{error::SynchronizerError, event::SynchronizerEvent, instruction::{MAX_ORACLES, MAX_SIGNERS, SynchronizerInstruction, TRADE_ASSET_ACCOUNT, TRADE_COLLATERAL_SET_ACCOUNT, TRADE_FEE_SCHEDULE_ACCOUNT, TRADE_MARKET_SCHEDULE_ACCOUNT, TRADE_RATE_LIMIT_ACCOUNT, TRADE_REFERRER_ACCOUNT, TRADE_USER_POSITION_ACCOUNT, TRADE_USER_VOLUME_ACCOUNT}, quote::{quote_buy, quote_sell, settle_buy, settle_sell, signed_prices, Settlement}, state::{ASSET_SEED, AcceptedCollateral, AssetData, COLLATERAL_SET_SEED, CollateralSetData, FEE_SCHEDULE_SEED, FeeScheduleData, FeeTier, MARKET_SCHEDULE_SEED, MarketScheduleData, RATE_LIMIT_SEED, RateBounds, RateLimitData, SYNCHRONIZER_DATA_VERSION, SynchronizerData, TradingHours, USER_POSITION_SEED, USER_VOLUME_SEED, UserPositionData, UserVolumeData}, validation::{check_synchronizer_authority, check_token_program, load_asset, load_collateral_account, load_collateral_set, load_fee_schedule, load_market_schedule, load_rate_limit, load_user_position, load_user_volume, load_collateral_mint, load_fiat_account, load_fiat_mint, load_mint, load_synchronizer, load_token_account}};
 use num_traits::FromPrimitive;
-use solana_program::{account_info::{next_account_info, AccountInfo}, decode_error::DecodeError, entrypoint::ProgramResult, msg, program::{invoke}, program_error::{PrintProgramError, ProgramError}, program_option::COption, program_pack::Pack, pubkey::Pubkey, rent::Rent, sysvar::Sysvar};
-use spl_token::{error::TokenError, state::{Account, Mint}};
+use solana_program::{account_info::{next_account_info, AccountInfo}, decode_error::DecodeError, entrypoint::ProgramResult, msg, program::{invoke, invoke_signed}, program_error::{PrintProgramError, ProgramError}, program_pack::Pack, program_option::COption, pubkey::Pubkey, rent::Rent, system_instruction, system_program, sysvar::{clock::Clock, Sysvar}};
+use spl_token::{instruction::AuthorityType, state::Mint};
 
 // Synchronizer program_id
 solana_program::declare_id!("urNhxed8ocNiFApoooLSAJ1xnWSMUiC9S6fKcRon1rk");
@@ -16,9 +16,24 @@ pub fn check_program_account(program_id: &Pubkey) -> ProgramResult {
     Ok(())
 }
 
+/// Optional accounts of `BuyFor`/`SellFor`, `None` when they aren't flagged
+struct TradeAccountInfos<'a, 'b> {
+    /// Default when no optional account is flagged, only the optional accounts need the clock
+    clock: Clock,
+    asset: Option<&'a AccountInfo<'b>>,
+    rate_limit: Option<&'a AccountInfo<'b>>,
+    user_volume: Option<&'a AccountInfo<'b>>,
+    system_program: Option<&'a AccountInfo<'b>>,
+    user_position: Option<&'a AccountInfo<'b>>,
+    market_schedule: Option<&'a AccountInfo<'b>>,
+    fee_schedule: Option<&'a AccountInfo<'b>>,
+    referrer: Option<&'a AccountInfo<'b>>,
+    collateral_set: Option<&'a AccountInfo<'b>>,
+}
+
 pub struct Processor {}
 impl Processor {
-/// Default Scale
+/// Default Scale, decimals of the collateral of Synchronizers initialized before collateral decimals were stored
 pub const DEFAULT_DECIMALS: u8 = 9;
 
 // Instructions handlers
@@ -29,6 +44,7 @@ pub fn process_buy_for(
     amount: u64,
     fee: u64,
     prices: &Vec<u64>,
+    optional_accounts: u8,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let fiat_asset_mint_info = next_account_info(account_info_iter)?;
@@ -39,98 +55,107 @@ pub fn process_buy_for(
     let synchronizer_authority_info = next_account_info(account_info_iter)?;
     let spl_token_info = next_account_info(account_info_iter)?;
 
-    if !synchronizer_authority_info.owner.eq(&id()) {
-        return Err(SynchronizerError::AccessDenied.into());
-    }
-    if !synchronizer_authority_info.is_signer {
-        return Err(SynchronizerError::InvalidSigner.into());
-    }
+    check_token_program(spl_token_info)?;
+    check_synchronizer_authority(synchronizer_authority_info)?;
     if !user_authority_info.is_signer {
         return Err(SynchronizerError::InvalidSigner.into());
     }
 
-    let mut synchronizer = SynchronizerData::unpack_unchecked(&synchronizer_authority_info.data.borrow())?;
-    if !synchronizer.is_initialized {
-        return Err(SynchronizerError::NotInitialized.into());
-    }
+    let mut synchronizer = load_synchronizer(synchronizer_authority_info)?;
+    let TradeAccountInfos {
+        clock,
+        asset: asset_info,
+        rate_limit: rate_limit_info,
+        user_volume: user_volume_info,
+        system_program: system_program_info,
+        user_position: user_position_info,
+        market_schedule: market_schedule_info,
+        fee_schedule: fee_schedule_info,
+        referrer: referrer_info,
+        collateral_set: collateral_set_info,
+    } = Self::next_trade_accounts(account_info_iter, &synchronizer, optional_accounts)?;
 
     let oracles_infos = account_info_iter.as_slice();
-    if oracles_infos.len() < synchronizer.minimum_required_signature as usize {
-        return Err(SynchronizerError::NotEnoughOracles.into());
-    }
     if prices.len() < synchronizer.minimum_required_signature as usize {
         return Err(SynchronizerError::NotEnoughOracles.into());
     }
 
-    let mut price = prices[0];
-    for i in 0..synchronizer.minimum_required_signature as usize {
-        let oracle = oracles_infos.iter().next().unwrap();
-        if !synchronizer.oracles.contains(&oracle.key) || !oracle.is_signer {
-            return Err(SynchronizerError::BadOracle.into());
-        }
-
-        if prices[i] > price {
-            price = prices[i];
-        }
-    }
-
-    let synchronizer_collateral_account = Account::unpack(&synchronizer_collateral_account_info.data.borrow()).unwrap();
-    let user_collateral_account = Account::unpack(&user_collateral_account_info.data.borrow()).unwrap();
-    if !synchronizer_collateral_account.mint.eq(&synchronizer.collateral_token_key) {
-        return Err(SynchronizerError::BadCollateralMint.into());
-    }
-    if !synchronizer_collateral_account.owner.eq(synchronizer_authority_info.key) {
-        return Err(TokenError::OwnerMismatch.into());
-    }
-    if !user_collateral_account.mint.eq(&synchronizer.collateral_token_key) {
-        return Err(SynchronizerError::BadCollateralMint.into());
-    }
-    if !user_collateral_account.owner.eq(user_authority_info.key) {
-        return Err(TokenError::OwnerMismatch.into());
-    }
-
-    let fiat_mint = Mint::unpack(&fiat_asset_mint_info.data.borrow_mut()).unwrap();
-    let decimals= fiat_mint.decimals;
-    if decimals != Self::DEFAULT_DECIMALS {
-        return Err(SynchronizerError::BadDecimals.into());
-    }
-
-    match fiat_mint.mint_authority {
-        COption::Some(authority) => {
-            if !authority.eq(&synchronizer_authority_info.key) {
-                return Err(SynchronizerError::BadMintAuthority.into());
-            }
-        },
-        COption::None => return Err(SynchronizerError::BadMintAuthority.into()),
-    }
-
-    if !Account::unpack(&user_fiat_account_info.data.borrow()).unwrap().owner.eq(user_authority_info.key) {
-        return Err(TokenError::OwnerMismatch.into())
-    }
-
-    msg!("Process buy_for, user fiat amount: {}, collateral price: {}", amount, price);
-
-    let collateral_amount_ui= spl_token::amount_to_ui_amount(amount, decimals) * spl_token::amount_to_ui_amount(price, decimals);
-    let fee_amount_ui = collateral_amount_ui * spl_token::amount_to_ui_amount(fee, decimals);
-    msg!("collateral_amount_ui: {}, fee_amount_ui: {}", collateral_amount_ui, fee_amount_ui);
+    Self::check_oracle_signers(&synchronizer, oracles_infos, synchronizer.minimum_required_signature as usize)?;
 
-    let collateral_amount = spl_token::ui_amount_to_amount(collateral_amount_ui, decimals);
-    let fee_amount = spl_token::ui_amount_to_amount(fee_amount_ui, decimals);
-    msg!("collateral_amount: {}, fee_amount: {}", collateral_amount, fee_amount);
+    // Trades passing the vault of an accepted collateral are settled in that collateral
+    let mut accepted = match collateral_set_info {
+        Some(collateral_set_info) => Self::accepted_collateral(collateral_set_info, synchronizer_authority_info.key, synchronizer_collateral_account_info.key)?,
+        None => None,
+    };
+    let collateral_mint = accepted.map_or(synchronizer.collateral_token_key, |(set, index)| set.collaterals[index].mint);
+    load_collateral_account(synchronizer_collateral_account_info, &collateral_mint, Some(synchronizer_authority_info.key))?;
+    let user_collateral_account = load_collateral_account(user_collateral_account_info, &collateral_mint, Some(user_authority_info.key))?;
+    let has_referrer = Self::check_referrer(referrer_info, synchronizer_collateral_account_info.key, &collateral_mint, user_authority_info.key)?;
+
+    let fiat_decimals = load_fiat_mint(fiat_asset_mint_info, synchronizer_authority_info.key)?.decimals;
+    load_fiat_account(user_fiat_account_info, fiat_asset_mint_info.key, Some(user_authority_info.key))?;
+    Self::check_market_open(market_schedule_info, synchronizer_authority_info.key, fiat_asset_mint_info.key, &clock)?;
+
+    // Assets without an asset account have no per-asset limits nor spreads
+    let mut asset = asset_info.map(|info| load_asset(info, synchronizer_authority_info.key, fiat_asset_mint_info.key)).transpose()?.flatten();
+    let ask_spread_bps = asset.map_or(0, |asset| asset.ask_spread_bps);
+    let fee_schedule = fee_schedule_info.map(|info| load_fee_schedule(info, synchronizer_authority_info.key)).transpose()?.flatten();
+    let fee = Self::trade_fee(fee_schedule.as_ref(), synchronizer_authority_info.key, user_authority_info.key, user_volume_info, &clock, fee)?;
+    let collateral_decimals = synchronizer.collateral_token_decimals();
+    let quote = quote_buy(signed_prices(prices, synchronizer.minimum_required_signature), amount, fee, multiplier, fiat_decimals, collateral_decimals, ask_spread_bps)?;
+    let settlement = match accepted {
+        Some((set, index)) => settle_buy(&quote, set.collaterals[index].current_rate(clock.unix_timestamp)?, set.collaterals[index].decimals)?,
+        None => Settlement::from(&quote),
+    };
+    let referral_amount = Self::referral_amount(fee_schedule.as_ref(), has_referrer, settlement.fee_amount);
+    msg!("Process buy_for, user fiat amount: {}, collateral price: {}", amount, quote.price);
+    msg!("collateral_amount: {}, fee_amount: {}, referral_amount: {}", quote.collateral_amount, quote.fee_amount, referral_amount);
 
-    if user_collateral_account.amount < (collateral_amount + fee_amount) {
+    if user_collateral_account.amount < settlement.user_collateral_amount {
         return Err(SynchronizerError::InsufficientFunds.into());
     }
+    synchronizer.remaining_dollar_cap = synchronizer.remaining_dollar_cap.checked_sub(quote.dollar_cap_amount)
+        .ok_or(SynchronizerError::DollarCapExceeded)?;
+
+    if let Some(asset) = asset.as_mut() {
+        asset.outstanding_notional = asset.outstanding_after_buy(quote.collateral_amount)?;
+    }
+    // Users without a position account don't track their trades
+    let mut position = user_position_info
+        .map(|info| load_user_position(info, synchronizer_authority_info.key, user_authority_info.key, fiat_asset_mint_info.key))
+        .transpose()?
+        .flatten();
+    if let Some(position) = position.as_mut() {
+        position.record_buy(amount, &collateral_mint, settlement.user_collateral_amount, settlement.fee_amount, clock.slot)?;
+    }
+    Self::record_volume(
+        synchronizer_authority_info.key,
+        user_authority_info,
+        rate_limit_info,
+        user_volume_info,
+        system_program_info,
+        &clock,
+        quote.collateral_amount,
+    )?;
+    Self::record_fee_volume(
+        fee_schedule.as_ref(),
+        synchronizer_authority_info.key,
+        user_authority_info,
+        user_volume_info,
+        system_program_info,
+        &clock,
+        quote.collateral_amount,
+    )?;
 
     // User send collateral token to synchronizer
     let instruction = spl_token::instruction::transfer(
-        &spl_token::id(),
+        spl_token_info.key,
         &user_collateral_account_info.key,
         &synchronizer_collateral_account_info.key,
         &user_authority_info.key,
         &[],
-        collateral_amount + fee_amount
-    ).unwrap();
+        settlement.user_collateral_amount
+    )?;
     let account_infos = [
         spl_token_info.clone(),
         user_collateral_account_info.clone(),
@@ -138,17 +163,17 @@ pub fn process_buy_for(
         user_authority_info.clone(),
     ];
     invoke(&instruction, &account_infos)?;
-    msg!("Transfer {} collateral tokens from user to synchronizer", collateral_amount + fee_amount);
+    msg!("Transfer {} collateral tokens from user to synchronizer", settlement.user_collateral_amount);
 
     // Synchronizer mint fiat asset to user associated token account
     let instruction = spl_token::instruction::mint_to(
-        &spl_token::id(),
+        spl_token_info.key,
         &fiat_asset_mint_info.key,
         &user_fiat_account_info.key,
         &synchronizer_authority_info.key,
         &[],
         amount
-    ).unwrap();
+    )?;
     let account_infos = [
         spl_token_info.clone(),
         fiat_asset_mint_info.clone(),
@@ -158,9 +183,56 @@ pub fn process_buy_for(
     invoke(&instruction, &account_infos)?;
     msg!("Mint {} fiat tokens to user_account", {amount});
 
-    synchronizer.remaining_dollar_cap -= spl_token::ui_amount_to_amount(collateral_amount_ui * multiplier as f64, decimals);
-    synchronizer.withdrawable_fee_amount += fee_amount;
+    Self::pay_referral(spl_token_info, synchronizer_collateral_account_info, referrer_info, synchronizer_authority_info, referral_amount)?;
+
+    let withdrawable_fee_amount = match accepted.as_mut() {
+        Some((set, index)) => &mut set.collaterals[*index].withdrawable_fee_amount,
+        None => &mut synchronizer.withdrawable_fee_amount,
+    };
+    *withdrawable_fee_amount = Self::accrue_fee(*withdrawable_fee_amount, settlement.fee_amount, referral_amount)?;
+    SynchronizerEvent::BuyExecuted {
+        synchronizer: *synchronizer_authority_info.key,
+        user: *user_authority_info.key,
+        fiat_asset_mint: *fiat_asset_mint_info.key,
+        amount,
+        price: quote.price,
+        collateral_amount: quote.collateral_amount,
+        fee_amount: quote.fee_amount,
+        dollar_cap_amount: quote.dollar_cap_amount,
+        remaining_dollar_cap: synchronizer.remaining_dollar_cap,
+    }.emit();
+    match (accepted, referrer_info) {
+        // Amounts of the trade event are in the Synchronizer collateral token
+        (Some((set, index)), _) => SynchronizerEvent::CollateralSettled {
+            synchronizer: *synchronizer_authority_info.key,
+            user: *user_authority_info.key,
+            fiat_asset_mint: *fiat_asset_mint_info.key,
+            collateral_mint,
+            buy: true,
+            user_collateral_amount: settlement.user_collateral_amount,
+            fee_amount: settlement.fee_amount,
+            referral_amount,
+            withdrawable_fee_amount: set.collaterals[index].withdrawable_fee_amount,
+        }.emit(),
+        (None, Some(referrer_info)) if referral_amount > 0 => SynchronizerEvent::ReferralFeePaid {
+            synchronizer: *synchronizer_authority_info.key,
+            user: *user_authority_info.key,
+            referrer: *referrer_info.key,
+            fiat_asset_mint: *fiat_asset_mint_info.key,
+            amount: referral_amount,
+        }.emit(),
+        _ => {}
+    }
     SynchronizerData::pack(synchronizer, &mut synchronizer_authority_info.data.borrow_mut())?;
+    if let (Some((set, _)), Some(collateral_set_info)) = (accepted, collateral_set_info) {
+        CollateralSetData::pack(set, &mut collateral_set_info.data.borrow_mut())?;
+    }
+    if let (Some(asset), Some(asset_info)) = (asset, asset_info) {
+        AssetData::pack(asset, &mut asset_info.data.borrow_mut())?;
+    }
+    if let (Some(position), Some(user_position_info)) = (position, user_position_info) {
+        UserPositionData::pack(position, &mut user_position_info.data.borrow_mut())?;
+    }
 
     Ok(())
 }
@@ -171,6 +243,7 @@ pub fn process_sell_for(
     amount: u64,
     fee: u64,
     prices: &Vec<u64>,
+    optional_accounts: u8,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let fiat_asset_mint_info = next_account_info(account_info_iter)?;
@@ -181,92 +254,112 @@ pub fn process_sell_for(
     let synchronizer_authority_info = next_account_info(account_info_iter)?;
     let spl_token_info = next_account_info(account_info_iter)?;
 
-    if !synchronizer_authority_info.owner.eq(&id()) {
-        return Err(SynchronizerError::AccessDenied.into());
-    }
-    if !synchronizer_authority_info.is_signer {
-        return Err(SynchronizerError::InvalidSigner.into());
-    }
+    check_token_program(spl_token_info)?;
+    check_synchronizer_authority(synchronizer_authority_info)?;
     if !user_authority_info.is_signer {
         return Err(SynchronizerError::InvalidSigner.into());
     }
 
-    let mut synchronizer = SynchronizerData::unpack_unchecked(&synchronizer_authority_info.data.borrow())?;
-    if !synchronizer.is_initialized {
-        return Err(SynchronizerError::NotInitialized.into());
-    }
+    let mut synchronizer = load_synchronizer(synchronizer_authority_info)?;
+    let TradeAccountInfos {
+        clock,
+        asset: asset_info,
+        rate_limit: rate_limit_info,
+        user_volume: user_volume_info,
+        system_program: system_program_info,
+        user_position: user_position_info,
+        market_schedule: market_schedule_info,
+        fee_schedule: fee_schedule_info,
+        referrer: referrer_info,
+        collateral_set: collateral_set_info,
+    } = Self::next_trade_accounts(account_info_iter, &synchronizer, optional_accounts)?;
 
     let oracles_infos = account_info_iter.as_slice();
-    if oracles_infos.len() < synchronizer.minimum_required_signature as usize {
-        return Err(SynchronizerError::NotEnoughOracles.into());
-    }
     if prices.len() < synchronizer.minimum_required_signature as usize {
         return Err(SynchronizerError::NotEnoughOracles.into());
     }
 
-    let mut price = prices[0];
-    for i in 0..synchronizer.minimum_required_signature as usize {
-        let oracle = oracles_infos.iter().next().unwrap();
-        if !synchronizer.oracles.contains(&oracle.key) || !oracle.is_signer {
-            return Err(SynchronizerError::BadOracle.into());
-        }
-
-        if prices[i] < price {
-            price = prices[i];
-        }
-    }
-
-    let synchronizer_collateral_account = Account::unpack(&synchronizer_collateral_account_info.data.borrow()).unwrap();
-    let user_collateral_account = Account::unpack(&user_collateral_account_info.data.borrow()).unwrap();
-    if !synchronizer_collateral_account.mint.eq(&synchronizer.collateral_token_key) {
-        return Err(SynchronizerError::BadCollateralMint.into());
-    }
-    if !user_collateral_account.mint.eq(&synchronizer.collateral_token_key) {
-        return Err(SynchronizerError::BadCollateralMint.into());
-    }
-    if !synchronizer_collateral_account.owner.eq(synchronizer_authority_info.key) {
-        return Err(TokenError::OwnerMismatch.into());
-    }
-    if !user_collateral_account.owner.eq(user_authority_info.key) {
-        return Err(TokenError::OwnerMismatch.into());
-    }
-
-    let user_fiat_account = Account::unpack(&user_fiat_account_info.data.borrow()).unwrap();
-    if !user_fiat_account.owner.eq(user_authority_info.key) {
-        return Err(TokenError::OwnerMismatch.into());
-    }
-
-    let decimals= Mint::unpack(&fiat_asset_mint_info.data.borrow_mut()).unwrap().decimals;
-    if decimals != Self::DEFAULT_DECIMALS {
-        return Err(SynchronizerError::BadDecimals.into());
-    }
-
-    msg!("Process sell_for, user fiat amount: {}, collateral price: {}", amount, price);
+    Self::check_oracle_signers(&synchronizer, oracles_infos, synchronizer.minimum_required_signature as usize)?;
 
-    let collateral_amount_ui=spl_token::amount_to_ui_amount(amount, decimals) * spl_token::amount_to_ui_amount(price, decimals);
-    let fee_amount_ui = collateral_amount_ui * spl_token::amount_to_ui_amount(fee, decimals);
-    msg!("collateral_amount_ui: {}, fee_amount_ui: {}", collateral_amount_ui, fee_amount_ui);
-
-    let collateral_amount = spl_token::ui_amount_to_amount(collateral_amount_ui, decimals);
-    let fee_amount = spl_token::ui_amount_to_amount(fee_amount_ui, decimals);
-    msg!("collateral_amount: {}, fee_amount: {}", collateral_amount, fee_amount);
+    // Trades passing the vault of an accepted collateral are settled in that collateral
+    let mut accepted = match collateral_set_info {
+        Some(collateral_set_info) => Self::accepted_collateral(collateral_set_info, synchronizer_authority_info.key, synchronizer_collateral_account_info.key)?,
+        None => None,
+    };
+    let collateral_mint = accepted.map_or(synchronizer.collateral_token_key, |(set, index)| set.collaterals[index].mint);
+    let synchronizer_collateral_account = load_collateral_account(synchronizer_collateral_account_info, &collateral_mint, Some(synchronizer_authority_info.key))?;
+    load_collateral_account(user_collateral_account_info, &collateral_mint, Some(user_authority_info.key))?;
+    let has_referrer = Self::check_referrer(referrer_info, synchronizer_collateral_account_info.key, &collateral_mint, user_authority_info.key)?;
+
+    let fiat_decimals = load_fiat_mint(fiat_asset_mint_info, synchronizer_authority_info.key)?.decimals;
+    let user_fiat_account = load_fiat_account(user_fiat_account_info, fiat_asset_mint_info.key, Some(user_authority_info.key))?;
+    Self::check_market_open(market_schedule_info, synchronizer_authority_info.key, fiat_asset_mint_info.key, &clock)?;
+
+    let mut asset = asset_info.map(|info| load_asset(info, synchronizer_authority_info.key, fiat_asset_mint_info.key)).transpose()?.flatten();
+    let bid_spread_bps = asset.map_or(0, |asset| asset.bid_spread_bps);
+    let fee_schedule = fee_schedule_info.map(|info| load_fee_schedule(info, synchronizer_authority_info.key)).transpose()?.flatten();
+    let fee = Self::trade_fee(fee_schedule.as_ref(), synchronizer_authority_info.key, user_authority_info.key, user_volume_info, &clock, fee)?;
+    let collateral_decimals = synchronizer.collateral_token_decimals();
+    let quote = quote_sell(signed_prices(prices, synchronizer.minimum_required_signature), amount, fee, multiplier, fiat_decimals, collateral_decimals, bid_spread_bps)?;
+    let settlement = match accepted {
+        Some((set, index)) => settle_sell(&quote, set.collaterals[index].current_rate(clock.unix_timestamp)?, set.collaterals[index].decimals)?,
+        None => Settlement::from(&quote),
+    };
+    let referral_amount = Self::referral_amount(fee_schedule.as_ref(), has_referrer, settlement.fee_amount);
+    msg!("Process sell_for, user fiat amount: {}, collateral price: {}", amount, quote.price);
+    msg!("collateral_amount: {}, fee_amount: {}, referral_amount: {}", quote.collateral_amount, quote.fee_amount, referral_amount);
 
     if user_fiat_account.amount < amount {
         return Err(SynchronizerError::InsufficientFunds.into());
     }
-    if synchronizer_collateral_account.amount < (collateral_amount - fee_amount) {
+    // The referral is paid from the fee, which stays in the Synchronizer collateral account
+    let paid_amount = settlement.user_collateral_amount.checked_add(referral_amount)
+        .ok_or(SynchronizerError::InsufficientFunds)?;
+    if synchronizer_collateral_account.amount < paid_amount {
         return Err(SynchronizerError::InsufficientFunds.into());
     }
+    synchronizer.remaining_dollar_cap = synchronizer.remaining_dollar_cap.saturating_add(quote.dollar_cap_amount);
+
+    if let Some(asset) = asset.as_mut() {
+        // Fiat assets bought before the asset account was created aren't counted
+        asset.outstanding_notional = asset.outstanding_notional.saturating_sub(quote.collateral_amount);
+    }
+    // Users without a position account don't track their trades
+    let mut position = user_position_info
+        .map(|info| load_user_position(info, synchronizer_authority_info.key, user_authority_info.key, fiat_asset_mint_info.key))
+        .transpose()?
+        .flatten();
+    if let Some(position) = position.as_mut() {
+        position.record_sell(amount, &collateral_mint, settlement.user_collateral_amount, settlement.fee_amount, clock.slot)?;
+    }
+    Self::record_volume(
+        synchronizer_authority_info.key,
+        user_authority_info,
+        rate_limit_info,
+        user_volume_info,
+        system_program_info,
+        &clock,
+        quote.collateral_amount,
+    )?;
+    Self::record_fee_volume(
+        fee_schedule.as_ref(),
+        synchronizer_authority_info.key,
+        user_authority_info,
+        user_volume_info,
+        system_program_info,
+        &clock,
+        quote.collateral_amount,
+    )?;
 
     // Burn fiat asset from user
     let instruction = spl_token::instruction::burn(
-        &spl_token::id(),
+        spl_token_info.key,
         &user_fiat_account_info.key,
         &fiat_asset_mint_info.key,
         &user_authority_info.key,
         &[],
         amount
-    ).unwrap();
+    )?;
     let account_infos = [
         spl_token_info.clone(),
         user_fiat_account_info.clone(),
@@ -278,12 +371,12 @@ pub fn process_sell_for(
 
     // Transfer collateral token from synchronizer to user
     let instruction = spl_token::instruction::transfer(
-        &spl_token::id(),
+        spl_token_info.key,
         &synchronizer_collateral_account_info.key,
         &user_collateral_account_info.key,
         &synchronizer_authority_info.key,
         &[],
-        collateral_amount - fee_amount
+        settlement.user_collateral_amount
     )?;
     let account_infos = [
         spl_token_info.clone(),
@@ -292,11 +385,58 @@ pub fn process_sell_for(
         synchronizer_authority_info.clone(),
     ];
     invoke(&instruction, &account_infos)?;
-    msg!("Transfer {} collateral asset from synchronizer to user", collateral_amount - fee_amount);
+    msg!("Transfer {} collateral asset from synchronizer to user", settlement.user_collateral_amount);
 
-    synchronizer.remaining_dollar_cap += spl_token::ui_amount_to_amount(collateral_amount_ui * multiplier as f64, decimals);
-    synchronizer.withdrawable_fee_amount += fee_amount;
+    Self::pay_referral(spl_token_info, synchronizer_collateral_account_info, referrer_info, synchronizer_authority_info, referral_amount)?;
+
+    let withdrawable_fee_amount = match accepted.as_mut() {
+        Some((set, index)) => &mut set.collaterals[*index].withdrawable_fee_amount,
+        None => &mut synchronizer.withdrawable_fee_amount,
+    };
+    *withdrawable_fee_amount = Self::accrue_fee(*withdrawable_fee_amount, settlement.fee_amount, referral_amount)?;
+    SynchronizerEvent::SellExecuted {
+        synchronizer: *synchronizer_authority_info.key,
+        user: *user_authority_info.key,
+        fiat_asset_mint: *fiat_asset_mint_info.key,
+        amount,
+        price: quote.price,
+        collateral_amount: quote.collateral_amount,
+        fee_amount: quote.fee_amount,
+        dollar_cap_amount: quote.dollar_cap_amount,
+        remaining_dollar_cap: synchronizer.remaining_dollar_cap,
+    }.emit();
+    match (accepted, referrer_info) {
+        // Amounts of the trade event are in the Synchronizer collateral token
+        (Some((set, index)), _) => SynchronizerEvent::CollateralSettled {
+            synchronizer: *synchronizer_authority_info.key,
+            user: *user_authority_info.key,
+            fiat_asset_mint: *fiat_asset_mint_info.key,
+            collateral_mint,
+            buy: false,
+            user_collateral_amount: settlement.user_collateral_amount,
+            fee_amount: settlement.fee_amount,
+            referral_amount,
+            withdrawable_fee_amount: set.collaterals[index].withdrawable_fee_amount,
+        }.emit(),
+        (None, Some(referrer_info)) if referral_amount > 0 => SynchronizerEvent::ReferralFeePaid {
+            synchronizer: *synchronizer_authority_info.key,
+            user: *user_authority_info.key,
+            referrer: *referrer_info.key,
+            fiat_asset_mint: *fiat_asset_mint_info.key,
+            amount: referral_amount,
+        }.emit(),
+        _ => {}
+    }
     SynchronizerData::pack(synchronizer, &mut synchronizer_authority_info.data.borrow_mut())?;
+    if let (Some((set, _)), Some(collateral_set_info)) = (accepted, collateral_set_info) {
+        CollateralSetData::pack(set, &mut collateral_set_info.data.borrow_mut())?;
+    }
+    if let (Some(asset), Some(asset_info)) = (asset, asset_info) {
+        AssetData::pack(asset, &mut asset_info.data.borrow_mut())?;
+    }
+    if let (Some(position), Some(user_position_info)) = (position, user_position_info) {
+        UserPositionData::pack(position, &mut user_position_info.data.borrow_mut())?;
+    }
 
     Ok(())
 }
@@ -312,14 +452,14 @@ pub fn process_initialize_synchronizer_account(
     let account_info_iter = &mut accounts.iter();
     let synchronizer_account_info = next_account_info(account_info_iter)?;
     let rent_account_info = next_account_info(account_info_iter)?;
+    let collateral_mint_info = account_info_iter.next();
 
-    if !synchronizer_account_info.owner.eq(&id()) {
-        return Err(SynchronizerError::AccessDenied.into());
-    }
-
-    if !synchronizer_account_info.is_signer {
-        return Err(SynchronizerError::InvalidSigner.into());
-    }
+    check_synchronizer_authority(synchronizer_account_info)?;
+    // The legacy layout has no collateral mint account
+    let collateral_decimals = match collateral_mint_info {
+        Some(collateral_mint_info) => Some(load_collateral_mint(collateral_mint_info, &collateral_token_key)?.decimals),
+        None => None,
+    };
 
     if oracles.len() > MAX_ORACLES {
         return Err(SynchronizerError::MaxOraclesExceed.into());
@@ -342,6 +482,7 @@ pub fn process_initialize_synchronizer_account(
 
     synchronizer.is_initialized = true;
     synchronizer.collateral_token_key = collateral_token_key;
+    synchronizer.collateral_decimals = collateral_decimals;
     synchronizer.remaining_dollar_cap = remaining_dollar_cap;
     synchronizer.withdrawable_fee_amount = withdrawable_fee_amount;
     synchronizer.minimum_required_signature = minimum_required_signature;
@@ -350,6 +491,14 @@ pub fn process_initialize_synchronizer_account(
     }
 
     SynchronizerData::pack(synchronizer, &mut synchronizer_account_info.data.borrow_mut())?;
+    SynchronizerEvent::SynchronizerInitialized {
+        synchronizer: *synchronizer_account_info.key,
+        collateral_token_key,
+        remaining_dollar_cap,
+        withdrawable_fee_amount,
+        minimum_required_signature,
+        oracles,
+    }.emit();
 
     Ok(())
 }
@@ -361,13 +510,7 @@ pub fn process_set_minimum_required_signature(
     let account_info_iter = &mut accounts.iter();
     let synchronizer_account_info = next_account_info(account_info_iter)?;
 
-    if !synchronizer_account_info.owner.eq(&id()) {
-        return Err(SynchronizerError::AccessDenied.into());
-    }
-
-    if !synchronizer_account_info.is_signer {
-        return Err(SynchronizerError::InvalidSigner.into());
-    }
+    check_synchronizer_authority(synchronizer_account_info)?;
 
     if minimum_required_signature > MAX_ORACLES as u8 {
         return Err(SynchronizerError::MaxOraclesExceed.into());
@@ -377,14 +520,15 @@ pub fn process_set_minimum_required_signature(
         return Err(SynchronizerError::MaxSignersExceed.into());
     }
 
-    let mut synchronizer = SynchronizerData::unpack_unchecked(&synchronizer_account_info.data.borrow())?;
-    if !synchronizer.is_initialized {
-        return Err(SynchronizerError::NotInitialized.into());
-    }
+    let mut synchronizer = load_synchronizer(synchronizer_account_info)?;
 
     msg!("Set minimum required signature {}", minimum_required_signature);
     synchronizer.minimum_required_signature = minimum_required_signature;
     SynchronizerData::pack(synchronizer, &mut synchronizer_account_info.data.borrow_mut())?;
+    SynchronizerEvent::MinimumRequiredSignatureChanged {
+        synchronizer: *synchronizer_account_info.key,
+        minimum_required_signature,
+    }.emit();
 
     Ok(())
 }
@@ -395,23 +539,26 @@ pub fn process_set_collateral_token(
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let synchronizer_account_info = next_account_info(account_info_iter)?;
+    let collateral_mint_info = next_account_info(account_info_iter)?;
 
-    if !synchronizer_account_info.owner.eq(&id()) {
-        return Err(SynchronizerError::AccessDenied.into());
-    }
-
-    if !synchronizer_account_info.is_signer {
-        return Err(SynchronizerError::InvalidSigner.into());
-    }
+    check_synchronizer_authority(synchronizer_account_info)?;
 
-    let mut synchronizer = SynchronizerData::unpack_unchecked(&synchronizer_account_info.data.borrow())?;
-    if !synchronizer.is_initialized {
-        return Err(SynchronizerError::NotInitialized.into());
+    let mut synchronizer = load_synchronizer(synchronizer_account_info)?;
+    let collateral_mint = load_collateral_mint(collateral_mint_info, &collateral_token_key)?;
+    // The dollar cap and withdrawable fees are in collateral base units and would change value with the decimals
+    if collateral_mint.decimals != synchronizer.collateral_token_decimals()
+        && (synchronizer.remaining_dollar_cap != 0 || synchronizer.withdrawable_fee_amount != 0) {
+        return Err(SynchronizerError::BadDecimals.into());
     }
 
-    msg!("Set collateral token key {}", collateral_token_key);
+    msg!("Set collateral token key {} with {} decimals", collateral_token_key, collateral_mint.decimals);
     synchronizer.collateral_token_key = collateral_token_key;
+    synchronizer.collateral_decimals = Some(collateral_mint.decimals);
     SynchronizerData::pack(synchronizer, &mut synchronizer_account_info.data.borrow_mut())?;
+    SynchronizerEvent::CollateralTokenChanged {
+        synchronizer: *synchronizer_account_info.key,
+        collateral_token_key,
+    }.emit();
 
     Ok(())
 }
@@ -423,22 +570,17 @@ pub fn process_set_remaining_dollar_cap(
     let account_info_iter = &mut accounts.iter();
     let synchronizer_account_info = next_account_info(account_info_iter)?;
 
-    if !synchronizer_account_info.owner.eq(&id()) {
-        return Err(SynchronizerError::AccessDenied.into());
-    }
-
-    if !synchronizer_account_info.is_signer {
-        return Err(SynchronizerError::InvalidSigner.into());
-    }
+    check_synchronizer_authority(synchronizer_account_info)?;
 
-    let mut synchronizer = SynchronizerData::unpack_unchecked(&synchronizer_account_info.data.borrow())?;
-    if !synchronizer.is_initialized {
-        return Err(SynchronizerError::NotInitialized.into());
-    }
+    let mut synchronizer = load_synchronizer(synchronizer_account_info)?;
 
     msg!("Set remaining dollar cap {}", remaining_dollar_cap);
     synchronizer.remaining_dollar_cap = remaining_dollar_cap;
     SynchronizerData::pack(synchronizer, &mut synchronizer_account_info.data.borrow_mut())?;
+    SynchronizerEvent::CapChanged {
+        synchronizer: *synchronizer_account_info.key,
+        remaining_dollar_cap,
+    }.emit();
 
     Ok(())
 }
@@ -450,22 +592,13 @@ pub fn process_set_oracles(
     let account_info_iter = &mut accounts.iter();
     let synchronizer_account_info = next_account_info(account_info_iter)?;
 
-    if !synchronizer_account_info.owner.eq(&id()) {
-        return Err(SynchronizerError::AccessDenied.into());
-    }
-
-    if !synchronizer_account_info.is_signer {
-        return Err(SynchronizerError::InvalidSigner.into());
-    }
+    check_synchronizer_authority(synchronizer_account_info)?;
 
     if oracles.len() > MAX_ORACLES {
         return Err(SynchronizerError::MaxOraclesExceed.into());
     }
 
-    let mut synchronizer = SynchronizerData::unpack_unchecked(&synchronizer_account_info.data.borrow())?;
-    if !synchronizer.is_initialized {
-        return Err(SynchronizerError::NotInitialized.into());
-    }
+    let mut synchronizer = load_synchronizer(synchronizer_account_info)?;
 
     msg!("Set oracles {:?}", oracles);
     for i in 0..MAX_ORACLES {
@@ -476,6 +609,10 @@ pub fn process_set_oracles(
     }
 
     SynchronizerData::pack(synchronizer, &mut synchronizer_account_info.data.borrow_mut())?;
+    SynchronizerEvent::OraclesChanged {
+        synchronizer: *synchronizer_account_info.key,
+        oracles,
+    }.emit();
     Ok(())
 }
 
@@ -488,32 +625,39 @@ pub fn process_withdraw_fee(
     let recipient_collateral_account_info = next_account_info(account_info_iter)?;
     let synchronizer_account_info = next_account_info(account_info_iter)?;
     let spl_token_info = next_account_info(account_info_iter)?;
+    let collateral_set_info = account_info_iter.next();
 
-    if !synchronizer_account_info.owner.eq(&id()) {
-        return Err(SynchronizerError::AccessDenied.into());
-    }
+    check_token_program(spl_token_info)?;
+    check_synchronizer_authority(synchronizer_account_info)?;
 
-    if !synchronizer_account_info.is_signer {
-        return Err(SynchronizerError::InvalidSigner.into());
-    }
+    let mut synchronizer = load_synchronizer(synchronizer_account_info)?;
 
-    let mut synchronizer = SynchronizerData::unpack_unchecked(&synchronizer_account_info.data.borrow())?;
-    if !synchronizer.is_initialized {
-        return Err(SynchronizerError::NotInitialized.into());
-    }
+    // Fees of the trades settled in an accepted collateral are withdrawn from its vault,
+    // withdrawals without the collateral set account are in the Synchronizer collateral token
+    let mut accepted = match collateral_set_info {
+        Some(collateral_set_info) => Self::accepted_collateral(collateral_set_info, synchronizer_account_info.key, synchronizer_collateral_account_info.key)?,
+        None => None,
+    };
+    let collateral_mint = accepted.map_or(synchronizer.collateral_token_key, |(set, index)| set.collaterals[index].mint);
+    let synchronizer_collateral_account = load_collateral_account(synchronizer_collateral_account_info, &collateral_mint, Some(synchronizer_account_info.key))?;
+    load_collateral_account(recipient_collateral_account_info, &collateral_mint, None)?;
 
-    if synchronizer.withdrawable_fee_amount < amount {
+    let withdrawable_fee_amount = accepted.map_or(synchronizer.withdrawable_fee_amount, |(set, index)| set.collaterals[index].withdrawable_fee_amount);
+    if withdrawable_fee_amount < amount {
+        return Err(SynchronizerError::InsufficientFunds.into());
+    }
+    if synchronizer_collateral_account.amount < amount {
         return Err(SynchronizerError::InsufficientFunds.into());
     }
 
     let instruction = spl_token::instruction::transfer(
-        &spl_token::id(),
+        spl_token_info.key,
         &synchronizer_collateral_account_info.key,
         &recipient_collateral_account_info.key,
         &synchronizer_account_info.key,
         &[],
         amount
-    ).unwrap();
+    )?;
     let account_infos = [
         spl_token_info.clone(),
         synchronizer_collateral_account_info.clone(),
@@ -523,8 +667,29 @@ pub fn process_withdraw_fee(
     invoke(&instruction, &account_infos)?;
     msg!("Transfer {} collateral asset from synchronizer to recipient {}", amount, recipient_collateral_account_info.key);
 
-    synchronizer.withdrawable_fee_amount -= amount;
-    SynchronizerData::pack(synchronizer, &mut synchronizer_account_info.data.borrow_mut())?;
+    match (accepted.as_mut(), collateral_set_info) {
+        (Some((set, index)), Some(collateral_set_info)) => {
+            set.collaterals[*index].withdrawable_fee_amount -= amount;
+            CollateralSetData::pack(*set, &mut collateral_set_info.data.borrow_mut())?;
+            SynchronizerEvent::CollateralFeeWithdrawn {
+                synchronizer: *synchronizer_account_info.key,
+                collateral_mint,
+                recipient: *recipient_collateral_account_info.key,
+                amount,
+                withdrawable_fee_amount: set.collaterals[*index].withdrawable_fee_amount,
+            }.emit();
+        }
+        _ => {
+            synchronizer.withdrawable_fee_amount -= amount;
+            SynchronizerData::pack(synchronizer, &mut synchronizer_account_info.data.borrow_mut())?;
+            SynchronizerEvent::FeeWithdrawn {
+                synchronizer: *synchronizer_account_info.key,
+                recipient: *recipient_collateral_account_info.key,
+                amount,
+                withdrawable_fee_amount: synchronizer.withdrawable_fee_amount,
+            }.emit();
+        }
+    }
 
     Ok(())
 }
@@ -538,32 +703,33 @@ pub fn process_withdraw_collateral(
     let recipient_collateral_account_info = next_account_info(account_info_iter)?;
     let synchronizer_account_info = next_account_info(account_info_iter)?;
     let spl_token_info = next_account_info(account_info_iter)?;
+    let collateral_set_info = account_info_iter.next();
 
-    if !synchronizer_account_info.owner.eq(&id()) {
-        return Err(SynchronizerError::AccessDenied.into());
-    }
+    check_token_program(spl_token_info)?;
+    check_synchronizer_authority(synchronizer_account_info)?;
 
-    if !synchronizer_account_info.is_signer {
-        return Err(SynchronizerError::InvalidSigner.into());
-    }
+    let synchronizer = load_synchronizer(synchronizer_account_info)?;
 
-    let synchronizer = SynchronizerData::unpack_unchecked(&synchronizer_account_info.data.borrow())?;
-    if !synchronizer.is_initialized {
-        return Err(SynchronizerError::NotInitialized.into());
-    }
+    let accepted = match collateral_set_info {
+        Some(collateral_set_info) => Self::accepted_collateral(collateral_set_info, synchronizer_account_info.key, synchronizer_collateral_account_info.key)?,
+        None => None,
+    };
+    let collateral_mint = accepted.map_or(synchronizer.collateral_token_key, |(set, index)| set.collaterals[index].mint);
+    let synchronizer_collateral_account = load_collateral_account(synchronizer_collateral_account_info, &collateral_mint, Some(synchronizer_account_info.key))?;
+    load_collateral_account(recipient_collateral_account_info, &collateral_mint, None)?;
 
-    if Account::unpack(&synchronizer_collateral_account_info.data.borrow()).unwrap().amount < amount {
+    if synchronizer_collateral_account.amount < amount {
         return Err(SynchronizerError::InsufficientFunds.into());
     }
 
     let instruction = spl_token::instruction::transfer(
-        &spl_token::id(),
+        spl_token_info.key,
         &synchronizer_collateral_account_info.key,
         &recipient_collateral_account_info.key,
         &synchronizer_account_info.key,
         &[],
         amount
-    ).unwrap();
+    )?;
     let account_infos = [
         spl_token_info.clone(),
         synchronizer_collateral_account_info.clone(),
@@ -572,120 +738,1117 @@ pub fn process_withdraw_collateral(
     ];
     invoke(&instruction, &account_infos)?;
     msg!("Transfer {} collateral asset from synchronizer to recipient {}", amount, recipient_collateral_account_info.key);
+    if accepted.is_some() {
+        SynchronizerEvent::AcceptedCollateralWithdrawn {
+            synchronizer: *synchronizer_account_info.key,
+            collateral_mint,
+            recipient: *recipient_collateral_account_info.key,
+            amount,
+        }.emit();
+    } else {
+        SynchronizerEvent::CollateralWithdrawn {
+            synchronizer: *synchronizer_account_info.key,
+            recipient: *recipient_collateral_account_info.key,
+            amount,
+        }.emit();
+    }
 
     Ok(())
 }
 
-pub fn process_instruction(
-    program_id: &Pubkey,
+pub fn process_migrate_synchronizer_account(
     accounts: &[AccountInfo],
-    instruction_data: &[u8],
 ) -> ProgramResult {
-    msg!("Synchronizer entrypoint");
-    check_program_account(program_id)?;
-
-    let instruction = SynchronizerInstruction::unpack(instruction_data)?;
-    match instruction {
-        // Public instructions
-        SynchronizerInstruction::BuyFor {
-            multiplier,
-            amount,
-            fee,
-            ref prices,
-        } => {
-            msg!("Instruction: BuyFor");
-            Self::process_buy_for(accounts, multiplier, amount, fee, prices)
-        }
-        SynchronizerInstruction::SellFor {
-            multiplier,
-            amount,
-            fee,
-            ref prices,
-        } => {
-            msg!("Instruction: SellFor");
-            Self::process_sell_for(accounts, multiplier, amount, fee, prices)
-        }
+    let account_info_iter = &mut accounts.iter();
+    let synchronizer_account_info = next_account_info(account_info_iter)?;
+    let new_synchronizer_account_info = next_account_info(account_info_iter)?;
+    let rent_account_info = next_account_info(account_info_iter)?;
+    let spl_token_info = next_account_info(account_info_iter)?;
 
-        // Admin Instructions
-        SynchronizerInstruction::InitializeSynchronizerAccount {
-            collateral_token_key,
-            remaining_dollar_cap,
-            withdrawable_fee_amount,
-            minimum_required_signature,
-            oracles
-        } => {
-            msg!("Instruction: InitializeSynchronizerAccount");
-            Self::process_initialize_synchronizer_account(accounts, collateral_token_key, remaining_dollar_cap, withdrawable_fee_amount, minimum_required_signature, oracles)
-        }
+    check_token_program(spl_token_info)?;
+    check_synchronizer_authority(synchronizer_account_info)?;
+    check_synchronizer_authority(new_synchronizer_account_info)?;
 
-        SynchronizerInstruction::SetMinimumRequiredSignature {
-            minimum_required_signature
-        } => {
-            msg!("Instruction: SetMinimumRequiredSignature");
-            Self::process_set_minimum_required_signature(accounts, minimum_required_signature)
-        }
+    let (version, synchronizer) = SynchronizerData::unpack_versioned(&synchronizer_account_info.data.borrow())?;
+    if !synchronizer.is_initialized {
+        return Err(SynchronizerError::NotInitialized.into());
+    }
+    if version == SYNCHRONIZER_DATA_VERSION {
+        return Err(SynchronizerError::AlreadyMigrated.into());
+    }
 
-        SynchronizerInstruction::SetCollateralToken {
-            collateral_token_key
-        } => {
-            msg!("Instruction: SetCollateralToken");
-            Self::process_set_collateral_token(accounts, collateral_token_key)
-        }
+    let rent = &Rent::from_account_info(rent_account_info)?;
+    if !rent.is_exempt(new_synchronizer_account_info.lamports(), new_synchronizer_account_info.data_len()) {
+        return Err(SynchronizerError::NotRentExempt.into());
+    }
 
-        SynchronizerInstruction::SetOracles {
-            oracles
-        } => {
-            msg!("Instruction: SetOracles");
-            Self::process_set_oracles(accounts, oracles)
-        }
+    let new_synchronizer = SynchronizerData::unpack_unchecked(&new_synchronizer_account_info.data.borrow())?;
+    if new_synchronizer.is_initialized {
+        return Err(SynchronizerError::AlreadyInitialized.into());
+    }
 
-        SynchronizerInstruction::SetRemainingDollarCap {
-            remaining_dollar_cap
-        } => {
-            msg!("Instruction: SetRemainingDollarCap");
-            Self::process_set_remaining_dollar_cap(accounts, remaining_dollar_cap)
-        }
+    // The Synchronizer account is the authority of its token accounts and fiat asset mints
+    for authority_account_info in account_info_iter {
+        let authority_type = if authority_account_info.data_len() == Mint::LEN {
+            let mint = load_mint(authority_account_info)?;
+            if mint.mint_authority != COption::Some(*synchronizer_account_info.key) {
+                return Err(SynchronizerError::BadMintAuthority.into());
+            }
+            AuthorityType::MintTokens
+        } else {
+            load_token_account(authority_account_info, Some(synchronizer_account_info.key))?;
+            AuthorityType::AccountOwner
+        };
+
+        let instruction = spl_token::instruction::set_authority(
+            spl_token_info.key,
+            authority_account_info.key,
+            Some(new_synchronizer_account_info.key),
+            authority_type,
+            synchronizer_account_info.key,
+            &[]
+        )?;
+        let account_infos = [
+            spl_token_info.clone(),
+            authority_account_info.clone(),
+            synchronizer_account_info.clone(),
+        ];
+        invoke(&instruction, &account_infos)?;
+        msg!("Move authority of {} to the new Synchronizer account", authority_account_info.key);
+    }
 
-        SynchronizerInstruction::WithdrawFee {
-            amount
-        } => {
-            msg!("Instruction: WithdrawFee");
-            Self::process_withdraw_fee(accounts, amount)
-        }
+    SynchronizerData::pack(synchronizer, &mut new_synchronizer_account_info.data.borrow_mut())?;
 
-        SynchronizerInstruction::WithdrawCollateral {
-            amount
-        } => {
-            msg!("Instruction: WithdrawCollateral");
-            Self::process_withdraw_collateral(accounts, amount)
-        }
+    // Close the old account
+    let new_lamports = new_synchronizer_account_info.lamports()
+        .checked_add(synchronizer_account_info.lamports())
+        .ok_or(ProgramError::InvalidArgument)?;
+    **new_synchronizer_account_info.lamports.borrow_mut() = new_lamports;
+    **synchronizer_account_info.lamports.borrow_mut() = 0;
+    for byte in synchronizer_account_info.data.borrow_mut().iter_mut() {
+        *byte = 0;
     }
+    msg!("Synchronizer account migrated from version {} to {}", version, new_synchronizer_account_info.key);
+    SynchronizerEvent::SynchronizerMigrated {
+        synchronizer: *synchronizer_account_info.key,
+        new_synchronizer: *new_synchronizer_account_info.key,
+        from_version: version,
+    }.emit();
+
+    Ok(())
 }
 
-} // impl Processor
+pub fn process_set_asset_caps(
+    accounts: &[AccountInfo],
+    fiat_asset_mint: Pubkey,
+    max_outstanding_notional: u64,
+    max_transaction_notional: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let synchronizer_account_info = next_account_info(account_info_iter)?;
+    let asset_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_account_info = next_account_info(account_info_iter)?;
 
-impl PrintProgramError for SynchronizerError {
-    fn print<E>(&self)
-    where
-        E: 'static + std::error::Error + DecodeError<E> + PrintProgramError + FromPrimitive,
-    {
-        match self {
-            SynchronizerError::AlreadyInitialized => msg!("Error: Synchronizer account already initialized"),
-            SynchronizerError::NotInitialized => msg!("Error: Synchronizer account is not initialized"),
-            SynchronizerError::NotRentExempt => msg!("Error: Lamport balance below rent-exempt threshold"),
-            SynchronizerError::InsufficientFunds => msg!("Error: Insufficient funds"),
-            SynchronizerError::AccessDenied => msg!("Error: Access Denied"),
+    check_synchronizer_authority(synchronizer_account_info)?;
+    let synchronizer = load_synchronizer(synchronizer_account_info)?;
 
-            SynchronizerError::NotEnoughOracles => msg!("Error: Not enough oracles"),
-            SynchronizerError::MaxOraclesExceed => msg!("Error: Exceed limit of maximum oracles"),
-            SynchronizerError::MaxSignersExceed => msg!("Error: Exceed limit of maximum signers"),
-            SynchronizerError::BadOracle => msg!("Error: signer is not an oracle"),
-            SynchronizerError::BadMintAuthority => msg!("Error: Bad mint authority"),
+    let (address, bump_seed) = AssetData::find_address(synchronizer_account_info.key, &fiat_asset_mint);
+    if !asset_info.key.eq(&address) {
+        return Err(SynchronizerError::InvalidAssetAccount.into());
+    }
+
+    // Create the asset account on first use
+    if !asset_info.owner.eq(&id()) {
+        let rent = &Rent::from_account_info(rent_account_info)?;
+        let seeds: &[&[u8]] = &[ASSET_SEED, synchronizer_account_info.key.as_ref(), fiat_asset_mint.as_ref(), &[bump_seed]];
+        Self::create_program_account(payer_info, asset_info, system_program_info, rent, seeds, AssetData::LEN)?;
+        msg!("Create asset account {}", asset_info.key);
+    }
+
+    let mut asset = match load_asset(asset_info, synchronizer_account_info.key, &fiat_asset_mint)? {
+        Some(asset) => asset,
+        None => AssetData {
+            is_initialized: true,
+            synchronizer: *synchronizer_account_info.key,
+            fiat_asset_mint,
+            bump_seed,
+            ..AssetData::default()
+        },
+    };
+
+    msg!("Set asset caps {} {}", max_outstanding_notional, max_transaction_notional);
+    asset.max_outstanding_notional = max_outstanding_notional;
+    asset.max_transaction_notional = max_transaction_notional;
+    AssetData::pack(asset, &mut asset_info.data.borrow_mut())?;
+    Self::require_trade_accounts(synchronizer_account_info, synchronizer, TRADE_ASSET_ACCOUNT)?;
+    SynchronizerEvent::AssetCapsChanged {
+        synchronizer: *synchronizer_account_info.key,
+        fiat_asset_mint,
+        max_outstanding_notional,
+        max_transaction_notional,
+    }.emit();
+
+    Ok(())
+}
+
+pub fn process_set_rate_limits(
+    accounts: &[AccountInfo],
+    window_slots: u64,
+    max_synchronizer_notional: u64,
+    max_user_notional: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let synchronizer_account_info = next_account_info(account_info_iter)?;
+    let rate_limit_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_account_info = next_account_info(account_info_iter)?;
+
+    check_synchronizer_authority(synchronizer_account_info)?;
+    let synchronizer = load_synchronizer(synchronizer_account_info)?;
+
+    let (address, bump_seed) = RateLimitData::find_address(synchronizer_account_info.key);
+    if !rate_limit_info.key.eq(&address) {
+        return Err(SynchronizerError::InvalidRateLimitAccount.into());
+    }
+
+    // Create the rate limit account on first use
+    if !rate_limit_info.owner.eq(&id()) {
+        let rent = &Rent::from_account_info(rent_account_info)?;
+        let seeds: &[&[u8]] = &[RATE_LIMIT_SEED, synchronizer_account_info.key.as_ref(), &[bump_seed]];
+        Self::create_program_account(payer_info, rate_limit_info, system_program_info, rent, seeds, RateLimitData::LEN)?;
+        msg!("Create rate limit account {}", rate_limit_info.key);
+    }
+
+    let mut rate_limit = match load_rate_limit(rate_limit_info, synchronizer_account_info.key)? {
+        Some(rate_limit) => rate_limit,
+        None => RateLimitData {
+            is_initialized: true,
+            synchronizer: *synchronizer_account_info.key,
+            bump_seed,
+            ..RateLimitData::default()
+        },
+    };
+
+    msg!("Set rate limits {} {} {}", window_slots, max_synchronizer_notional, max_user_notional);
+    rate_limit.window_slots = window_slots;
+    rate_limit.max_synchronizer_notional = max_synchronizer_notional;
+    rate_limit.max_user_notional = max_user_notional;
+    RateLimitData::pack(rate_limit, &mut rate_limit_info.data.borrow_mut())?;
+    Self::require_trade_accounts(synchronizer_account_info, synchronizer, TRADE_RATE_LIMIT_ACCOUNT | TRADE_USER_VOLUME_ACCOUNT)?;
+    SynchronizerEvent::RateLimitsChanged {
+        synchronizer: *synchronizer_account_info.key,
+        window_slots,
+        max_synchronizer_notional,
+        max_user_notional,
+    }.emit();
+
+    Ok(())
+}
+
+pub fn process_create_user_position(
+    accounts: &[AccountInfo],
+    fiat_asset_mint: Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let user_authority_info = next_account_info(account_info_iter)?;
+    let synchronizer_account_info = next_account_info(account_info_iter)?;
+    let user_position_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_account_info = next_account_info(account_info_iter)?;
+
+    if !user_authority_info.is_signer {
+        return Err(SynchronizerError::InvalidSigner.into());
+    }
+    load_synchronizer(synchronizer_account_info)?;
+
+    let (address, bump_seed) = UserPositionData::find_address(synchronizer_account_info.key, user_authority_info.key, &fiat_asset_mint);
+    if !user_position_info.key.eq(&address) {
+        return Err(SynchronizerError::InvalidUserPositionAccount.into());
+    }
+    if load_user_position(user_position_info, synchronizer_account_info.key, user_authority_info.key, &fiat_asset_mint)?.is_some() {
+        return Err(SynchronizerError::AlreadyInitialized.into());
+    }
+
+    if !user_position_info.owner.eq(&id()) {
+        let rent = &Rent::from_account_info(rent_account_info)?;
+        let seeds: &[&[u8]] = &[
+            USER_POSITION_SEED,
+            synchronizer_account_info.key.as_ref(),
+            user_authority_info.key.as_ref(),
+            fiat_asset_mint.as_ref(),
+            &[bump_seed],
+        ];
+        Self::create_program_account(user_authority_info, user_position_info, system_program_info, rent, seeds, UserPositionData::LEN)?;
+    }
+
+    msg!("Create user position account {}", user_position_info.key);
+    let position = UserPositionData {
+        is_initialized: true,
+        synchronizer: *synchronizer_account_info.key,
+        user: *user_authority_info.key,
+        fiat_asset_mint,
+        bump_seed,
+        ..UserPositionData::default()
+    };
+    UserPositionData::pack(position, &mut user_position_info.data.borrow_mut())?;
+    SynchronizerEvent::UserPositionCreated {
+        synchronizer: *synchronizer_account_info.key,
+        user: *user_authority_info.key,
+        fiat_asset_mint,
+    }.emit();
+
+    Ok(())
+}
+
+pub fn process_close_user_position(
+    accounts: &[AccountInfo],
+    fiat_asset_mint: Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let user_authority_info = next_account_info(account_info_iter)?;
+    let synchronizer_account_info = next_account_info(account_info_iter)?;
+    let user_position_info = next_account_info(account_info_iter)?;
+    let recipient_info = next_account_info(account_info_iter)?;
+
+    if !user_authority_info.is_signer {
+        return Err(SynchronizerError::InvalidSigner.into());
+    }
+    // The Synchronizer may be gone, its key only derives the position address
+    if load_user_position(user_position_info, synchronizer_account_info.key, user_authority_info.key, &fiat_asset_mint)?.is_none() {
+        return Err(SynchronizerError::NotInitialized.into());
+    }
+
+    msg!("Close user position account {}", user_position_info.key);
+    let recipient_lamports = recipient_info.lamports()
+        .checked_add(user_position_info.lamports())
+        .ok_or(ProgramError::InvalidArgument)?;
+    **recipient_info.lamports.borrow_mut() = recipient_lamports;
+    **user_position_info.lamports.borrow_mut() = 0;
+    for byte in user_position_info.data.borrow_mut().iter_mut() {
+        *byte = 0;
+    }
+    SynchronizerEvent::UserPositionClosed {
+        synchronizer: *synchronizer_account_info.key,
+        user: *user_authority_info.key,
+        fiat_asset_mint,
+        recipient: *recipient_info.key,
+    }.emit();
+
+    Ok(())
+}
+
+pub fn process_set_market_schedule(
+    accounts: &[AccountInfo],
+    fiat_asset_mint: Pubkey,
+    weekly_hours: [TradingHours; 7],
+    holidays: Vec<u32>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let synchronizer_account_info = next_account_info(account_info_iter)?;
+    let market_schedule_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_account_info = next_account_info(account_info_iter)?;
+
+    check_synchronizer_authority(synchronizer_account_info)?;
+    let synchronizer = load_synchronizer(synchronizer_account_info)?;
+
+    let (address, bump_seed) = MarketScheduleData::find_address(synchronizer_account_info.key, &fiat_asset_mint);
+    if !market_schedule_info.key.eq(&address) {
+        return Err(SynchronizerError::InvalidMarketScheduleAccount.into());
+    }
+
+    let mut schedule = MarketScheduleData {
+        is_initialized: true,
+        synchronizer: *synchronizer_account_info.key,
+        fiat_asset_mint,
+        bump_seed,
+        ..MarketScheduleData::default()
+    };
+    schedule.set(weekly_hours, &holidays)?;
+
+    // Create the market schedule account on first use
+    if !market_schedule_info.owner.eq(&id()) {
+        let rent = &Rent::from_account_info(rent_account_info)?;
+        let seeds: &[&[u8]] = &[MARKET_SCHEDULE_SEED, synchronizer_account_info.key.as_ref(), fiat_asset_mint.as_ref(), &[bump_seed]];
+        Self::create_program_account(payer_info, market_schedule_info, system_program_info, rent, seeds, MarketScheduleData::LEN)?;
+        msg!("Create market schedule account {}", market_schedule_info.key);
+    }
+
+    msg!("Set market schedule of {} with {} holidays", fiat_asset_mint, holidays.len());
+    MarketScheduleData::pack(schedule, &mut market_schedule_info.data.borrow_mut())?;
+    Self::require_trade_accounts(synchronizer_account_info, synchronizer, TRADE_MARKET_SCHEDULE_ACCOUNT)?;
+    SynchronizerEvent::MarketScheduleChanged {
+        synchronizer: *synchronizer_account_info.key,
+        fiat_asset_mint,
+        weekly_hours,
+        holidays,
+    }.emit();
+
+    Ok(())
+}
+
+pub fn process_set_asset_spreads(
+    accounts: &[AccountInfo],
+    fiat_asset_mint: Pubkey,
+    ask_spread_bps: u16,
+    bid_spread_bps: u16,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let synchronizer_account_info = next_account_info(account_info_iter)?;
+    let asset_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_account_info = next_account_info(account_info_iter)?;
+
+    check_synchronizer_authority(synchronizer_account_info)?;
+    let synchronizer = load_synchronizer(synchronizer_account_info)?;
+
+    let (_, bump_seed) = AssetData::find_address(synchronizer_account_info.key, &fiat_asset_mint);
+    let mut asset = match load_asset(asset_info, synchronizer_account_info.key, &fiat_asset_mint)? {
+        Some(asset) => asset,
+        // Spreads alone don't cap the asset
+        None => AssetData {
+            is_initialized: true,
+            synchronizer: *synchronizer_account_info.key,
+            fiat_asset_mint,
+            max_outstanding_notional: u64::MAX,
+            max_transaction_notional: u64::MAX,
+            bump_seed,
+            ..AssetData::default()
+        },
+    };
+    asset.set_spreads(ask_spread_bps, bid_spread_bps)?;
+
+    // Create the asset account on first use
+    if !asset_info.owner.eq(&id()) {
+        let rent = &Rent::from_account_info(rent_account_info)?;
+        let seeds: &[&[u8]] = &[ASSET_SEED, synchronizer_account_info.key.as_ref(), fiat_asset_mint.as_ref(), &[bump_seed]];
+        Self::create_program_account(payer_info, asset_info, system_program_info, rent, seeds, AssetData::LEN)?;
+        msg!("Create asset account {}", asset_info.key);
+    }
+
+    msg!("Set asset spreads {} {}", ask_spread_bps, bid_spread_bps);
+    AssetData::pack(asset, &mut asset_info.data.borrow_mut())?;
+    Self::require_trade_accounts(synchronizer_account_info, synchronizer, TRADE_ASSET_ACCOUNT)?;
+    SynchronizerEvent::AssetSpreadsChanged {
+        synchronizer: *synchronizer_account_info.key,
+        fiat_asset_mint,
+        ask_spread_bps,
+        bid_spread_bps,
+    }.emit();
+
+    Ok(())
+}
+
+pub fn process_set_fee_tiers(accounts: &[AccountInfo], tiers: Vec<FeeTier>, volume_window_slots: u64) -> ProgramResult {
+    let (synchronizer, _) = Self::update_fee_schedule(accounts, |schedule| {
+        schedule.set_tiers(&tiers)?;
+        schedule.volume_window_slots = volume_window_slots;
+        Ok(())
+    })?;
+    msg!("Set {} fee tiers, volume window of {} slots", tiers.len(), volume_window_slots);
+    SynchronizerEvent::FeeTiersChanged { synchronizer, tiers, volume_window_slots }.emit();
+
+    Ok(())
+}
+
+pub fn process_set_fee_override(accounts: &[AccountInfo], user: Pubkey, fee: Option<u64>) -> ProgramResult {
+    let (synchronizer, schedule) = Self::update_fee_schedule(accounts, |schedule| schedule.set_override(&user, fee))?;
+    msg!("Set fee override of {} to {:?}, {} overrides", user, fee, schedule.override_count);
+    SynchronizerEvent::FeeOverrideChanged { synchronizer, user, fee }.emit();
+
+    Ok(())
+}
+
+pub fn process_set_referral_share(accounts: &[AccountInfo], referral_share_bps: u16) -> ProgramResult {
+    let (synchronizer, _) = Self::update_fee_schedule(accounts, |schedule| schedule.set_referral_share(referral_share_bps))?;
+    msg!("Set referral share to {} bps", referral_share_bps);
+    SynchronizerEvent::ReferralShareChanged { synchronizer, referral_share_bps }.emit();
+
+    Ok(())
+}
+
+pub fn process_add_collateral(accounts: &[AccountInfo], rate: u64, rate_bounds: RateBounds) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let synchronizer_account_info = next_account_info(account_info_iter)?;
+    let collateral_set_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_account_info = next_account_info(account_info_iter)?;
+    let collateral_mint_info = next_account_info(account_info_iter)?;
+    let vault_info = next_account_info(account_info_iter)?;
+    let clock = Clock::from_account_info(next_account_info(account_info_iter)?)?;
+
+    check_synchronizer_authority(synchronizer_account_info)?;
+    let synchronizer = load_synchronizer(synchronizer_account_info)?;
+
+    // Trades in the Synchronizer collateral token don't need a conversion rate
+    if collateral_mint_info.key.eq(&synchronizer.collateral_token_key) {
+        return Err(SynchronizerError::InvalidCollateral.into());
+    }
+    let decimals = load_mint(collateral_mint_info)?.decimals;
+    load_collateral_account(vault_info, collateral_mint_info.key, Some(synchronizer_account_info.key))?;
+
+    let (_, bump_seed) = CollateralSetData::find_address(synchronizer_account_info.key);
+    let mut set = load_collateral_set(collateral_set_info, synchronizer_account_info.key)?
+        .unwrap_or(CollateralSetData {
+            is_initialized: true,
+            synchronizer: *synchronizer_account_info.key,
+            bump_seed,
+            ..CollateralSetData::default()
+        });
+    set.add(AcceptedCollateral {
+        mint: *collateral_mint_info.key,
+        vault: *vault_info.key,
+        decimals,
+        rate,
+        rate_updated_at: clock.unix_timestamp,
+        rate_bounds,
+        ..AcceptedCollateral::default()
+    })?;
+
+    // Create the collateral set account on first use
+    if !collateral_set_info.owner.eq(&id()) {
+        let rent = &Rent::from_account_info(rent_account_info)?;
+        let seeds: &[&[u8]] = &[COLLATERAL_SET_SEED, synchronizer_account_info.key.as_ref(), &[bump_seed]];
+        Self::create_program_account(payer_info, collateral_set_info, system_program_info, rent, seeds, CollateralSetData::LEN)?;
+        msg!("Create collateral set account {}", collateral_set_info.key);
+    }
+
+    CollateralSetData::pack(set, &mut collateral_set_info.data.borrow_mut())?;
+    msg!("Add collateral {} with vault {} at rate {}", collateral_mint_info.key, vault_info.key, rate);
+    SynchronizerEvent::CollateralAdded {
+        synchronizer: *synchronizer_account_info.key,
+        collateral_mint: *collateral_mint_info.key,
+        vault: *vault_info.key,
+        decimals,
+        rate,
+        rate_bounds,
+    }.emit();
+
+    Ok(())
+}
+
+pub fn process_remove_collateral(accounts: &[AccountInfo], collateral_mint: Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let synchronizer_account_info = next_account_info(account_info_iter)?;
+    let collateral_set_info = next_account_info(account_info_iter)?;
+    let vault_info = next_account_info(account_info_iter)?;
+
+    check_synchronizer_authority(synchronizer_account_info)?;
+    load_synchronizer(synchronizer_account_info)?;
+
+    let mut set = load_collateral_set(collateral_set_info, synchronizer_account_info.key)?
+        .ok_or(SynchronizerError::InvalidCollateral)?;
+    // Removed collaterals can't be withdrawn anymore
+    let index = set.position_by_mint(&collateral_mint).ok_or(SynchronizerError::InvalidCollateral)?;
+    if !vault_info.key.eq(&set.collaterals[index].vault) {
+        return Err(SynchronizerError::InvalidCollateral.into());
+    }
+    let vault = load_collateral_account(vault_info, &collateral_mint, Some(synchronizer_account_info.key))?;
+    set.remove(&collateral_mint, vault.amount)?;
+    CollateralSetData::pack(set, &mut collateral_set_info.data.borrow_mut())?;
+    msg!("Remove collateral {}", collateral_mint);
+    SynchronizerEvent::CollateralRemoved { synchronizer: *synchronizer_account_info.key, collateral_mint }.emit();
+
+    Ok(())
+}
+
+pub fn process_update_collateral_rate(accounts: &[AccountInfo], collateral_mint: Pubkey, rate: u64) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let synchronizer_account_info = next_account_info(account_info_iter)?;
+    let collateral_set_info = next_account_info(account_info_iter)?;
+    let clock = Clock::from_account_info(next_account_info(account_info_iter)?)?;
+    let oracles_infos = account_info_iter.as_slice();
+
+    let synchronizer = load_synchronizer(synchronizer_account_info)?;
+    let mut set = load_collateral_set(collateral_set_info, synchronizer_account_info.key)?
+        .ok_or(SynchronizerError::InvalidCollateral)?;
+    set.position_by_mint(&collateral_mint).ok_or(SynchronizerError::InvalidCollateral)?;
+
+    // Rates are set by the admin or attested by as many oracles as trade prices, within the rate bounds
+    let oracles = if synchronizer_account_info.is_signer {
+        set.set_rate(&collateral_mint, rate, clock.unix_timestamp)?;
+        Vec::new()
+    } else {
+        Self::check_oracle_signers(&synchronizer, oracles_infos, synchronizer.required_signatures())?;
+        set.set_oracle_rate(&collateral_mint, rate, clock.unix_timestamp)?;
+        oracles_infos.iter().take(synchronizer.required_signatures()).map(|oracle| *oracle.key).collect()
+    };
+
+    CollateralSetData::pack(set, &mut collateral_set_info.data.borrow_mut())?;
+    msg!("Set rate of collateral {} to {}", collateral_mint, rate);
+    SynchronizerEvent::CollateralRateUpdated {
+        synchronizer: *synchronizer_account_info.key,
+        collateral_mint,
+        rate,
+        oracles,
+    }.emit();
+
+    Ok(())
+}
+
+// Helpers
+
+/// Checks that the first `required_signatures` oracle accounts are distinct oracles which signed,
+/// the prices of these oracles are the ones counted by `signed_prices`
+fn check_oracle_signers(synchronizer: &SynchronizerData, oracles_infos: &[AccountInfo], required_signatures: usize) -> ProgramResult {
+    if oracles_infos.len() < required_signatures {
+        return Err(SynchronizerError::NotEnoughOracles.into());
+    }
+    for (index, oracle) in oracles_infos[..required_signatures].iter().enumerate() {
+        if !synchronizer.oracles.contains(oracle.key) || !oracle.is_signer {
+            return Err(SynchronizerError::BadOracle.into());
+        }
+        // An oracle signing twice would count as two signatures
+        if oracles_infos[..index].iter().any(|other| other.key.eq(oracle.key)) {
+            return Err(SynchronizerError::BadOracle.into());
+        }
+    }
+    Ok(())
+}
+
+/// Reads the `BuyFor`/`SellFor` optional accounts flagged in `optional_accounts`, which must flag
+/// the accounts required by the Synchronizer
+fn next_trade_accounts<'a, 'b>(
+    account_info_iter: &mut std::slice::Iter<'a, AccountInfo<'b>>,
+    synchronizer: &SynchronizerData,
+    optional_accounts: u8,
+) -> Result<TradeAccountInfos<'a, 'b>, ProgramError> {
+    if optional_accounts & synchronizer.required_trade_accounts != synchronizer.required_trade_accounts {
+        return Err(SynchronizerError::MissingTradeAccount.into());
+    }
+    let clock = match optional_accounts {
+        0 => Clock::default(),
+        _ => Clock::from_account_info(next_account_info(account_info_iter)?)?,
+    };
+    let mut next = |flag: u8| match optional_accounts & flag {
+        0 => Ok(None),
+        _ => next_account_info(account_info_iter).map(Some),
+    };
+    // Fields are read in the order of the flags
+    Ok(TradeAccountInfos {
+        clock,
+        asset: next(TRADE_ASSET_ACCOUNT)?,
+        rate_limit: next(TRADE_RATE_LIMIT_ACCOUNT)?,
+        user_volume: next(TRADE_USER_VOLUME_ACCOUNT)?,
+        system_program: next(TRADE_USER_VOLUME_ACCOUNT)?,
+        user_position: next(TRADE_USER_POSITION_ACCOUNT)?,
+        market_schedule: next(TRADE_MARKET_SCHEDULE_ACCOUNT)?,
+        fee_schedule: next(TRADE_FEE_SCHEDULE_ACCOUNT)?,
+        referrer: next(TRADE_REFERRER_ACCOUNT)?,
+        collateral_set: next(TRADE_COLLATERAL_SET_ACCOUNT)?,
+    })
+}
+
+/// Requires `accounts` in later trades, which hold limits or fees the Synchronizer now sets
+fn require_trade_accounts(synchronizer_account_info: &AccountInfo, mut synchronizer: SynchronizerData, accounts: u8) -> ProgramResult {
+    if synchronizer.required_trade_accounts & accounts != accounts {
+        synchronizer.required_trade_accounts |= accounts;
+        SynchronizerData::pack(synchronizer, &mut synchronizer_account_info.data.borrow_mut())?;
+    }
+    Ok(())
+}
+
+/// Fails with `MarketClosed` outside the trading hours of a fiat asset with a market schedule
+fn check_market_open(
+    market_schedule_info: Option<&AccountInfo>,
+    synchronizer_key: &Pubkey,
+    fiat_asset_mint: &Pubkey,
+    clock: &Clock,
+) -> ProgramResult {
+    let market_schedule_info = match market_schedule_info {
+        Some(market_schedule_info) => market_schedule_info,
+        None => return Ok(()),
+    };
+    match load_market_schedule(market_schedule_info, synchronizer_key, fiat_asset_mint)? {
+        Some(schedule) if !schedule.is_open(clock.unix_timestamp) => Err(SynchronizerError::MarketClosed.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Fee rate of a trade, the fee schedule rate of the user when it's below the rate signed by the oracles
+fn trade_fee(
+    fee_schedule: Option<&FeeScheduleData>,
+    synchronizer_key: &Pubkey,
+    user_key: &Pubkey,
+    user_volume_info: Option<&AccountInfo>,
+    clock: &Clock,
+    fee: u64,
+) -> Result<u64, ProgramError> {
+    let schedule = match fee_schedule {
+        Some(schedule) => schedule,
+        None => return Ok(fee),
+    };
+    // Users without a volume account haven't traded since the fee schedule got a volume window
+    let volume = user_volume_info.map(|info| load_user_volume(info, synchronizer_key, user_key)).transpose()?
+        .flatten()
+        .map_or(0, |user_volume| user_volume.fee_window.volume(clock.slot));
+    Ok(schedule.fee_rate(user_key, volume).map_or(fee, |rate| rate.min(fee)))
+}
+
+/// Is `true` when a trade has a referrer.
+/// Fails when the referrer account isn't a collateral account other than the Synchronizer one,
+/// or when it's owned by the user, who would get back part of the fee.
+/// Referrers aren't registered, so a user referring an account of another key they own isn't detected.
+fn check_referrer(
+    referrer_info: Option<&AccountInfo>,
+    synchronizer_collateral_account_key: &Pubkey,
+    collateral_token_key: &Pubkey,
+    user_key: &Pubkey,
+) -> Result<bool, ProgramError> {
+    let referrer_info = match referrer_info {
+        Some(referrer_info) => referrer_info,
+        None => return Ok(false),
+    };
+    if referrer_info.key.eq(synchronizer_collateral_account_key) {
+        return Err(SynchronizerError::InvalidReferrerAccount.into());
+    }
+    if load_collateral_account(referrer_info, collateral_token_key, None)?.owner.eq(user_key) {
+        return Err(SynchronizerError::InvalidReferrerAccount.into());
+    }
+    Ok(true)
+}
+
+/// Accepted collateral whose vault is `vault_key` and the collateral set holding it at that index,
+/// `None` for the Synchronizer collateral token account
+fn accepted_collateral(
+    collateral_set_info: &AccountInfo,
+    synchronizer_key: &Pubkey,
+    vault_key: &Pubkey,
+) -> Result<Option<(CollateralSetData, usize)>, ProgramError> {
+    let set = load_collateral_set(collateral_set_info, synchronizer_key)?;
+    Ok(set.and_then(|set| set.position_by_vault(vault_key).map(|index| (set, index))))
+}
+
+/// Part of the fee amount of a trade paid to its referrer, nothing without referrer or fee schedule
+fn referral_amount(fee_schedule: Option<&FeeScheduleData>, has_referrer: bool, fee_amount: u64) -> u64 {
+    match fee_schedule {
+        Some(schedule) if has_referrer => schedule.referral_amount(fee_amount),
+        _ => 0,
+    }
+}
+
+/// Withdrawable fee amount after a trade, which keeps its fee amount but the referral amount
+fn accrue_fee(withdrawable_fee_amount: u64, fee_amount: u64, referral_amount: u64) -> Result<u64, ProgramError> {
+    fee_amount.checked_sub(referral_amount)
+        .and_then(|fee_amount| withdrawable_fee_amount.checked_add(fee_amount))
+        .ok_or_else(|| SynchronizerError::InsufficientFunds.into())
+}
+
+/// Transfers the referral amount of a trade from the Synchronizer collateral account to the referrer
+fn pay_referral<'a>(
+    spl_token_info: &AccountInfo<'a>,
+    synchronizer_collateral_account_info: &AccountInfo<'a>,
+    referrer_info: Option<&AccountInfo<'a>>,
+    synchronizer_authority_info: &AccountInfo<'a>,
+    amount: u64,
+) -> ProgramResult {
+    let referrer_info = match referrer_info {
+        Some(referrer_info) if amount > 0 => referrer_info,
+        _ => return Ok(()),
+    };
+    let instruction = spl_token::instruction::transfer(
+        spl_token_info.key,
+        synchronizer_collateral_account_info.key,
+        referrer_info.key,
+        synchronizer_authority_info.key,
+        &[],
+        amount
+    )?;
+    let account_infos = [
+        spl_token_info.clone(),
+        synchronizer_collateral_account_info.clone(),
+        referrer_info.clone(),
+        synchronizer_authority_info.clone(),
+    ];
+    invoke(&instruction, &account_infos)?;
+    msg!("Transfer {} collateral tokens from synchronizer to referrer", amount);
+    Ok(())
+}
+
+/// Applies `update` to the fee schedule of the Synchronizer of `SetFeeTiers`/`SetFeeOverride`/`SetReferralShare`
+/// accounts, creating the account on first use. Returns the Synchronizer key and updated schedule.
+fn update_fee_schedule(
+    accounts: &[AccountInfo],
+    update: impl FnOnce(&mut FeeScheduleData) -> Result<(), SynchronizerError>,
+) -> Result<(Pubkey, FeeScheduleData), ProgramError> {
+    let account_info_iter = &mut accounts.iter();
+    let synchronizer_account_info = next_account_info(account_info_iter)?;
+    let fee_schedule_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_account_info = next_account_info(account_info_iter)?;
+
+    check_synchronizer_authority(synchronizer_account_info)?;
+    let synchronizer = load_synchronizer(synchronizer_account_info)?;
+
+    let (_, bump_seed) = FeeScheduleData::find_address(synchronizer_account_info.key);
+    let mut schedule = load_fee_schedule(fee_schedule_info, synchronizer_account_info.key)?
+        .unwrap_or(FeeScheduleData {
+            is_initialized: true,
+            synchronizer: *synchronizer_account_info.key,
+            bump_seed,
+            ..FeeScheduleData::default()
+        });
+    update(&mut schedule)?;
+
+    // Create the fee schedule account on first use
+    if !fee_schedule_info.owner.eq(&id()) {
+        let rent = &Rent::from_account_info(rent_account_info)?;
+        let seeds: &[&[u8]] = &[FEE_SCHEDULE_SEED, synchronizer_account_info.key.as_ref(), &[bump_seed]];
+        Self::create_program_account(payer_info, fee_schedule_info, system_program_info, rent, seeds, FeeScheduleData::LEN)?;
+        msg!("Create fee schedule account {}", fee_schedule_info.key);
+    }
+
+    FeeScheduleData::pack(schedule, &mut fee_schedule_info.data.borrow_mut())?;
+    Self::require_trade_accounts(synchronizer_account_info, synchronizer, TRADE_FEE_SCHEDULE_ACCOUNT | TRADE_USER_VOLUME_ACCOUNT)?;
+    Ok((*synchronizer_account_info.key, schedule))
+}
+
+/// Creates a rent exempt program derived account of `len` bytes at the `seeds` address, paid by `payer_info`
+fn create_program_account<'a>(
+    payer_info: &AccountInfo<'a>,
+    account_info: &AccountInfo<'a>,
+    system_program_info: &AccountInfo<'a>,
+    rent: &Rent,
+    seeds: &[&[u8]],
+    len: usize,
+) -> ProgramResult {
+    if !system_program_info.key.eq(&system_program::id()) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if !payer_info.is_signer {
+        return Err(SynchronizerError::InvalidSigner.into());
+    }
+    let account_infos = [payer_info.clone(), account_info.clone(), system_program_info.clone()];
+    let required_lamports = rent.minimum_balance(len).saturating_sub(account_info.lamports());
+
+    // Lamports may have been sent to the address before, create_account would fail
+    if account_info.lamports() == 0 {
+        let instruction = system_instruction::create_account(payer_info.key, account_info.key, required_lamports, len as u64, &id());
+        invoke_signed(&instruction, &account_infos, &[seeds])?;
+    } else {
+        if required_lamports > 0 {
+            invoke(&system_instruction::transfer(payer_info.key, account_info.key, required_lamports), &account_infos)?;
+        }
+        invoke_signed(&system_instruction::allocate(account_info.key, len as u64), &account_infos, &[seeds])?;
+        invoke_signed(&system_instruction::assign(account_info.key, &id()), &account_infos, &[seeds])?;
+    }
+    Ok(())
+}
+
+/// Adds the notional of a trade to the Synchronizer and user volume windows, which must stay within the rate limits.
+/// The user volume account is created by the first trade of the user under a user limit.
+fn record_volume<'a>(
+    synchronizer_key: &Pubkey,
+    user_authority_info: &AccountInfo<'a>,
+    rate_limit_info: Option<&AccountInfo<'a>>,
+    user_volume_info: Option<&AccountInfo<'a>>,
+    system_program_info: Option<&AccountInfo<'a>>,
+    clock: &Clock,
+    notional: u64,
+) -> ProgramResult {
+    let rate_limit_info = match rate_limit_info {
+        Some(rate_limit_info) => rate_limit_info,
+        None => return Ok(()),
+    };
+    let mut rate_limit = match load_rate_limit(rate_limit_info, synchronizer_key)? {
+        Some(rate_limit) if rate_limit.window_slots > 0 => rate_limit,
+        _ => return Ok(()),
+    };
+
+    if rate_limit.max_synchronizer_notional > 0 {
+        rate_limit.window.record(clock.slot, rate_limit.window_slots, notional, rate_limit.max_synchronizer_notional)?;
+    }
+
+    if rate_limit.max_user_notional > 0 {
+        let (mut user_volume, user_volume_info) = Self::load_or_create_user_volume(synchronizer_key, user_authority_info, user_volume_info, system_program_info)?;
+        user_volume.window.record(clock.slot, rate_limit.window_slots, notional, rate_limit.max_user_notional)?;
+        UserVolumeData::pack(user_volume, &mut user_volume_info.data.borrow_mut())?;
+    }
+    RateLimitData::pack(rate_limit, &mut rate_limit_info.data.borrow_mut())?;
+    Ok(())
+}
+
+/// Adds the notional of a trade to the user volume of the fee tiers, counted without rate limits.
+/// The user volume account is created by the first trade of the user once the tiers have a volume window.
+fn record_fee_volume<'a>(
+    fee_schedule: Option<&FeeScheduleData>,
+    synchronizer_key: &Pubkey,
+    user_authority_info: &AccountInfo<'a>,
+    user_volume_info: Option<&AccountInfo<'a>>,
+    system_program_info: Option<&AccountInfo<'a>>,
+    clock: &Clock,
+    notional: u64,
+) -> ProgramResult {
+    let window_slots = match fee_schedule {
+        Some(schedule) if schedule.tier_count > 0 && schedule.volume_window_slots > 0 => schedule.volume_window_slots,
+        _ => return Ok(()),
+    };
+
+    let (mut user_volume, user_volume_info) = Self::load_or_create_user_volume(synchronizer_key, user_authority_info, user_volume_info, system_program_info)?;
+    user_volume.fee_window.add(clock.slot, window_slots, notional);
+    UserVolumeData::pack(user_volume, &mut user_volume_info.data.borrow_mut())?;
+    Ok(())
+}
+
+/// Loads the volume account of a user and its account info, creating the account paid by the user when it's missing.
+/// Fails with `MissingTradeAccount` when the trade doesn't pass the user volume account.
+fn load_or_create_user_volume<'a, 'b>(
+    synchronizer_key: &Pubkey,
+    user_authority_info: &AccountInfo<'a>,
+    user_volume_info: Option<&'b AccountInfo<'a>>,
+    system_program_info: Option<&AccountInfo<'a>>,
+) -> Result<(UserVolumeData, &'b AccountInfo<'a>), ProgramError> {
+    let (user_volume_info, system_program_info) = match (user_volume_info, system_program_info) {
+        (Some(user_volume_info), Some(system_program_info)) => (user_volume_info, system_program_info),
+        _ => return Err(SynchronizerError::MissingTradeAccount.into()),
+    };
+    if let Some(user_volume) = load_user_volume(user_volume_info, synchronizer_key, user_authority_info.key)? {
+        return Ok((user_volume, user_volume_info));
+    }
+
+    let (_, bump_seed) = UserVolumeData::find_address(synchronizer_key, user_authority_info.key);
+    if !user_volume_info.owner.eq(&id()) {
+        let seeds: &[&[u8]] = &[USER_VOLUME_SEED, synchronizer_key.as_ref(), user_authority_info.key.as_ref(), &[bump_seed]];
+        Self::create_program_account(user_authority_info, user_volume_info, system_program_info, &Rent::get()?, seeds, UserVolumeData::LEN)?;
+        msg!("Create user volume account {}", user_volume_info.key);
+    }
+    let user_volume = UserVolumeData {
+        is_initialized: true,
+        synchronizer: *synchronizer_key,
+        user: *user_authority_info.key,
+        bump_seed,
+        ..UserVolumeData::default()
+    };
+    Ok((user_volume, user_volume_info))
+}
+
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    msg!("Synchronizer entrypoint");
+    check_program_account(program_id)?;
+
+    let instruction = SynchronizerInstruction::unpack(instruction_data)?;
+    match instruction {
+        // Public instructions
+        SynchronizerInstruction::BuyFor {
+            multiplier,
+            amount,
+            fee,
+            ref prices,
+            optional_accounts,
+        } => {
+            msg!("Instruction: BuyFor");
+            Self::process_buy_for(accounts, multiplier, amount, fee, prices, optional_accounts)
+        }
+        SynchronizerInstruction::SellFor {
+            multiplier,
+            amount,
+            fee,
+            ref prices,
+            optional_accounts,
+        } => {
+            msg!("Instruction: SellFor");
+            Self::process_sell_for(accounts, multiplier, amount, fee, prices, optional_accounts)
+        }
+
+        // Admin Instructions
+        SynchronizerInstruction::InitializeSynchronizerAccount {
+            collateral_token_key,
+            remaining_dollar_cap,
+            withdrawable_fee_amount,
+            minimum_required_signature,
+            oracles
+        } => {
+            msg!("Instruction: InitializeSynchronizerAccount");
+            Self::process_initialize_synchronizer_account(accounts, collateral_token_key, remaining_dollar_cap, withdrawable_fee_amount, minimum_required_signature, oracles)
+        }
+
+        SynchronizerInstruction::SetMinimumRequiredSignature {
+            minimum_required_signature
+        } => {
+            msg!("Instruction: SetMinimumRequiredSignature");
+            Self::process_set_minimum_required_signature(accounts, minimum_required_signature)
+        }
+
+        SynchronizerInstruction::SetCollateralToken {
+            collateral_token_key
+        } => {
+            msg!("Instruction: SetCollateralToken");
+            Self::process_set_collateral_token(accounts, collateral_token_key)
+        }
+
+        SynchronizerInstruction::SetOracles {
+            oracles
+        } => {
+            msg!("Instruction: SetOracles");
+            Self::process_set_oracles(accounts, oracles)
+        }
+
+        SynchronizerInstruction::SetRemainingDollarCap {
+            remaining_dollar_cap
+        } => {
+            msg!("Instruction: SetRemainingDollarCap");
+            Self::process_set_remaining_dollar_cap(accounts, remaining_dollar_cap)
+        }
+
+        SynchronizerInstruction::WithdrawFee {
+            amount
+        } => {
+            msg!("Instruction: WithdrawFee");
+            Self::process_withdraw_fee(accounts, amount)
+        }
+
+        SynchronizerInstruction::WithdrawCollateral {
+            amount
+        } => {
+            msg!("Instruction: WithdrawCollateral");
+            Self::process_withdraw_collateral(accounts, amount)
+        }
+
+        SynchronizerInstruction::MigrateSynchronizerAccount => {
+            msg!("Instruction: MigrateSynchronizerAccount");
+            Self::process_migrate_synchronizer_account(accounts)
+        }
+
+        SynchronizerInstruction::SetAssetCaps {
+            fiat_asset_mint,
+            max_outstanding_notional,
+            max_transaction_notional,
+        } => {
+            msg!("Instruction: SetAssetCaps");
+            Self::process_set_asset_caps(accounts, fiat_asset_mint, max_outstanding_notional, max_transaction_notional)
+        }
+
+        SynchronizerInstruction::SetRateLimits {
+            window_slots,
+            max_synchronizer_notional,
+            max_user_notional,
+        } => {
+            msg!("Instruction: SetRateLimits");
+            Self::process_set_rate_limits(accounts, window_slots, max_synchronizer_notional, max_user_notional)
+        }
+
+        SynchronizerInstruction::CreateUserPosition { fiat_asset_mint } => {
+            msg!("Instruction: CreateUserPosition");
+            Self::process_create_user_position(accounts, fiat_asset_mint)
+        }
+
+        SynchronizerInstruction::CloseUserPosition { fiat_asset_mint } => {
+            msg!("Instruction: CloseUserPosition");
+            Self::process_close_user_position(accounts, fiat_asset_mint)
+        }
+
+        SynchronizerInstruction::SetMarketSchedule {
+            fiat_asset_mint,
+            weekly_hours,
+            holidays,
+        } => {
+            msg!("Instruction: SetMarketSchedule");
+            Self::process_set_market_schedule(accounts, fiat_asset_mint, weekly_hours, holidays)
+        }
+        SynchronizerInstruction::SetAssetSpreads {
+            fiat_asset_mint,
+            ask_spread_bps,
+            bid_spread_bps,
+        } => {
+            msg!("Instruction: SetAssetSpreads");
+            Self::process_set_asset_spreads(accounts, fiat_asset_mint, ask_spread_bps, bid_spread_bps)
+        }
+        SynchronizerInstruction::SetFeeTiers { tiers, volume_window_slots } => {
+            msg!("Instruction: SetFeeTiers");
+            Self::process_set_fee_tiers(accounts, tiers, volume_window_slots)
+        }
+        SynchronizerInstruction::SetFeeOverride { user, fee } => {
+            msg!("Instruction: SetFeeOverride");
+            Self::process_set_fee_override(accounts, user, fee)
+        }
+        SynchronizerInstruction::SetReferralShare { referral_share_bps } => {
+            msg!("Instruction: SetReferralShare");
+            Self::process_set_referral_share(accounts, referral_share_bps)
+        }
+        SynchronizerInstruction::AddCollateral { rate, rate_bounds } => {
+            msg!("Instruction: AddCollateral");
+            Self::process_add_collateral(accounts, rate, rate_bounds)
+        }
+        SynchronizerInstruction::RemoveCollateral { collateral_mint } => {
+            msg!("Instruction: RemoveCollateral");
+            Self::process_remove_collateral(accounts, collateral_mint)
+        }
+        SynchronizerInstruction::UpdateCollateralRate { collateral_mint, rate } => {
+            msg!("Instruction: UpdateCollateralRate");
+            Self::process_update_collateral_rate(accounts, collateral_mint, rate)
+        }
+    }
+}
+
+} // impl Processor
+
+impl PrintProgramError for SynchronizerError {
+    fn print<E>(&self)
+    where
+        E: 'static + std::error::Error + DecodeError<E> + PrintProgramError + FromPrimitive,
+    {
+        match self {
+            SynchronizerError::AlreadyInitialized => msg!("Error: Synchronizer account already initialized"),
+            SynchronizerError::NotInitialized => msg!("Error: Synchronizer account is not initialized"),
+            SynchronizerError::NotRentExempt => msg!("Error: Lamport balance below rent-exempt threshold"),
+            SynchronizerError::InsufficientFunds => msg!("Error: Insufficient funds"),
+            SynchronizerError::AccessDenied => msg!("Error: Access Denied"),
+
+            SynchronizerError::NotEnoughOracles => msg!("Error: Not enough oracles"),
+            SynchronizerError::MaxOraclesExceed => msg!("Error: Exceed limit of maximum oracles"),
+            SynchronizerError::MaxSignersExceed => msg!("Error: Exceed limit of maximum signers"),
+            SynchronizerError::BadOracle => msg!("Error: signer is not an oracle"),
+            SynchronizerError::BadMintAuthority => msg!("Error: Bad mint authority"),
             SynchronizerError::BadCollateralMint => msg!("Error: Bad collateral mint"),
             SynchronizerError::BadDecimals => msg!("Error: Bad mint decimals"),
 
             SynchronizerError::InvalidSigner => msg!("Error: Invalid transaction Signer"),
             SynchronizerError::InvalidInstruction => msg!("Error: Invalid instruction"),
+
+            SynchronizerError::InvalidAccountOwner => msg!("Error: Account is not owned by the expected program"),
+            SynchronizerError::InvalidTokenAccount => msg!("Error: Invalid token account data"),
+            SynchronizerError::UninitializedTokenAccount => msg!("Error: Token account is not initialized"),
+            SynchronizerError::FrozenTokenAccount => msg!("Error: Token account is frozen"),
+            SynchronizerError::OwnerMismatch => msg!("Error: Token account owner mismatch"),
+            SynchronizerError::BadFiatMint => msg!("Error: Bad fiat asset mint"),
+            SynchronizerError::InvalidMint => msg!("Error: Invalid mint data"),
+            SynchronizerError::UninitializedMint => msg!("Error: Mint is not initialized"),
+            SynchronizerError::InvalidTokenProgram => msg!("Error: Invalid token program"),
+            SynchronizerError::MigrationRequired => msg!("Error: Synchronizer account must be migrated to the current layout"),
+            SynchronizerError::AlreadyMigrated => msg!("Error: Synchronizer account already has the current layout"),
+            SynchronizerError::InvalidAccountType => msg!("Error: Account type discriminator mismatch"),
+            SynchronizerError::AssetCapExceeded => msg!("Error: Buy exceeds the maximum outstanding notional of the asset"),
+            SynchronizerError::TransactionCapExceeded => msg!("Error: Buy exceeds the maximum transaction notional of the asset"),
+            SynchronizerError::InvalidAssetAccount => msg!("Error: Invalid asset account"),
+            SynchronizerError::DollarCapExceeded => msg!("Error: Dollar cap exceeded"),
+            SynchronizerError::RateLimitExceeded => msg!("Error: Rolling volume limit exceeded"),
+            SynchronizerError::InvalidRateLimitAccount => msg!("Error: Invalid rate limit account"),
+            SynchronizerError::InvalidUserVolumeAccount => msg!("Error: Invalid user volume account"),
+            SynchronizerError::InvalidUserPositionAccount => msg!("Error: Invalid user position account"),
+            SynchronizerError::MarketClosed => msg!("Error: Market is closed"),
+            SynchronizerError::InvalidMarketSchedule => msg!("Error: Invalid market schedule"),
+            SynchronizerError::InvalidMarketScheduleAccount => msg!("Error: Invalid market schedule account"),
+            SynchronizerError::InvalidSpread => msg!("Error: Invalid spread"),
+            SynchronizerError::InvalidFeeSchedule => msg!("Error: Invalid fee schedule"),
+            SynchronizerError::InvalidFeeScheduleAccount => msg!("Error: Invalid fee schedule account"),
+            SynchronizerError::InvalidReferrerAccount => msg!("Error: Invalid referrer account"),
+            SynchronizerError::MissingTradeAccount => msg!("Error: Trade doesn't pass a required account"),
+            SynchronizerError::InvalidCollateral => msg!("Error: Invalid collateral"),
+            SynchronizerError::InvalidCollateralRate => msg!("Error: Invalid collateral rate"),
+            SynchronizerError::InvalidCollateralSetAccount => msg!("Error: Invalid collateral set account"),
+            SynchronizerError::StaleCollateralRate => msg!("Error: Stale collateral rate"),
+            SynchronizerError::CollateralRateDeviationExceeded => msg!("Error: Collateral rate deviation exceeded"),
         }
     }
 }
@@ -697,7 +1860,9 @@ mod test {
     use solana_sdk::{
         account::{create_is_signer_account_infos,Account as SolanaAccount,create_account_for_test},
     };
+    use borsh::BorshSerialize;
     use spl_token::{processor::Processor as SPLTokenProcessor, state::{Account, Mint}, ui_amount_to_amount};
+    use crate::{instruction::{first_trade_oracle_account, TRADE_PROGRAM_ACCOUNTS}, state::{BPS_DENOMINATOR, RateBounds, SECONDS_PER_DAY, SYNCHRONIZER_DATA_V1_LEN, VolumeWindow}};
     use super::*;
 
     fn mint_minimum_balance() -> u64 {
@@ -742,6 +1907,76 @@ mod test {
         Processor::process_instruction(&instruction.program_id, &account_infos, &instruction.data)
     }
 
+    // Accounts of buy_for and sell_for in instruction order, with two oracles
+    #[derive(Clone)]
+    struct TradeAccounts {
+        fiat_asset_mint: SolanaAccount,
+        user_collateral_account: SolanaAccount,
+        user_fiat_account: SolanaAccount,
+        synchronizer_collateral_account: SolanaAccount,
+        user_account: SolanaAccount,
+        synchronizer_account: SolanaAccount,
+        spl_token_account: SolanaAccount,
+        asset_account: SolanaAccount,
+        rate_limit_account: SolanaAccount,
+        user_volume_account: SolanaAccount,
+        clock_sysvar: SolanaAccount,
+        system_program_account: SolanaAccount,
+        user_position_account: SolanaAccount,
+        market_schedule_account: SolanaAccount,
+        fee_schedule_account: SolanaAccount,
+        referrer_account: SolanaAccount,
+        collateral_set_account: SolanaAccount,
+        oracle1_acc: SolanaAccount,
+        oracle2_acc: SolanaAccount,
+    }
+
+    impl TradeAccounts {
+        // Accounts of trades passing every optional program account, without referrer
+        fn trade_accounts(&mut self) -> Vec<&mut SolanaAccount> {
+            self.optional_trade_accounts(TRADE_PROGRAM_ACCOUNTS)
+        }
+
+        fn optional_trade_accounts(&mut self, optional_accounts: u8) -> Vec<&mut SolanaAccount> {
+            let mut accounts = vec![
+                &mut self.fiat_asset_mint,
+                &mut self.user_collateral_account,
+                &mut self.user_fiat_account,
+                &mut self.synchronizer_collateral_account,
+                &mut self.user_account,
+                &mut self.synchronizer_account,
+                &mut self.spl_token_account,
+            ];
+            if optional_accounts != 0 {
+                accounts.push(&mut self.clock_sysvar);
+            }
+            let optional = vec![
+                (TRADE_ASSET_ACCOUNT, &mut self.asset_account),
+                (TRADE_RATE_LIMIT_ACCOUNT, &mut self.rate_limit_account),
+                (TRADE_USER_VOLUME_ACCOUNT, &mut self.user_volume_account),
+                (TRADE_USER_VOLUME_ACCOUNT, &mut self.system_program_account),
+                (TRADE_USER_POSITION_ACCOUNT, &mut self.user_position_account),
+                (TRADE_MARKET_SCHEDULE_ACCOUNT, &mut self.market_schedule_account),
+                (TRADE_FEE_SCHEDULE_ACCOUNT, &mut self.fee_schedule_account),
+                (TRADE_REFERRER_ACCOUNT, &mut self.referrer_account),
+                (TRADE_COLLATERAL_SET_ACCOUNT, &mut self.collateral_set_account),
+            ];
+            accounts.extend(optional.into_iter().filter(|(flag, _)| optional_accounts & flag != 0).map(|(_, account)| account));
+            accounts.push(&mut self.oracle1_acc);
+            accounts.push(&mut self.oracle2_acc);
+            accounts
+        }
+
+        // Accounts of trades passing `optional_accounts` with the accounts at the given indexes replaced
+        fn trade_accounts_with<'a>(&'a mut self, optional_accounts: u8, replaced: Vec<(usize, &'a mut SolanaAccount)>) -> Vec<&'a mut SolanaAccount> {
+            let mut accounts = self.optional_trade_accounts(optional_accounts);
+            for (index, account) in replaced {
+                accounts[index] = account;
+            }
+            accounts
+        }
+    }
+
     #[test]
     fn test_init_synchronizer_account() {
         let program_id = id();
@@ -749,6 +1984,11 @@ mod test {
         let mut synchronizer_account = SolanaAccount::new(init_acc_minimum_balance(), SynchronizerData::get_packed_len(), &program_id);
         let mut rent_sysvar_account = create_account_for_test(&Rent::default());
         let collateral_key = Pubkey::new_unique();
+        let mut collateral_mint = SolanaAccount::new(mint_minimum_balance(), Mint::get_packed_len(), &spl_token::id());
+        do_token_program(
+            spl_token::instruction::initialize_mint(&spl_token::id(), &collateral_key, &Pubkey::new_unique(), None, 6).unwrap(),
+            vec![&mut collateral_mint, &mut rent_sysvar_account],
+        ).unwrap();
         let oracles = vec![Pubkey::new_unique(), Pubkey::new_unique()];
 
         let mut bad_sync_acc = SolanaAccount::new(init_acc_minimum_balance() - 100, SynchronizerData::get_packed_len(), &program_id);
@@ -764,7 +2004,7 @@ mod test {
                     &oracles,
                     &synchronizer_key,
                 ).unwrap(),
-                vec![&mut bad_sync_acc, &mut rent_sysvar_account]
+                vec![&mut bad_sync_acc, &mut rent_sysvar_account, &mut collateral_mint]
             )
         );
 
@@ -782,7 +2022,7 @@ mod test {
                     &oracles,
                     &synchronizer_key
                 ).unwrap(),
-                vec![&mut bad_sync_acc, &mut rent_sysvar_account]
+                vec![&mut bad_sync_acc, &mut rent_sysvar_account, &mut collateral_mint]
             )
         );
 
@@ -800,10 +2040,41 @@ mod test {
                     &oracles,
                     &fake_sync_key
                 ).unwrap(),
-                vec![&mut fake_sync_acc, &mut rent_sysvar_account]
+                vec![&mut fake_sync_acc, &mut rent_sysvar_account, &mut collateral_mint]
             )
         );
 
+        let mut instruction = crate::instruction::initialize_synchronizer_account(
+            &id(),
+            &collateral_key,
+            0,
+            0,
+            2,
+            &oracles,
+            &synchronizer_key
+        ).unwrap();
+        instruction.accounts[2].pubkey = Pubkey::new_unique();
+        assert_eq!(
+            Err(SynchronizerError::BadCollateralMint.into()), // mint is not the collateral token
+            do_process(instruction, vec![&mut synchronizer_account, &mut rent_sysvar_account, &mut collateral_mint])
+        );
+
+        // Legacy layout without the collateral mint leaves the decimals unset
+        let mut legacy_account = SolanaAccount::new(init_acc_minimum_balance(), SynchronizerData::get_packed_len(), &id());
+        let mut instruction = crate::instruction::initialize_synchronizer_account(
+            &id(),
+            &collateral_key,
+            0,
+            0,
+            2,
+            &oracles,
+            &synchronizer_key
+        ).unwrap();
+        instruction.accounts.truncate(2);
+        do_process(instruction, vec![&mut legacy_account, &mut rent_sysvar_account]).unwrap();
+        let synchronizer = SynchronizerData::unpack(&legacy_account.data).unwrap();
+        assert_eq!((synchronizer.collateral_token_key, synchronizer.collateral_decimals), (collateral_key, None));
+
         do_process(
             crate::instruction::initialize_synchronizer_account(
                 &id(),
@@ -814,8 +2085,10 @@ mod test {
                 &oracles,
                 &synchronizer_key
             ).unwrap(),
-            vec![&mut synchronizer_account, &mut rent_sysvar_account]
+            vec![&mut synchronizer_account, &mut rent_sysvar_account, &mut collateral_mint]
         ).unwrap();
+        let synchronizer = SynchronizerData::unpack(&synchronizer_account.data).unwrap();
+        assert_eq!(synchronizer.collateral_decimals, Some(6));
 
         assert_eq!(
             Err(SynchronizerError::AlreadyInitialized.into()),
@@ -829,7 +2102,7 @@ mod test {
                     &oracles,
                     &synchronizer_key
                 ).unwrap(),
-                vec![&mut synchronizer_account, &mut rent_sysvar_account]
+                vec![&mut synchronizer_account, &mut rent_sysvar_account, &mut collateral_mint]
             )
         );
     }
@@ -840,11 +2113,21 @@ mod test {
         let synchronizer_key = Pubkey::new_unique();
         let mut synchronizer_account = SolanaAccount::new(init_acc_minimum_balance(), SynchronizerData::get_packed_len(), &program_id);
         let mut rent_sysvar = create_account_for_test(&Rent::default());
-        let mut spl_token_account = SolanaAccount::default();
+        let spl_token_account = SolanaAccount::default();
+        let asset_account = SolanaAccount::default();
+        let rate_limit_account = SolanaAccount::default();
+        let user_volume_account = SolanaAccount::default();
+        let clock_sysvar = create_account_for_test(&Clock::default());
+        let system_program_account = SolanaAccount::default();
+        let user_position_account = SolanaAccount::default();
+        let market_schedule_account = SolanaAccount::default();
+        let fee_schedule_account = SolanaAccount::default();
+        let referrer_account = SolanaAccount::default();
+        let collateral_set_account = SolanaAccount::default();
         let collateral_key = Pubkey::new_unique();
         let oracles = vec![Pubkey::new_unique(), Pubkey::new_unique()];
-        let mut oracle1_acc = SolanaAccount::default();
-        let mut oracle2_acc = SolanaAccount::default();
+        let oracle1_acc = SolanaAccount::default();
+        let oracle2_acc = SolanaAccount::default();
         let user_key = Pubkey::new_unique();
         let mut user_account = SolanaAccount::default();
 
@@ -903,25 +2186,47 @@ mod test {
             ],
         ).unwrap();
 
+        let mut trade = TradeAccounts {
+            fiat_asset_mint,
+            user_collateral_account,
+            user_fiat_account,
+            synchronizer_collateral_account,
+            user_account,
+            synchronizer_account,
+            spl_token_account,
+            asset_account,
+            rate_limit_account,
+            user_volume_account,
+            clock_sysvar,
+            system_program_account,
+            user_position_account,
+            market_schedule_account,
+            fee_schedule_account,
+            referrer_account,
+            collateral_set_account,
+            oracle1_acc,
+            oracle2_acc,
+        };
+
         // Mint some collateral asset to synchronizer account
         let amount = spl_token::ui_amount_to_amount(500.0, decimals);
         do_token_program(
             spl_token::instruction::mint_to(&spl_token::id(), &collateral_key, &synchronizer_collateral_key, &synchronizer_key, &[], amount).unwrap(),
-            vec![&mut collateral_asset_mint, &mut synchronizer_collateral_account, &mut synchronizer_account],
+            vec![&mut collateral_asset_mint, &mut trade.synchronizer_collateral_account, &mut trade.synchronizer_account],
         ).unwrap();
 
         // Mint some collateral asset to user account
         let amount = spl_token::ui_amount_to_amount(500.0, decimals);
         do_token_program(
             spl_token::instruction::mint_to(&spl_token::id(), &collateral_key, &user_collateral_key, &synchronizer_key, &[], amount).unwrap(),
-            vec![&mut collateral_asset_mint, &mut user_collateral_account, &mut synchronizer_account],
+            vec![&mut collateral_asset_mint, &mut trade.user_collateral_account, &mut trade.synchronizer_account],
         ).unwrap();
 
         // Mint some fiat asset to user account
         let amount = spl_token::ui_amount_to_amount(500.0, decimals);
         do_token_program(
             spl_token::instruction::mint_to(&spl_token::id(), &fiat_asset_key, &user_fiat_key, &synchronizer_key, &[], amount).unwrap(),
-            vec![&mut fiat_asset_mint, &mut user_fiat_account, &mut synchronizer_account],
+            vec![&mut trade.fiat_asset_mint, &mut trade.user_fiat_account, &mut trade.synchronizer_account],
         ).unwrap();
 
         // Initialize Syncronizer account
@@ -935,7 +2240,7 @@ mod test {
                 &oracles,
                 &synchronizer_key
             ).unwrap(),
-            vec![&mut synchronizer_account, &mut rent_sysvar]
+            vec![&mut trade.synchronizer_account, &mut rent_sysvar, &mut collateral_asset_mint]
         ).unwrap();
 
         // Parameters for sell/buy instructions
@@ -946,6 +2251,58 @@ mod test {
             spl_token::ui_amount_to_amount(0.4, decimals)
         ];
 
+        // BadCase: fake token program
+        let fake_token_program_key = Pubkey::new_unique();
+        let mut instruction = crate::instruction::buy_for(
+            program_id,
+            mul_stocks,
+            spl_token::ui_amount_to_amount(10.0, decimals),
+            fee,
+            &prices,
+            &oracles,
+            &fiat_asset_key,
+            &user_collateral_key,
+            &user_fiat_key,
+            &synchronizer_collateral_key,
+            &user_key,
+            &synchronizer_key,
+            TRADE_PROGRAM_ACCOUNTS,
+            None
+        ).unwrap();
+        instruction.accounts[6].pubkey = fake_token_program_key;
+        assert_eq!(
+            Err(SynchronizerError::InvalidTokenProgram.into()),
+            do_process(
+                instruction,
+                trade.trade_accounts()
+            )
+        );
+
+        let mut instruction = crate::instruction::sell_for(
+            program_id,
+            mul_stocks,
+            spl_token::ui_amount_to_amount(10.0, decimals),
+            fee,
+            &prices,
+            &oracles,
+            &fiat_asset_key,
+            &user_collateral_key,
+            &user_fiat_key,
+            &synchronizer_collateral_key,
+            &user_key,
+            &synchronizer_key,
+            TRADE_PROGRAM_ACCOUNTS,
+            None
+        ).unwrap();
+        instruction.accounts[6].pubkey = fake_token_program_key;
+        assert_eq!(
+            Err(SynchronizerError::InvalidTokenProgram.into()),
+            do_process(
+                instruction,
+                trade.trade_accounts()
+            )
+        );
+
         // Test sell_for instruction
         let sell_fiat_amount = spl_token::ui_amount_to_amount(100.0, decimals);
 
@@ -967,19 +2324,11 @@ mod test {
                     &user_fiat_key,
                     &synchronizer_collateral_key,
                     &user_key,
-                    &fake_synchronizer_key
+                    &fake_synchronizer_key,
+                    TRADE_PROGRAM_ACCOUNTS,
+                    None
                 ).unwrap(),
-                vec![
-                    &mut fiat_asset_mint,
-                    &mut user_collateral_account,
-                    &mut user_fiat_account,
-                    &mut synchronizer_collateral_account,
-                    &mut user_account,
-                    &mut fake_synchronizer_account,
-                    &mut spl_token_account,
-                    &mut oracle1_acc,
-                    &mut oracle2_acc,
-                ]
+                trade.trade_accounts_with(TRADE_PROGRAM_ACCOUNTS, vec![(5, &mut fake_synchronizer_account)])
             )
         );
 
@@ -998,30 +2347,24 @@ mod test {
                     &user_fiat_key,
                     &synchronizer_collateral_key,
                     &user_key,
-                    &synchronizer_key
+                    &synchronizer_key,
+                    TRADE_PROGRAM_ACCOUNTS,
+                    None
                 ).unwrap(),
-                vec![
-                    &mut fiat_asset_mint,
-                    &mut user_collateral_account,
-                    &mut user_fiat_account,
-                    &mut synchronizer_collateral_account,
-                    &mut user_account,
-                    &mut synchronizer_account,
-                    &mut spl_token_account,
-                    &mut oracle1_acc,
-                ]
+                trade.trade_accounts().into_iter().take(17).collect()
             )
         );
 
         assert_eq!(
             user_key,
-            Account::unpack_unchecked(&user_fiat_account.data).unwrap().owner
+            Account::unpack_unchecked(&trade.user_fiat_account.data).unwrap().owner
         );
 
-        let synchronizer = SynchronizerData::unpack(&synchronizer_account.data).unwrap();
+        let synchronizer = SynchronizerData::unpack(&trade.synchronizer_account.data).unwrap();
         assert_eq!(synchronizer.remaining_dollar_cap, 500_000_000_000);
         assert_eq!(synchronizer.withdrawable_fee_amount, 0);
 
+        // Case: trades without optional accounts, as legacy clients send them
         do_process(
             crate::instruction::sell_for(
                 program_id,
@@ -1035,19 +2378,11 @@ mod test {
                 &user_fiat_key,
                 &synchronizer_collateral_key,
                 &user_key,
-                &synchronizer_key
+                &synchronizer_key,
+                0,
+                None
             ).unwrap(),
-            vec![
-                &mut fiat_asset_mint,
-                &mut user_collateral_account,
-                &mut user_fiat_account,
-                &mut synchronizer_collateral_account,
-                &mut user_account,
-                &mut synchronizer_account,
-                &mut spl_token_account,
-                &mut oracle1_acc,
-                &mut oracle2_acc,
-            ]
+            trade.optional_trade_accounts(0)
         ).unwrap();
 
         // Test buy_for instruction
@@ -1070,24 +2405,69 @@ mod test {
                     &user_fiat_key,
                     &synchronizer_collateral_key,
                     &user_key,
-                    &fake_synchronizer_key
+                    &fake_synchronizer_key,
+                    TRADE_PROGRAM_ACCOUNTS,
+                    None
                 ).unwrap(),
-                vec![
-                    &mut fiat_asset_mint,
-                    &mut user_collateral_account,
-                    &mut user_fiat_account,
-                    &mut synchronizer_collateral_account,
-                    &mut user_account,
-                    &mut fake_synchronizer_account,
-                    &mut spl_token_account,
-                    &mut oracle1_acc,
-                    &mut oracle2_acc,
-                ]
+                trade.trade_accounts_with(TRADE_PROGRAM_ACCOUNTS, vec![(5, &mut fake_synchronizer_account)])
+            )
+        );
+
+        assert_eq!(
+            Err(SynchronizerError::NotEnoughOracles.into()),
+            do_process(
+                crate::instruction::buy_for(
+                    program_id,
+                    mul_stocks,
+                    buy_fiat_amount,
+                    fee,
+                    &prices,
+                    &oracles,
+                    &fiat_asset_key,
+                    &user_collateral_key,
+                    &user_fiat_key,
+                    &synchronizer_collateral_key,
+                    &user_key,
+                    &synchronizer_key,
+                    TRADE_PROGRAM_ACCOUNTS,
+                    None
+                ).unwrap(),
+                trade.trade_accounts().into_iter().take(17).collect()
             )
         );
 
+        // Good case
+        let synchronizer = SynchronizerData::unpack(&trade.synchronizer_account.data).unwrap();
+        assert_eq!(synchronizer.remaining_dollar_cap, 580_000_000_000);
+        assert_eq!(synchronizer.withdrawable_fee_amount, 40_000_000);
+
+        do_process(
+            crate::instruction::buy_for(
+                program_id,
+                mul_stocks,
+                buy_fiat_amount,
+                fee,
+                &prices,
+                &oracles,
+                &fiat_asset_key,
+                &user_collateral_key,
+                &user_fiat_key,
+                &synchronizer_collateral_key,
+                &user_key,
+                &synchronizer_key,
+                TRADE_PROGRAM_ACCOUNTS,
+                None
+            ).unwrap(),
+            trade.trade_accounts()
+        ).unwrap();
+
+        // BadCase: buy above the remaining dollar cap
+        let mut synchronizer = SynchronizerData::unpack(&trade.synchronizer_account.data).unwrap();
+        let remaining_dollar_cap = synchronizer.remaining_dollar_cap;
+        synchronizer.remaining_dollar_cap = 1;
+        SynchronizerData::pack(synchronizer, &mut trade.synchronizer_account.data).unwrap();
         assert_eq!(
-            Err(SynchronizerError::NotEnoughOracles.into()),
+            Err(SynchronizerError::DollarCapExceeded.into()),
             do_process(
                 crate::instruction::buy_for(
                     program_id,
@@ -1101,31 +2481,25 @@ mod test {
                     &user_fiat_key,
                     &synchronizer_collateral_key,
                     &user_key,
-                    &synchronizer_key
+                    &synchronizer_key,
+                    TRADE_PROGRAM_ACCOUNTS,
+                    None
                 ).unwrap(),
-                vec![
-                    &mut fiat_asset_mint,
-                    &mut user_collateral_account,
-                    &mut user_fiat_account,
-                    &mut synchronizer_collateral_account,
-                    &mut user_account,
-                    &mut synchronizer_account,
-                    &mut spl_token_account,
-                    &mut oracle1_acc,
-                ]
+                trade.trade_accounts()
             )
         );
+        synchronizer.remaining_dollar_cap = remaining_dollar_cap;
+        SynchronizerData::pack(synchronizer, &mut trade.synchronizer_account.data).unwrap();
 
-        // Good case
-        let synchronizer = SynchronizerData::unpack(&synchronizer_account.data).unwrap();
-        assert_eq!(synchronizer.remaining_dollar_cap, 580_000_000_000);
-        assert_eq!(synchronizer.withdrawable_fee_amount, 40_000_000);
-
+        // Good case: sell saturates the remaining dollar cap
+        let mut saturated = trade.clone();
+        synchronizer.remaining_dollar_cap = u64::MAX - 1;
+        SynchronizerData::pack(synchronizer, &mut saturated.synchronizer_account.data).unwrap();
         do_process(
-            crate::instruction::buy_for(
+            crate::instruction::sell_for(
                 program_id,
                 mul_stocks,
-                buy_fiat_amount,
+                spl_token::ui_amount_to_amount(100.0, decimals),
                 fee,
                 &prices,
                 &oracles,
@@ -1134,20 +2508,13 @@ mod test {
                 &user_fiat_key,
                 &synchronizer_collateral_key,
                 &user_key,
-                &synchronizer_key
+                &synchronizer_key,
+                TRADE_PROGRAM_ACCOUNTS,
+                None
             ).unwrap(),
-            vec![
-                &mut fiat_asset_mint,
-                &mut user_collateral_account,
-                &mut user_fiat_account,
-                &mut synchronizer_collateral_account,
-                &mut user_account,
-                &mut synchronizer_account,
-                &mut spl_token_account,
-                &mut oracle1_acc,
-                &mut oracle2_acc,
-            ]
+            saturated.trade_accounts()
         ).unwrap();
+        assert_eq!(SynchronizerData::unpack(&saturated.synchronizer_account.data).unwrap().remaining_dollar_cap, u64::MAX);
 
         // BadCase: too big buy amount
         let buy_fiat_amount = spl_token::ui_amount_to_amount(999999.0, decimals);
@@ -1166,19 +2533,11 @@ mod test {
                     &user_fiat_key,
                     &synchronizer_collateral_key,
                     &user_key,
-                    &synchronizer_key
+                    &synchronizer_key,
+                    TRADE_PROGRAM_ACCOUNTS,
+                    None
                 ).unwrap(),
-                vec![
-                    &mut fiat_asset_mint,
-                    &mut user_collateral_account,
-                    &mut user_fiat_account,
-                    &mut synchronizer_collateral_account,
-                    &mut user_account,
-                    &mut synchronizer_account,
-                    &mut spl_token_account,
-                    &mut oracle1_acc,
-                    &mut oracle2_acc,
-                ]
+                trade.trade_accounts()
             )
         );
 
@@ -1199,21 +2558,801 @@ mod test {
                     &user_fiat_key,
                     &synchronizer_collateral_key,
                     &user_key,
-                    &synchronizer_key
+                    &synchronizer_key,
+                    TRADE_PROGRAM_ACCOUNTS,
+                    None
                 ).unwrap(),
-                vec![
-                    &mut fiat_asset_mint,
-                    &mut user_collateral_account,
-                    &mut user_fiat_account,
-                    &mut synchronizer_collateral_account,
-                    &mut user_account,
-                    &mut synchronizer_account,
-                    &mut spl_token_account,
-                    &mut oracle1_acc,
-                    &mut oracle2_acc,
-                ]
+                trade.trade_accounts()
+            )
+        );
+
+        // Per-asset caps
+        let payer_key = Pubkey::new_unique();
+        let mut payer_account = SolanaAccount::new(init_acc_minimum_balance(), 0, &solana_program::system_program::id());
+        let buy_fiat_amount = spl_token::ui_amount_to_amount(50.0, decimals);
+        let notional = quote_buy(&prices, buy_fiat_amount, fee, mul_stocks, decimals, decimals, 0).unwrap().collateral_amount;
+
+        // BadCase: asset account of another fiat asset
+        let mut instruction = crate::instruction::set_asset_caps(program_id, &fiat_asset_key, 2 * notional, notional, &synchronizer_key, &payer_key).unwrap();
+        instruction.accounts[1].pubkey = AssetData::find_address(&synchronizer_key, &collateral_key).0;
+        assert_eq!(
+            Err(SynchronizerError::InvalidAssetAccount.into()),
+            do_process(
+                instruction,
+                vec![&mut trade.synchronizer_account, &mut trade.asset_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar]
+            )
+        );
+
+        // The system program creates the asset account
+        trade.asset_account = SolanaAccount::new(Rent::default().minimum_balance(AssetData::LEN), AssetData::LEN, program_id);
+        do_process(
+            crate::instruction::set_asset_caps(program_id, &fiat_asset_key, 2 * notional, notional, &synchronizer_key, &payer_key).unwrap(),
+            vec![&mut trade.synchronizer_account, &mut trade.asset_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar]
+        ).unwrap();
+        let (_, bump_seed) = AssetData::find_address(&synchronizer_key, &fiat_asset_key);
+        assert_eq!(AssetData::unpack(&trade.asset_account.data).unwrap(), AssetData {
+            is_initialized: true,
+            synchronizer: synchronizer_key,
+            fiat_asset_mint: fiat_asset_key,
+            max_outstanding_notional: 2 * notional,
+            max_transaction_notional: notional,
+            outstanding_notional: 0,
+            bump_seed,
+            ..AssetData::default()
+        });
+
+        let buy_instruction = |amount| crate::instruction::buy_for(
+            program_id,
+            mul_stocks,
+            amount,
+            fee,
+            &prices,
+            &oracles,
+            &fiat_asset_key,
+            &user_collateral_key,
+            &user_fiat_key,
+            &synchronizer_collateral_key,
+            &user_key,
+            &synchronizer_key,
+            TRADE_PROGRAM_ACCOUNTS,
+            None
+        ).unwrap();
+
+        // BadCase: trades without the asset account once it holds caps
+        let optional_accounts = TRADE_PROGRAM_ACCOUNTS & !TRADE_ASSET_ACCOUNT;
+        assert_eq!(
+            Err(SynchronizerError::MissingTradeAccount.into()),
+            do_process(
+                crate::instruction::buy_for(
+                    program_id, mul_stocks, buy_fiat_amount, fee, &prices, &oracles, &fiat_asset_key, &user_collateral_key,
+                    &user_fiat_key, &synchronizer_collateral_key, &user_key, &synchronizer_key, optional_accounts, None,
+                ).unwrap(),
+                trade.optional_trade_accounts(optional_accounts)
+            )
+        );
+        assert_eq!(
+            Err(SynchronizerError::MissingTradeAccount.into()),
+            do_process(
+                crate::instruction::buy_for(
+                    program_id, mul_stocks, buy_fiat_amount, fee, &prices, &oracles, &fiat_asset_key, &user_collateral_key,
+                    &user_fiat_key, &synchronizer_collateral_key, &user_key, &synchronizer_key, 0, None,
+                ).unwrap(),
+                trade.optional_trade_accounts(0)
+            )
+        );
+        for _ in 0..2 {
+            do_process(
+                buy_instruction(buy_fiat_amount),
+                trade.trade_accounts()
+            ).unwrap();
+        }
+        assert_eq!(AssetData::unpack(&trade.asset_account.data).unwrap().outstanding_notional, 2 * notional);
+
+        // BadCase: outstanding notional cap exceeded
+        let synchronizer = SynchronizerData::unpack(&trade.synchronizer_account.data).unwrap();
+        assert_eq!(
+            Err(SynchronizerError::AssetCapExceeded.into()),
+            do_process(
+                buy_instruction(buy_fiat_amount),
+                trade.trade_accounts()
+            )
+        );
+        assert_eq!(SynchronizerData::unpack(&trade.synchronizer_account.data).unwrap(), synchronizer);
+
+        // Sells restore the outstanding notional
+        let sell_notional = quote_sell(&prices, buy_fiat_amount, fee, mul_stocks, decimals, decimals, 0).unwrap().collateral_amount;
+        do_process(
+            crate::instruction::sell_for(
+                program_id,
+                mul_stocks,
+                buy_fiat_amount,
+                fee,
+                &prices,
+                &oracles,
+                &fiat_asset_key,
+                &user_collateral_key,
+                &user_fiat_key,
+                &synchronizer_collateral_key,
+                &user_key,
+                &synchronizer_key,
+                TRADE_PROGRAM_ACCOUNTS,
+                None
+            ).unwrap(),
+            trade.trade_accounts()
+        ).unwrap();
+        assert_eq!(AssetData::unpack(&trade.asset_account.data).unwrap().outstanding_notional, 2 * notional - sell_notional);
+
+        // BadCase: transaction cap exceeded
+        assert_eq!(
+            Err(SynchronizerError::TransactionCapExceeded.into()),
+            do_process(
+                buy_instruction(2 * buy_fiat_amount),
+                trade.trade_accounts()
+            )
+        );
+
+        // BadCase: asset account of another fiat asset
+        let mut instruction = buy_instruction(buy_fiat_amount);
+        instruction.accounts[8].pubkey = Pubkey::new_unique();
+        assert_eq!(
+            Err(SynchronizerError::InvalidAssetAccount.into()),
+            do_process(
+                instruction,
+                trade.trade_accounts()
+            )
+        );
+
+        // Raising the caps keeps the outstanding notional
+        do_process(
+            crate::instruction::set_asset_caps(program_id, &fiat_asset_key, u64::MAX, u64::MAX, &synchronizer_key, &payer_key).unwrap(),
+            vec![&mut trade.synchronizer_account, &mut trade.asset_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar]
+        ).unwrap();
+        assert_eq!(AssetData::unpack(&trade.asset_account.data).unwrap().outstanding_notional, 2 * notional - sell_notional);
+
+        // Rolling volume limits
+        // BadCase: rate limit account of another Synchronizer
+        let mut instruction = crate::instruction::set_rate_limits(program_id, 160, 3 * notional, 2 * notional, &synchronizer_key, &payer_key).unwrap();
+        instruction.accounts[1].pubkey = RateLimitData::find_address(&user_key).0;
+        assert_eq!(
+            Err(SynchronizerError::InvalidRateLimitAccount.into()),
+            do_process(instruction, vec![&mut trade.synchronizer_account, &mut trade.rate_limit_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar])
+        );
+
+        // The system program creates the rate limit and user volume accounts
+        trade.rate_limit_account = SolanaAccount::new(Rent::default().minimum_balance(RateLimitData::LEN), RateLimitData::LEN, program_id);
+        trade.user_volume_account = SolanaAccount::new(Rent::default().minimum_balance(UserVolumeData::LEN), UserVolumeData::LEN, program_id);
+        trade.clock_sysvar = create_account_for_test(&Clock { slot: 1000, ..Clock::default() });
+        do_process(
+            crate::instruction::set_rate_limits(program_id, 160, 3 * notional, 2 * notional, &synchronizer_key, &payer_key).unwrap(),
+            vec![&mut trade.synchronizer_account, &mut trade.rate_limit_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar]
+        ).unwrap();
+
+        for _ in 0..2 {
+            do_process(
+                buy_instruction(buy_fiat_amount),
+                trade.trade_accounts()
+            ).unwrap();
+        }
+        let user_volume = UserVolumeData::unpack(&trade.user_volume_account.data).unwrap();
+        assert_eq!((user_volume.user, user_volume.window.volume(1000)), (user_key, 2 * notional));
+        assert_eq!(RateLimitData::unpack(&trade.rate_limit_account.data).unwrap().window.volume(1000), 2 * notional);
+
+        // BadCase: user limit exceeded
+        assert_eq!(
+            Err(SynchronizerError::RateLimitExceeded.into()),
+            do_process(
+                buy_instruction(buy_fiat_amount),
+                trade.trade_accounts()
+            )
+        );
+        assert_eq!(RateLimitData::unpack(&trade.rate_limit_account.data).unwrap().window.volume(1000), 2 * notional);
+
+        // BadCase: Synchronizer limit exceeded
+        do_process(
+            crate::instruction::set_rate_limits(program_id, 160, 3 * notional, 0, &synchronizer_key, &payer_key).unwrap(),
+            vec![&mut trade.synchronizer_account, &mut trade.rate_limit_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar]
+        ).unwrap();
+        do_process(
+            buy_instruction(buy_fiat_amount),
+            trade.trade_accounts()
+        ).unwrap();
+        assert_eq!(
+            Err(SynchronizerError::RateLimitExceeded.into()),
+            do_process(
+                buy_instruction(buy_fiat_amount),
+                trade.trade_accounts()
+            )
+        );
+
+        // Trades leave the window
+        trade.clock_sysvar = create_account_for_test(&Clock { slot: 1160, ..Clock::default() });
+        do_process(
+            buy_instruction(buy_fiat_amount),
+            trade.trade_accounts()
+        ).unwrap();
+        assert_eq!(RateLimitData::unpack(&trade.rate_limit_account.data).unwrap().window.volume(1160), notional);
+
+        // A zero window disables the limits
+        do_process(
+            crate::instruction::set_rate_limits(program_id, 0, 1, 1, &synchronizer_key, &payer_key).unwrap(),
+            vec![&mut trade.synchronizer_account, &mut trade.rate_limit_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar]
+        ).unwrap();
+        do_process(
+            buy_instruction(buy_fiat_amount),
+            trade.trade_accounts()
+        ).unwrap();
+
+        // User positions
+        // BadCase: position account of another fiat asset
+        let mut instruction = crate::instruction::create_user_position(program_id, &fiat_asset_key, &user_key, &synchronizer_key).unwrap();
+        instruction.data = SynchronizerInstruction::CreateUserPosition { fiat_asset_mint: Pubkey::new_unique() }.pack();
+        assert_eq!(
+            Err(SynchronizerError::InvalidUserPositionAccount.into()),
+            do_process(instruction, vec![&mut trade.user_account, &mut trade.synchronizer_account, &mut trade.user_position_account, &mut trade.system_program_account, &mut rent_sysvar])
+        );
+
+        // The system program creates the position account
+        trade.user_position_account = SolanaAccount::new(Rent::default().minimum_balance(UserPositionData::LEN), UserPositionData::LEN, program_id);
+        do_process(
+            crate::instruction::create_user_position(program_id, &fiat_asset_key, &user_key, &synchronizer_key).unwrap(),
+            vec![&mut trade.user_account, &mut trade.synchronizer_account, &mut trade.user_position_account, &mut trade.system_program_account, &mut rent_sysvar]
+        ).unwrap();
+        let (_, bump_seed) = UserPositionData::find_address(&synchronizer_key, &user_key, &fiat_asset_key);
+        let mut position = UserPositionData {
+            is_initialized: true,
+            synchronizer: synchronizer_key,
+            user: user_key,
+            fiat_asset_mint: fiat_asset_key,
+            bump_seed,
+            ..UserPositionData::default()
+        };
+        assert_eq!(UserPositionData::unpack(&trade.user_position_account.data).unwrap(), position);
+
+        // BadCase: position account already created
+        assert_eq!(
+            Err(SynchronizerError::AlreadyInitialized.into()),
+            do_process(
+                crate::instruction::create_user_position(program_id, &fiat_asset_key, &user_key, &synchronizer_key).unwrap(),
+                vec![&mut trade.user_account, &mut trade.synchronizer_account, &mut trade.user_position_account, &mut trade.system_program_account, &mut rent_sysvar]
+            )
+        );
+
+        // Trades are recorded in the position
+        do_process(buy_instruction(buy_fiat_amount), trade.trade_accounts()).unwrap();
+        let sell_instruction = crate::instruction::sell_for(
+            program_id,
+            mul_stocks,
+            buy_fiat_amount,
+            fee,
+            &prices,
+            &oracles,
+            &fiat_asset_key,
+            &user_collateral_key,
+            &user_fiat_key,
+            &synchronizer_collateral_key,
+            &user_key,
+            &synchronizer_key,
+            TRADE_PROGRAM_ACCOUNTS,
+            None
+        ).unwrap();
+        do_process(sell_instruction.clone(), trade.trade_accounts()).unwrap();
+        let buy_quote = quote_buy(&prices, buy_fiat_amount, fee, mul_stocks, decimals, decimals, 0).unwrap();
+        let sell_quote = quote_sell(&prices, buy_fiat_amount, fee, mul_stocks, decimals, decimals, 0).unwrap();
+        position.record_buy(buy_fiat_amount, &collateral_key, buy_quote.user_collateral_amount, buy_quote.fee_amount, 1160).unwrap();
+        position.record_sell(buy_fiat_amount, &collateral_key, sell_quote.user_collateral_amount, sell_quote.fee_amount, 1160).unwrap();
+        assert_eq!(UserPositionData::unpack(&trade.user_position_account.data).unwrap(), position);
+        assert_eq!(
+            (position.bought_amount, position.sold_amount, position.fee_paid),
+            (buy_fiat_amount, buy_fiat_amount, buy_quote.fee_amount + sell_quote.fee_amount)
+        );
+
+        // BadCase: position account of another user
+        let mut instruction = sell_instruction.clone();
+        instruction.accounts[12].pubkey = UserPositionData::find_address(&synchronizer_key, &payer_key, &fiat_asset_key).0;
+        let synchronizer = SynchronizerData::unpack(&trade.synchronizer_account.data).unwrap();
+        assert_eq!(Err(SynchronizerError::InvalidUserPositionAccount.into()), do_process(instruction, trade.trade_accounts()));
+        assert_eq!(SynchronizerData::unpack(&trade.synchronizer_account.data).unwrap(), synchronizer);
+
+        // Closing returns the rent to the recipient
+        let payer_lamports = payer_account.lamports;
+        do_process(
+            crate::instruction::close_user_position(program_id, &fiat_asset_key, &payer_key, &user_key, &synchronizer_key).unwrap(),
+            vec![&mut trade.user_account, &mut trade.synchronizer_account, &mut trade.user_position_account, &mut payer_account]
+        ).unwrap();
+        assert_eq!(payer_account.lamports, payer_lamports + Rent::default().minimum_balance(UserPositionData::LEN));
+        assert_eq!(trade.user_position_account.lamports, 0);
+        assert!(trade.user_position_account.data.iter().all(|byte| *byte == 0));
+
+        // BadCase: position account already closed
+        assert_eq!(
+            Err(SynchronizerError::NotInitialized.into()),
+            do_process(
+                crate::instruction::close_user_position(program_id, &fiat_asset_key, &payer_key, &user_key, &synchronizer_key).unwrap(),
+                vec![&mut trade.user_account, &mut trade.synchronizer_account, &mut trade.user_position_account, &mut payer_account]
+            )
+        );
+
+        // Trades without a position account don't record anything
+        do_process(sell_instruction.clone(), trade.trade_accounts()).unwrap();
+        assert!(trade.user_position_account.data.iter().all(|byte| *byte == 0));
+        // BadCase: only the first oracle is known, or it signs twice
+        for oracles in [vec![oracles[0], Pubkey::new_unique()], vec![oracles[0], oracles[0]]].iter() {
+            let mut second_oracle_acc = trade.oracle1_acc.clone();
+            assert_eq!(
+                Err(SynchronizerError::BadOracle.into()),
+                do_process(
+                    crate::instruction::buy_for(
+                        program_id,
+                        mul_stocks,
+                        buy_fiat_amount,
+                        fee,
+                        &prices,
+                        oracles,
+                        &fiat_asset_key,
+                        &user_collateral_key,
+                        &user_fiat_key,
+                        &synchronizer_collateral_key,
+                        &user_key,
+                        &synchronizer_key,
+                        TRADE_PROGRAM_ACCOUNTS,
+                        None
+                    ).unwrap(),
+                    trade.trade_accounts_with(TRADE_PROGRAM_ACCOUNTS, vec![(17, &mut second_oracle_acc)])
+                )
+            );
+        }
+
+        // Market schedules
+        // Weekdays from 14:30 to 21:00 UTC, 2021-12-24 is a holiday
+        let session = TradingHours { open: 52_200, close: 75_600 };
+        let weekdays = [session, session, session, session, session, TradingHours::default(), TradingHours::default()];
+        let thursday = 18_984 * SECONDS_PER_DAY;
+        // BadCase: invalid hours
+        assert_eq!(
+            Err(SynchronizerError::InvalidMarketSchedule.into()),
+            do_process(
+                crate::instruction::set_market_schedule(program_id, &fiat_asset_key, [TradingHours { open: 2, close: 1 }; 7], &[], &synchronizer_key, &payer_key).unwrap(),
+                vec![&mut trade.synchronizer_account, &mut trade.market_schedule_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar]
+            )
+        );
+        // BadCase: market schedule account of another fiat asset
+        let mut instruction = crate::instruction::set_market_schedule(program_id, &fiat_asset_key, weekdays, &[18_985], &synchronizer_key, &payer_key).unwrap();
+        instruction.accounts[1].pubkey = MarketScheduleData::find_address(&synchronizer_key, &Pubkey::new_unique()).0;
+        assert_eq!(
+            Err(SynchronizerError::InvalidMarketScheduleAccount.into()),
+            do_process(instruction, vec![&mut trade.synchronizer_account, &mut trade.market_schedule_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar])
+        );
+
+        // The system program creates the market schedule account
+        trade.market_schedule_account = SolanaAccount::new(Rent::default().minimum_balance(MarketScheduleData::LEN), MarketScheduleData::LEN, program_id);
+        do_process(
+            crate::instruction::set_market_schedule(program_id, &fiat_asset_key, weekdays, &[18_985], &synchronizer_key, &payer_key).unwrap(),
+            vec![&mut trade.synchronizer_account, &mut trade.market_schedule_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar]
+        ).unwrap();
+        let schedule = MarketScheduleData::unpack(&trade.market_schedule_account.data).unwrap();
+        assert_eq!((schedule.weekly_hours, schedule.holidays[0], schedule.holidays[1]), (weekdays, 18_985, 0));
+
+        trade.clock_sysvar = create_account_for_test(&Clock { slot: 1160, unix_timestamp: thursday + 54_000, ..Clock::default() });
+        do_process(buy_instruction(buy_fiat_amount), trade.trade_accounts()).unwrap();
+        do_process(sell_instruction.clone(), trade.trade_accounts()).unwrap();
+
+        // BadCase: trades after the close, on a holiday or on a weekend
+        let synchronizer = SynchronizerData::unpack(&trade.synchronizer_account.data).unwrap();
+        for unix_timestamp in [thursday + 75_600, thursday + SECONDS_PER_DAY + 54_000, thursday + 2 * SECONDS_PER_DAY + 54_000].iter() {
+            trade.clock_sysvar = create_account_for_test(&Clock { slot: 1160, unix_timestamp: *unix_timestamp, ..Clock::default() });
+            assert_eq!(Err(SynchronizerError::MarketClosed.into()), do_process(buy_instruction(buy_fiat_amount), trade.trade_accounts()));
+            assert_eq!(Err(SynchronizerError::MarketClosed.into()), do_process(sell_instruction.clone(), trade.trade_accounts()));
+        }
+        assert_eq!(SynchronizerData::unpack(&trade.synchronizer_account.data).unwrap(), synchronizer);
+
+        // BadCase: market schedule account of another fiat asset
+        let mut instruction = buy_instruction(buy_fiat_amount);
+        instruction.accounts[13].pubkey = MarketScheduleData::find_address(&synchronizer_key, &Pubkey::new_unique()).0;
+        assert_eq!(Err(SynchronizerError::InvalidMarketScheduleAccount.into()), do_process(instruction, trade.trade_accounts()));
+
+        // Open every day again
+        do_process(
+            crate::instruction::set_market_schedule(program_id, &fiat_asset_key, [TradingHours::ALL_DAY; 7], &[], &synchronizer_key, &payer_key).unwrap(),
+            vec![&mut trade.synchronizer_account, &mut trade.market_schedule_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar]
+        ).unwrap();
+        do_process(buy_instruction(buy_fiat_amount), trade.trade_accounts()).unwrap();
+
+        // Asset spreads
+        // BadCase: bid spread of the whole price
+        assert_eq!(
+            Err(SynchronizerError::InvalidSpread.into()),
+            do_process(
+                crate::instruction::set_asset_spreads(program_id, &fiat_asset_key, 100, BPS_DENOMINATOR, &synchronizer_key, &payer_key).unwrap(),
+                vec![&mut trade.synchronizer_account, &mut trade.asset_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar]
+            )
+        );
+        do_process(
+            crate::instruction::set_asset_spreads(program_id, &fiat_asset_key, 100, 200, &synchronizer_key, &payer_key).unwrap(),
+            vec![&mut trade.synchronizer_account, &mut trade.asset_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar]
+        ).unwrap();
+        let asset = AssetData::unpack(&trade.asset_account.data).unwrap();
+        assert_eq!((asset.ask_spread_bps, asset.bid_spread_bps, asset.max_transaction_notional), (100, 200, u64::MAX));
+
+        // Trades pay the spreads on top of the fee
+        let synchronizer = SynchronizerData::unpack(&trade.synchronizer_account.data).unwrap();
+        let buy_quote = quote_buy(&prices, buy_fiat_amount, fee, mul_stocks, decimals, decimals, 100).unwrap();
+        let sell_quote = quote_sell(&prices, buy_fiat_amount, fee, mul_stocks, decimals, decimals, 200).unwrap();
+        assert!(buy_quote.price > quote_buy(&prices, buy_fiat_amount, fee, mul_stocks, decimals, decimals, 0).unwrap().price);
+        do_process(buy_instruction(buy_fiat_amount), trade.trade_accounts()).unwrap();
+        do_process(sell_instruction.clone(), trade.trade_accounts()).unwrap();
+        assert_eq!(
+            AssetData::unpack(&trade.asset_account.data).unwrap().outstanding_notional,
+            asset.outstanding_notional.saturating_add(buy_quote.collateral_amount).saturating_sub(sell_quote.collateral_amount)
+        );
+        assert_eq!(
+            SynchronizerData::unpack(&trade.synchronizer_account.data).unwrap().withdrawable_fee_amount,
+            synchronizer.withdrawable_fee_amount + buy_quote.fee_amount + sell_quote.fee_amount
+        );
+
+        // Fee schedules
+        do_process(
+            crate::instruction::set_remaining_dollar_cap(program_id, u64::MAX, &synchronizer_key).unwrap(),
+            vec![&mut trade.synchronizer_account]
+        ).unwrap();
+        // BadCase: tiers out of order
+        let tiers = [FeeTier { min_volume: 0, fee: fee / 2 }, FeeTier { min_volume: u64::MAX, fee: 0 }];
+        assert_eq!(
+            Err(SynchronizerError::InvalidFeeSchedule.into()),
+            do_process(
+                crate::instruction::set_fee_tiers(program_id, &[tiers[1], tiers[0]], 0, &synchronizer_key, &payer_key).unwrap(),
+                vec![&mut trade.synchronizer_account, &mut trade.fee_schedule_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar]
+            )
+        );
+
+        // The system program creates the fee schedule account
+        trade.fee_schedule_account = SolanaAccount::new(Rent::default().minimum_balance(FeeScheduleData::LEN), FeeScheduleData::LEN, program_id);
+        do_process(
+            crate::instruction::set_fee_tiers(program_id, &tiers, 0, &synchronizer_key, &payer_key).unwrap(),
+            vec![&mut trade.synchronizer_account, &mut trade.fee_schedule_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar]
+        ).unwrap();
+        assert_eq!(FeeScheduleData::unpack(&trade.fee_schedule_account.data).unwrap().tiers(), &tiers);
+
+        // Tiers and overrides below the signed fee rate are charged
+        let withdrawable_fee_amount = |account: &SolanaAccount| SynchronizerData::unpack(&account.data).unwrap().withdrawable_fee_amount;
+        let fee_amount = |fee| quote_buy(&prices, buy_fiat_amount, fee, mul_stocks, decimals, decimals, 100).unwrap().fee_amount;
+        for (user_fee, charged_fee) in [(None, fee / 2), (Some(0), 0), (Some(2 * fee), fee)].iter() {
+            do_process(
+                crate::instruction::set_fee_override(program_id, &user_key, *user_fee, &synchronizer_key, &payer_key).unwrap(),
+                vec![&mut trade.synchronizer_account, &mut trade.fee_schedule_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar]
+            ).unwrap();
+            let before = withdrawable_fee_amount(&trade.synchronizer_account);
+            do_process(buy_instruction(buy_fiat_amount), trade.trade_accounts()).unwrap();
+            assert_eq!(withdrawable_fee_amount(&trade.synchronizer_account), before + fee_amount(*charged_fee));
+        }
+        assert_eq!(FeeScheduleData::unpack(&trade.fee_schedule_account.data).unwrap().override_count, 1);
+
+        // BadCase: fee schedule account of another Synchronizer
+        let mut instruction = buy_instruction(buy_fiat_amount);
+        instruction.accounts[14].pubkey = FeeScheduleData::find_address(&payer_key).0;
+        assert_eq!(Err(SynchronizerError::InvalidFeeScheduleAccount.into()), do_process(instruction, trade.trade_accounts()));
+
+        // An empty schedule charges the signed fee rate
+        do_process(
+            crate::instruction::set_fee_override(program_id, &user_key, None, &synchronizer_key, &payer_key).unwrap(),
+            vec![&mut trade.synchronizer_account, &mut trade.fee_schedule_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar]
+        ).unwrap();
+        do_process(
+            crate::instruction::set_fee_tiers(program_id, &[], 0, &synchronizer_key, &payer_key).unwrap(),
+            vec![&mut trade.synchronizer_account, &mut trade.fee_schedule_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar]
+        ).unwrap();
+        assert_eq!(FeeScheduleData::unpack(&trade.fee_schedule_account.data).unwrap().override_count, 0);
+        let before = withdrawable_fee_amount(&trade.synchronizer_account);
+        do_process(buy_instruction(buy_fiat_amount), trade.trade_accounts()).unwrap();
+        assert_eq!(withdrawable_fee_amount(&trade.synchronizer_account), before + fee_amount(fee));
+
+        // Volume tiers count the user volume without rate limits, the first trade creates the volume account
+        trade.user_volume_account = SolanaAccount::new(Rent::default().minimum_balance(UserVolumeData::LEN), UserVolumeData::LEN, program_id);
+        let tiers = [FeeTier { min_volume: 0, fee }, FeeTier { min_volume: 1, fee: fee / 2 }];
+        do_process(
+            crate::instruction::set_fee_tiers(program_id, &tiers, 1000, &synchronizer_key, &payer_key).unwrap(),
+            vec![&mut trade.synchronizer_account, &mut trade.fee_schedule_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar]
+        ).unwrap();
+        for charged_fee in [fee, fee / 2].iter() {
+            let before = withdrawable_fee_amount(&trade.synchronizer_account);
+            do_process(buy_instruction(buy_fiat_amount), trade.trade_accounts()).unwrap();
+            assert_eq!(withdrawable_fee_amount(&trade.synchronizer_account), before + fee_amount(*charged_fee));
+        }
+        let user_volume = UserVolumeData::unpack(&trade.user_volume_account.data).unwrap();
+        assert_eq!((user_volume.fee_window.bucket_slots, user_volume.window), (VolumeWindow::bucket_slots(1000), VolumeWindow::default()));
+        do_process(
+            crate::instruction::set_fee_tiers(program_id, &[], 0, &synchronizer_key, &payer_key).unwrap(),
+            vec![&mut trade.synchronizer_account, &mut trade.fee_schedule_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar]
+        ).unwrap();
+
+        // Referrals
+        let referrer_collateral_key = Pubkey::new_unique();
+        let mut referrer_collateral_account = SolanaAccount::new(account_minimum_balance(), Account::get_packed_len(), &spl_token::id());
+        do_token_program(
+            spl_token::instruction::initialize_account(&spl_token::id(), &referrer_collateral_key, &collateral_key, &payer_key).unwrap(),
+            vec![
+                &mut referrer_collateral_account,
+                &mut collateral_asset_mint,
+                &mut payer_account,
+                &mut rent_sysvar,
+            ],
+        ).unwrap();
+        let referred_buy_instruction = |referrer: &Pubkey| crate::instruction::buy_for(
+            program_id, mul_stocks, buy_fiat_amount, fee, &prices, &oracles, &fiat_asset_key, &user_collateral_key,
+            &user_fiat_key, &synchronizer_collateral_key, &user_key, &synchronizer_key, TRADE_PROGRAM_ACCOUNTS, Some(referrer),
+        ).unwrap();
+        // BadCase: referral share above 100%
+        assert_eq!(
+            Err(SynchronizerError::InvalidFeeSchedule.into()),
+            do_process(
+                crate::instruction::set_referral_share(program_id, 10_001, &synchronizer_key, &payer_key).unwrap(),
+                vec![&mut trade.synchronizer_account, &mut trade.fee_schedule_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar]
             )
         );
+        do_process(
+            crate::instruction::set_referral_share(program_id, 2500, &synchronizer_key, &payer_key).unwrap(),
+            vec![&mut trade.synchronizer_account, &mut trade.fee_schedule_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar]
+        ).unwrap();
+        assert_eq!(FeeScheduleData::unpack(&trade.fee_schedule_account.data).unwrap().referral_share_bps, 2500);
+
+        // The referrer gets its share of the fee, the rest is withdrawable
+        let before = withdrawable_fee_amount(&trade.synchronizer_account);
+        do_process(referred_buy_instruction(&referrer_collateral_key), trade.trade_accounts_with(TRADE_PROGRAM_ACCOUNTS | TRADE_REFERRER_ACCOUNT, vec![(15, &mut referrer_collateral_account)])).unwrap();
+        assert_eq!(withdrawable_fee_amount(&trade.synchronizer_account), before + fee_amount(fee) - fee_amount(fee) / 4);
+
+        // BadCase: withdrawable fees overflowing or a referral above the fee
+        assert_eq!(Processor::accrue_fee(u64::MAX - 1, 2, 1), Ok(u64::MAX));
+        assert_eq!(Processor::accrue_fee(u64::MAX, 2, 1), Err(SynchronizerError::InsufficientFunds.into()));
+        assert_eq!(Processor::accrue_fee(0, 1, 2), Err(SynchronizerError::InsufficientFunds.into()));
+
+        // Trades without referrer keep the whole fee
+        let before = withdrawable_fee_amount(&trade.synchronizer_account);
+        do_process(buy_instruction(buy_fiat_amount), trade.trade_accounts()).unwrap();
+        assert_eq!(withdrawable_fee_amount(&trade.synchronizer_account), before + fee_amount(fee));
+
+        // BadCase: the Synchronizer collateral account or a fiat account as referrer
+        let mut synchronizer_collateral_copy = trade.synchronizer_collateral_account.clone();
+        assert_eq!(Err(SynchronizerError::InvalidReferrerAccount.into()), do_process(referred_buy_instruction(&synchronizer_collateral_key), trade.trade_accounts_with(TRADE_PROGRAM_ACCOUNTS | TRADE_REFERRER_ACCOUNT, vec![(15, &mut synchronizer_collateral_copy)])));
+        let mut user_fiat_copy = trade.user_fiat_account.clone();
+        assert_eq!(Err(SynchronizerError::BadCollateralMint.into()), do_process(referred_buy_instruction(&user_fiat_key), trade.trade_accounts_with(TRADE_PROGRAM_ACCOUNTS | TRADE_REFERRER_ACCOUNT, vec![(15, &mut user_fiat_copy)])));
+        // BadCase: the program id is no referrer placeholder
+        let mut program_account = SolanaAccount::default();
+        assert_eq!(Err(SynchronizerError::InvalidAccountOwner.into()), do_process(referred_buy_instruction(&id()), trade.trade_accounts_with(TRADE_PROGRAM_ACCOUNTS | TRADE_REFERRER_ACCOUNT, vec![(15, &mut program_account)])));
+        // BadCase: the user refers itself
+        let mut user_collateral_copy = trade.user_collateral_account.clone();
+        assert_eq!(Err(SynchronizerError::InvalidReferrerAccount.into()), do_process(referred_buy_instruction(&user_collateral_key), trade.trade_accounts_with(TRADE_PROGRAM_ACCOUNTS | TRADE_REFERRER_ACCOUNT, vec![(15, &mut user_collateral_copy)])));
+
+        // Accepted collaterals
+        let usdt_decimals = 6;
+        let usdt_key = Pubkey::new_unique();
+        let mut usdt_mint = SolanaAccount::new(mint_minimum_balance(), Mint::get_packed_len(), &spl_token::id());
+        do_token_program(
+            spl_token::instruction::initialize_mint(&spl_token::id(), &usdt_key, &payer_key, None, usdt_decimals).unwrap(),
+            vec![&mut usdt_mint, &mut rent_sysvar],
+        ).unwrap();
+        let synchronizer_usdt_key = Pubkey::new_unique();
+        let mut synchronizer_usdt_account = SolanaAccount::new(account_minimum_balance(), Account::get_packed_len(), &spl_token::id());
+        do_token_program(
+            spl_token::instruction::initialize_account(&spl_token::id(), &synchronizer_usdt_key, &usdt_key, &synchronizer_key).unwrap(),
+            vec![&mut synchronizer_usdt_account, &mut usdt_mint, &mut trade.synchronizer_account, &mut rent_sysvar],
+        ).unwrap();
+        let user_usdt_key = Pubkey::new_unique();
+        let mut user_usdt_account = SolanaAccount::new(account_minimum_balance(), Account::get_packed_len(), &spl_token::id());
+        do_token_program(
+            spl_token::instruction::initialize_account(&spl_token::id(), &user_usdt_key, &usdt_key, &user_key).unwrap(),
+            vec![&mut user_usdt_account, &mut usdt_mint, &mut trade.user_account, &mut rent_sysvar],
+        ).unwrap();
+        do_token_program(
+            spl_token::instruction::mint_to(&spl_token::id(), &usdt_key, &user_usdt_key, &payer_key, &[], spl_token::ui_amount_to_amount(500.0, usdt_decimals)).unwrap(),
+            vec![&mut usdt_mint, &mut user_usdt_account, &mut payer_account],
+        ).unwrap();
+        do_token_program(
+            spl_token::instruction::mint_to(&spl_token::id(), &usdt_key, &synchronizer_usdt_key, &payer_key, &[], spl_token::ui_amount_to_amount(500.0, usdt_decimals)).unwrap(),
+            vec![&mut usdt_mint, &mut synchronizer_usdt_account, &mut payer_account],
+        ).unwrap();
+        let rate = spl_token::ui_amount_to_amount(0.998, decimals);
+        let rate_bounds = RateBounds { max_age: 3_600, max_deviation_bps: 100 };
+        let add_usdt = |rate, rate_bounds| crate::instruction::add_collateral(program_id, rate, rate_bounds, &usdt_key, &synchronizer_usdt_key, &synchronizer_key, &payer_key).unwrap();
+
+        // BadCase: the Synchronizer collateral token, a vault of another mint, a zero rate, rates which never expire
+        assert_eq!(Err(SynchronizerError::InvalidCollateral.into()), do_process(
+            crate::instruction::add_collateral(program_id, rate, rate_bounds, &collateral_key, &synchronizer_collateral_key, &synchronizer_key, &payer_key).unwrap(),
+            vec![&mut trade.synchronizer_account, &mut trade.collateral_set_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar, &mut collateral_asset_mint, &mut trade.synchronizer_collateral_account, &mut trade.clock_sysvar]
+        ));
+        let mut instruction = add_usdt(rate, rate_bounds);
+        instruction.accounts[6].pubkey = synchronizer_collateral_key;
+        assert_eq!(Err(SynchronizerError::BadCollateralMint.into()), do_process(
+            instruction,
+            vec![&mut trade.synchronizer_account, &mut trade.collateral_set_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar, &mut usdt_mint, &mut trade.synchronizer_collateral_account, &mut trade.clock_sysvar]
+        ));
+        assert_eq!(Err(SynchronizerError::InvalidCollateralRate.into()), do_process(
+            add_usdt(0, rate_bounds),
+            vec![&mut trade.synchronizer_account, &mut trade.collateral_set_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar, &mut usdt_mint, &mut synchronizer_usdt_account, &mut trade.clock_sysvar]
+        ));
+        assert_eq!(Err(SynchronizerError::InvalidCollateralRate.into()), do_process(
+            add_usdt(rate, RateBounds { max_age: 0, ..rate_bounds }),
+            vec![&mut trade.synchronizer_account, &mut trade.collateral_set_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar, &mut usdt_mint, &mut synchronizer_usdt_account, &mut trade.clock_sysvar]
+        ));
+
+        // The system program creates the collateral set account
+        trade.collateral_set_account = SolanaAccount::new(Rent::default().minimum_balance(CollateralSetData::LEN), CollateralSetData::LEN, program_id);
+        do_process(
+            add_usdt(rate, rate_bounds),
+            vec![&mut trade.synchronizer_account, &mut trade.collateral_set_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar, &mut usdt_mint, &mut synchronizer_usdt_account, &mut trade.clock_sysvar]
+        ).unwrap();
+        let accepted = CollateralSetData::unpack(&trade.collateral_set_account.data).unwrap().collaterals[0];
+        assert_eq!((accepted.mint, accepted.vault, accepted.decimals, accepted.rate, accepted.rate_bounds), (usdt_key, synchronizer_usdt_key, usdt_decimals, rate, rate_bounds));
+        // BadCase: accepted twice
+        assert_eq!(Err(SynchronizerError::InvalidCollateral.into()), do_process(
+            add_usdt(rate, rate_bounds),
+            vec![&mut trade.synchronizer_account, &mut trade.collateral_set_account, &mut payer_account, &mut trade.system_program_account, &mut rent_sysvar, &mut usdt_mint, &mut synchronizer_usdt_account, &mut trade.clock_sysvar]
+        ));
+
+        // The oracles update the rate within its bounds, the admin to any rate
+        let update_usdt_rate = |rate, oracles: &[Pubkey]| crate::instruction::update_collateral_rate(program_id, &usdt_key, rate, &synchronizer_key, oracles).unwrap();
+        let rate = spl_token::ui_amount_to_amount(0.999, decimals);
+        do_process(
+            update_usdt_rate(rate, &oracles),
+            vec![&mut trade.synchronizer_account, &mut trade.collateral_set_account, &mut trade.clock_sysvar, &mut trade.oracle1_acc, &mut trade.oracle2_acc]
+        ).unwrap();
+        assert_eq!(CollateralSetData::unpack(&trade.collateral_set_account.data).unwrap().collaterals[0].rate, rate);
+        do_process(
+            update_usdt_rate(spl_token::ui_amount_to_amount(0.9, decimals), &[]),
+            vec![&mut trade.synchronizer_account, &mut trade.collateral_set_account, &mut trade.clock_sysvar]
+        ).unwrap();
+        do_process(
+            update_usdt_rate(rate, &[]),
+            vec![&mut trade.synchronizer_account, &mut trade.collateral_set_account, &mut trade.clock_sysvar]
+        ).unwrap();
+        assert_eq!(CollateralSetData::unpack(&trade.collateral_set_account.data).unwrap().collaterals[0].rate, rate);
+
+        // BadCase: a single oracle, an unknown or repeated oracle, a rate deviating by more than 1%
+        assert_eq!(Err(SynchronizerError::NotEnoughOracles.into()), do_process(
+            update_usdt_rate(rate, &[oracles[0]]),
+            vec![&mut trade.synchronizer_account, &mut trade.collateral_set_account, &mut trade.clock_sysvar, &mut trade.oracle1_acc]
+        ));
+        let mut second_oracle_acc = trade.oracle1_acc.clone();
+        for bad_oracles in [vec![oracles[0], user_key], vec![oracles[0], oracles[0]]].iter() {
+            assert_eq!(Err(SynchronizerError::BadOracle.into()), do_process(
+                update_usdt_rate(rate, bad_oracles),
+                vec![&mut trade.synchronizer_account, &mut trade.collateral_set_account, &mut trade.clock_sysvar, &mut trade.oracle1_acc, &mut second_oracle_acc]
+            ));
+        }
+        assert_eq!(Err(SynchronizerError::CollateralRateDeviationExceeded.into()), do_process(
+            update_usdt_rate(spl_token::ui_amount_to_amount(1.01, decimals), &oracles),
+            vec![&mut trade.synchronizer_account, &mut trade.collateral_set_account, &mut trade.clock_sysvar, &mut trade.oracle1_acc, &mut trade.oracle2_acc]
+        ));
+
+        // BadCase: repeated oracle updates can't move the rate further from the one set by the admin
+        let mut repeated = trade.clone();
+        do_process(
+            update_usdt_rate(spl_token::ui_amount_to_amount(1.008, decimals), &oracles),
+            vec![&mut repeated.synchronizer_account, &mut repeated.collateral_set_account, &mut repeated.clock_sysvar, &mut repeated.oracle1_acc, &mut repeated.oracle2_acc]
+        ).unwrap();
+        assert_eq!(Err(SynchronizerError::CollateralRateDeviationExceeded.into()), do_process(
+            update_usdt_rate(spl_token::ui_amount_to_amount(1.017, decimals), &oracles),
+            vec![&mut repeated.synchronizer_account, &mut repeated.collateral_set_account, &mut repeated.clock_sysvar, &mut repeated.oracle1_acc, &mut repeated.oracle2_acc]
+        ));
+
+        // BadCase: unsigned update while no signature is required for trades
+        let mut unsigned = trade.clone();
+        let mut synchronizer = SynchronizerData::unpack(&unsigned.synchronizer_account.data).unwrap();
+        synchronizer.minimum_required_signature = 0;
+        SynchronizerData::pack(synchronizer, &mut unsigned.synchronizer_account.data).unwrap();
+        let mut instruction = update_usdt_rate(rate, &[]);
+        instruction.accounts[0].is_signer = false;
+        assert_eq!(Err(SynchronizerError::NotEnoughOracles.into()), do_process(
+            instruction,
+            vec![&mut unsigned.synchronizer_account, &mut unsigned.collateral_set_account, &mut unsigned.clock_sysvar]
+        ));
+        let mut instruction = update_usdt_rate(rate, &[oracles[0]]);
+        instruction.accounts[3].is_signer = false;
+        assert_eq!(Err(SynchronizerError::BadOracle.into()), do_process(
+            instruction,
+            vec![&mut unsigned.synchronizer_account, &mut unsigned.collateral_set_account, &mut unsigned.clock_sysvar, &mut unsigned.oracle1_acc]
+        ));
+        // Trades still don't need oracle signatures
+        let mut instruction = buy_instruction(buy_fiat_amount);
+        let first_oracle = first_trade_oracle_account(TRADE_PROGRAM_ACCOUNTS);
+        for oracle in instruction.accounts[first_oracle..].iter_mut() {
+            oracle.is_signer = false;
+        }
+        do_process(instruction, unsigned.trade_accounts()).unwrap();
+
+        // Trades passing the vault settle in the accepted collateral and accrue its fees
+        let usdt_trade = |buy, referrer: Option<&Pubkey>| if buy {
+            crate::instruction::buy_for(program_id, mul_stocks, buy_fiat_amount, fee, &prices, &oracles, &fiat_asset_key, &user_usdt_key, &user_fiat_key, &synchronizer_usdt_key, &user_key, &synchronizer_key, TRADE_PROGRAM_ACCOUNTS, referrer).unwrap()
+        } else {
+            crate::instruction::sell_for(program_id, mul_stocks, buy_fiat_amount, fee, &prices, &oracles, &fiat_asset_key, &user_usdt_key, &user_fiat_key, &synchronizer_usdt_key, &user_key, &synchronizer_key, TRADE_PROGRAM_ACCOUNTS, referrer).unwrap()
+        };
+        let synchronizer = SynchronizerData::unpack(&trade.synchronizer_account.data).unwrap();
+        let buy_quote = quote_buy(&prices, buy_fiat_amount, fee, mul_stocks, decimals, decimals, 100).unwrap();
+        let sell_quote = quote_sell(&prices, buy_fiat_amount, fee, mul_stocks, decimals, decimals, 200).unwrap();
+        let buy_settlement = settle_buy(&buy_quote, rate, usdt_decimals).unwrap();
+        let sell_settlement = settle_sell(&sell_quote, rate, usdt_decimals).unwrap();
+        trade.user_position_account = SolanaAccount::new(Rent::default().minimum_balance(UserPositionData::LEN), UserPositionData::LEN, program_id);
+        do_process(
+            crate::instruction::create_user_position(program_id, &fiat_asset_key, &user_key, &synchronizer_key).unwrap(),
+            vec![&mut trade.user_account, &mut trade.synchronizer_account, &mut trade.user_position_account, &mut trade.system_program_account, &mut rent_sysvar]
+        ).unwrap();
+        do_process(usdt_trade(true, None), trade.trade_accounts_with(TRADE_PROGRAM_ACCOUNTS, vec![(1, &mut user_usdt_account), (3, &mut synchronizer_usdt_account)])).unwrap();
+        let referrer_usdt_key = Pubkey::new_unique();
+        let mut referrer_usdt_account = SolanaAccount::new(account_minimum_balance(), Account::get_packed_len(), &spl_token::id());
+        do_token_program(
+            spl_token::instruction::initialize_account(&spl_token::id(), &referrer_usdt_key, &usdt_key, &payer_key).unwrap(),
+            vec![&mut referrer_usdt_account, &mut usdt_mint, &mut payer_account, &mut rent_sysvar],
+        ).unwrap();
+        do_process(usdt_trade(false, Some(&referrer_usdt_key)), trade.trade_accounts_with(TRADE_PROGRAM_ACCOUNTS | TRADE_REFERRER_ACCOUNT, vec![(1, &mut user_usdt_account), (3, &mut synchronizer_usdt_account), (15, &mut referrer_usdt_account)])).unwrap();
+        // The referrer gets its share of the fee in the accepted collateral
+        let sell_referral = sell_settlement.fee_amount / 4;
+        let usdt_fee_amount = buy_settlement.fee_amount + sell_settlement.fee_amount - sell_referral;
+        assert_eq!(CollateralSetData::unpack(&trade.collateral_set_account.data).unwrap().collaterals[0].withdrawable_fee_amount, usdt_fee_amount);
+        let after = SynchronizerData::unpack(&trade.synchronizer_account.data).unwrap();
+        assert_eq!(after.withdrawable_fee_amount, synchronizer.withdrawable_fee_amount);
+        assert_eq!(after.remaining_dollar_cap, synchronizer.remaining_dollar_cap - buy_quote.dollar_cap_amount + sell_quote.dollar_cap_amount);
+        // Positions record the settled amounts and their collateral
+        let position = UserPositionData::unpack(&trade.user_position_account.data).unwrap();
+        assert_eq!(
+            (position.collateral_mint, position.collateral_paid, position.collateral_received, position.fee_paid),
+            (usdt_key, buy_settlement.user_collateral_amount, sell_settlement.user_collateral_amount, buy_settlement.fee_amount + sell_settlement.fee_amount)
+        );
+        // BadCase: trades in another collateral than the recorded one
+        assert_eq!(Err(SynchronizerError::InvalidUserPositionAccount.into()), do_process(buy_instruction(buy_fiat_amount), trade.trade_accounts()));
+        trade.user_position_account = SolanaAccount::default();
+
+        // BadCase: user or referrer account of the Synchronizer collateral token, the vault as referrer
+        assert_eq!(Err(SynchronizerError::BadCollateralMint.into()), do_process(usdt_trade(true, None), trade.trade_accounts_with(TRADE_PROGRAM_ACCOUNTS, vec![(3, &mut synchronizer_usdt_account)])));
+        assert_eq!(Err(SynchronizerError::BadCollateralMint.into()), do_process(usdt_trade(true, Some(&referrer_collateral_key)), trade.trade_accounts_with(TRADE_PROGRAM_ACCOUNTS | TRADE_REFERRER_ACCOUNT, vec![(1, &mut user_usdt_account), (3, &mut synchronizer_usdt_account), (15, &mut referrer_collateral_account)])));
+        let mut synchronizer_usdt_copy = synchronizer_usdt_account.clone();
+        assert_eq!(Err(SynchronizerError::InvalidReferrerAccount.into()), do_process(usdt_trade(true, Some(&synchronizer_usdt_key)), trade.trade_accounts_with(TRADE_PROGRAM_ACCOUNTS | TRADE_REFERRER_ACCOUNT, vec![(1, &mut user_usdt_account), (3, &mut synchronizer_usdt_account), (15, &mut synchronizer_usdt_copy)])));
+
+        // BadCase: trades at a rate older than its maximum age
+        let rate_updated_at = CollateralSetData::unpack(&trade.collateral_set_account.data).unwrap().collaterals[0].rate_updated_at;
+        let mut stale_clock_sysvar = create_account_for_test(&Clock { slot: 1160, unix_timestamp: rate_updated_at + rate_bounds.max_age + 1, ..Clock::default() });
+        for buy in [true, false].iter() {
+            assert_eq!(Err(SynchronizerError::StaleCollateralRate.into()), do_process(usdt_trade(*buy, None), trade.trade_accounts_with(TRADE_PROGRAM_ACCOUNTS, vec![(1, &mut user_usdt_account), (3, &mut synchronizer_usdt_account), (7, &mut stale_clock_sysvar)])));
+        }
+
+        // Fees of the accepted collateral are withdrawn from its vault
+        let withdraw_usdt_fee = |amount| crate::instruction::withdraw_fee(program_id, amount, &synchronizer_usdt_key, &user_usdt_key, &synchronizer_key, true).unwrap();
+        assert_eq!(Err(SynchronizerError::InsufficientFunds.into()), do_process(
+            withdraw_usdt_fee(usdt_fee_amount + 1),
+            vec![&mut synchronizer_usdt_account, &mut user_usdt_account, &mut trade.synchronizer_account, &mut trade.spl_token_account, &mut trade.collateral_set_account]
+        ));
+        // BadCase: removed with fees left
+        let remove_usdt = |vault| crate::instruction::remove_collateral(program_id, &usdt_key, vault, &synchronizer_key).unwrap();
+        assert_eq!(Err(SynchronizerError::InvalidCollateral.into()), do_process(
+            remove_usdt(&synchronizer_usdt_key),
+            vec![&mut trade.synchronizer_account, &mut trade.collateral_set_account, &mut synchronizer_usdt_account]
+        ));
+        do_process(
+            withdraw_usdt_fee(usdt_fee_amount),
+            vec![&mut synchronizer_usdt_account, &mut user_usdt_account, &mut trade.synchronizer_account, &mut trade.spl_token_account, &mut trade.collateral_set_account]
+        ).unwrap();
+        assert_eq!(CollateralSetData::unpack(&trade.collateral_set_account.data).unwrap().collaterals[0].withdrawable_fee_amount, 0);
+        assert_eq!(SynchronizerData::unpack(&trade.synchronizer_account.data).unwrap().withdrawable_fee_amount, synchronizer.withdrawable_fee_amount);
+
+        // BadCase: removed with collateral left in its vault, or passing another vault
+        assert_eq!(Err(SynchronizerError::InvalidCollateral.into()), do_process(
+            remove_usdt(&synchronizer_usdt_key),
+            vec![&mut trade.synchronizer_account, &mut trade.collateral_set_account, &mut synchronizer_usdt_account]
+        ));
+        let mut user_usdt_copy = user_usdt_account.clone();
+        assert_eq!(Err(SynchronizerError::InvalidCollateral.into()), do_process(
+            remove_usdt(&user_usdt_key),
+            vec![&mut trade.synchronizer_account, &mut trade.collateral_set_account, &mut user_usdt_copy]
+        ));
+
+        // Empty the vault as `WithdrawCollateral` would
+        let mut vault = Account::unpack(&synchronizer_usdt_account.data).unwrap();
+        vault.amount = 0;
+        Account::pack(vault, &mut synchronizer_usdt_account.data).unwrap();
+        do_process(
+            remove_usdt(&synchronizer_usdt_key),
+            vec![&mut trade.synchronizer_account, &mut trade.collateral_set_account, &mut synchronizer_usdt_account]
+        ).unwrap();
+        assert_eq!(CollateralSetData::unpack(&trade.collateral_set_account.data).unwrap().collateral_count, 0);
+        // BadCase: trade with the vault of a removed collateral
+        assert_eq!(Err(SynchronizerError::BadCollateralMint.into()), do_process(usdt_trade(true, None), trade.trade_accounts_with(TRADE_PROGRAM_ACCOUNTS, vec![(1, &mut user_usdt_account), (3, &mut synchronizer_usdt_account)])));
 
         // BadCase: wrong oracles
         let oracles = vec![Pubkey::new_unique(), Pubkey::new_unique()];
@@ -1232,19 +3371,11 @@ mod test {
                     &user_fiat_key,
                     &synchronizer_collateral_key,
                     &user_key,
-                    &synchronizer_key
+                    &synchronizer_key,
+                    TRADE_PROGRAM_ACCOUNTS,
+                    None
                 ).unwrap(),
-                vec![
-                    &mut fiat_asset_mint,
-                    &mut user_collateral_account,
-                    &mut user_fiat_account,
-                    &mut synchronizer_collateral_account,
-                    &mut user_account,
-                    &mut synchronizer_account,
-                    &mut spl_token_account,
-                    &mut oracle1_acc,
-                    &mut oracle2_acc,
-                ]
+                trade.trade_accounts()
             )
         );
 
@@ -1263,19 +3394,11 @@ mod test {
                     &user_fiat_key,
                     &synchronizer_collateral_key,
                     &user_key,
-                    &synchronizer_key
+                    &synchronizer_key,
+                    TRADE_PROGRAM_ACCOUNTS,
+                    None
                 ).unwrap(),
-                vec![
-                    &mut fiat_asset_mint,
-                    &mut user_collateral_account,
-                    &mut user_fiat_account,
-                    &mut synchronizer_collateral_account,
-                    &mut user_account,
-                    &mut synchronizer_account,
-                    &mut spl_token_account,
-                    &mut oracle1_acc,
-                    &mut oracle2_acc,
-                ]
+                trade.trade_accounts()
             )
         );
 
@@ -1298,7 +3421,7 @@ mod test {
                     &synchronizer_key
                 ).unwrap(),
                 vec![
-                    &mut synchronizer_account,
+                    &mut trade.synchronizer_account,
                 ]
             )
         );
@@ -1318,7 +3441,7 @@ mod test {
                 &synchronizer_key
             ).unwrap(),
             vec![
-                &mut synchronizer_account,
+                &mut trade.synchronizer_account,
             ]
         ).unwrap();
 
@@ -1336,10 +3459,13 @@ mod test {
                 &user_fiat_key,
                 &synchronizer_collateral_key,
                 &user_key,
-                &synchronizer_key
+                &synchronizer_key,
+                TRADE_PROGRAM_ACCOUNTS,
+                None
             ).unwrap(),
-            vec![&mut fiat_asset_mint, &mut user_collateral_account, &mut user_fiat_account,
-                &mut synchronizer_collateral_account, &mut user_account, &mut synchronizer_account, &mut spl_token_account,
+            vec![&mut trade.fiat_asset_mint, &mut trade.user_collateral_account, &mut trade.user_fiat_account,
+                &mut trade.synchronizer_collateral_account, &mut trade.user_account, &mut trade.synchronizer_account, &mut trade.spl_token_account, &mut trade.clock_sysvar, &mut trade.asset_account, &mut trade.rate_limit_account, &mut trade.user_volume_account, &mut trade.system_program_account, &mut trade.user_position_account, &mut trade.market_schedule_account, &mut trade.fee_schedule_account,
+                &mut trade.collateral_set_account,
                 &mut or1, &mut or2, &mut or3
             ]
         ).unwrap();
@@ -1350,6 +3476,18 @@ mod test {
         let synchronizer_key = Pubkey::new_unique();
         let mut synchronizer_account = SolanaAccount::new(init_acc_minimum_balance(), SynchronizerData::get_packed_len(), &id());
         let mut rent_sysvar_account = create_account_for_test(&Rent::default());
+        let start_collateral_token_key = Pubkey::new_unique();
+        let mut start_collateral_mint = SolanaAccount::new(mint_minimum_balance(), Mint::get_packed_len(), &spl_token::id());
+        do_token_program(
+            spl_token::instruction::initialize_mint(&spl_token::id(), &start_collateral_token_key, &Pubkey::new_unique(), None, Processor::DEFAULT_DECIMALS).unwrap(),
+            vec![&mut start_collateral_mint, &mut rent_sysvar_account],
+        ).unwrap();
+        let collateral_token_key = Pubkey::new_unique();
+        let mut collateral_mint = SolanaAccount::new(mint_minimum_balance(), Mint::get_packed_len(), &spl_token::id());
+        do_token_program(
+            spl_token::instruction::initialize_mint(&spl_token::id(), &collateral_token_key, &Pubkey::new_unique(), None, 6).unwrap(),
+            vec![&mut collateral_mint, &mut rent_sysvar_account],
+        ).unwrap();
 
         // BadCase: bad synchronizer account
         let mut fake_acc = SolanaAccount::default();
@@ -1370,8 +3508,8 @@ mod test {
         assert_eq!(
             Err(SynchronizerError::AccessDenied.into()),
             do_process(
-                crate::instruction::set_collateral_token(&id(), &Pubkey::new_unique(), &synchronizer_key).unwrap(),
-                vec![&mut fake_acc]
+                crate::instruction::set_collateral_token(&id(), &collateral_token_key, &synchronizer_key).unwrap(),
+                vec![&mut fake_acc, &mut collateral_mint]
             )
         );
 
@@ -1393,8 +3531,8 @@ mod test {
         assert_eq!(
             Err(SynchronizerError::NotInitialized.into()),
             do_process(
-                crate::instruction::set_collateral_token(&id(), &Pubkey::new_unique(), &synchronizer_key).unwrap(),
-                vec![&mut synchronizer_account]
+                crate::instruction::set_collateral_token(&id(), &collateral_token_key, &synchronizer_key).unwrap(),
+                vec![&mut synchronizer_account, &mut collateral_mint]
             )
         );
 
@@ -1407,7 +3545,6 @@ mod test {
             )
         );
 
-        let start_collateral_token_key = Pubkey::new_unique();
         let oracles = vec![Pubkey::new_unique(), Pubkey::new_unique()];
         let start_remaining_dollar_cap: u64 = 10;
         let start_minimum_required_signature: u8 = oracles.len() as u8;
@@ -1421,13 +3558,14 @@ mod test {
                 &oracles,
                 &synchronizer_key
             ).unwrap(),
-            vec![&mut synchronizer_account, &mut rent_sysvar_account]
+            vec![&mut synchronizer_account, &mut rent_sysvar_account, &mut start_collateral_mint]
         ).unwrap();
 
         let sync_data = SynchronizerData::unpack(&synchronizer_account.data).unwrap();
         assert_eq!(sync_data.minimum_required_signature, start_minimum_required_signature);
         assert_eq!(sync_data.remaining_dollar_cap, start_remaining_dollar_cap);
         assert_eq!(sync_data.collateral_token_key, start_collateral_token_key);
+        assert_eq!(sync_data.collateral_decimals, Some(Processor::DEFAULT_DECIMALS));
 
         let minimum_required_signature = 3;
         do_process(
@@ -1445,13 +3583,34 @@ mod test {
         let sync_data = SynchronizerData::unpack(&synchronizer_account.data).unwrap();
         assert_eq!(sync_data.remaining_dollar_cap, remaining_dollar_cap);
 
-        let collateral_token_key = Pubkey::new_unique();
+        // BadCase: mint is not the new collateral token
+        let mut instruction = crate::instruction::set_collateral_token(&id(), &collateral_token_key, &synchronizer_key).unwrap();
+        instruction.accounts[1].pubkey = start_collateral_token_key;
+        assert_eq!(
+            Err(SynchronizerError::BadCollateralMint.into()),
+            do_process(instruction, vec![&mut synchronizer_account, &mut start_collateral_mint])
+        );
+
+        // BadCase: decimals change with a remaining dollar cap in the old decimals
+        assert_eq!(
+            Err(SynchronizerError::BadDecimals.into()),
+            do_process(
+                crate::instruction::set_collateral_token(&id(), &collateral_token_key, &synchronizer_key).unwrap(),
+                vec![&mut synchronizer_account, &mut collateral_mint]
+            )
+        );
+
         do_process(
-            crate::instruction::set_collateral_token(&id(), &collateral_token_key, &synchronizer_key).unwrap(),
+            crate::instruction::set_remaining_dollar_cap(&id(), 0, &synchronizer_key).unwrap(),
             vec![&mut synchronizer_account]
         ).unwrap();
+        do_process(
+            crate::instruction::set_collateral_token(&id(), &collateral_token_key, &synchronizer_key).unwrap(),
+            vec![&mut synchronizer_account, &mut collateral_mint]
+        ).unwrap();
         let sync_data = SynchronizerData::unpack(&synchronizer_account.data).unwrap();
         assert_eq!(sync_data.collateral_token_key, collateral_token_key);
+        assert_eq!(sync_data.collateral_decimals, Some(6));
     }
 
     #[test]
@@ -1462,6 +3621,7 @@ mod test {
         let mut recipient_account = SolanaAccount::default();
         let mut rent_sysvar = create_account_for_test(&Rent::default());
         let mut spl_token_account = SolanaAccount::default();
+        let mut collateral_set_account = SolanaAccount::default();
         let collateral_token_key = Pubkey::new_unique();
         let oracles = vec![Pubkey::new_unique(), Pubkey::new_unique()];
 
@@ -1525,7 +3685,7 @@ mod test {
                 &oracles,
                 &synchronizer_key
             ).unwrap(),
-            vec![&mut synchronizer_account, &mut rent_sysvar]
+            vec![&mut synchronizer_account, &mut rent_sysvar, &mut collateral_asset_mint]
         ).unwrap();
 
         let amount = spl_token::ui_amount_to_amount(300.0, decimals);
@@ -1537,13 +3697,15 @@ mod test {
                     amount,
                     &synchronizer_collateral_key,
                     &recipient_collateral_key,
-                    &synchronizer_key
+                    &synchronizer_key,
+                    true
                 ).unwrap(),
                 vec![
                     &mut synchronizer_collateral_account,
                     &mut recipient_collateral_account,
                     &mut synchronizer_account,
-                    &mut spl_token_account
+                    &mut spl_token_account,
+                    &mut collateral_set_account
                 ]
             )
         );
@@ -1557,16 +3719,328 @@ mod test {
                     amount,
                     &synchronizer_collateral_key,
                     &recipient_collateral_key,
-                    &synchronizer_key
+                    &synchronizer_key,
+                    true
+                ).unwrap(),
+                vec![
+                    &mut synchronizer_collateral_account,
+                    &mut recipient_collateral_account,
+                    &mut synchronizer_account,
+                    &mut spl_token_account,
+                    &mut collateral_set_account
+                ]
+            )
+        );
+
+        // BadCase: fake token program
+        let fake_token_program_key = Pubkey::new_unique();
+        let mut instruction = crate::instruction::withdraw_fee(
+            &id(),
+            spl_token::ui_amount_to_amount(50.0, decimals),
+            &synchronizer_collateral_key,
+            &recipient_collateral_key,
+            &synchronizer_key,
+            true
+        ).unwrap();
+        instruction.accounts[3].pubkey = fake_token_program_key;
+        assert_eq!(
+            Err(SynchronizerError::InvalidTokenProgram.into()),
+            do_process(
+                instruction,
+                vec![
+                    &mut synchronizer_collateral_account,
+                    &mut recipient_collateral_account,
+                    &mut synchronizer_account,
+                    &mut spl_token_account,
+                    &mut collateral_set_account
+                ]
+            )
+        );
+
+        let mut instruction = crate::instruction::withdraw_collateral(
+            &id(),
+            spl_token::ui_amount_to_amount(50.0, decimals),
+            &synchronizer_collateral_key,
+            &recipient_collateral_key,
+            &synchronizer_key,
+            true
+        ).unwrap();
+        instruction.accounts[3].pubkey = fake_token_program_key;
+        assert_eq!(
+            Err(SynchronizerError::InvalidTokenProgram.into()),
+            do_process(
+                instruction,
+                vec![
+                    &mut synchronizer_collateral_account,
+                    &mut recipient_collateral_account,
+                    &mut synchronizer_account,
+                    &mut spl_token_account,
+                    &mut collateral_set_account
+                ]
+            )
+        );
+
+        // BadCase: recipient account of other mint
+        let other_token_key = Pubkey::new_unique();
+        let mut other_asset_mint = SolanaAccount::new(mint_minimum_balance(), Mint::get_packed_len(), &spl_token::id());
+        do_token_program(
+            spl_token::instruction::initialize_mint(&spl_token::id(), &other_token_key, &synchronizer_key, None, decimals).unwrap(),
+            vec![&mut other_asset_mint, &mut rent_sysvar],
+        ).unwrap();
+        let recipient_other_key = Pubkey::new_unique();
+        let mut recipient_other_account = SolanaAccount::new(account_minimum_balance(), Account::get_packed_len(), &spl_token::id());
+        do_token_program(
+            spl_token::instruction::initialize_account(&spl_token::id(), &recipient_other_key, &other_token_key, &recipient_key).unwrap(),
+            vec![
+                &mut recipient_other_account,
+                &mut other_asset_mint,
+                &mut recipient_account,
+                &mut rent_sysvar,
+            ],
+        ).unwrap();
+
+        let amount = spl_token::ui_amount_to_amount(50.0, decimals);
+        assert_eq!(
+            Err(SynchronizerError::BadCollateralMint.into()),
+            do_process(
+                crate::instruction::withdraw_fee(
+                    &id(),
+                    amount,
+                    &synchronizer_collateral_key,
+                    &recipient_other_key,
+                    &synchronizer_key,
+                    true
+                ).unwrap(),
+                vec![
+                    &mut synchronizer_collateral_account,
+                    &mut recipient_other_account,
+                    &mut synchronizer_account,
+                    &mut spl_token_account,
+                    &mut collateral_set_account
+                ]
+            )
+        );
+        assert_eq!(
+            Err(SynchronizerError::BadCollateralMint.into()),
+            do_process(
+                crate::instruction::withdraw_collateral(
+                    &id(),
+                    amount,
+                    &synchronizer_collateral_key,
+                    &recipient_other_key,
+                    &synchronizer_key,
+                    true
+                ).unwrap(),
+                vec![
+                    &mut synchronizer_collateral_account,
+                    &mut recipient_other_account,
+                    &mut synchronizer_account,
+                    &mut spl_token_account,
+                    &mut collateral_set_account
+                ]
+            )
+        );
+
+        // BadCase: uninitialized recipient account
+        let mut uninitialized_account = SolanaAccount::new(account_minimum_balance(), Account::get_packed_len(), &spl_token::id());
+        assert_eq!(
+            Err(SynchronizerError::UninitializedTokenAccount.into()),
+            do_process(
+                crate::instruction::withdraw_fee(
+                    &id(),
+                    amount,
+                    &synchronizer_collateral_key,
+                    &recipient_collateral_key,
+                    &synchronizer_key,
+                    true
+                ).unwrap(),
+                vec![
+                    &mut synchronizer_collateral_account,
+                    &mut uninitialized_account,
+                    &mut synchronizer_account,
+                    &mut spl_token_account,
+                    &mut collateral_set_account
+                ]
+            )
+        );
+
+        // BadCase: source account isn't owned by synchronizer
+        assert_eq!(
+            Err(SynchronizerError::OwnerMismatch.into()),
+            do_process(
+                crate::instruction::withdraw_fee(
+                    &id(),
+                    amount,
+                    &recipient_collateral_key,
+                    &synchronizer_collateral_key,
+                    &synchronizer_key,
+                    true
                 ).unwrap(),
                 vec![
+                    &mut recipient_collateral_account,
                     &mut synchronizer_collateral_account,
+                    &mut synchronizer_account,
+                    &mut spl_token_account,
+                    &mut collateral_set_account
+                ]
+            )
+        );
+        assert_eq!(
+            Err(SynchronizerError::OwnerMismatch.into()),
+            do_process(
+                crate::instruction::withdraw_collateral(
+                    &id(),
+                    amount,
+                    &recipient_collateral_key,
+                    &synchronizer_collateral_key,
+                    &synchronizer_key,
+                    true
+                ).unwrap(),
+                vec![
                     &mut recipient_collateral_account,
+                    &mut synchronizer_collateral_account,
                     &mut synchronizer_account,
-                    &mut spl_token_account
+                    &mut spl_token_account,
+                    &mut collateral_set_account
                 ]
             )
         );
+
+        // Good case, without the optional collateral set account
+        do_process(
+            crate::instruction::withdraw_fee(
+                &id(),
+                amount,
+                &synchronizer_collateral_key,
+                &recipient_collateral_key,
+                &synchronizer_key,
+                false
+            ).unwrap(),
+            vec![
+                &mut synchronizer_collateral_account,
+                &mut recipient_collateral_account,
+                &mut synchronizer_account,
+                &mut spl_token_account
+            ]
+        ).unwrap();
+        let synchronizer = SynchronizerData::unpack(&synchronizer_account.data).unwrap();
+        assert_eq!(synchronizer.withdrawable_fee_amount, 200_000_000_000);
+    }
+
+    #[test]
+    fn test_migrate_synchronizer_account() {
+        let synchronizer_key = Pubkey::new_unique();
+        let new_synchronizer_key = Pubkey::new_unique();
+        let mut rent_sysvar = create_account_for_test(&Rent::default());
+        let mut spl_token_account = SolanaAccount::default();
+        let collateral_token_key = Pubkey::new_unique();
+        let decimals = Processor::DEFAULT_DECIMALS;
+
+        // Version 1 Synchronizer account
+        let synchronizer = SynchronizerData {
+            is_initialized: true,
+            collateral_token_key,
+            remaining_dollar_cap: 500,
+            withdrawable_fee_amount: 20,
+            minimum_required_signature: 2,
+            oracles: [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::default()],
+            required_trade_accounts: 0,
+            collateral_decimals: None,
+        };
+        let v1_lamports = Rent::default().minimum_balance(SYNCHRONIZER_DATA_V1_LEN);
+        let mut synchronizer_account = SolanaAccount::new(v1_lamports, SYNCHRONIZER_DATA_V1_LEN, &id());
+        synchronizer_account.data = synchronizer.try_to_vec().unwrap()[..SYNCHRONIZER_DATA_V1_LEN].to_vec();
+        let mut new_synchronizer_account = SolanaAccount::new(init_acc_minimum_balance(), SynchronizerData::get_packed_len(), &id());
+
+        // Synchronizer fiat asset mint and collateral account
+        let fiat_asset_key = Pubkey::new_unique();
+        let mut fiat_asset_mint = SolanaAccount::new(mint_minimum_balance(), Mint::get_packed_len(), &spl_token::id());
+        do_token_program(
+            spl_token::instruction::initialize_mint(&spl_token::id(), &fiat_asset_key, &synchronizer_key, None, decimals).unwrap(),
+            vec![&mut fiat_asset_mint, &mut rent_sysvar],
+        ).unwrap();
+
+        let mut collateral_asset_mint = SolanaAccount::new(mint_minimum_balance(), Mint::get_packed_len(), &spl_token::id());
+        do_token_program(
+            spl_token::instruction::initialize_mint(&spl_token::id(), &collateral_token_key, &Pubkey::new_unique(), None, decimals).unwrap(),
+            vec![&mut collateral_asset_mint, &mut rent_sysvar],
+        ).unwrap();
+
+        let synchronizer_collateral_key = Pubkey::new_unique();
+        let mut synchronizer_collateral_account = SolanaAccount::new(account_minimum_balance(), Account::get_packed_len(), &spl_token::id());
+        do_token_program(
+            spl_token::instruction::initialize_account(&spl_token::id(), &synchronizer_collateral_key, &collateral_token_key, &synchronizer_key).unwrap(),
+            vec![
+                &mut synchronizer_collateral_account,
+                &mut collateral_asset_mint,
+                &mut synchronizer_account,
+                &mut rent_sysvar,
+            ],
+        ).unwrap();
+
+        // BadCase: version 1 account can't be used before migration
+        assert_eq!(
+            Err(SynchronizerError::MigrationRequired.into()),
+            do_process(
+                crate::instruction::set_remaining_dollar_cap(&id(), 100, &synchronizer_key).unwrap(),
+                vec![&mut synchronizer_account]
+            )
+        );
+
+        // BadCase: version 1 account can't be upgraded in place
+        let mut same_synchronizer_account = synchronizer_account.clone();
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            do_process(
+                crate::instruction::migrate_synchronizer_account(&id(), &synchronizer_key, &synchronizer_key, &[]).unwrap(),
+                vec![&mut synchronizer_account, &mut same_synchronizer_account, &mut rent_sysvar, &mut spl_token_account]
+            )
+        );
+
+        // BadCase: mint authority is not the Synchronizer
+        assert_eq!(
+            Err(SynchronizerError::BadMintAuthority.into()),
+            do_process(
+                crate::instruction::migrate_synchronizer_account(&id(), &synchronizer_key, &new_synchronizer_key, &[collateral_token_key]).unwrap(),
+                vec![&mut synchronizer_account, &mut new_synchronizer_account, &mut rent_sysvar, &mut spl_token_account, &mut collateral_asset_mint]
+            )
+        );
+
+        // BadCase: new account is not rent exempt
+        let mut bad_new_synchronizer_account = SolanaAccount::new(init_acc_minimum_balance() - 100, SynchronizerData::get_packed_len(), &id());
+        assert_eq!(
+            Err(SynchronizerError::NotRentExempt.into()),
+            do_process(
+                crate::instruction::migrate_synchronizer_account(&id(), &synchronizer_key, &new_synchronizer_key, &[]).unwrap(),
+                vec![&mut synchronizer_account, &mut bad_new_synchronizer_account, &mut rent_sysvar, &mut spl_token_account]
+            )
+        );
+
+        // Good case
+        do_process(
+            crate::instruction::migrate_synchronizer_account(&id(), &synchronizer_key, &new_synchronizer_key, &[fiat_asset_key, synchronizer_collateral_key]).unwrap(),
+            vec![
+                &mut synchronizer_account,
+                &mut new_synchronizer_account,
+                &mut rent_sysvar,
+                &mut spl_token_account,
+                &mut fiat_asset_mint,
+                &mut synchronizer_collateral_account,
+            ]
+        ).unwrap();
+        assert_eq!(SynchronizerData::unpack(&new_synchronizer_account.data).unwrap(), synchronizer);
+        assert_eq!(new_synchronizer_account.lamports, init_acc_minimum_balance() + v1_lamports);
+        assert_eq!(synchronizer_account.lamports, 0);
+        assert_eq!(synchronizer_account.data, vec![0; SYNCHRONIZER_DATA_V1_LEN]);
+
+        // BadCase: migrated account can't be migrated again
+        assert_eq!(
+            Err(SynchronizerError::AlreadyMigrated.into()),
+            do_process(
+                crate::instruction::migrate_synchronizer_account(&id(), &new_synchronizer_key, &new_synchronizer_key, &[]).unwrap(),
+                vec![&mut new_synchronizer_account.clone(), &mut new_synchronizer_account, &mut rent_sysvar, &mut spl_token_account]
+            )
+        );
     }
 
     #[test]
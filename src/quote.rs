@@ -0,0 +1,280 @@
+//! Trade quotes shared by the `BuyFor`/`SellFor` handlers and off-chain previews
+
+use crate::{error::SynchronizerError, state::BPS_DENOMINATOR};
+
+/// Decimals of the fee rates signed by the oracles, whatever the collateral decimals
+pub const FEE_DECIMALS: u8 = 9;
+
+/// Result of a trade
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Quote {
+    /// Aggregated oracle price: the highest price plus the ask spread for a buy,
+    /// the lowest price minus the bid spread for a sell
+    pub price: u64,
+    /// Collateral value of the fiat asset amount
+    pub collateral_amount: u64,
+    /// Fee in collateral tokens
+    pub fee_amount: u64,
+    /// Collateral paid by the user for a buy, or received by the user for a sell
+    pub user_collateral_amount: u64,
+    /// Decrease of the remaining dollar cap for a buy, or increase for a sell
+    pub dollar_cap_amount: u64,
+}
+
+/// Amounts of a trade settled in an accepted collateral
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Settlement {
+    /// Collateral paid by the user for a buy, or received by the user for a sell
+    pub user_collateral_amount: u64,
+    /// Fee in collateral tokens
+    pub fee_amount: u64,
+}
+
+impl From<&Quote> for Settlement {
+    /// Settlement in the Synchronizer collateral token
+    fn from(quote: &Quote) -> Self {
+        Settlement { user_collateral_amount: quote.user_collateral_amount, fee_amount: quote.fee_amount }
+    }
+}
+
+/// Prices counted by the program: the first `minimum_required_signature` ones, at least one
+pub fn signed_prices(prices: &[u64], minimum_required_signature: u8) -> &[u64] {
+    let count = (minimum_required_signature as usize).max(1).min(prices.len());
+    &prices[..count]
+}
+
+/// Fiat asset amounts are in base units of `fiat_decimals`, prices and collateral amounts in base units
+/// of `collateral_decimals`, fee rates in base units of `FEE_DECIMALS`
+fn quote(price: u64, amount: u64, fee: u64, multiplier: u64, fiat_decimals: u8, collateral_decimals: u8) -> Quote {
+    let collateral_amount_ui = spl_token::amount_to_ui_amount(amount, fiat_decimals) * spl_token::amount_to_ui_amount(price, collateral_decimals);
+    let fee_amount_ui = collateral_amount_ui * spl_token::amount_to_ui_amount(fee, FEE_DECIMALS);
+    Quote {
+        price,
+        collateral_amount: spl_token::ui_amount_to_amount(collateral_amount_ui, collateral_decimals),
+        fee_amount: spl_token::ui_amount_to_amount(fee_amount_ui, collateral_decimals),
+        user_collateral_amount: 0,
+        dollar_cap_amount: spl_token::ui_amount_to_amount(collateral_amount_ui * multiplier as f64, collateral_decimals),
+    }
+}
+
+/// Quotes buying `amount` fiat assets at the highest of `prices` plus `ask_spread_bps`, rounded up.
+/// `fee` is the fee rate in base units of `FEE_DECIMALS`. `amount` is in base units of `fiat_decimals`,
+/// prices and collateral amounts are in base units of `collateral_decimals`.
+pub fn quote_buy(
+    prices: &[u64],
+    amount: u64,
+    fee: u64,
+    multiplier: u64,
+    fiat_decimals: u8,
+    collateral_decimals: u8,
+    ask_spread_bps: u16,
+) -> Result<Quote, SynchronizerError> {
+    let price = *prices.iter().max().ok_or(SynchronizerError::NotEnoughOracles)? as u128;
+    let denominator = BPS_DENOMINATOR as u128;
+    let scaled = price * (denominator + ask_spread_bps as u128);
+    let price = scaled / denominator;
+    let price = if price * denominator < scaled { price + 1 } else { price };
+    let price = if price > u64::MAX as u128 { u64::MAX } else { price as u64 };
+    let quote = quote(price, amount, fee, multiplier, fiat_decimals, collateral_decimals);
+    Ok(Quote {
+        user_collateral_amount: quote.collateral_amount.checked_add(quote.fee_amount).ok_or(SynchronizerError::InsufficientFunds)?,
+        ..quote
+    })
+}
+
+/// Quotes selling `amount` fiat assets at the lowest of `prices` minus `bid_spread_bps`, rounded down.
+/// `fee` is the fee rate in base units of `FEE_DECIMALS`. `amount` is in base units of `fiat_decimals`,
+/// prices and collateral amounts are in base units of `collateral_decimals`.
+pub fn quote_sell(
+    prices: &[u64],
+    amount: u64,
+    fee: u64,
+    multiplier: u64,
+    fiat_decimals: u8,
+    collateral_decimals: u8,
+    bid_spread_bps: u16,
+) -> Result<Quote, SynchronizerError> {
+    let price = *prices.iter().min().ok_or(SynchronizerError::NotEnoughOracles)? as u128;
+    let price = (price * BPS_DENOMINATOR.saturating_sub(bid_spread_bps) as u128 / BPS_DENOMINATOR as u128) as u64;
+    let quote = quote(price, amount, fee, multiplier, fiat_decimals, collateral_decimals);
+    Ok(Quote {
+        user_collateral_amount: quote.collateral_amount.checked_sub(quote.fee_amount).ok_or(SynchronizerError::InsufficientFunds)?,
+        ..quote
+    })
+}
+
+/// Converts `amount` Synchronizer collateral base units into base units of a collateral with `decimals`,
+/// one collateral token being worth `rate` Synchronizer collateral base units
+pub fn convert_collateral_amount(amount: u64, rate: u64, decimals: u8, round_up: bool) -> Result<u64, SynchronizerError> {
+    if rate == 0 {
+        return Err(SynchronizerError::InvalidCollateralRate);
+    }
+    let scaled = 10u128.checked_pow(decimals as u32)
+        .and_then(|unit| unit.checked_mul(amount as u128))
+        .ok_or(SynchronizerError::InsufficientFunds)?;
+    let converted = scaled / rate as u128;
+    let converted = if round_up && converted * (rate as u128) < scaled { converted + 1 } else { converted };
+    if converted > u64::MAX as u128 {
+        return Err(SynchronizerError::InsufficientFunds);
+    }
+    Ok(converted as u64)
+}
+
+/// Converts `amount` base units of a collateral with `decimals` worth `rate` into Synchronizer collateral
+/// base units rounded down, the inverse of `convert_collateral_amount`
+pub fn collateral_value(amount: u64, rate: u64, decimals: u8) -> Result<u64, SynchronizerError> {
+    let unit = 10u128.checked_pow(decimals as u32).ok_or(SynchronizerError::InsufficientFunds)?;
+    let value = amount as u128 * rate as u128 / unit;
+    if value > u64::MAX as u128 {
+        return Err(SynchronizerError::InsufficientFunds);
+    }
+    Ok(value as u64)
+}
+
+/// Settles a buy quote in a collateral with `decimals` worth `rate`, the user pays rounded up
+pub fn settle_buy(quote: &Quote, rate: u64, decimals: u8) -> Result<Settlement, SynchronizerError> {
+    Ok(Settlement {
+        user_collateral_amount: convert_collateral_amount(quote.user_collateral_amount, rate, decimals, true)?,
+        fee_amount: convert_collateral_amount(quote.fee_amount, rate, decimals, true)?,
+    })
+}
+
+/// Settles a sell quote in a collateral with `decimals` worth `rate`, the user receives rounded down
+pub fn settle_sell(quote: &Quote, rate: u64, decimals: u8) -> Result<Settlement, SynchronizerError> {
+    Ok(Settlement {
+        user_collateral_amount: convert_collateral_amount(quote.user_collateral_amount, rate, decimals, false)?,
+        fee_amount: convert_collateral_amount(quote.fee_amount, rate, decimals, true)?,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_signed_prices() {
+        let prices = [1, 2, 3];
+        assert_eq!(signed_prices(&prices, 2), &[1, 2]);
+        assert_eq!(signed_prices(&prices, 3), &[1, 2, 3]);
+        // The first price counts without required signatures
+        assert_eq!(signed_prices(&prices, 0), &[1]);
+        assert_eq!(signed_prices(&prices, 5), &[1, 2, 3]);
+        assert!(signed_prices(&[], 0).is_empty());
+    }
+
+    #[test]
+    fn test_quote() {
+        let decimals = 9;
+        let amount = spl_token::ui_amount_to_amount(10.0, decimals);
+        let fee = spl_token::ui_amount_to_amount(0.001, decimals);
+        let prices = [spl_token::ui_amount_to_amount(5.0, decimals), spl_token::ui_amount_to_amount(5.5, decimals)];
+
+        let quote = quote_buy(&prices, amount, fee, 5, decimals, decimals, 0).unwrap();
+        assert_eq!(quote, Quote {
+            price: spl_token::ui_amount_to_amount(5.5, decimals),
+            collateral_amount: spl_token::ui_amount_to_amount(55.0, decimals),
+            fee_amount: spl_token::ui_amount_to_amount(0.055, decimals),
+            user_collateral_amount: spl_token::ui_amount_to_amount(55.055, decimals),
+            dollar_cap_amount: spl_token::ui_amount_to_amount(275.0, decimals),
+        });
+
+        let quote = quote_sell(&prices, amount, fee, 5, decimals, decimals, 0).unwrap();
+        assert_eq!(quote, Quote {
+            price: spl_token::ui_amount_to_amount(5.0, decimals),
+            collateral_amount: spl_token::ui_amount_to_amount(50.0, decimals),
+            fee_amount: spl_token::ui_amount_to_amount(0.05, decimals),
+            user_collateral_amount: spl_token::ui_amount_to_amount(49.95, decimals),
+            dollar_cap_amount: spl_token::ui_amount_to_amount(250.0, decimals),
+        });
+
+        // Spreads move the prices away from the oracle prices
+        let quote = quote_buy(&prices, amount, fee, 5, decimals, decimals, 100).unwrap();
+        assert_eq!(quote.price, spl_token::ui_amount_to_amount(5.555, decimals));
+        assert_eq!(quote.collateral_amount, spl_token::ui_amount_to_amount(55.55, decimals));
+        let quote = quote_sell(&prices, amount, fee, 5, decimals, decimals, 200).unwrap();
+        assert_eq!(quote.price, spl_token::ui_amount_to_amount(4.9, decimals));
+        assert_eq!(quote.collateral_amount, spl_token::ui_amount_to_amount(49.0, decimals));
+        // Buys round up and sells round down
+        assert_eq!(quote_buy(&[1], 1, 0, 1, 0, 0, 1).unwrap().price, 2);
+        assert_eq!(quote_sell(&[1], 1, 0, 1, 0, 0, 1).unwrap().price, 0);
+        assert_eq!(quote_buy(&[u64::MAX], 1, 0, 1, 0, 0, BPS_DENOMINATOR).unwrap().price, u64::MAX);
+
+        // BadCase: no prices
+        assert_eq!(quote_buy(&[], amount, fee, 5, decimals, decimals, 0).unwrap_err(), SynchronizerError::NotEnoughOracles);
+        assert_eq!(quote_sell(&[], amount, fee, 5, decimals, decimals, 0).unwrap_err(), SynchronizerError::NotEnoughOracles);
+
+        // BadCase: fee above the collateral amount
+        let fee = spl_token::ui_amount_to_amount(2.0, decimals);
+        assert_eq!(quote_sell(&prices, amount, fee, 5, decimals, decimals, 0).unwrap_err(), SynchronizerError::InsufficientFunds);
+    }
+
+    #[test]
+    fn test_quote_decimals() {
+        // 9 decimals fiat asset traded for a 6 decimals collateral, the fee rate keeps its 9 decimals
+        let (fiat_decimals, collateral_decimals) = (9, 6);
+        let amount = spl_token::ui_amount_to_amount(10.0, fiat_decimals);
+        let fee = spl_token::ui_amount_to_amount(0.001, FEE_DECIMALS);
+        let prices = [spl_token::ui_amount_to_amount(5.5, collateral_decimals)];
+        let collateral = |amount| spl_token::ui_amount_to_amount(amount, collateral_decimals);
+
+        let quote = quote_buy(&prices, amount, fee, 5, fiat_decimals, collateral_decimals, 0).unwrap();
+        assert_eq!(quote, Quote {
+            price: collateral(5.5),
+            collateral_amount: collateral(55.0),
+            fee_amount: collateral(0.055),
+            user_collateral_amount: collateral(55.055),
+            dollar_cap_amount: collateral(275.0),
+        });
+        let quote = quote_sell(&prices, amount, fee, 5, fiat_decimals, collateral_decimals, 0).unwrap();
+        assert_eq!(
+            (quote.collateral_amount, quote.fee_amount, quote.user_collateral_amount),
+            (collateral(55.0), collateral(0.055), collateral(54.945))
+        );
+
+        // Fiat assets with fewer decimals than the collateral
+        let quote = quote_buy(&prices, 1_000, fee, 5, 2, collateral_decimals, 0).unwrap();
+        assert_eq!(quote.collateral_amount, collateral(55.0));
+
+        // Amounts below a collateral base unit round to zero
+        let quote = quote_sell(&prices, 1, fee, 5, fiat_decimals, collateral_decimals, 0).unwrap();
+        assert_eq!(quote.collateral_amount, 0);
+    }
+
+    #[test]
+    fn test_settlement() {
+        // 6 decimals Synchronizer collateral, 9 decimals collateral worth 0.998
+        let rate = 998_000;
+        let quote = Quote {
+            price: 5_500_000,
+            collateral_amount: 55_000_000,
+            fee_amount: 55_000,
+            user_collateral_amount: 55_055_000,
+            dollar_cap_amount: 275_000_000,
+        };
+        assert_eq!(Settlement::from(&quote), Settlement { user_collateral_amount: 55_055_000, fee_amount: 55_000 });
+
+        let settlement = settle_buy(&quote, rate, 9).unwrap();
+        assert_eq!(settlement, Settlement {
+            user_collateral_amount: 55_165_330_662,
+            fee_amount: 55_110_221,
+        });
+        let settlement = settle_sell(&quote, rate, 9).unwrap();
+        assert_eq!(settlement.user_collateral_amount, 55_165_330_661);
+        assert_eq!(settlement.fee_amount, 55_110_221);
+
+        // Same decimals at par
+        assert_eq!(settle_buy(&quote, 1_000_000, 6).unwrap(), Settlement::from(&quote));
+        assert_eq!(convert_collateral_amount(10, 3, 0, false).unwrap(), 3);
+        assert_eq!(convert_collateral_amount(10, 3, 0, true).unwrap(), 4);
+        assert_eq!(convert_collateral_amount(0, 3, 0, true).unwrap(), 0);
+        assert_eq!(collateral_value(settlement.user_collateral_amount, rate, 9).unwrap(), 55_054_999);
+        assert_eq!(collateral_value(10, 3, 0).unwrap(), 30);
+
+        // BadCase: zero rate, overflow
+        assert_eq!(convert_collateral_amount(10, 0, 6, true).unwrap_err(), SynchronizerError::InvalidCollateralRate);
+        assert_eq!(convert_collateral_amount(u64::MAX, 1, 1, false).unwrap_err(), SynchronizerError::InsufficientFunds);
+        assert_eq!(convert_collateral_amount(1, 1, 40, false).unwrap_err(), SynchronizerError::InsufficientFunds);
+        assert_eq!(collateral_value(u64::MAX, 10, 0).unwrap_err(), SynchronizerError::InsufficientFunds);
+        assert_eq!(collateral_value(1, 1, 40).unwrap_err(), SynchronizerError::InsufficientFunds);
+    }
+}
@@ -1,13 +1,76 @@
 //! Synchronizer data
 
+use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{program_error::ProgramError, program_pack::{IsInitialized, Pack, Sealed}, pubkey::Pubkey};
-use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
 
-use crate::instruction::MAX_ORACLES;
+use crate::{error::SynchronizerError, instruction::MAX_ORACLES, processor::{id, Processor}};
+
+/// Length of the type discriminator at the start of every program-owned account
+pub const ACCOUNT_DISCRIMINATOR_LEN: usize = 8;
+
+/// Types of program-owned accounts
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AccountType {
+    /// Synchronizer config
+    Synchronizer,
+    /// Asset registry
+    AssetRegistry,
+    /// User position
+    UserPosition,
+    /// Synchronizer rate limits
+    RateLimit,
+    /// User traded volume
+    UserVolume,
+    /// Asset trading schedule
+    MarketSchedule,
+    /// Fee tiers and overrides
+    FeeSchedule,
+    /// Accepted collateral tokens
+    CollateralSet,
+}
+
+impl AccountType {
+    /// Type discriminator stored in the first `ACCOUNT_DISCRIMINATOR_LEN` bytes of the account
+    pub const fn discriminator(self) -> [u8; ACCOUNT_DISCRIMINATOR_LEN] {
+        match self {
+            AccountType::Synchronizer => *b"SYNCCONF",
+            AccountType::AssetRegistry => *b"ASSETREG",
+            AccountType::UserPosition => *b"USERPOSN",
+            AccountType::RateLimit => *b"RATELIMT",
+            AccountType::UserVolume => *b"USERVOLM",
+            AccountType::MarketSchedule => *b"MKTSCHED",
+            AccountType::FeeSchedule => *b"FEESCHED",
+            AccountType::CollateralSet => *b"COLLSET_",
+        }
+    }
+
+    /// Checks that account data starts with the type discriminator
+    pub fn check(self, data: &[u8]) -> Result<(), ProgramError> {
+        if data.len() < ACCOUNT_DISCRIMINATOR_LEN || data[..ACCOUNT_DISCRIMINATOR_LEN] != self.discriminator() {
+            return Err(SynchronizerError::InvalidAccountType.into());
+        }
+        Ok(())
+    }
+}
+
+/// Current layout version of Synchronizer data
+pub const SYNCHRONIZER_DATA_VERSION: u8 = 2;
+/// Length of the version 1 layout, which has no header
+pub const SYNCHRONIZER_DATA_V1_LEN: usize = 146;
+/// Length of the current layout header: type discriminator and version byte
+pub const SYNCHRONIZER_DATA_HEADER_LEN: usize = ACCOUNT_DISCRIMINATOR_LEN + 1;
+/// Zeroed bytes at the end of the current layout, reserved for new fields
+pub const SYNCHRONIZER_DATA_RESERVED_LEN: usize = 117;
 
 /// Synchronizer data.
+///
+/// Layout versions:
+/// 1. Borsh encoded fields, `SYNCHRONIZER_DATA_V1_LEN` bytes
+/// 2. Type discriminator, version byte, Borsh encoded fields, `SYNCHRONIZER_DATA_RESERVED_LEN` reserved bytes
+///
+/// A zeroed header marks an account which was never initialized.
 #[repr(C)]
-#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize)]
 pub struct SynchronizerData {
     /// Is `true` if this structure has been initialized
     pub is_initialized: bool,
@@ -21,6 +84,11 @@ pub struct SynchronizerData {
     pub minimum_required_signature: u8,
     /// Array of public keys of known oracles
     pub oracles: [Pubkey; MAX_ORACLES],
+    /// `TRADE_*_ACCOUNT` flags of the optional `BuyFor`/`SellFor` accounts every trade must pass,
+    /// set once the Synchronizer stores trade limits or fees in them
+    pub required_trade_accounts: u8,
+    /// Decimals of the collateral token mint, `None` for accounts initialized before they were stored
+    pub collateral_decimals: Option<u8>,
 }
 impl Sealed for SynchronizerData {}
 impl IsInitialized for SynchronizerData {
@@ -29,58 +97,1412 @@ impl IsInitialized for SynchronizerData {
     }
 }
 impl Pack for SynchronizerData {
-    /// 1 + 32 + 8 + 8 + 1 + 32 * MAX_ORACLES(3)
-    const LEN: usize = 146;
+    /// 8 + 1 + 1 + 32 + 8 + 8 + 1 + 32 * MAX_ORACLES(3) + 1 + 2 + 117
+    const LEN: usize = 275;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let (header, mut rest) = src.split_at(SYNCHRONIZER_DATA_HEADER_LEN);
+        if header.iter().any(|byte| *byte != 0) {
+            AccountType::Synchronizer.check(header)?;
+            if header[ACCOUNT_DISCRIMINATOR_LEN] != SYNCHRONIZER_DATA_VERSION {
+                return Err(ProgramError::InvalidAccountData);
+            }
+        }
+        Self::deserialize(&mut rest).map_err(|_| ProgramError::InvalidAccountData)
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        // Serialization into a Vec can't fail
+        let data = self.try_to_vec().unwrap();
+        let (header, rest) = dst.split_at_mut(SYNCHRONIZER_DATA_HEADER_LEN);
+        header[..ACCOUNT_DISCRIMINATOR_LEN].copy_from_slice(&AccountType::Synchronizer.discriminator());
+        header[ACCOUNT_DISCRIMINATOR_LEN] = SYNCHRONIZER_DATA_VERSION;
+        rest[..data.len()].copy_from_slice(&data);
+        for byte in rest[data.len()..].iter_mut() {
+            *byte = 0;
+        }
+    }
+}
+
+impl SynchronizerData {
+    /// Unpacks Synchronizer data stored with any known layout version.
+    /// Returns the layout version, zero for a never initialized account, and the data.
+    pub fn unpack_versioned(src: &[u8]) -> Result<(u8, Self), ProgramError> {
+        if src.len() == SYNCHRONIZER_DATA_V1_LEN {
+            // Version 1 has no required trade accounts nor collateral decimals, decoded as zero and `None`
+            let mut data = src.to_vec();
+            data.extend_from_slice(&[0, 0]);
+            let synchronizer = Self::try_from_slice(&data).map_err(|_| ProgramError::InvalidAccountData)?;
+            return Ok((1, synchronizer));
+        }
+        if src.len() != Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let synchronizer = Self::unpack_unchecked(src)?;
+        Ok((src[ACCOUNT_DISCRIMINATOR_LEN], synchronizer))
+    }
+
+    /// Number of distinct oracles which must attest a collateral rate, at least one even when
+    /// `minimum_required_signature` is zero and trades don't need oracle signatures
+    pub fn required_signatures(&self) -> usize {
+        (self.minimum_required_signature as usize).max(1)
+    }
+
+    /// Decimals of the collateral token, `DEFAULT_DECIMALS` when they weren't stored
+    pub fn collateral_token_decimals(&self) -> u8 {
+        self.collateral_decimals.unwrap_or(Processor::DEFAULT_DECIMALS)
+    }
+}
+
+/// Unpacks a type discriminator followed by Borsh encoded data, a zeroed discriminator marks
+/// an account which was never initialized
+fn unpack_typed<T: BorshDeserialize>(account_type: AccountType, src: &[u8]) -> Result<T, ProgramError> {
+    let (header, mut rest) = src.split_at(ACCOUNT_DISCRIMINATOR_LEN);
+    if header.iter().any(|byte| *byte != 0) {
+        account_type.check(header)?;
+    }
+    T::deserialize(&mut rest).map_err(|_| ProgramError::InvalidAccountData)
+}
+
+/// Packs the type discriminator and Borsh encoded data, zeroing the reserved bytes
+fn pack_typed<T: BorshSerialize>(account_type: AccountType, value: &T, dst: &mut [u8]) {
+    // Serialization into a Vec can't fail
+    let data = value.try_to_vec().unwrap();
+    let (header, rest) = dst.split_at_mut(ACCOUNT_DISCRIMINATOR_LEN);
+    header.copy_from_slice(&account_type.discriminator());
+    rest[..data.len()].copy_from_slice(&data);
+    for byte in rest[data.len()..].iter_mut() {
+        *byte = 0;
+    }
+}
+
+/// Seed prefix of asset account addresses
+pub const ASSET_SEED: &[u8] = b"asset";
+/// Zeroed bytes at the end of the asset account, reserved for new fields
+pub const ASSET_DATA_RESERVED_LEN: usize = 60;
+/// Basis points in a whole price
+pub const BPS_DENOMINATOR: u16 = 10_000;
+
+/// Per-asset limits of a Synchronizer, stored in a program derived account of the Synchronizer
+/// and the fiat asset mint.
+///
+/// Layout: type discriminator, Borsh encoded fields, `ASSET_DATA_RESERVED_LEN` reserved bytes.
+/// Notional amounts are collateral values of fiat asset amounts at trade prices.
+/// Spreads are added to the aggregated oracle price for buys and taken from it for sells.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct AssetData {
+    /// Is `true` if this structure has been initialized
+    pub is_initialized: bool,
+    /// Synchronizer account authority
+    pub synchronizer: Pubkey,
+    /// Fiat asset mint
+    pub fiat_asset_mint: Pubkey,
+    /// Maximum outstanding notional of the asset
+    pub max_outstanding_notional: u64,
+    /// Maximum notional of a single buy
+    pub max_transaction_notional: u64,
+    /// Notional bought minus notional sold
+    pub outstanding_notional: u64,
+    /// Bump seed of the account address
+    pub bump_seed: u8,
+    /// Ask spread in basis points of the price
+    pub ask_spread_bps: u16,
+    /// Bid spread in basis points of the price
+    pub bid_spread_bps: u16,
+}
+impl Sealed for AssetData {}
+impl IsInitialized for AssetData {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+impl Pack for AssetData {
+    /// 8 + 1 + 32 + 32 + 8 + 8 + 8 + 1 + 2 + 2 + 60
+    const LEN: usize = 162;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        unpack_typed(AccountType::AssetRegistry, src)
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        pack_typed(AccountType::AssetRegistry, self, dst)
+    }
+}
+
+impl AssetData {
+    /// Address and bump seed of the asset account of a Synchronizer and fiat asset mint
+    pub fn find_address(synchronizer_key: &Pubkey, fiat_asset_mint: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[ASSET_SEED, synchronizer_key.as_ref(), fiat_asset_mint.as_ref()], &id())
+    }
+
+    /// Outstanding notional after a buy of `notional`, which must fit the asset limits
+    pub fn outstanding_after_buy(&self, notional: u64) -> Result<u64, SynchronizerError> {
+        if notional > self.max_transaction_notional {
+            return Err(SynchronizerError::TransactionCapExceeded);
+        }
+        match self.outstanding_notional.checked_add(notional) {
+            Some(outstanding_notional) if outstanding_notional <= self.max_outstanding_notional => Ok(outstanding_notional),
+            _ => Err(SynchronizerError::AssetCapExceeded),
+        }
+    }
+
+    /// Sets the spreads, the bid spread must leave a positive sell price
+    pub fn set_spreads(&mut self, ask_spread_bps: u16, bid_spread_bps: u16) -> Result<(), SynchronizerError> {
+        if ask_spread_bps > BPS_DENOMINATOR || bid_spread_bps >= BPS_DENOMINATOR {
+            return Err(SynchronizerError::InvalidSpread);
+        }
+        self.ask_spread_bps = ask_spread_bps;
+        self.bid_spread_bps = bid_spread_bps;
+        Ok(())
+    }
+}
+
+/// Seed prefix of rate limit account addresses
+pub const RATE_LIMIT_SEED: &[u8] = b"rate_limit";
+/// Seed prefix of user volume account addresses
+pub const USER_VOLUME_SEED: &[u8] = b"user_volume";
+/// Number of buckets of a volume window
+pub const VOLUME_WINDOW_BUCKETS: usize = 16;
+/// Zeroed bytes at the end of rate limit and user volume accounts, reserved for new fields
+pub const VOLUME_DATA_RESERVED_LEN: usize = 64;
+
+/// Notional traded during the bucket `index`, the slots `index * bucket_slots..(index + 1) * bucket_slots`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct VolumeBucket {
+    pub index: u64,
+    pub notional: u64,
+}
+
+/// Rolling window of traded notional, a ring buffer of `VOLUME_WINDOW_BUCKETS` buckets.
+///
+/// The window is the current bucket and the previous ones, so it spans the last `window_slots`
+/// slots rounded up to whole buckets.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct VolumeWindow {
+    /// Slots per bucket, buckets are reset when the window length changes
+    pub bucket_slots: u64,
+    pub buckets: [VolumeBucket; VOLUME_WINDOW_BUCKETS],
+}
+
+impl VolumeWindow {
+    /// Slots per bucket of a window of `window_slots` slots
+    pub fn bucket_slots(window_slots: u64) -> u64 {
+        let buckets = VOLUME_WINDOW_BUCKETS as u64;
+        let bucket_slots = window_slots / buckets;
+        if bucket_slots * buckets < window_slots {
+            bucket_slots + 1
+        } else {
+            bucket_slots.max(1)
+        }
+    }
+
+    /// Notional traded in the window ending at `slot`
+    pub fn volume(&self, slot: u64) -> u64 {
+        if self.bucket_slots == 0 {
+            return 0;
+        }
+        let current = slot / self.bucket_slots;
+        self.buckets.iter()
+            .filter(|bucket| bucket.index <= current && bucket.index + VOLUME_WINDOW_BUCKETS as u64 > current)
+            .fold(0, |volume: u64, bucket| volume.saturating_add(bucket.notional))
+    }
+
+    /// Adds `notional` traded at `slot`, the window volume must stay within `max_notional`
+    pub fn record(&mut self, slot: u64, window_slots: u64, notional: u64, max_notional: u64) -> Result<(), SynchronizerError> {
+        self.resize(window_slots);
+        match self.volume(slot).checked_add(notional) {
+            Some(volume) if volume <= max_notional => {}
+            _ => return Err(SynchronizerError::RateLimitExceeded),
+        }
+        self.add(slot, window_slots, notional);
+        Ok(())
+    }
+
+    /// Adds `notional` traded at `slot` without limit, the bucket volume saturates
+    pub fn add(&mut self, slot: u64, window_slots: u64, notional: u64) {
+        self.resize(window_slots);
+        let index = slot / self.bucket_slots;
+        let bucket = &mut self.buckets[(index % VOLUME_WINDOW_BUCKETS as u64) as usize];
+        if bucket.index != index {
+            *bucket = VolumeBucket { index, notional: 0 };
+        }
+        bucket.notional = bucket.notional.saturating_add(notional);
+    }
+
+    /// Clears the window when its length changes
+    fn resize(&mut self, window_slots: u64) {
+        let bucket_slots = Self::bucket_slots(window_slots);
+        if self.bucket_slots != bucket_slots {
+            *self = VolumeWindow { bucket_slots, ..VolumeWindow::default() };
+        }
+    }
+}
+
+/// Rolling volume limits of a Synchronizer, stored in a program derived account of the Synchronizer.
+///
+/// Layout: type discriminator, Borsh encoded fields, `VOLUME_DATA_RESERVED_LEN` reserved bytes.
+/// Volumes are collateral notional amounts of buys and sells.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct RateLimitData {
+    /// Is `true` if this structure has been initialized
+    pub is_initialized: bool,
+    /// Synchronizer account authority
+    pub synchronizer: Pubkey,
+    /// Window length in slots, `0` disables the limits
+    pub window_slots: u64,
+    /// Maximum notional traded on the Synchronizer in the window, `0` for no limit
+    pub max_synchronizer_notional: u64,
+    /// Maximum notional traded by a user in the window, `0` for no limit
+    pub max_user_notional: u64,
+    /// Bump seed of the account address
+    pub bump_seed: u8,
+    /// Notional traded on the Synchronizer
+    pub window: VolumeWindow,
+}
+impl Sealed for RateLimitData {}
+impl IsInitialized for RateLimitData {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+impl Pack for RateLimitData {
+    /// 8 + 1 + 32 + 8 + 8 + 8 + 1 + (8 + 16 * 16) + 64
+    const LEN: usize = 394;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        unpack_typed(AccountType::RateLimit, src)
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        pack_typed(AccountType::RateLimit, self, dst)
+    }
+}
+
+impl RateLimitData {
+    /// Address and bump seed of the rate limit account of a Synchronizer
+    pub fn find_address(synchronizer_key: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[RATE_LIMIT_SEED, synchronizer_key.as_ref()], &id())
+    }
+}
+
+/// Notional traded by a user on a Synchronizer, stored in a program derived account of the
+/// Synchronizer and the user. Created by the first trade of the user while a user limit is set
+/// or the fee schedule has volume tiers.
+///
+/// Layout: type discriminator, Borsh encoded fields, `VOLUME_DATA_RESERVED_LEN` reserved bytes.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct UserVolumeData {
+    /// Is `true` if this structure has been initialized
+    pub is_initialized: bool,
+    /// Synchronizer account authority
+    pub synchronizer: Pubkey,
+    /// User authority
+    pub user: Pubkey,
+    /// Bump seed of the account address
+    pub bump_seed: u8,
+    /// Notional traded by the user in the rate limit window
+    pub window: VolumeWindow,
+    /// Notional traded by the user in the fee schedule window
+    pub fee_window: VolumeWindow,
+}
+impl Sealed for UserVolumeData {}
+impl IsInitialized for UserVolumeData {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+impl Pack for UserVolumeData {
+    /// 8 + 1 + 32 + 32 + 1 + (8 + 16 * 16) * 2 + 64
+    const LEN: usize = 666;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        unpack_typed(AccountType::UserVolume, src)
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        pack_typed(AccountType::UserVolume, self, dst)
+    }
+}
+
+impl UserVolumeData {
+    /// Address and bump seed of the volume account of a user on a Synchronizer
+    pub fn find_address(synchronizer_key: &Pubkey, user: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[USER_VOLUME_SEED, synchronizer_key.as_ref(), user.as_ref()], &id())
+    }
+}
+
+/// Seed prefix of user position account addresses
+pub const USER_POSITION_SEED: &[u8] = b"user_position";
+/// Zeroed bytes at the end of user position accounts, reserved for new fields
+pub const USER_POSITION_DATA_RESERVED_LEN: usize = 64;
+
+/// Trades of a user in a fiat asset of a Synchronizer, stored in a program derived account of the
+/// Synchronizer, the user and the fiat asset mint. Created and closed by the user.
+///
+/// Layout: type discriminator, Borsh encoded fields, `USER_POSITION_DATA_RESERVED_LEN` reserved bytes.
+/// Collateral amounts are the amounts transferred by the trades, fees included, in base units of the
+/// collateral the first recorded trade settled in. Trades settled in another collateral can't be recorded.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct UserPositionData {
+    /// Is `true` if this structure has been initialized
+    pub is_initialized: bool,
+    /// Synchronizer account authority
+    pub synchronizer: Pubkey,
+    /// User authority
+    pub user: Pubkey,
+    /// Fiat asset mint
+    pub fiat_asset_mint: Pubkey,
+    /// Mint of the collateral of the recorded trades, unset before the first trade
+    pub collateral_mint: Pubkey,
+    /// Fiat asset amount bought
+    pub bought_amount: u64,
+    /// Fiat asset amount sold
+    pub sold_amount: u64,
+    /// Collateral paid for buys
+    pub collateral_paid: u64,
+    /// Collateral received for sells
+    pub collateral_received: u64,
+    /// Fees paid on buys and sells
+    pub fee_paid: u64,
+    /// Slot of the last trade, `0` before the first trade
+    pub last_trade_slot: u64,
+    /// Bump seed of the account address
+    pub bump_seed: u8,
+}
+impl Sealed for UserPositionData {}
+impl IsInitialized for UserPositionData {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+impl Pack for UserPositionData {
+    /// 8 + 1 + 32 * 4 + 8 * 6 + 1 + 64
+    const LEN: usize = 250;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        unpack_typed(AccountType::UserPosition, src)
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        pack_typed(AccountType::UserPosition, self, dst)
+    }
+}
+
+impl UserPositionData {
+    /// Address and bump seed of the position account of a user in a fiat asset of a Synchronizer
+    pub fn find_address(synchronizer_key: &Pubkey, user: &Pubkey, fiat_asset_mint: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[USER_POSITION_SEED, synchronizer_key.as_ref(), user.as_ref(), fiat_asset_mint.as_ref()],
+            &id(),
+        )
+    }
+
+    /// Records a buy of `amount` for `collateral_amount` of `collateral_mint`, `fee_amount` of it being the fee.
+    /// Totals saturate, only trades in another collateral than the recorded ones fail.
+    pub fn record_buy(&mut self, amount: u64, collateral_mint: &Pubkey, collateral_amount: u64, fee_amount: u64, slot: u64) -> Result<(), SynchronizerError> {
+        self.record_collateral_mint(collateral_mint)?;
+        self.bought_amount = self.bought_amount.saturating_add(amount);
+        self.collateral_paid = self.collateral_paid.saturating_add(collateral_amount);
+        self.fee_paid = self.fee_paid.saturating_add(fee_amount);
+        self.last_trade_slot = slot;
+        Ok(())
+    }
+
+    /// Records a sell of `amount` for `collateral_amount` of `collateral_mint`, after a fee of `fee_amount`.
+    /// Totals saturate, only trades in another collateral than the recorded ones fail.
+    pub fn record_sell(&mut self, amount: u64, collateral_mint: &Pubkey, collateral_amount: u64, fee_amount: u64, slot: u64) -> Result<(), SynchronizerError> {
+        self.record_collateral_mint(collateral_mint)?;
+        self.sold_amount = self.sold_amount.saturating_add(amount);
+        self.collateral_received = self.collateral_received.saturating_add(collateral_amount);
+        self.fee_paid = self.fee_paid.saturating_add(fee_amount);
+        self.last_trade_slot = slot;
+        Ok(())
+    }
+
+    fn record_collateral_mint(&mut self, collateral_mint: &Pubkey) -> Result<(), SynchronizerError> {
+        if self.collateral_mint == Pubkey::default() {
+            self.collateral_mint = *collateral_mint;
+        }
+        if !self.collateral_mint.eq(collateral_mint) {
+            return Err(SynchronizerError::InvalidUserPositionAccount);
+        }
+        Ok(())
+    }
+}
+
+/// Seed prefix of market schedule account addresses
+pub const MARKET_SCHEDULE_SEED: &[u8] = b"market_schedule";
+/// Maximum holidays of a market schedule
+pub const MAX_MARKET_HOLIDAYS: usize = 32;
+/// Zeroed bytes at the end of market schedule accounts, reserved for new fields
+pub const MARKET_SCHEDULE_DATA_RESERVED_LEN: usize = 64;
+/// Seconds in a UTC day
+pub const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Trading hours of a weekday, the market is open from `open` until before `close`, in seconds
+/// since UTC midnight. Equal times close the market for the day.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct TradingHours {
+    pub open: u32,
+    pub close: u32,
+}
+
+impl TradingHours {
+    /// Hours of a market open all day
+    pub const ALL_DAY: TradingHours = TradingHours { open: 0, close: SECONDS_PER_DAY as u32 };
+
+    /// Checks that the hours are within a day
+    pub fn is_valid(&self) -> bool {
+        self.open <= self.close && i64::from(self.close) <= SECONDS_PER_DAY
+    }
+}
+
+/// Weekly trading hours and holidays of a fiat asset, stored in a program derived account of the
+/// Synchronizer and the fiat asset mint. Assets without a schedule trade at any time.
+///
+/// Layout: type discriminator, Borsh encoded fields, `MARKET_SCHEDULE_DATA_RESERVED_LEN` reserved bytes.
+/// Days are counted from 1970-01-01, which is the zero of `Clock::unix_timestamp`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct MarketScheduleData {
+    /// Is `true` if this structure has been initialized
+    pub is_initialized: bool,
+    /// Synchronizer account authority
+    pub synchronizer: Pubkey,
+    /// Fiat asset mint
+    pub fiat_asset_mint: Pubkey,
+    /// Bump seed of the account address
+    pub bump_seed: u8,
+    /// Trading hours from Monday to Sunday
+    pub weekly_hours: [TradingHours; 7],
+    /// Days the market is closed, `0` for unused entries
+    pub holidays: [u32; MAX_MARKET_HOLIDAYS],
+}
+impl Sealed for MarketScheduleData {}
+impl IsInitialized for MarketScheduleData {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+impl Pack for MarketScheduleData {
+    /// 8 + 1 + 32 + 32 + 1 + 7 * 8 + 32 * 4 + 64
+    const LEN: usize = 322;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        unpack_typed(AccountType::MarketSchedule, src)
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        pack_typed(AccountType::MarketSchedule, self, dst)
+    }
+}
+
+impl MarketScheduleData {
+    /// Address and bump seed of the market schedule account of a Synchronizer and fiat asset mint
+    pub fn find_address(synchronizer_key: &Pubkey, fiat_asset_mint: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[MARKET_SCHEDULE_SEED, synchronizer_key.as_ref(), fiat_asset_mint.as_ref()], &id())
+    }
+
+    /// Sets the trading hours and holidays, which must be valid
+    pub fn set(&mut self, weekly_hours: [TradingHours; 7], holidays: &[u32]) -> Result<(), SynchronizerError> {
+        if holidays.len() > MAX_MARKET_HOLIDAYS || holidays.contains(&0) || weekly_hours.iter().any(|hours| !hours.is_valid()) {
+            return Err(SynchronizerError::InvalidMarketSchedule);
+        }
+        self.weekly_hours = weekly_hours;
+        self.holidays = [0; MAX_MARKET_HOLIDAYS];
+        self.holidays[..holidays.len()].copy_from_slice(holidays);
+        Ok(())
+    }
+
+    /// Checks whether the market is open at a unix timestamp
+    pub fn is_open(&self, unix_timestamp: i64) -> bool {
+        let day = unix_timestamp.div_euclid(SECONDS_PER_DAY);
+        let seconds = unix_timestamp.rem_euclid(SECONDS_PER_DAY) as u32;
+        if self.holidays.iter().any(|holiday| *holiday != 0 && i64::from(*holiday) == day) {
+            return false;
+        }
+        // 1970-01-01 was a Thursday
+        let hours = self.weekly_hours[(day + 3).rem_euclid(7) as usize];
+        hours.open <= seconds && seconds < hours.close
+    }
+}
+
+/// Seed prefix of fee schedule account addresses
+pub const FEE_SCHEDULE_SEED: &[u8] = b"fee_schedule";
+/// Maximum number of volume tiers of a fee schedule
+pub const MAX_FEE_TIERS: usize = 8;
+/// Maximum number of users with an override fee rate
+pub const MAX_FEE_OVERRIDES: usize = 16;
+/// Zeroed bytes at the end of fee schedule accounts, reserved for new fields
+pub const FEE_SCHEDULE_DATA_RESERVED_LEN: usize = 54;
+
+/// Fee rate of users who traded at least `min_volume` in the rolling volume window
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct FeeTier {
+    pub min_volume: u64,
+    pub fee: u64,
+}
+
+/// Fee rate of a whitelisted user, used instead of the tiers
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct FeeOverride {
+    pub user: Pubkey,
+    pub fee: u64,
+}
+
+/// Volume tiers and per-user overrides of the fee rate, stored in a program derived account of
+/// the Synchronizer. The schedule only lowers the fee rate signed by the oracles.
+/// It also holds the share of trade fees paid to the referrer of a trade.
+///
+/// Layout: type discriminator, Borsh encoded fields, `FEE_SCHEDULE_DATA_RESERVED_LEN` reserved bytes.
+/// Volumes are the collateral notional traded by the user in the fee schedule volume window.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct FeeScheduleData {
+    /// Is `true` if this structure has been initialized
+    pub is_initialized: bool,
+    /// Synchronizer account authority
+    pub synchronizer: Pubkey,
+    /// Bump seed of the account address
+    pub bump_seed: u8,
+    /// Number of used tiers
+    pub tier_count: u8,
+    /// Tiers by increasing minimum volume
+    pub tiers: [FeeTier; MAX_FEE_TIERS],
+    /// Number of used overrides
+    pub override_count: u8,
+    /// Overrides of whitelisted users
+    pub overrides: [FeeOverride; MAX_FEE_OVERRIDES],
+    /// Share of the fee amount of a trade paid to its referrer, in basis points
+    pub referral_share_bps: u16,
+    /// Volume window of the tiers in slots, `0` counts no volume
+    pub volume_window_slots: u64,
+}
+impl Sealed for FeeScheduleData {}
+impl IsInitialized for FeeScheduleData {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+impl Pack for FeeScheduleData {
+    /// 8 + 1 + 32 + 1 + 1 + 8 * 16 + 1 + 16 * 40 + 2 + 8 + 54
+    const LEN: usize = 876;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        unpack_typed(AccountType::FeeSchedule, src)
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        pack_typed(AccountType::FeeSchedule, self, dst)
+    }
+}
+
+impl FeeScheduleData {
+    /// Address and bump seed of the fee schedule account of a Synchronizer
+    pub fn find_address(synchronizer_key: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[FEE_SCHEDULE_SEED, synchronizer_key.as_ref()], &id())
+    }
+
+    /// Used tiers
+    pub fn tiers(&self) -> &[FeeTier] {
+        &self.tiers[..self.tier_count as usize]
+    }
+
+    /// Used overrides
+    pub fn overrides(&self) -> &[FeeOverride] {
+        &self.overrides[..self.override_count as usize]
+    }
+
+    /// Replaces the tiers, which must have strictly increasing minimum volumes
+    pub fn set_tiers(&mut self, tiers: &[FeeTier]) -> Result<(), SynchronizerError> {
+        if tiers.len() > MAX_FEE_TIERS || tiers.windows(2).any(|pair| pair[0].min_volume >= pair[1].min_volume) {
+            return Err(SynchronizerError::InvalidFeeSchedule);
+        }
+        self.tiers = [FeeTier::default(); MAX_FEE_TIERS];
+        self.tiers[..tiers.len()].copy_from_slice(tiers);
+        self.tier_count = tiers.len() as u8;
+        Ok(())
+    }
+
+    /// Sets the override fee rate of a user, or removes it with `None`
+    pub fn set_override(&mut self, user: &Pubkey, fee: Option<u64>) -> Result<(), SynchronizerError> {
+        let count = self.override_count as usize;
+        let position = self.overrides().iter().position(|fee_override| fee_override.user == *user);
+        match (position, fee) {
+            (Some(index), Some(fee)) => self.overrides[index].fee = fee,
+            (None, Some(fee)) => {
+                if count == MAX_FEE_OVERRIDES {
+                    return Err(SynchronizerError::InvalidFeeSchedule);
+                }
+                self.overrides[count] = FeeOverride { user: *user, fee };
+                self.override_count += 1;
+            }
+            (Some(index), None) => {
+                // Keep used overrides first
+                self.overrides[index] = self.overrides[count - 1];
+                self.overrides[count - 1] = FeeOverride::default();
+                self.override_count -= 1;
+            }
+            (None, None) => {}
+        }
+        Ok(())
+    }
+
+    /// Fee rate of a user who traded `volume` in the window, `None` below the first tier
+    pub fn fee_rate(&self, user: &Pubkey, volume: u64) -> Option<u64> {
+        match self.overrides().iter().find(|fee_override| fee_override.user == *user) {
+            Some(fee_override) => Some(fee_override.fee),
+            None => self.tiers().iter().rev().find(|tier| tier.min_volume <= volume).map(|tier| tier.fee),
+        }
+    }
+
+    /// Sets the referral share, at most `BPS_DENOMINATOR`
+    pub fn set_referral_share(&mut self, referral_share_bps: u16) -> Result<(), SynchronizerError> {
+        if referral_share_bps > BPS_DENOMINATOR {
+            return Err(SynchronizerError::InvalidFeeSchedule);
+        }
+        self.referral_share_bps = referral_share_bps;
+        Ok(())
+    }
+
+    /// Part of `fee_amount` paid to the referrer of a trade, rounded down
+    pub fn referral_amount(&self, fee_amount: u64) -> u64 {
+        (fee_amount as u128 * self.referral_share_bps as u128 / BPS_DENOMINATOR as u128) as u64
+    }
+}
+
+/// Seed prefix of collateral set account addresses
+pub const COLLATERAL_SET_SEED: &[u8] = b"collateral_set";
+/// Maximum number of collateral tokens accepted besides the Synchronizer collateral token
+pub const MAX_COLLATERALS: usize = 8;
+/// Zeroed bytes at the end of collateral set accounts, reserved for new fields
+pub const COLLATERAL_SET_DATA_RESERVED_LEN: usize = 64;
+
+/// Bounds of the rate of an accepted collateral, rates older than `max_age` seconds can't be traded at
+/// and oracle rate updates can't move the rate by more than `max_deviation_bps` of the reference rate
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct RateBounds {
+    pub max_age: i64,
+    pub max_deviation_bps: u16,
+}
+
+impl RateBounds {
+    /// Checks that rates expire and that updates can move them
+    pub fn is_valid(&self) -> bool {
+        self.max_age > 0 && self.max_deviation_bps > 0 && self.max_deviation_bps <= BPS_DENOMINATOR
+    }
+
+    /// Checks that `new_rate` is within `max_deviation_bps` of `reference_rate`
+    pub fn allows(&self, reference_rate: u64, new_rate: u64) -> bool {
+        let deviation = (reference_rate.max(new_rate) - reference_rate.min(new_rate)) as u128;
+        deviation * BPS_DENOMINATOR as u128 <= reference_rate as u128 * self.max_deviation_bps as u128
+    }
+}
+
+/// Collateral token accepted by `BuyFor` and `SellFor` in place of the Synchronizer collateral token
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct AcceptedCollateral {
+    /// Collateral token mint
+    pub mint: Pubkey,
+    /// Collateral token account of the Synchronizer receiving and paying the trades
+    pub vault: Pubkey,
+    /// Decimals of the collateral token mint
+    pub decimals: u8,
+    /// Price of one collateral token in Synchronizer collateral token base units
+    pub rate: u64,
+    /// Unix timestamp of the last rate change
+    pub rate_updated_at: i64,
+    /// Rate last set by the admin, oracle updates stay within the maximum deviation of it
+    pub reference_rate: u64,
+    /// Maximum age and update deviation of the rate
+    pub rate_bounds: RateBounds,
+    /// Fee amount of the trades in this collateral which can be withdrawn, in its base units
+    pub withdrawable_fee_amount: u64,
+}
+
+impl AcceptedCollateral {
+    /// Rate to trade at, failing when it's older than the maximum rate age
+    pub fn current_rate(&self, unix_timestamp: i64) -> Result<u64, SynchronizerError> {
+        if unix_timestamp.saturating_sub(self.rate_updated_at) > self.rate_bounds.max_age {
+            return Err(SynchronizerError::StaleCollateralRate);
+        }
+        Ok(self.rate)
+    }
+}
+
+/// Collateral tokens accepted besides the Synchronizer collateral token, stored in a program
+/// derived account of the Synchronizer. Trades select a collateral by the vault they pass,
+/// amounts are converted at the rate of the collateral set by the admin or the oracles.
+///
+/// Layout: type discriminator, Borsh encoded fields, `COLLATERAL_SET_DATA_RESERVED_LEN` reserved bytes.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct CollateralSetData {
+    /// Is `true` if this structure has been initialized
+    pub is_initialized: bool,
+    /// Synchronizer account authority
+    pub synchronizer: Pubkey,
+    /// Bump seed of the account address
+    pub bump_seed: u8,
+    /// Number of accepted collaterals
+    pub collateral_count: u8,
+    /// Accepted collaterals
+    pub collaterals: [AcceptedCollateral; MAX_COLLATERALS],
+}
+impl Sealed for CollateralSetData {}
+impl IsInitialized for CollateralSetData {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+impl Pack for CollateralSetData {
+    /// 8 + 1 + 32 + 1 + 1 + 8 * 107 + 64
+    const LEN: usize = 963;
     fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
-        let src = array_ref![src, 0, 146];
-        let (
-            is_initialized,
-            collateral_token_key,
-            remaining_dollar_cap,
-            withdrawable_fee_amount,
-            minminimum_required_signature,
-            oracles_flat
-        ) = array_refs![src, 1, 32, 8, 8, 1, 32 * MAX_ORACLES];
-
-        let is_initialized = match is_initialized {
-            [0] => false,
-            [1] => true,
-            _ => return Err(ProgramError::InvalidAccountData),
+        unpack_typed(AccountType::CollateralSet, src)
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        pack_typed(AccountType::CollateralSet, self, dst)
+    }
+}
+
+impl CollateralSetData {
+    /// Address and bump seed of the collateral set account of a Synchronizer
+    pub fn find_address(synchronizer_key: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[COLLATERAL_SET_SEED, synchronizer_key.as_ref()], &id())
+    }
+
+    /// Accepted collaterals
+    pub fn collaterals(&self) -> &[AcceptedCollateral] {
+        &self.collaterals[..self.collateral_count as usize]
+    }
+
+    /// Index of the accepted collateral with this mint
+    pub fn position_by_mint(&self, mint: &Pubkey) -> Option<usize> {
+        self.collaterals().iter().position(|collateral| collateral.mint == *mint)
+    }
+
+    /// Index of the accepted collateral with this vault
+    pub fn position_by_vault(&self, vault: &Pubkey) -> Option<usize> {
+        self.collaterals().iter().position(|collateral| collateral.vault == *vault)
+    }
+
+    /// Accepts a collateral, its mint and vault must not be accepted already
+    pub fn add(&mut self, collateral: AcceptedCollateral) -> Result<(), SynchronizerError> {
+        let count = self.collateral_count as usize;
+        if count == MAX_COLLATERALS
+            || self.position_by_mint(&collateral.mint).is_some()
+            || self.position_by_vault(&collateral.vault).is_some()
+        {
+            return Err(SynchronizerError::InvalidCollateral);
+        }
+        if collateral.rate == 0 || !collateral.rate_bounds.is_valid() {
+            return Err(SynchronizerError::InvalidCollateralRate);
+        }
+        self.collaterals[count] = AcceptedCollateral { reference_rate: collateral.rate, ..collateral };
+        self.collateral_count += 1;
+        Ok(())
+    }
+
+    /// Stops accepting a collateral, its fees must have been withdrawn and its vault emptied,
+    /// `vault_amount` is the balance of its vault
+    pub fn remove(&mut self, mint: &Pubkey, vault_amount: u64) -> Result<AcceptedCollateral, SynchronizerError> {
+        let count = self.collateral_count as usize;
+        let index = self.position_by_mint(mint).ok_or(SynchronizerError::InvalidCollateral)?;
+        let collateral = self.collaterals[index];
+        if collateral.withdrawable_fee_amount > 0 || vault_amount > 0 {
+            return Err(SynchronizerError::InvalidCollateral);
+        }
+        // Keep accepted collaterals first
+        self.collaterals[index] = self.collaterals[count - 1];
+        self.collaterals[count - 1] = AcceptedCollateral::default();
+        self.collateral_count -= 1;
+        Ok(collateral)
+    }
+
+    /// Sets the rate of an accepted collateral, which becomes its reference rate
+    pub fn set_rate(&mut self, mint: &Pubkey, rate: u64, unix_timestamp: i64) -> Result<(), SynchronizerError> {
+        let index = self.position_by_mint(mint).ok_or(SynchronizerError::InvalidCollateral)?;
+        let collateral = &mut self.collaterals[index];
+        if rate == 0 {
+            return Err(SynchronizerError::InvalidCollateralRate);
+        }
+        collateral.rate = rate;
+        collateral.rate_updated_at = unix_timestamp;
+        collateral.reference_rate = rate;
+        Ok(())
+    }
+
+    /// Sets the rate of an accepted collateral attested by the oracles, within the maximum deviation
+    /// of the reference rate so that repeated updates can't move it further
+    pub fn set_oracle_rate(&mut self, mint: &Pubkey, rate: u64, unix_timestamp: i64) -> Result<(), SynchronizerError> {
+        let index = self.position_by_mint(mint).ok_or(SynchronizerError::InvalidCollateral)?;
+        let collateral = &mut self.collaterals[index];
+        if rate == 0 {
+            return Err(SynchronizerError::InvalidCollateralRate);
+        }
+        if !collateral.rate_bounds.allows(collateral.reference_rate, rate) {
+            return Err(SynchronizerError::CollateralRateDeviationExceeded);
+        }
+        collateral.rate = rate;
+        collateral.rate_updated_at = unix_timestamp;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_synchronizer_data_packing() {
+        let synchronizer = SynchronizerData {
+            is_initialized: true,
+            collateral_token_key: Pubkey::new(&[1; 32]),
+            remaining_dollar_cap: 500_000_000_000,
+            withdrawable_fee_amount: 200,
+            minimum_required_signature: 2,
+            oracles: [Pubkey::new(&[2; 32]), Pubkey::new(&[3; 32]), Pubkey::default()],
+            required_trade_accounts: 0,
+            collateral_decimals: None,
+        };
+
+        // Version 1 layout
+        let mut expect_v1 = vec![1u8];
+        expect_v1.extend_from_slice(&[1; 32]);
+        expect_v1.extend_from_slice(&[0, 136, 82, 106, 116, 0, 0, 0]);
+        expect_v1.extend_from_slice(&[200, 0, 0, 0, 0, 0, 0, 0]);
+        expect_v1.extend_from_slice(&[2]);
+        expect_v1.extend_from_slice(&[2; 32]);
+        expect_v1.extend_from_slice(&[3; 32]);
+        expect_v1.extend_from_slice(&[0; 32]);
+        assert_eq!(expect_v1.len(), SYNCHRONIZER_DATA_V1_LEN);
+        assert_eq!(SynchronizerData::unpack_versioned(&expect_v1).unwrap(), (1, synchronizer));
+
+        // Current layout
+        let mut expect = b"SYNCCONF".to_vec();
+        expect.push(SYNCHRONIZER_DATA_VERSION);
+        expect.extend_from_slice(&expect_v1);
+        // No required trade accounts nor collateral decimals, padded to the length of stored decimals
+        expect.extend_from_slice(&[0; 3]);
+        expect.extend_from_slice(&[0; SYNCHRONIZER_DATA_RESERVED_LEN]);
+
+        let mut packed = vec![0; SynchronizerData::LEN];
+        SynchronizerData::pack(synchronizer, &mut packed).unwrap();
+        assert_eq!(packed, expect);
+        assert_eq!(SynchronizerData::unpack(&expect).unwrap(), synchronizer);
+        assert_eq!(SynchronizerData::unpack_versioned(&expect).unwrap(), (SYNCHRONIZER_DATA_VERSION, synchronizer));
+
+        // Required trade accounts follow the oracles
+        let with_required = SynchronizerData { required_trade_accounts: 0b101, ..synchronizer };
+        SynchronizerData::pack(with_required, &mut packed).unwrap();
+        let mut expect_required = expect.clone();
+        expect_required[SYNCHRONIZER_DATA_HEADER_LEN + SYNCHRONIZER_DATA_V1_LEN] = 0b101;
+        assert_eq!(packed, expect_required);
+        assert_eq!(SynchronizerData::unpack(&packed).unwrap(), with_required);
+
+        // Stored collateral decimals
+        let with_decimals = SynchronizerData { collateral_decimals: Some(6), ..synchronizer };
+        SynchronizerData::pack(with_decimals, &mut packed).unwrap();
+        let mut expect_decimals = expect.clone();
+        expect_decimals[SYNCHRONIZER_DATA_HEADER_LEN + SYNCHRONIZER_DATA_V1_LEN + 1..][..2].copy_from_slice(&[1, 6]);
+        assert_eq!(packed, expect_decimals);
+        assert_eq!(SynchronizerData::unpack(&packed).unwrap().collateral_token_decimals(), 6);
+        assert_eq!(synchronizer.collateral_token_decimals(), Processor::DEFAULT_DECIMALS);
+
+        // Never initialized account
+        let zeroed = vec![0; SynchronizerData::LEN];
+        assert_eq!(SynchronizerData::unpack_unchecked(&zeroed).unwrap(), SynchronizerData::default());
+        assert_eq!(SynchronizerData::unpack_versioned(&zeroed).unwrap(), (0, SynchronizerData::default()));
+
+        // Other account type
+        let mut bad = expect.clone();
+        bad[..ACCOUNT_DISCRIMINATOR_LEN].copy_from_slice(&AccountType::UserPosition.discriminator());
+        assert_eq!(SynchronizerData::unpack_versioned(&bad), Err(SynchronizerError::InvalidAccountType.into()));
+
+        // Unknown version
+        let mut bad = expect.clone();
+        bad[ACCOUNT_DISCRIMINATOR_LEN] = SYNCHRONIZER_DATA_VERSION + 1;
+        assert_eq!(SynchronizerData::unpack_versioned(&bad), Err(ProgramError::InvalidAccountData));
+
+        // Bad bool value
+        let mut bad = expect.clone();
+        bad[SYNCHRONIZER_DATA_HEADER_LEN] = 2;
+        assert_eq!(SynchronizerData::unpack_unchecked(&bad), Err(ProgramError::InvalidAccountData));
+        let mut bad = expect_v1.clone();
+        bad[0] = 2;
+        assert_eq!(SynchronizerData::unpack_versioned(&bad), Err(ProgramError::InvalidAccountData));
+
+        // Unknown length
+        assert_eq!(SynchronizerData::unpack_versioned(&expect[..200]), Err(ProgramError::InvalidAccountData));
+    }
+
+    #[test]
+    fn test_account_type_check() {
+        let types = [
+            AccountType::Synchronizer,
+            AccountType::AssetRegistry,
+            AccountType::UserPosition,
+            AccountType::RateLimit,
+            AccountType::UserVolume,
+            AccountType::MarketSchedule,
+            AccountType::FeeSchedule,
+            AccountType::CollateralSet,
+        ];
+        for account_type in types.iter() {
+            let mut data = account_type.discriminator().to_vec();
+            data.extend_from_slice(&[0; 16]);
+            account_type.check(&data).unwrap();
+            assert_eq!(account_type.check(&data[..4]), Err(SynchronizerError::InvalidAccountType.into()));
+
+            for other_type in types.iter().filter(|other_type| *other_type != account_type) {
+                assert_ne!(account_type.discriminator(), other_type.discriminator());
+                assert_eq!(other_type.check(&data), Err(SynchronizerError::InvalidAccountType.into()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_asset_data() {
+        let asset = AssetData {
+            is_initialized: true,
+            synchronizer: Pubkey::new(&[1; 32]),
+            fiat_asset_mint: Pubkey::new(&[2; 32]),
+            max_outstanding_notional: 1000,
+            max_transaction_notional: 300,
+            outstanding_notional: 800,
+            bump_seed: 254,
+            ask_spread_bps: 150,
+            bid_spread_bps: 50,
+        };
+
+        let mut expect = b"ASSETREG".to_vec();
+        expect.push(1);
+        expect.extend_from_slice(&[1; 32]);
+        expect.extend_from_slice(&[2; 32]);
+        expect.extend_from_slice(&1000u64.to_le_bytes());
+        expect.extend_from_slice(&300u64.to_le_bytes());
+        expect.extend_from_slice(&800u64.to_le_bytes());
+        expect.push(254);
+        expect.extend_from_slice(&150u16.to_le_bytes());
+        expect.extend_from_slice(&50u16.to_le_bytes());
+        expect.extend_from_slice(&[0; ASSET_DATA_RESERVED_LEN]);
+        assert_eq!(expect.len(), AssetData::LEN);
+
+        let mut packed = vec![0; AssetData::LEN];
+        AssetData::pack(asset, &mut packed).unwrap();
+        assert_eq!(packed, expect);
+        assert_eq!(AssetData::unpack(&expect).unwrap(), asset);
+        assert_eq!(AssetData::unpack_unchecked(&[0; AssetData::LEN]).unwrap(), AssetData::default());
+
+        // BadCase: other account type
+        let mut bad = expect.clone();
+        bad[..ACCOUNT_DISCRIMINATOR_LEN].copy_from_slice(&AccountType::Synchronizer.discriminator());
+        assert_eq!(AssetData::unpack(&bad), Err(SynchronizerError::InvalidAccountType.into()));
+
+        // Address depends on the Synchronizer and the mint
+        let (address, bump_seed) = AssetData::find_address(&asset.synchronizer, &asset.fiat_asset_mint);
+        assert_eq!(
+            Pubkey::create_program_address(&[ASSET_SEED, asset.synchronizer.as_ref(), asset.fiat_asset_mint.as_ref(), &[bump_seed]], &id()).unwrap(),
+            address
+        );
+        assert_ne!(AssetData::find_address(&asset.fiat_asset_mint, &asset.synchronizer).0, address);
+
+        assert_eq!(asset.outstanding_after_buy(200), Ok(1000));
+        // BadCase: above the transaction or the outstanding cap
+        assert_eq!(asset.outstanding_after_buy(301), Err(SynchronizerError::TransactionCapExceeded));
+        assert_eq!(asset.outstanding_after_buy(201), Err(SynchronizerError::AssetCapExceeded));
+        let full = AssetData { outstanding_notional: u64::MAX, max_transaction_notional: u64::MAX, ..asset };
+        assert_eq!(full.outstanding_after_buy(1), Err(SynchronizerError::AssetCapExceeded));
+
+        let mut spread = asset;
+        spread.set_spreads(BPS_DENOMINATOR, BPS_DENOMINATOR - 1).unwrap();
+        assert_eq!((spread.ask_spread_bps, spread.bid_spread_bps), (BPS_DENOMINATOR, BPS_DENOMINATOR - 1));
+        // BadCase: spreads above the whole price or a zero sell price
+        assert_eq!(spread.set_spreads(BPS_DENOMINATOR + 1, 0), Err(SynchronizerError::InvalidSpread));
+        assert_eq!(spread.set_spreads(0, BPS_DENOMINATOR), Err(SynchronizerError::InvalidSpread));
+        assert_eq!((spread.ask_spread_bps, spread.bid_spread_bps), (BPS_DENOMINATOR, BPS_DENOMINATOR - 1));
+    }
+
+    #[test]
+    fn test_volume_window() {
+        assert_eq!(VolumeWindow::bucket_slots(0), 1);
+        assert_eq!(VolumeWindow::bucket_slots(16), 1);
+        assert_eq!(VolumeWindow::bucket_slots(17), 2);
+        assert_eq!(VolumeWindow::bucket_slots(160), 10);
+
+        // Buckets of 10 slots
+        let mut window = VolumeWindow::default();
+        assert_eq!(window.volume(1000), 0);
+        window.record(1000, 160, 300, 500).unwrap();
+        window.record(1009, 160, 100, 500).unwrap();
+        window.record(1055, 160, 100, 500).unwrap();
+        assert_eq!(window.bucket_slots, 10);
+        assert_eq!(window.buckets[100 % VOLUME_WINDOW_BUCKETS], VolumeBucket { index: 100, notional: 400 });
+        assert_eq!(window.volume(1159), 500);
+
+        // BadCase: above the limit in the window
+        assert_eq!(window.record(1159, 160, 1, 500), Err(SynchronizerError::RateLimitExceeded));
+        assert_eq!(window.record(1159, 160, u64::MAX, u64::MAX), Err(SynchronizerError::RateLimitExceeded));
+        assert_eq!(window.volume(1159), 500);
+
+        // The first bucket leaves the window
+        assert_eq!(window.volume(1160), 100);
+        window.record(1160, 160, 400, 500).unwrap();
+        assert_eq!(window.buckets[116 % VOLUME_WINDOW_BUCKETS], VolumeBucket { index: 116, notional: 400 });
+        assert_eq!(window.volume(1160), 500);
+        assert_eq!(window.volume(10_000), 0);
+
+        // A new window length resets the buckets
+        window.record(1160, 320, 50, 500).unwrap();
+        assert_eq!(window.bucket_slots, 20);
+        assert_eq!(window.volume(1160), 50);
+
+        // Volumes without limit saturate
+        window.add(1160, 320, u64::MAX);
+        assert_eq!(window.volume(1160), u64::MAX);
+        window.add(1160, 160, 50);
+        assert_eq!((window.bucket_slots, window.volume(1160)), (10, 50));
+    }
+
+    #[test]
+    fn test_volume_data_packing() {
+        let mut window = VolumeWindow::default();
+        window.record(100, 16, 700, 1000).unwrap();
+        let rate_limit = RateLimitData {
+            is_initialized: true,
+            synchronizer: Pubkey::new(&[1; 32]),
+            window_slots: 16,
+            max_synchronizer_notional: 1000,
+            max_user_notional: 200,
+            bump_seed: 253,
+            window,
         };
 
-        let mut result = SynchronizerData {
-            is_initialized,
-            collateral_token_key: Pubkey::new_from_array(*collateral_token_key),
-            remaining_dollar_cap: u64::from_le_bytes(*remaining_dollar_cap),
-            withdrawable_fee_amount: u64::from_le_bytes(*withdrawable_fee_amount),
-            minimum_required_signature: u8::from_le_bytes(*minminimum_required_signature),
-            oracles: [Pubkey::new_from_array([0u8; 32]); MAX_ORACLES],
+        let mut expect = b"RATELIMT".to_vec();
+        expect.push(1);
+        expect.extend_from_slice(&[1; 32]);
+        expect.extend_from_slice(&16u64.to_le_bytes());
+        expect.extend_from_slice(&1000u64.to_le_bytes());
+        expect.extend_from_slice(&200u64.to_le_bytes());
+        expect.push(253);
+        expect.extend_from_slice(&1u64.to_le_bytes());
+        for i in 0..VOLUME_WINDOW_BUCKETS {
+            let bucket = if i == 100 % VOLUME_WINDOW_BUCKETS { (100u64, 700u64) } else { (0, 0) };
+            expect.extend_from_slice(&bucket.0.to_le_bytes());
+            expect.extend_from_slice(&bucket.1.to_le_bytes());
+        }
+        expect.extend_from_slice(&[0; VOLUME_DATA_RESERVED_LEN]);
+        assert_eq!(expect.len(), RateLimitData::LEN);
+
+        let mut packed = vec![0; RateLimitData::LEN];
+        RateLimitData::pack(rate_limit, &mut packed).unwrap();
+        assert_eq!(packed, expect);
+        assert_eq!(RateLimitData::unpack(&expect).unwrap(), rate_limit);
+        assert_eq!(RateLimitData::unpack_unchecked(&[0; RateLimitData::LEN]).unwrap(), RateLimitData::default());
+
+        let user_volume = UserVolumeData {
+            is_initialized: true,
+            synchronizer: Pubkey::new(&[1; 32]),
+            user: Pubkey::new(&[2; 32]),
+            bump_seed: 255,
+            window,
+            fee_window: window,
+        };
+        let mut packed = vec![0; UserVolumeData::LEN];
+        UserVolumeData::pack(user_volume, &mut packed).unwrap();
+        assert_eq!(&packed[..ACCOUNT_DISCRIMINATOR_LEN], b"USERVOLM");
+        assert!(packed[UserVolumeData::LEN - VOLUME_DATA_RESERVED_LEN..].iter().all(|byte| *byte == 0));
+        assert_eq!(UserVolumeData::unpack(&packed).unwrap(), user_volume);
+
+        // BadCase: other account type
+        let mut bad = packed.clone();
+        bad[..ACCOUNT_DISCRIMINATOR_LEN].copy_from_slice(&AccountType::RateLimit.discriminator());
+        assert_eq!(UserVolumeData::unpack(&bad), Err(SynchronizerError::InvalidAccountType.into()));
+        assert_eq!(RateLimitData::unpack(&packed[..RateLimitData::LEN]), Err(SynchronizerError::InvalidAccountType.into()));
+
+        // Addresses depend on the Synchronizer and the user
+        let (address, bump_seed) = UserVolumeData::find_address(&user_volume.synchronizer, &user_volume.user);
+        assert_eq!(
+            Pubkey::create_program_address(&[USER_VOLUME_SEED, user_volume.synchronizer.as_ref(), user_volume.user.as_ref(), &[bump_seed]], &id()).unwrap(),
+            address
+        );
+        assert_ne!(UserVolumeData::find_address(&user_volume.user, &user_volume.synchronizer).0, address);
+        assert_ne!(RateLimitData::find_address(&user_volume.synchronizer).0, address);
+    }
+
+    #[test]
+    fn test_user_position_data() {
+        let mut position = UserPositionData {
+            is_initialized: true,
+            synchronizer: Pubkey::new(&[1; 32]),
+            user: Pubkey::new(&[2; 32]),
+            fiat_asset_mint: Pubkey::new(&[3; 32]),
+            bump_seed: 254,
+            ..UserPositionData::default()
         };
-        for (src, dst) in oracles_flat.chunks(32).zip(result.oracles.iter_mut()) {
-            *dst = Pubkey::new(src);
+        let collateral_mint = Pubkey::new(&[4; 32]);
+        position.record_buy(10, &collateral_mint, 51, 1, 100).unwrap();
+        position.record_buy(20, &collateral_mint, 102, 2, 105).unwrap();
+        position.record_sell(5, &collateral_mint, 24, 1, 110).unwrap();
+        assert_eq!(
+            (position.bought_amount, position.sold_amount, position.collateral_paid, position.collateral_received, position.fee_paid, position.last_trade_slot),
+            (30, 5, 153, 24, 4, 110)
+        );
+        assert_eq!(position.collateral_mint, collateral_mint);
+
+        // Totals saturate
+        let mut saturated = position;
+        saturated.record_buy(u64::MAX, &collateral_mint, u64::MAX, u64::MAX, 120).unwrap();
+        assert_eq!((saturated.bought_amount, saturated.collateral_paid, saturated.fee_paid), (u64::MAX, u64::MAX, u64::MAX));
+
+        // BadCase: trades in another collateral
+        let mut other = position;
+        assert_eq!(other.record_buy(1, &Pubkey::new(&[5; 32]), 1, 0, 120), Err(SynchronizerError::InvalidUserPositionAccount));
+        assert_eq!(other.record_sell(1, &Pubkey::new(&[5; 32]), 1, 0, 120), Err(SynchronizerError::InvalidUserPositionAccount));
+        assert_eq!(other, position);
+
+        let mut expect = b"USERPOSN".to_vec();
+        expect.push(1);
+        expect.extend_from_slice(&[1; 32]);
+        expect.extend_from_slice(&[2; 32]);
+        expect.extend_from_slice(&[3; 32]);
+        expect.extend_from_slice(&[4; 32]);
+        for value in [30u64, 5, 153, 24, 4, 110].iter() {
+            expect.extend_from_slice(&value.to_le_bytes());
         }
-        Ok(result)
+        expect.push(254);
+        expect.extend_from_slice(&[0; USER_POSITION_DATA_RESERVED_LEN]);
+        assert_eq!(expect.len(), UserPositionData::LEN);
+
+        let mut packed = vec![0; UserPositionData::LEN];
+        UserPositionData::pack(position, &mut packed).unwrap();
+        assert_eq!(packed, expect);
+        assert_eq!(UserPositionData::unpack(&expect).unwrap(), position);
+        assert_eq!(UserPositionData::unpack_unchecked(&[0; UserPositionData::LEN]).unwrap(), UserPositionData::default());
+
+        // BadCase: other account type
+        expect[..ACCOUNT_DISCRIMINATOR_LEN].copy_from_slice(&AccountType::AssetRegistry.discriminator());
+        assert_eq!(UserPositionData::unpack(&expect), Err(SynchronizerError::InvalidAccountType.into()));
+
+        // Addresses depend on the Synchronizer, the user and the fiat asset mint
+        let (address, bump_seed) = UserPositionData::find_address(&position.synchronizer, &position.user, &position.fiat_asset_mint);
+        assert_eq!(
+            Pubkey::create_program_address(
+                &[USER_POSITION_SEED, position.synchronizer.as_ref(), position.user.as_ref(), position.fiat_asset_mint.as_ref(), &[bump_seed]],
+                &id(),
+            ).unwrap(),
+            address
+        );
+        assert_ne!(UserPositionData::find_address(&position.synchronizer, &position.fiat_asset_mint, &position.user).0, address);
+        assert_ne!(UserPositionData::find_address(&position.synchronizer, &position.user, &Pubkey::new_unique()).0, address);
     }
 
-    fn pack_into_slice(&self, dst: &mut [u8]) {
-        let dst = array_mut_ref![dst, 0, 146];
-        let (
-            is_initialized_dst,
-            collateral_token_key_dst,
-            remaining_dollar_cap_dst,
-            withdrawable_fee_amount_dst,
-            minimum_required_signature_dst,
-            oracles_flat_dst,
-        ) = mut_array_refs![dst, 1, 32, 8, 8, 1, 32 * MAX_ORACLES];
-
-        is_initialized_dst[0] = self.is_initialized as u8;
-        collateral_token_key_dst.copy_from_slice(self.collateral_token_key.as_ref());
-        *remaining_dollar_cap_dst = self.remaining_dollar_cap.to_le_bytes();
-        *withdrawable_fee_amount_dst = self.withdrawable_fee_amount.to_le_bytes();
-        minimum_required_signature_dst[0] = self.minimum_required_signature as u8;
-        for (i, src) in self.oracles.iter().enumerate() {
-            let dst_array = array_mut_ref![oracles_flat_dst, 32 * i, 32];
-            dst_array.copy_from_slice(src.as_ref());
+    #[test]
+    fn test_market_schedule_data() {
+        // Weekdays from 14:30 to 21:00 UTC
+        let session = TradingHours { open: 14 * 3600 + 1800, close: 21 * 3600 };
+        let closed = TradingHours::default();
+        let mut schedule = MarketScheduleData {
+            is_initialized: true,
+            synchronizer: Pubkey::new(&[1; 32]),
+            fiat_asset_mint: Pubkey::new(&[2; 32]),
+            bump_seed: 253,
+            ..MarketScheduleData::default()
+        };
+        // 2021-12-24 and 2021-12-31, days 18985 and 18992
+        schedule.set([session, session, session, session, session, closed, closed], &[18985, 18992]).unwrap();
+
+        // Thursday 2021-12-23
+        let thursday = 18984 * SECONDS_PER_DAY;
+        assert!(!schedule.is_open(thursday + 14 * 3600 + 1799));
+        assert!(schedule.is_open(thursday + 14 * 3600 + 1800));
+        assert!(schedule.is_open(thursday + 21 * 3600 - 1));
+        assert!(!schedule.is_open(thursday + 21 * 3600));
+        // Holiday on Friday, weekend
+        assert!(!schedule.is_open(thursday + SECONDS_PER_DAY + 15 * 3600));
+        assert!(!schedule.is_open(thursday + 2 * SECONDS_PER_DAY + 15 * 3600));
+        assert!(!schedule.is_open(thursday + 3 * SECONDS_PER_DAY + 15 * 3600));
+        // Monday
+        assert!(schedule.is_open(thursday + 4 * SECONDS_PER_DAY + 15 * 3600));
+        // Wednesday 1969-12-31
+        assert!(schedule.is_open(-SECONDS_PER_DAY + 15 * 3600));
+
+        // Holidays are replaced
+        schedule.set([TradingHours::ALL_DAY; 7], &[]).unwrap();
+        assert!(schedule.is_open(thursday + SECONDS_PER_DAY));
+        assert_eq!(schedule.holidays, [0; MAX_MARKET_HOLIDAYS]);
+
+        // BadCase: invalid hours or holidays
+        let mut bad = schedule;
+        let late = TradingHours { open: 0, close: SECONDS_PER_DAY as u32 + 1 };
+        let reversed = TradingHours { open: 2, close: 1 };
+        assert_eq!(bad.set([late; 7], &[]), Err(SynchronizerError::InvalidMarketSchedule));
+        assert_eq!(bad.set([reversed; 7], &[]), Err(SynchronizerError::InvalidMarketSchedule));
+        assert_eq!(bad.set([session; 7], &[0]), Err(SynchronizerError::InvalidMarketSchedule));
+        assert_eq!(bad.set([session; 7], &[1; MAX_MARKET_HOLIDAYS + 1]), Err(SynchronizerError::InvalidMarketSchedule));
+        assert_eq!(bad, schedule);
+
+        let mut packed = vec![0; MarketScheduleData::LEN];
+        MarketScheduleData::pack(schedule, &mut packed).unwrap();
+        assert_eq!(&packed[..ACCOUNT_DISCRIMINATOR_LEN], b"MKTSCHED");
+        assert!(packed[MarketScheduleData::LEN - MARKET_SCHEDULE_DATA_RESERVED_LEN..].iter().all(|byte| *byte == 0));
+        assert_eq!(MarketScheduleData::unpack(&packed).unwrap(), schedule);
+        assert_eq!(MarketScheduleData::unpack_unchecked(&[0; MarketScheduleData::LEN]).unwrap(), MarketScheduleData::default());
+
+        let (address, _) = MarketScheduleData::find_address(&schedule.synchronizer, &schedule.fiat_asset_mint);
+        assert_ne!(AssetData::find_address(&schedule.synchronizer, &schedule.fiat_asset_mint).0, address);
+    }
+
+    #[test]
+    fn test_fee_schedule_data() {
+        let market_maker = Pubkey::new(&[3; 32]);
+        let user = Pubkey::new(&[4; 32]);
+        let mut schedule = FeeScheduleData {
+            is_initialized: true,
+            synchronizer: Pubkey::new(&[1; 32]),
+            bump_seed: 253,
+            ..FeeScheduleData::default()
+        };
+        let tiers = [FeeTier { min_volume: 1000, fee: 8 }, FeeTier { min_volume: 5000, fee: 5 }];
+        schedule.set_tiers(&tiers).unwrap();
+        assert_eq!(schedule.tiers(), &tiers);
+
+        assert_eq!(schedule.fee_rate(&user, 999), None);
+        assert_eq!(schedule.fee_rate(&user, 1000), Some(8));
+        assert_eq!(schedule.fee_rate(&user, 4999), Some(8));
+        assert_eq!(schedule.fee_rate(&user, u64::MAX), Some(5));
+
+        // Overrides come before the tiers
+        schedule.set_override(&market_maker, Some(0)).unwrap();
+        schedule.set_override(&user, Some(9)).unwrap();
+        schedule.set_override(&user, Some(7)).unwrap();
+        assert_eq!(schedule.overrides(), &[FeeOverride { user: market_maker, fee: 0 }, FeeOverride { user, fee: 7 }]);
+        assert_eq!(schedule.fee_rate(&market_maker, 0), Some(0));
+        assert_eq!(schedule.fee_rate(&user, u64::MAX), Some(7));
+        schedule.set_override(&market_maker, None).unwrap();
+        schedule.set_override(&market_maker, None).unwrap();
+        assert_eq!(schedule.overrides(), &[FeeOverride { user, fee: 7 }]);
+        assert_eq!(schedule.overrides[1], FeeOverride::default());
+        assert_eq!(schedule.fee_rate(&market_maker, 1000), Some(8));
+
+        assert_eq!(schedule.referral_amount(999), 0);
+        schedule.set_referral_share(2500).unwrap();
+        assert_eq!(schedule.referral_amount(999), 249);
+        assert_eq!(schedule.referral_amount(u64::MAX), u64::MAX / 4);
+
+        let mut packed = vec![0; FeeScheduleData::LEN];
+        FeeScheduleData::pack(schedule, &mut packed).unwrap();
+        assert_eq!(&packed[..ACCOUNT_DISCRIMINATOR_LEN], b"FEESCHED");
+        assert!(packed[FeeScheduleData::LEN - FEE_SCHEDULE_DATA_RESERVED_LEN..].iter().all(|byte| *byte == 0));
+        assert_eq!(FeeScheduleData::unpack(&packed).unwrap(), schedule);
+        assert_eq!(FeeScheduleData::unpack_unchecked(&[0; FeeScheduleData::LEN]).unwrap(), FeeScheduleData::default());
+        assert_ne!(FeeScheduleData::find_address(&schedule.synchronizer).0, RateLimitData::find_address(&schedule.synchronizer).0);
+
+        // BadCase: unordered or too many tiers, too many overrides, referral share above 100%
+        let mut bad = schedule;
+        assert_eq!(bad.set_referral_share(BPS_DENOMINATOR + 1), Err(SynchronizerError::InvalidFeeSchedule));
+        assert_eq!(bad.set_tiers(&[tiers[1], tiers[0]]), Err(SynchronizerError::InvalidFeeSchedule));
+        assert_eq!(bad.set_tiers(&[tiers[0], tiers[0]]), Err(SynchronizerError::InvalidFeeSchedule));
+        let many: Vec<FeeTier> = (0..=MAX_FEE_TIERS as u64).map(|min_volume| FeeTier { min_volume, fee: 1 }).collect();
+        assert_eq!(bad.set_tiers(&many), Err(SynchronizerError::InvalidFeeSchedule));
+        assert_eq!(bad, schedule);
+        for index in 1..MAX_FEE_OVERRIDES {
+            bad.set_override(&Pubkey::new(&[index as u8 + 10; 32]), Some(1)).unwrap();
+        }
+        assert_eq!(bad.set_override(&market_maker, Some(1)), Err(SynchronizerError::InvalidFeeSchedule));
+        bad.set_override(&user, Some(2)).unwrap();
+    }
+
+    #[test]
+    fn test_collateral_set_data() {
+        let usdt = AcceptedCollateral {
+            mint: Pubkey::new(&[3; 32]),
+            vault: Pubkey::new(&[4; 32]),
+            decimals: 6,
+            rate: 1_000_000_000,
+            rate_updated_at: 1_600_000_000,
+            reference_rate: 1_000_000_000,
+            rate_bounds: RateBounds { max_age: 3_600, max_deviation_bps: 100 },
+            withdrawable_fee_amount: 0,
+        };
+        let dai = AcceptedCollateral { mint: Pubkey::new(&[5; 32]), vault: Pubkey::new(&[6; 32]), decimals: 18, ..usdt };
+        let mut set = CollateralSetData {
+            is_initialized: true,
+            synchronizer: Pubkey::new(&[1; 32]),
+            bump_seed: 252,
+            ..CollateralSetData::default()
+        };
+        set.add(usdt).unwrap();
+        set.add(dai).unwrap();
+        assert_eq!(set.collaterals(), &[usdt, dai]);
+        assert_eq!(set.position_by_mint(&dai.mint), Some(1));
+        assert_eq!(set.position_by_vault(&usdt.vault), Some(0));
+        assert_eq!(set.position_by_vault(&usdt.mint), None);
+
+        set.set_rate(&dai.mint, 999_000_000, 1_600_000_100).unwrap();
+        assert_eq!(set.collaterals[1].rate, 999_000_000);
+        assert_eq!(set.collaterals[1].rate_updated_at, 1_600_000_100);
+        assert_eq!(set.collaterals[1].reference_rate, 999_000_000);
+
+        // Oracle updates are anchored to the rate set by the admin, repeated updates can't move it further
+        set.set_oracle_rate(&dai.mint, 1_008_000_000, 1_600_000_200).unwrap();
+        assert_eq!((set.collaterals[1].rate, set.collaterals[1].reference_rate), (1_008_000_000, 999_000_000));
+        assert_eq!(set.set_oracle_rate(&dai.mint, 1_017_000_000, 1_600_000_300), Err(SynchronizerError::CollateralRateDeviationExceeded));
+        assert_eq!(set.set_oracle_rate(&dai.mint, 0, 1_600_000_300), Err(SynchronizerError::InvalidCollateralRate));
+        assert_eq!(set.collaterals[1].rate_updated_at, 1_600_000_200);
+        set.set_rate(&dai.mint, 999_000_000, 1_600_000_100).unwrap();
+        assert_eq!(set.collaterals[1].current_rate(1_600_003_700), Ok(999_000_000));
+        assert_eq!(set.collaterals[1].current_rate(1_600_003_701), Err(SynchronizerError::StaleCollateralRate));
+
+        // Oracle updates move the rate by at most 1%
+        let bounds = set.collaterals[1].rate_bounds;
+        assert!(bounds.allows(1_000_000_000, 990_000_000));
+        assert!(bounds.allows(1_000_000_000, 1_010_000_000));
+        assert!(!bounds.allows(1_000_000_000, 989_999_999));
+        assert!(!bounds.allows(1_000_000_000, 1_010_000_001));
+
+        let mut packed = vec![0; CollateralSetData::LEN];
+        CollateralSetData::pack(set, &mut packed).unwrap();
+        assert_eq!(&packed[..ACCOUNT_DISCRIMINATOR_LEN], b"COLLSET_");
+        assert!(packed[CollateralSetData::LEN - COLLATERAL_SET_DATA_RESERVED_LEN..].iter().all(|byte| *byte == 0));
+        assert_eq!(CollateralSetData::unpack(&packed).unwrap(), set);
+        assert_eq!(CollateralSetData::unpack_unchecked(&[0; CollateralSetData::LEN]).unwrap(), CollateralSetData::default());
+        assert_ne!(CollateralSetData::find_address(&set.synchronizer).0, FeeScheduleData::find_address(&set.synchronizer).0);
+
+        // BadCase: accepted mint or vault, zero rate, invalid rate bounds, unknown mint, pending fees, vault balance
+        let mut bad = set;
+        assert_eq!(bad.add(AcceptedCollateral { vault: Pubkey::new(&[7; 32]), ..usdt }), Err(SynchronizerError::InvalidCollateral));
+        assert_eq!(bad.add(AcceptedCollateral { mint: Pubkey::new(&[7; 32]), ..usdt }), Err(SynchronizerError::InvalidCollateral));
+        let other = AcceptedCollateral { mint: Pubkey::new(&[7; 32]), vault: Pubkey::new(&[8; 32]), ..usdt };
+        assert_eq!(bad.add(AcceptedCollateral { rate: 0, ..other }), Err(SynchronizerError::InvalidCollateralRate));
+        for rate_bounds in [
+            RateBounds { max_age: 0, ..usdt.rate_bounds },
+            RateBounds { max_deviation_bps: 0, ..usdt.rate_bounds },
+            RateBounds { max_deviation_bps: BPS_DENOMINATOR + 1, ..usdt.rate_bounds },
+        ].iter() {
+            assert_eq!(bad.add(AcceptedCollateral { rate_bounds: *rate_bounds, ..other }), Err(SynchronizerError::InvalidCollateralRate));
         }
+        assert_eq!(bad.set_rate(&usdt.mint, 0, 0), Err(SynchronizerError::InvalidCollateralRate));
+        assert_eq!(bad.set_rate(&other.mint, 1, 0), Err(SynchronizerError::InvalidCollateral));
+        assert_eq!(bad.set_oracle_rate(&other.mint, 1, 0), Err(SynchronizerError::InvalidCollateral));
+        assert_eq!(bad.remove(&other.mint, 0), Err(SynchronizerError::InvalidCollateral));
+        assert_eq!(bad.remove(&usdt.mint, 1), Err(SynchronizerError::InvalidCollateral));
+        bad.collaterals[0].withdrawable_fee_amount = 1;
+        assert_eq!(bad.remove(&usdt.mint, 0), Err(SynchronizerError::InvalidCollateral));
+        for index in 2..MAX_COLLATERALS {
+            bad.add(AcceptedCollateral { mint: Pubkey::new(&[index as u8 + 10; 32]), vault: Pubkey::new(&[index as u8 + 20; 32]), ..usdt }).unwrap();
+        }
+        assert_eq!(bad.add(other), Err(SynchronizerError::InvalidCollateral));
+
+        assert_eq!(set.remove(&usdt.mint, 0).unwrap(), usdt);
+        assert_eq!(set.collaterals(), &[AcceptedCollateral { rate: 999_000_000, rate_updated_at: 1_600_000_100, reference_rate: 999_000_000, ..dai }]);
+        assert_eq!(set.collaterals[1], AcceptedCollateral::default());
     }
 }
@@ -0,0 +1,578 @@
+//! Account validation
+
+use crate::{error::SynchronizerError, processor::id, state::{ASSET_SEED, AssetData, COLLATERAL_SET_SEED, CollateralSetData, FEE_SCHEDULE_SEED, FeeScheduleData, MARKET_SCHEDULE_SEED, MarketScheduleData, RATE_LIMIT_SEED, RateLimitData, SYNCHRONIZER_DATA_VERSION, SynchronizerData, USER_POSITION_SEED, USER_VOLUME_SEED, UserPositionData, UserVolumeData}};
+use solana_program::{account_info::AccountInfo, program_error::ProgramError, program_option::COption, program_pack::{IsInitialized, Pack}, pubkey::Pubkey};
+use spl_token::state::{Account, Mint};
+
+/// Checks that the Synchronizer account is owned by the program and signed the transaction
+pub fn check_synchronizer_authority(synchronizer_account_info: &AccountInfo) -> Result<(), ProgramError> {
+    if !synchronizer_account_info.owner.eq(&id()) {
+        return Err(SynchronizerError::AccessDenied.into());
+    }
+    if !synchronizer_account_info.is_signer {
+        return Err(SynchronizerError::InvalidSigner.into());
+    }
+    Ok(())
+}
+
+/// Checks that the supplied account is the SPL Token program
+pub fn check_token_program(spl_token_info: &AccountInfo) -> Result<(), ProgramError> {
+    if !spl_token_info.key.eq(&spl_token::id()) {
+        return Err(SynchronizerError::InvalidTokenProgram.into());
+    }
+    Ok(())
+}
+
+/// Loads initialized Synchronizer data with the current layout from the Synchronizer account authority
+pub fn load_synchronizer(synchronizer_account_info: &AccountInfo) -> Result<SynchronizerData, ProgramError> {
+    if !synchronizer_account_info.owner.eq(&id()) {
+        return Err(SynchronizerError::AccessDenied.into());
+    }
+
+    let (version, synchronizer) = SynchronizerData::unpack_versioned(&synchronizer_account_info.data.borrow())?;
+    if !synchronizer.is_initialized {
+        return Err(SynchronizerError::NotInitialized.into());
+    }
+    if version != SYNCHRONIZER_DATA_VERSION {
+        return Err(SynchronizerError::MigrationRequired.into());
+    }
+    Ok(synchronizer)
+}
+
+/// Loads the program derived account of `seeds`, `None` while it isn't created or initialized.
+/// The address of an initialized account is checked with its stored bump seed, only missing
+/// accounts need the bump seed search.
+fn load_program_account<T: Pack + IsInitialized>(
+    account_info: &AccountInfo,
+    seeds: &[&[u8]],
+    bump_seed: fn(&T) -> u8,
+    invalid_address: SynchronizerError,
+) -> Result<Option<T>, ProgramError> {
+    if account_info.owner.eq(&id()) {
+        let data = T::unpack_unchecked(&account_info.data.borrow())?;
+        if data.is_initialized() {
+            return match Pubkey::create_program_address(&[seeds, &[&[bump_seed(&data)]]].concat(), &id()) {
+                Ok(address) if account_info.key.eq(&address) => Ok(Some(data)),
+                _ => Err(invalid_address.into()),
+            };
+        }
+    }
+
+    let (address, _) = Pubkey::find_program_address(seeds, &id());
+    if !account_info.key.eq(&address) {
+        return Err(invalid_address.into());
+    }
+    Ok(None)
+}
+
+/// Loads the asset account of the Synchronizer and fiat asset mint.
+/// Returns `None` when no asset caps were set, the account then isn't created yet.
+pub fn load_asset(
+    asset_account_info: &AccountInfo,
+    synchronizer_key: &Pubkey,
+    fiat_asset_mint: &Pubkey,
+) -> Result<Option<AssetData>, ProgramError> {
+    let seeds: &[&[u8]] = &[ASSET_SEED, synchronizer_key.as_ref(), fiat_asset_mint.as_ref()];
+    load_program_account(asset_account_info, seeds, |asset: &AssetData| asset.bump_seed, SynchronizerError::InvalidAssetAccount)
+}
+
+/// Loads the rate limit account of the Synchronizer, `None` when no rate limits were set
+pub fn load_rate_limit(rate_limit_account_info: &AccountInfo, synchronizer_key: &Pubkey) -> Result<Option<RateLimitData>, ProgramError> {
+    let seeds: &[&[u8]] = &[RATE_LIMIT_SEED, synchronizer_key.as_ref()];
+    load_program_account(rate_limit_account_info, seeds, |rate_limit: &RateLimitData| rate_limit.bump_seed, SynchronizerError::InvalidRateLimitAccount)
+}
+
+/// Loads the volume account of a user, `None` before the first trade under a user limit
+pub fn load_user_volume(
+    user_volume_account_info: &AccountInfo,
+    synchronizer_key: &Pubkey,
+    user: &Pubkey,
+) -> Result<Option<UserVolumeData>, ProgramError> {
+    let seeds: &[&[u8]] = &[USER_VOLUME_SEED, synchronizer_key.as_ref(), user.as_ref()];
+    load_program_account(user_volume_account_info, seeds, |user_volume: &UserVolumeData| user_volume.bump_seed, SynchronizerError::InvalidUserVolumeAccount)
+}
+
+/// Loads the position account of a user in a fiat asset, `None` while the user has no position account
+pub fn load_user_position(
+    user_position_account_info: &AccountInfo,
+    synchronizer_key: &Pubkey,
+    user: &Pubkey,
+    fiat_asset_mint: &Pubkey,
+) -> Result<Option<UserPositionData>, ProgramError> {
+    let seeds: &[&[u8]] = &[USER_POSITION_SEED, synchronizer_key.as_ref(), user.as_ref(), fiat_asset_mint.as_ref()];
+    load_program_account(user_position_account_info, seeds, |position: &UserPositionData| position.bump_seed, SynchronizerError::InvalidUserPositionAccount)
+}
+
+/// Loads the market schedule account of the Synchronizer and fiat asset mint, `None` when the asset has no schedule
+pub fn load_market_schedule(
+    market_schedule_account_info: &AccountInfo,
+    synchronizer_key: &Pubkey,
+    fiat_asset_mint: &Pubkey,
+) -> Result<Option<MarketScheduleData>, ProgramError> {
+    let seeds: &[&[u8]] = &[MARKET_SCHEDULE_SEED, synchronizer_key.as_ref(), fiat_asset_mint.as_ref()];
+    load_program_account(market_schedule_account_info, seeds, |schedule: &MarketScheduleData| schedule.bump_seed, SynchronizerError::InvalidMarketScheduleAccount)
+}
+
+/// Loads the fee schedule account of the Synchronizer, `None` while no fee schedule was set
+pub fn load_fee_schedule(fee_schedule_account_info: &AccountInfo, synchronizer_key: &Pubkey) -> Result<Option<FeeScheduleData>, ProgramError> {
+    let seeds: &[&[u8]] = &[FEE_SCHEDULE_SEED, synchronizer_key.as_ref()];
+    load_program_account(fee_schedule_account_info, seeds, |schedule: &FeeScheduleData| schedule.bump_seed, SynchronizerError::InvalidFeeScheduleAccount)
+}
+
+/// Loads the collateral set account of the Synchronizer, `None` while no collateral was added
+pub fn load_collateral_set(collateral_set_account_info: &AccountInfo, synchronizer_key: &Pubkey) -> Result<Option<CollateralSetData>, ProgramError> {
+    let seeds: &[&[u8]] = &[COLLATERAL_SET_SEED, synchronizer_key.as_ref()];
+    load_program_account(collateral_set_account_info, seeds, |set: &CollateralSetData| set.bump_seed, SynchronizerError::InvalidCollateralSetAccount)
+}
+
+/// Loads an initialized and not frozen token account, optionally checking its owner
+pub fn load_token_account(
+    token_account_info: &AccountInfo,
+    expected_owner: Option<&Pubkey>,
+) -> Result<Account, ProgramError> {
+    if !token_account_info.owner.eq(&spl_token::id()) {
+        return Err(SynchronizerError::InvalidAccountOwner.into());
+    }
+
+    let token_account = Account::unpack_unchecked(&token_account_info.data.borrow())
+        .map_err(|_| SynchronizerError::InvalidTokenAccount)?;
+    if !token_account.is_initialized() {
+        return Err(SynchronizerError::UninitializedTokenAccount.into());
+    }
+    if token_account.is_frozen() {
+        return Err(SynchronizerError::FrozenTokenAccount.into());
+    }
+    if let Some(owner) = expected_owner {
+        if !token_account.owner.eq(owner) {
+            return Err(SynchronizerError::OwnerMismatch.into());
+        }
+    }
+    Ok(token_account)
+}
+
+/// Loads a token account of the Synchronizer collateral token
+pub fn load_collateral_account(
+    token_account_info: &AccountInfo,
+    collateral_token_key: &Pubkey,
+    expected_owner: Option<&Pubkey>,
+) -> Result<Account, ProgramError> {
+    let token_account = load_token_account(token_account_info, expected_owner)?;
+    if !token_account.mint.eq(collateral_token_key) {
+        return Err(SynchronizerError::BadCollateralMint.into());
+    }
+    Ok(token_account)
+}
+
+/// Loads a token account of the fiat asset
+pub fn load_fiat_account(
+    token_account_info: &AccountInfo,
+    fiat_mint_key: &Pubkey,
+    expected_owner: Option<&Pubkey>,
+) -> Result<Account, ProgramError> {
+    let token_account = load_token_account(token_account_info, expected_owner)?;
+    if !token_account.mint.eq(fiat_mint_key) {
+        return Err(SynchronizerError::BadFiatMint.into());
+    }
+    Ok(token_account)
+}
+
+/// Loads an initialized token mint
+pub fn load_mint(mint_info: &AccountInfo) -> Result<Mint, ProgramError> {
+    if !mint_info.owner.eq(&spl_token::id()) {
+        return Err(SynchronizerError::InvalidAccountOwner.into());
+    }
+
+    let mint = Mint::unpack_unchecked(&mint_info.data.borrow())
+        .map_err(|_| SynchronizerError::InvalidMint)?;
+    if !mint.is_initialized() {
+        return Err(SynchronizerError::UninitializedMint.into());
+    }
+    Ok(mint)
+}
+
+/// Loads the collateral token mint of a Synchronizer
+pub fn load_collateral_mint(
+    mint_info: &AccountInfo,
+    collateral_token_key: &Pubkey,
+) -> Result<Mint, ProgramError> {
+    if !mint_info.key.eq(collateral_token_key) {
+        return Err(SynchronizerError::BadCollateralMint.into());
+    }
+    load_mint(mint_info)
+}
+
+/// Loads a fiat asset mint, which must be minted by the Synchronizer
+pub fn load_fiat_mint(
+    mint_info: &AccountInfo,
+    synchronizer_key: &Pubkey,
+) -> Result<Mint, ProgramError> {
+    let mint = load_mint(mint_info)?;
+    match mint.mint_authority {
+        COption::Some(authority) if authority.eq(synchronizer_key) => Ok(mint),
+        _ => Err(SynchronizerError::BadMintAuthority.into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use borsh::BorshSerialize;
+    use spl_token::state::AccountState;
+    use crate::state::{ACCOUNT_DISCRIMINATOR_LEN, AccountType, SYNCHRONIZER_DATA_V1_LEN};
+    use super::*;
+
+    fn token_account_data(mint: &Pubkey, owner: &Pubkey, state: AccountState) -> Vec<u8> {
+        let mut data = vec![0; Account::get_packed_len()];
+        let account = Account {
+            mint: *mint,
+            owner: *owner,
+            state,
+            ..Account::default()
+        };
+        Account::pack(account, &mut data).unwrap();
+        data
+    }
+
+    fn mint_data(mint_authority: COption<Pubkey>, decimals: u8) -> Vec<u8> {
+        let mut data = vec![0; Mint::get_packed_len()];
+        let mint = Mint {
+            mint_authority,
+            decimals,
+            is_initialized: true,
+            ..Mint::default()
+        };
+        Mint::pack(mint, &mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_load_token_account() {
+        let key = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let token_program = spl_token::id();
+        let fake_program = Pubkey::new_unique();
+        let mut lamports = 0;
+
+        let mut data = token_account_data(&mint, &owner, AccountState::Initialized);
+        let account_info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &token_program, false, 0);
+        assert_eq!(load_collateral_account(&account_info, &mint, Some(&owner)).unwrap().owner, owner);
+        assert_eq!(load_fiat_account(&account_info, &mint, None).unwrap().mint, mint);
+        assert_eq!(
+            Err(SynchronizerError::OwnerMismatch.into()),
+            load_token_account(&account_info, Some(&Pubkey::new_unique()))
+        );
+        assert_eq!(
+            Err(SynchronizerError::BadCollateralMint.into()),
+            load_collateral_account(&account_info, &Pubkey::new_unique(), Some(&owner))
+        );
+        assert_eq!(
+            Err(SynchronizerError::BadFiatMint.into()),
+            load_fiat_account(&account_info, &Pubkey::new_unique(), Some(&owner))
+        );
+
+        let mut lamports = 0;
+        let mut data = token_account_data(&mint, &owner, AccountState::Initialized);
+        let account_info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &fake_program, false, 0);
+        assert_eq!(
+            Err(SynchronizerError::InvalidAccountOwner.into()),
+            load_token_account(&account_info, None)
+        );
+
+        let mut lamports = 0;
+        let mut data = token_account_data(&mint, &owner, AccountState::Uninitialized);
+        let account_info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &token_program, false, 0);
+        assert_eq!(
+            Err(SynchronizerError::UninitializedTokenAccount.into()),
+            load_token_account(&account_info, None)
+        );
+
+        let mut lamports = 0;
+        let mut data = token_account_data(&mint, &owner, AccountState::Frozen);
+        let account_info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &token_program, false, 0);
+        assert_eq!(
+            Err(SynchronizerError::FrozenTokenAccount.into()),
+            load_token_account(&account_info, None)
+        );
+
+        let mut lamports = 0;
+        let mut data = mint_data(COption::Some(owner), 9);
+        let account_info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &token_program, false, 0);
+        assert_eq!(
+            Err(SynchronizerError::InvalidTokenAccount.into()),
+            load_token_account(&account_info, None)
+        );
+    }
+
+    #[test]
+    fn test_load_mint() {
+        let key = Pubkey::new_unique();
+        let synchronizer_key = Pubkey::new_unique();
+        let token_program = spl_token::id();
+        let fake_program = Pubkey::new_unique();
+
+        let mut lamports = 0;
+        let mut data = mint_data(COption::Some(synchronizer_key), 9);
+        let mint_info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &token_program, false, 0);
+        assert_eq!(load_fiat_mint(&mint_info, &synchronizer_key).unwrap().decimals, 9);
+        assert_eq!(
+            Err(SynchronizerError::BadMintAuthority.into()),
+            load_fiat_mint(&mint_info, &Pubkey::new_unique())
+        );
+        // Collateral mints of any authority
+        assert_eq!(load_collateral_mint(&mint_info, &key).unwrap().decimals, 9);
+        assert_eq!(
+            Err(SynchronizerError::BadCollateralMint.into()),
+            load_collateral_mint(&mint_info, &Pubkey::new_unique())
+        );
+
+        let mut lamports = 0;
+        let mut data = mint_data(COption::Some(synchronizer_key), 6);
+        let mint_info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &token_program, false, 0);
+        assert_eq!(load_fiat_mint(&mint_info, &synchronizer_key).unwrap().decimals, 6);
+
+        let mut lamports = 0;
+        let mut data = mint_data(COption::None, 9);
+        let mint_info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &token_program, false, 0);
+        assert_eq!(
+            Err(SynchronizerError::BadMintAuthority.into()),
+            load_fiat_mint(&mint_info, &synchronizer_key)
+        );
+
+        let mut lamports = 0;
+        let mut data = mint_data(COption::Some(synchronizer_key), 9);
+        let mint_info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &fake_program, false, 0);
+        assert_eq!(
+            Err(SynchronizerError::InvalidAccountOwner.into()),
+            load_mint(&mint_info)
+        );
+
+        let mut lamports = 0;
+        let mut data = vec![0; Mint::get_packed_len()];
+        let mint_info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &token_program, false, 0);
+        assert_eq!(
+            Err(SynchronizerError::UninitializedMint.into()),
+            load_mint(&mint_info)
+        );
+
+        let mut lamports = 0;
+        let mut data = vec![0; Account::get_packed_len()];
+        let mint_info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &token_program, false, 0);
+        assert_eq!(
+            Err(SynchronizerError::InvalidMint.into()),
+            load_mint(&mint_info)
+        );
+    }
+
+    #[test]
+    fn test_check_token_program() {
+        let token_program_key = spl_token::id();
+        let fake_program_key = Pubkey::new_unique();
+        let owner = Pubkey::default();
+        let mut lamports = 0;
+        let mut data = vec![];
+        let token_program_info = AccountInfo::new(&token_program_key, false, false, &mut lamports, &mut data, &owner, true, 0);
+        check_token_program(&token_program_info).unwrap();
+
+        let mut lamports = 0;
+        let mut data = vec![];
+        let fake_program_info = AccountInfo::new(&fake_program_key, false, false, &mut lamports, &mut data, &owner, true, 0);
+        assert_eq!(
+            Err(SynchronizerError::InvalidTokenProgram.into()),
+            check_token_program(&fake_program_info)
+        );
+    }
+
+    #[test]
+    fn test_load_synchronizer() {
+        let key = Pubkey::new_unique();
+        let program_id = id();
+        let fake_program = Pubkey::new_unique();
+
+        let mut lamports = 0;
+        let mut data = vec![0; SynchronizerData::get_packed_len()];
+        let synchronizer_info = AccountInfo::new(&key, true, true, &mut lamports, &mut data, &program_id, false, 0);
+        check_synchronizer_authority(&synchronizer_info).unwrap();
+        assert_eq!(
+            Err(SynchronizerError::NotInitialized.into()),
+            load_synchronizer(&synchronizer_info)
+        );
+
+        let mut lamports = 0;
+        let mut data = vec![0; SynchronizerData::get_packed_len()];
+        let synchronizer = SynchronizerData {
+            is_initialized: true,
+            ..SynchronizerData::default()
+        };
+        SynchronizerData::pack(synchronizer, &mut data).unwrap();
+        let synchronizer_info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &program_id, false, 0);
+        assert_eq!(load_synchronizer(&synchronizer_info).unwrap(), synchronizer);
+        assert_eq!(
+            Err(SynchronizerError::InvalidSigner.into()),
+            check_synchronizer_authority(&synchronizer_info)
+        );
+
+        let mut lamports = 0;
+        let mut data = vec![0; SynchronizerData::get_packed_len()];
+        let synchronizer_info = AccountInfo::new(&key, true, true, &mut lamports, &mut data, &fake_program, false, 0);
+        assert_eq!(
+            Err(SynchronizerError::AccessDenied.into()),
+            check_synchronizer_authority(&synchronizer_info)
+        );
+        assert_eq!(
+            Err(SynchronizerError::AccessDenied.into()),
+            load_synchronizer(&synchronizer_info)
+        );
+
+        // Other account type
+        let mut lamports = 0;
+        let mut data = vec![0; SynchronizerData::get_packed_len()];
+        data[..ACCOUNT_DISCRIMINATOR_LEN].copy_from_slice(&AccountType::UserPosition.discriminator());
+        let synchronizer_info = AccountInfo::new(&key, true, true, &mut lamports, &mut data, &program_id, false, 0);
+        assert_eq!(
+            Err(SynchronizerError::InvalidAccountType.into()),
+            load_synchronizer(&synchronizer_info)
+        );
+
+        // Version 1 layout
+        let mut lamports = 0;
+        let mut data = synchronizer.try_to_vec().unwrap()[..SYNCHRONIZER_DATA_V1_LEN].to_vec();
+        let synchronizer_info = AccountInfo::new(&key, true, true, &mut lamports, &mut data, &program_id, false, 0);
+        assert_eq!(
+            Err(SynchronizerError::MigrationRequired.into()),
+            load_synchronizer(&synchronizer_info)
+        );
+    }
+
+    #[test]
+    fn test_load_asset() {
+        let synchronizer_key = Pubkey::new_unique();
+        let fiat_asset_mint = Pubkey::new_unique();
+        let (key, bump_seed) = AssetData::find_address(&synchronizer_key, &fiat_asset_mint);
+        let program_id = id();
+        let system_program = solana_program::system_program::id();
+
+        // Not created yet
+        let mut lamports = 0;
+        let mut data = vec![];
+        let asset_info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &system_program, false, 0);
+        assert_eq!(load_asset(&asset_info, &synchronizer_key, &fiat_asset_mint).unwrap(), None);
+
+        let mut lamports = 0;
+        let mut data = vec![0; AssetData::get_packed_len()];
+        let asset_info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &program_id, false, 0);
+        assert_eq!(load_asset(&asset_info, &synchronizer_key, &fiat_asset_mint).unwrap(), None);
+
+        let mut lamports = 0;
+        let mut data = vec![0; AssetData::get_packed_len()];
+        let asset = AssetData {
+            is_initialized: true,
+            synchronizer: synchronizer_key,
+            fiat_asset_mint,
+            max_outstanding_notional: 100,
+            max_transaction_notional: 10,
+            outstanding_notional: 0,
+            bump_seed,
+            ..AssetData::default()
+        };
+        AssetData::pack(asset, &mut data).unwrap();
+        let asset_info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &program_id, false, 0);
+        assert_eq!(load_asset(&asset_info, &synchronizer_key, &fiat_asset_mint).unwrap(), Some(asset));
+
+        // BadCase: account of another asset
+        assert_eq!(
+            Err(SynchronizerError::InvalidAssetAccount.into()),
+            load_asset(&asset_info, &synchronizer_key, &Pubkey::new_unique())
+        );
+
+        // BadCase: stored bump seed doesn't derive the asset address
+        let mut lamports = 0;
+        let mut data = vec![0; AssetData::get_packed_len()];
+        AssetData::pack(AssetData { bump_seed: bump_seed.wrapping_sub(1), ..asset }, &mut data).unwrap();
+        let asset_info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &program_id, false, 0);
+        assert_eq!(
+            Err(SynchronizerError::InvalidAssetAccount.into()),
+            load_asset(&asset_info, &synchronizer_key, &fiat_asset_mint)
+        );
+
+        // BadCase: other account type at the asset address
+        let mut lamports = 0;
+        let mut data = vec![0; AssetData::get_packed_len()];
+        data[..ACCOUNT_DISCRIMINATOR_LEN].copy_from_slice(&AccountType::UserPosition.discriminator());
+        let asset_info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &program_id, false, 0);
+        assert_eq!(
+            Err(SynchronizerError::InvalidAccountType.into()),
+            load_asset(&asset_info, &synchronizer_key, &fiat_asset_mint)
+        );
+    }
+
+    #[test]
+    fn test_load_volume_accounts() {
+        let synchronizer_key = Pubkey::new_unique();
+        let user = Pubkey::new_unique();
+        let (rate_limit_key, _) = RateLimitData::find_address(&synchronizer_key);
+        let (user_volume_key, bump_seed) = UserVolumeData::find_address(&synchronizer_key, &user);
+        let program_id = id();
+
+        let mut lamports = 0;
+        let mut data = vec![0; RateLimitData::get_packed_len()];
+        let rate_limit_info = AccountInfo::new(&rate_limit_key, false, true, &mut lamports, &mut data, &program_id, false, 0);
+        assert_eq!(load_rate_limit(&rate_limit_info, &synchronizer_key).unwrap(), None);
+        // BadCase: rate limit account of another Synchronizer
+        assert_eq!(
+            Err(SynchronizerError::InvalidRateLimitAccount.into()),
+            load_rate_limit(&rate_limit_info, &user)
+        );
+
+        let mut lamports = 0;
+        let mut data = vec![0; UserVolumeData::get_packed_len()];
+        let user_volume = UserVolumeData {
+            is_initialized: true,
+            synchronizer: synchronizer_key,
+            user,
+            bump_seed,
+            ..UserVolumeData::default()
+        };
+        UserVolumeData::pack(user_volume, &mut data).unwrap();
+        let user_volume_info = AccountInfo::new(&user_volume_key, false, true, &mut lamports, &mut data, &program_id, false, 0);
+        assert_eq!(load_user_volume(&user_volume_info, &synchronizer_key, &user).unwrap(), Some(user_volume));
+        // BadCase: volume account of another user
+        assert_eq!(
+            Err(SynchronizerError::InvalidUserVolumeAccount.into()),
+            load_user_volume(&user_volume_info, &synchronizer_key, &Pubkey::new_unique())
+        );
+    }
+    #[test]
+    fn test_load_user_position() {
+        let synchronizer_key = Pubkey::new_unique();
+        let user = Pubkey::new_unique();
+        let fiat_asset_mint = Pubkey::new_unique();
+        let (user_position_key, bump_seed) = UserPositionData::find_address(&synchronizer_key, &user, &fiat_asset_mint);
+        let program_id = id();
+        let system_program_id = Pubkey::default();
+
+        // Not created yet
+        let mut lamports = 0;
+        let mut data = vec![];
+        let user_position_info = AccountInfo::new(&user_position_key, false, true, &mut lamports, &mut data, &system_program_id, false, 0);
+        assert_eq!(load_user_position(&user_position_info, &synchronizer_key, &user, &fiat_asset_mint).unwrap(), None);
+
+        let mut lamports = 0;
+        let mut data = vec![0; UserPositionData::get_packed_len()];
+        let user_position = UserPositionData {
+            is_initialized: true,
+            synchronizer: synchronizer_key,
+            user,
+            fiat_asset_mint,
+            bump_seed,
+            ..UserPositionData::default()
+        };
+        UserPositionData::pack(user_position, &mut data).unwrap();
+        let user_position_info = AccountInfo::new(&user_position_key, false, true, &mut lamports, &mut data, &program_id, false, 0);
+        assert_eq!(load_user_position(&user_position_info, &synchronizer_key, &user, &fiat_asset_mint).unwrap(), Some(user_position));
+        // BadCase: position account of another fiat asset
+        assert_eq!(
+            Err(SynchronizerError::InvalidUserPositionAccount.into()),
+            load_user_position(&user_position_info, &synchronizer_key, &user, &Pubkey::new_unique())
+        );
+    }
+}
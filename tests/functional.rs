@@ -1,7 +1,8 @@
-use solana_program::{hash::Hash, instruction::InstructionError, program_pack::Pack, system_instruction};
-use synchronizer::{error::SynchronizerError, processor::Processor, processor::id, state::SynchronizerData};
+use borsh::BorshSerialize;
+use solana_program::{hash::Hash, instruction::{Instruction, InstructionError}, program_option::COption, program_pack::Pack, system_instruction};
+use synchronizer::{error::SynchronizerError, instruction::TRADE_PROGRAM_ACCOUNTS, processor::Processor, processor::id, quote::FEE_DECIMALS, state::{AcceptedCollateral, AssetData, CollateralSetData, FeeScheduleData, FeeTier, MarketScheduleData, RateBounds, RateLimitData, SYNCHRONIZER_DATA_V1_LEN, SynchronizerData, TradingHours, UserPositionData}};
 use solana_program_test::*;
-use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::{Signer, SignerError}, transaction::{Transaction, TransactionError}, transport::TransportError};
+use solana_sdk::{account::Account, pubkey::Pubkey, signature::Keypair, signer::{Signer, SignerError}, transaction::{Transaction, TransactionError}, transport::TransportError};
 
 async fn create_mint(
     banks_client: &mut BanksClient,
@@ -151,6 +152,7 @@ async fn withdraw_fee(
                 &synchronizer_collateral_token_account,
                 &recipient_collateral_token_account,
                 &synchronizer_authority.pubkey(),
+                false,
             )
             .unwrap()
         ],
@@ -177,6 +179,7 @@ async fn withdraw_collateral(
                 &synchronizer_collateral_token_account,
                 &recipient_collateral_token_account,
                 &synchronizer_authority.pubkey(),
+                false,
             )
             .unwrap()
         ],
@@ -231,6 +234,21 @@ async fn set_remaining_dollar_cap(
     Ok(())
 }
 
+async fn process_signed(
+    banks_client: &mut BanksClient,
+    payer: &Keypair,
+    recent_blockhash: &Hash,
+    instruction: Instruction,
+    signers: &[&Keypair],
+) -> Result<(), TransportError> {
+    let mut transaction = Transaction::new_with_payer(&[instruction], Some(&payer.pubkey()));
+    let mut keypairs = vec![payer];
+    keypairs.extend_from_slice(signers);
+    transaction.sign(&keypairs, *recent_blockhash);
+    banks_client.process_transaction(transaction).await?;
+    Ok(())
+}
+
 async fn set_minimum_required_signature(
     banks_client: &mut BanksClient,
     payer: &Keypair,
@@ -305,7 +323,9 @@ async fn sell_for(
                 user_fiat_token_account,
                 synchronizer_collateral_token_account,
                 &user_authority.pubkey(),
-                &synchronizer_authority.pubkey()
+                &synchronizer_authority.pubkey(),
+                TRADE_PROGRAM_ACCOUNTS,
+                None
             )
             .unwrap()
         ],
@@ -346,7 +366,9 @@ async fn buy_for(
                 user_fiat_token_account,
                 synchronizer_collateral_token_account,
                 &user_authority.pubkey(),
-                &synchronizer_authority.pubkey()
+                &synchronizer_authority.pubkey(),
+                TRADE_PROGRAM_ACCOUNTS,
+                None
             )
             .unwrap()
         ],
@@ -357,12 +379,59 @@ async fn buy_for(
     Ok(())
 }
 
+async fn migrate_synchronizer_account(
+    banks_client: &mut BanksClient,
+    payer: &Keypair,
+    recent_blockhash: &Hash,
+    synchronizer_data_rent: u64,
+    authority_accounts: &[Pubkey],
+    synchronizer_authority: &Keypair,
+    new_synchronizer_authority: &Keypair,
+) -> Result<(), TransportError> {
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &new_synchronizer_authority.pubkey(),
+                synchronizer_data_rent,
+                synchronizer::state::SynchronizerData::LEN as u64,
+                &id(),
+            ),
+            synchronizer::instruction::migrate_synchronizer_account(
+                &id(),
+                &synchronizer_authority.pubkey(),
+                &new_synchronizer_authority.pubkey(),
+                authority_accounts,
+            )
+            .unwrap()
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[payer, synchronizer_authority, new_synchronizer_authority], *recent_blockhash);
+    banks_client.process_transaction(transaction).await?;
+    Ok(())
+}
+
 async fn get_token_balance(banks_client: &mut BanksClient, token_account: &Pubkey) -> u64 {
     let account = banks_client.get_account(*token_account).await.unwrap().unwrap();
     let account_data= spl_token::state::Account::unpack_from_slice(account.data.as_slice()).unwrap();
     account_data.amount
 }
 
+async fn get_asset_data(banks_client: &mut BanksClient, synchronizer_key: &Pubkey, fiat_asset_mint: &Pubkey) -> AssetData {
+    let (address, _) = AssetData::find_address(synchronizer_key, fiat_asset_mint);
+    let account = banks_client.get_account(address).await.unwrap().unwrap();
+    assert_eq!(account.owner, id());
+    AssetData::unpack(&account.data).unwrap()
+}
+
+async fn get_rate_limit_data(banks_client: &mut BanksClient, synchronizer_key: &Pubkey) -> RateLimitData {
+    let (address, _) = RateLimitData::find_address(synchronizer_key);
+    let account = banks_client.get_account(address).await.unwrap().unwrap();
+    assert_eq!(account.owner, id());
+    RateLimitData::unpack(&account.data).unwrap()
+}
+
 async fn get_synchronizer_data(banks_client: &mut BanksClient, synchronizer_key: &Pubkey) -> SynchronizerData {
     let synch_acc = banks_client.get_account(*synchronizer_key).await.unwrap().unwrap();
     assert_eq!(id(), synch_acc.owner);
@@ -713,6 +782,7 @@ async fn test_synchronizer_public_api() {
 
     // Case: change collateral token key
     let new_collateral_token_key = Keypair::new();
+    create_mint(&mut banks_client, &payer, &recent_blockhash, &new_collateral_token_key, mint_rent, &collateral_owner_key.pubkey(), decimals).await.unwrap();
     set_collateral_token(&mut banks_client, &payer, &recent_blockhash, &new_collateral_token_key.pubkey(), &synchronizer_key).await.unwrap();
 
     assert_eq!(
@@ -825,7 +895,9 @@ async fn test_synchronizer_public_api() {
                 &fake_user_fiat_acc.pubkey(), // bad acc
                 &synchronizer_collateral_account.pubkey(),
                 &user_key.pubkey(),
-                &synchronizer_key.pubkey()
+                &synchronizer_key.pubkey(),
+                TRADE_PROGRAM_ACCOUNTS,
+                None
             )
             .unwrap(),
         ],
@@ -834,7 +906,7 @@ async fn test_synchronizer_public_api() {
     transaction.sign(&[&payer, &user_key, &synchronizer_key, oracles[0]], recent_blockhash);
 
     assert_eq!(
-        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::AccessDenied as u32)),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::OwnerMismatch as u32)),
         banks_client.process_transaction(transaction).await.unwrap_err().unwrap()
     );
 
@@ -852,7 +924,9 @@ async fn test_synchronizer_public_api() {
                 &fake_user_fiat_acc.pubkey(), // bad acc
                 &synchronizer_collateral_account.pubkey(),
                 &user_key.pubkey(),
-                &synchronizer_key.pubkey()
+                &synchronizer_key.pubkey(),
+                TRADE_PROGRAM_ACCOUNTS,
+                None
             )
             .unwrap(),
         ],
@@ -861,7 +935,7 @@ async fn test_synchronizer_public_api() {
     transaction.sign(&[&payer, &user_key, &synchronizer_key, oracles[0]], recent_blockhash);
 
     assert_eq!(
-        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::AccessDenied as u32)),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::OwnerMismatch as u32)),
         banks_client.process_transaction(transaction).await.unwrap_err().unwrap()
     );
 
@@ -888,7 +962,9 @@ async fn test_synchronizer_public_api() {
                 &user_fiat_account.pubkey(),
                 &synchronizer_collateral_account.pubkey(),
                 &user_key.pubkey(),
-                &synchronizer_key.pubkey()
+                &synchronizer_key.pubkey(),
+                TRADE_PROGRAM_ACCOUNTS,
+                None
             )
             .unwrap(),
         ],
@@ -897,7 +973,7 @@ async fn test_synchronizer_public_api() {
     transaction.sign(&[&payer, &user_key, &synchronizer_key, oracles[0]], recent_blockhash);
 
     assert_eq!(
-        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::AccessDenied as u32)),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::OwnerMismatch as u32)),
         banks_client.process_transaction(transaction).await.unwrap_err().unwrap()
     );
 
@@ -915,7 +991,9 @@ async fn test_synchronizer_public_api() {
                 &user_fiat_account.pubkey(),
                 &synchronizer_collateral_account.pubkey(),
                 &user_key.pubkey(),
-                &synchronizer_key.pubkey()
+                &synchronizer_key.pubkey(),
+                TRADE_PROGRAM_ACCOUNTS,
+                None
             )
             .unwrap(),
         ],
@@ -924,7 +1002,7 @@ async fn test_synchronizer_public_api() {
     transaction.sign(&[&payer, &user_key, &synchronizer_key, oracles[0]], recent_blockhash);
 
     assert_eq!(
-        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::AccessDenied as u32)),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::OwnerMismatch as u32)),
         banks_client.process_transaction(transaction).await.unwrap_err().unwrap()
     );
 
@@ -952,7 +1030,9 @@ async fn test_synchronizer_public_api() {
                 &user_fiat_account.pubkey(),
                 &fake_synch_collateral_acc.pubkey(), // // bad acc
                 &user_key.pubkey(),
-                &synchronizer_key.pubkey()
+                &synchronizer_key.pubkey(),
+                TRADE_PROGRAM_ACCOUNTS,
+                None
             )
             .unwrap(),
         ],
@@ -961,7 +1041,7 @@ async fn test_synchronizer_public_api() {
     transaction.sign(&[&payer, &user_key, &synchronizer_key, oracles[0]], recent_blockhash);
 
     assert_eq!(
-        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::AccessDenied as u32)),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::OwnerMismatch as u32)),
         banks_client.process_transaction(transaction).await.unwrap_err().unwrap()
     );
 
@@ -979,7 +1059,9 @@ async fn test_synchronizer_public_api() {
                 &user_fiat_account.pubkey(),
                 &fake_synch_collateral_acc.pubkey(), // bad acc
                 &user_key.pubkey(),
-                &synchronizer_key.pubkey()
+                &synchronizer_key.pubkey(),
+                TRADE_PROGRAM_ACCOUNTS,
+                None
             )
             .unwrap(),
         ],
@@ -988,7 +1070,7 @@ async fn test_synchronizer_public_api() {
     transaction.sign(&[&payer, &user_key, &synchronizer_key, oracles[0]], recent_blockhash);
 
     assert_eq!(
-        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::AccessDenied as u32)),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::OwnerMismatch as u32)),
         banks_client.process_transaction(transaction).await.unwrap_err().unwrap()
     );
 
@@ -1008,7 +1090,9 @@ async fn test_synchronizer_public_api() {
                 &user_fiat_account.pubkey(),
                 &synchronizer_collateral_account.pubkey(),
                 &user_key.pubkey(),
-                &synchronizer_key.pubkey()
+                &synchronizer_key.pubkey(),
+                TRADE_PROGRAM_ACCOUNTS,
+                None
             )
             .unwrap(),
         ],
@@ -1035,7 +1119,9 @@ async fn test_synchronizer_public_api() {
                 &user_fiat_account.pubkey(),
                 &synchronizer_collateral_account.pubkey(),
                 &user_key.pubkey(),
-                &synchronizer_key.pubkey()
+                &synchronizer_key.pubkey(),
+                TRADE_PROGRAM_ACCOUNTS,
+                None
             )
             .unwrap(),
         ],
@@ -1082,15 +1168,16 @@ async fn test_synchronizer_public_api() {
 
 #[tokio::test]
 async fn test_synchronizer_admin_setters() {
-    let program_test = ProgramTest::new(
+    let mut program_test = ProgramTest::new(
         "synchronizer",
         id(),
         processor!(Processor::process_instruction),
     );
+    let collateral_token_key = add_collateral_mint(&mut program_test, 9);
+    let new_token_key = add_collateral_mint(&mut program_test, 9);
 
     let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
     let synchronizer_key = Keypair::new();
-    let collateral_token_key = Keypair::new();
 
     let rent = banks_client.get_rent().await.unwrap();
     let synchronizer_data_rent = rent.minimum_balance(SynchronizerData::LEN);
@@ -1110,7 +1197,7 @@ async fn test_synchronizer_admin_setters() {
         &payer,
         &recent_blockhash,
         synchronizer_data_rent,
-        &collateral_token_key.pubkey(),
+        &collateral_token_key,
         remaining_dollar_cap,
         withdrawable_fee_amount,
         oracles.len() as u8,
@@ -1120,7 +1207,8 @@ async fn test_synchronizer_admin_setters() {
 
     let synchronizer = get_synchronizer_data(&mut banks_client, &synchronizer_key.pubkey()).await;
     assert_eq!(synchronizer.is_initialized, true);
-    assert_eq!(synchronizer.collateral_token_key, collateral_token_key.pubkey());
+    assert_eq!(synchronizer.collateral_token_key, collateral_token_key);
+    assert_eq!(synchronizer.collateral_decimals, Some(9));
     assert_eq!(synchronizer.remaining_dollar_cap, 500_000_000_000);
     assert_eq!(synchronizer.withdrawable_fee_amount, 0);
     assert_eq!(synchronizer.minimum_required_signature, 2);
@@ -1129,20 +1217,20 @@ async fn test_synchronizer_admin_setters() {
 
     set_remaining_dollar_cap(&mut banks_client, &payer, &recent_blockhash, 123500_000_000_000, &synchronizer_key).await.unwrap();
     let synchronizer = get_synchronizer_data(&mut banks_client, &synchronizer_key.pubkey()).await;
-    assert_eq!(synchronizer.collateral_token_key, collateral_token_key.pubkey());
+    assert_eq!(synchronizer.collateral_token_key, collateral_token_key);
     assert_eq!(synchronizer.remaining_dollar_cap, 123500_000_000_000);
     assert_eq!(synchronizer.minimum_required_signature, 2);
 
     set_minimum_required_signature(&mut banks_client, &payer, &recent_blockhash, 3, &synchronizer_key).await.unwrap();
     let synchronizer = get_synchronizer_data(&mut banks_client, &synchronizer_key.pubkey()).await;
-    assert_eq!(synchronizer.collateral_token_key, collateral_token_key.pubkey());
+    assert_eq!(synchronizer.collateral_token_key, collateral_token_key);
     assert_eq!(synchronizer.remaining_dollar_cap, 123500_000_000_000);
     assert_eq!(synchronizer.minimum_required_signature, 3);
 
-    let new_token_key = Pubkey::new_unique();
     set_collateral_token(&mut banks_client, &payer, &recent_blockhash, &new_token_key, &synchronizer_key).await.unwrap();
     let synchronizer = get_synchronizer_data(&mut banks_client, &synchronizer_key.pubkey()).await;
     assert_eq!(synchronizer.collateral_token_key, new_token_key);
+    assert_eq!(synchronizer.collateral_decimals, Some(9));
     assert_eq!(synchronizer.remaining_dollar_cap, 123500_000_000_000);
     assert_eq!(synchronizer.minimum_required_signature, 3);
 
@@ -1251,7 +1339,7 @@ async fn test_synchronizer_admin_setters() {
         &[
             synchronizer::instruction::initialize_synchronizer_account(
                 &id(),
-                &collateral_token_key.pubkey(),
+                &collateral_token_key,
                 remaining_dollar_cap,
                 withdrawable_fee_amount,
                 3,
@@ -1283,144 +1371,683 @@ async fn test_synchronizer_admin_setters() {
     )
 }
 
+// Native program tests can't run the token program, tests without token transfers start with an
+// initialized collateral mint.
+fn add_collateral_mint(program_test: &mut ProgramTest, decimals: u8) -> Pubkey {
+    let address = Pubkey::new_unique();
+    let mut data = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint { decimals, is_initialized: true, ..Default::default() }.pack_into_slice(&mut data);
+    program_test.add_account(address, Account {
+        lamports: 1_000_000_000,
+        data,
+        owner: spl_token::id(),
+        executable: false,
+        rent_epoch: 0,
+    });
+    address
+}
+
+// Native program tests can't resize accounts in a CPI, the system program creates asset accounts
+// only in BPF tests. Other tests start with an allocated asset account.
+fn add_asset_account(program_test: &mut ProgramTest, synchronizer_key: &Pubkey, fiat_asset_mint: &Pubkey) {
+    let (address, _) = AssetData::find_address(synchronizer_key, fiat_asset_mint);
+    program_test.add_account(address, Account {
+        lamports: 1_000_000_000,
+        data: vec![0; AssetData::LEN],
+        owner: id(),
+        executable: false,
+        rent_epoch: 0,
+    });
+}
+
 #[tokio::test]
-async fn test_synchronizer_withdraw() {
-    let program_test = ProgramTest::new(
+async fn test_synchronizer_asset_caps() {
+    let mut program_test = ProgramTest::new(
         "synchronizer",
         id(),
         processor!(Processor::process_instruction),
     );
-
-    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
     let synchronizer_key = Keypair::new();
-    let recipient_key = Keypair::new();
-    let collateral_owner_key = Keypair::new();
+    let fiat_asset_mint = Pubkey::new_unique();
+    add_asset_account(&mut program_test, &synchronizer_key.pubkey(), &fiat_asset_mint);
+    let collateral_token_key = add_collateral_mint(&mut program_test, 9);
 
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
     let rent = banks_client.get_rent().await.unwrap();
-    let mint_rent = rent.minimum_balance(spl_token::state::Mint::LEN);
-    let account_rent = rent.minimum_balance(spl_token::state::Account::LEN);
     let synchronizer_data_rent = rent.minimum_balance(SynchronizerData::LEN);
-
-    // Infrastructure preparing
-    // Create collateral token mint
-    let decimals = Processor::DEFAULT_DECIMALS;
-    let collateral_token_key = Keypair::new();
-    create_mint(
+    let oracles_pubkeys = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+    initialize_synchronizer_account(
         &mut banks_client,
         &payer,
         &recent_blockhash,
+        synchronizer_data_rent,
         &collateral_token_key,
-        mint_rent,
-        &collateral_owner_key.pubkey(),
-        decimals
+        spl_token::ui_amount_to_amount(500.0, 9),
+        0,
+        2,
+        &oracles_pubkeys,
+        &synchronizer_key
     ).await.unwrap();
 
-    // Create and init token associated accounts for synchronizer
-    let synchronizer_collateral_account = Keypair::new();
-    create_token_account(
-        &mut banks_client,
-        &payer,
-        &recent_blockhash,
-        &synchronizer_collateral_account,
-        account_rent,
-        &collateral_token_key.pubkey(),
-        &synchronizer_key.pubkey()
-    ).await.unwrap();
+    process_signed(&mut banks_client, &payer, &recent_blockhash, synchronizer::instruction::set_asset_caps(&id(), &fiat_asset_mint, 1000, 100, &synchronizer_key.pubkey(), &payer.pubkey()).unwrap(), &[&synchronizer_key]).await.unwrap();
+    let (_, bump_seed) = AssetData::find_address(&synchronizer_key.pubkey(), &fiat_asset_mint);
+    assert_eq!(get_asset_data(&mut banks_client, &synchronizer_key.pubkey(), &fiat_asset_mint).await, AssetData {
+        is_initialized: true,
+        synchronizer: synchronizer_key.pubkey(),
+        fiat_asset_mint,
+        max_outstanding_notional: 1000,
+        max_transaction_notional: 100,
+        outstanding_notional: 0,
+        bump_seed,
+        ..AssetData::default()
+    });
+
+    process_signed(&mut banks_client, &payer, &recent_blockhash, synchronizer::instruction::set_asset_caps(&id(), &fiat_asset_mint, 2000, 200, &synchronizer_key.pubkey(), &payer.pubkey()).unwrap(), &[&synchronizer_key]).await.unwrap();
+    let asset = get_asset_data(&mut banks_client, &synchronizer_key.pubkey(), &fiat_asset_mint).await;
+    assert_eq!((asset.max_outstanding_notional, asset.max_transaction_notional), (2000, 200));
+
+    // Spreads keep the caps
+    process_signed(&mut banks_client, &payer, &recent_blockhash, synchronizer::instruction::set_asset_spreads(&id(), &fiat_asset_mint, 150, 50, &synchronizer_key.pubkey(), &payer.pubkey()).unwrap(), &[&synchronizer_key]).await.unwrap();
+    let asset = get_asset_data(&mut banks_client, &synchronizer_key.pubkey(), &fiat_asset_mint).await;
+    assert_eq!((asset.max_transaction_notional, asset.ask_spread_bps, asset.bid_spread_bps), (200, 150, 50));
+
+    // BadCase: bid spread of the whole price
+    assert_eq!(
+        process_signed(&mut banks_client, &payer, &recent_blockhash, synchronizer::instruction::set_asset_spreads(&id(), &fiat_asset_mint, 0, 10_000, &synchronizer_key.pubkey(), &payer.pubkey()).unwrap(), &[&synchronizer_key]).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::InvalidSpread as u32))
+    );
 
-    // Create token associated accounts for recipient
-    let recipient_collateral_account = Keypair::new();
-    create_token_account(
-        &mut banks_client,
-        &payer,
-        &recent_blockhash,
-        &recipient_collateral_account,
-        account_rent,
-        &collateral_token_key.pubkey(),
-        &recipient_key.pubkey()
-    ).await.unwrap();
+    // BadCase: asset account of another fiat asset
+    let mut instruction = synchronizer::instruction::set_asset_caps(
+        &id(), &fiat_asset_mint, 1000, 100, &synchronizer_key.pubkey(), &payer.pubkey()
+    ).unwrap();
+    instruction.accounts[1].pubkey = AssetData::find_address(&synchronizer_key.pubkey(), &Pubkey::new_unique()).0;
+    assert_eq!(
+        process_signed(&mut banks_client, &payer, &recent_blockhash, instruction, &[&synchronizer_key]).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::InvalidAssetAccount as u32))
+    );
 
-    // Mint some collateral asset to synchronizer account
-    mint_tokens_to(
+    // BadCase: Synchronizer account is not initialized
+    let fake_synchronizer_key = Keypair::new();
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &fake_synchronizer_key.pubkey(),
+                synchronizer_data_rent,
+                SynchronizerData::LEN as u64,
+                &id(),
+            ),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &fake_synchronizer_key], recent_blockhash);
+    banks_client.process_transaction(transaction).await.unwrap();
+    assert_eq!(
+        process_signed(&mut banks_client, &payer, &recent_blockhash, synchronizer::instruction::set_asset_caps(&id(), &fiat_asset_mint, 1000, 100, &fake_synchronizer_key.pubkey(), &payer.pubkey()).unwrap(), &[&fake_synchronizer_key]).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::NotInitialized as u32))
+    );
+}
+
+#[cfg(feature = "test-bpf")]
+#[tokio::test]
+async fn test_synchronizer_asset_account_creation() {
+    let mut program_test = ProgramTest::new(
+        "synchronizer",
+        id(),
+        processor!(Processor::process_instruction),
+    );
+    let collateral_token_key = add_collateral_mint(&mut program_test, 9);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    let synchronizer_key = Keypair::new();
+    let rent = banks_client.get_rent().await.unwrap();
+    initialize_synchronizer_account(
         &mut banks_client,
         &payer,
         &recent_blockhash,
-        &collateral_token_key.pubkey(),
-        &synchronizer_collateral_account.pubkey(),
-        &collateral_owner_key,
-        spl_token::ui_amount_to_amount(500.0, decimals)
+        rent.minimum_balance(SynchronizerData::LEN),
+        &collateral_token_key,
+        spl_token::ui_amount_to_amount(500.0, 9),
+        0,
+        2,
+        &vec![Pubkey::new_unique(), Pubkey::new_unique()],
+        &synchronizer_key
     ).await.unwrap();
 
-    // Mint some collateral asset to recipient account
+    // The first call creates the asset account
+    let fiat_asset_mint = Pubkey::new_unique();
+    process_signed(&mut banks_client, &payer, &recent_blockhash, synchronizer::instruction::set_asset_caps(&id(), &fiat_asset_mint, 1000, 100, &synchronizer_key.pubkey(), &payer.pubkey()).unwrap(), &[&synchronizer_key]).await.unwrap();
+    let (address, _) = AssetData::find_address(&synchronizer_key.pubkey(), &fiat_asset_mint);
+    let account = banks_client.get_account(address).await.unwrap().unwrap();
+    assert_eq!(account.lamports, rent.minimum_balance(AssetData::LEN));
+    assert_eq!(get_asset_data(&mut banks_client, &synchronizer_key.pubkey(), &fiat_asset_mint).await.max_outstanding_notional, 1000);
+
+    // Lamports sent to the address before creation are kept
+    let fiat_asset_mint = Pubkey::new_unique();
+    let (address, _) = AssetData::find_address(&synchronizer_key.pubkey(), &fiat_asset_mint);
+    let mut transaction = Transaction::new_with_payer(
+        &[system_instruction::transfer(&payer.pubkey(), &address, 1000)],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    banks_client.process_transaction(transaction).await.unwrap();
+    process_signed(&mut banks_client, &payer, &recent_blockhash, synchronizer::instruction::set_asset_caps(&id(), &fiat_asset_mint, 1000, 100, &synchronizer_key.pubkey(), &payer.pubkey()).unwrap(), &[&synchronizer_key]).await.unwrap();
+    let account = banks_client.get_account(address).await.unwrap().unwrap();
+    assert_eq!(account.lamports, rent.minimum_balance(AssetData::LEN));
+    assert_eq!(get_asset_data(&mut banks_client, &synchronizer_key.pubkey(), &fiat_asset_mint).await.max_transaction_notional, 100);
+}
+
+#[tokio::test]
+async fn test_synchronizer_asset_caps_trades() {
+    let mut program_test = ProgramTest::new(
+        "synchronizer",
+        id(),
+        processor!(Processor::process_instruction),
+    );
+    let synchronizer_key = Keypair::new();
+    let fiat_token_key = Keypair::new();
+    add_asset_account(&mut program_test, &synchronizer_key.pubkey(), &fiat_token_key.pubkey());
+
+    let (mut banks_client, payer, mut recent_blockhash) = program_test.start().await;
+    let user_key = Keypair::new();
+    let collateral_owner_key = Keypair::new();
+    let rent = banks_client.get_rent().await.unwrap();
+    let mint_rent = rent.minimum_balance(spl_token::state::Mint::LEN);
+    let account_rent = rent.minimum_balance(spl_token::state::Account::LEN);
+
+    // Infrastructure preparing
+    let decimals = Processor::DEFAULT_DECIMALS;
+    let collateral_token_key = Keypair::new();
+    create_mint(&mut banks_client, &payer, &recent_blockhash, &collateral_token_key, mint_rent, &collateral_owner_key.pubkey(), decimals).await.unwrap();
+    create_mint(&mut banks_client, &payer, &recent_blockhash, &fiat_token_key, mint_rent, &synchronizer_key.pubkey(), decimals).await.unwrap();
+    let synchronizer_collateral_account = Keypair::new();
+    create_token_account(&mut banks_client, &payer, &recent_blockhash, &synchronizer_collateral_account, account_rent, &collateral_token_key.pubkey(), &synchronizer_key.pubkey()).await.unwrap();
+    let user_collateral_account = Keypair::new();
+    create_token_account(&mut banks_client, &payer, &recent_blockhash, &user_collateral_account, account_rent, &collateral_token_key.pubkey(), &user_key.pubkey()).await.unwrap();
+    let user_fiat_account = Keypair::new();
+    create_token_account(&mut banks_client, &payer, &recent_blockhash, &user_fiat_account, account_rent, &fiat_token_key.pubkey(), &user_key.pubkey()).await.unwrap();
     mint_tokens_to(
         &mut banks_client,
         &payer,
         &recent_blockhash,
         &collateral_token_key.pubkey(),
-        &recipient_collateral_account.pubkey(),
+        &user_collateral_account.pubkey(),
         &collateral_owner_key,
-        spl_token::ui_amount_to_amount(100.0, decimals)
+        spl_token::ui_amount_to_amount(500.0, decimals)
     ).await.unwrap();
 
-    // Initialize Synchronizer account
-    let oracles = vec![
-        Keypair::new(),
-        Keypair::new(),
-    ];
-    let oracles_pubkeys = oracles.iter().map(|k| k.pubkey()).collect();
-
+    let oracle = Keypair::new();
+    let oracles = vec![&oracle];
     initialize_synchronizer_account(
         &mut banks_client,
         &payer,
         &recent_blockhash,
-        synchronizer_data_rent,
+        rent.minimum_balance(SynchronizerData::LEN),
         &collateral_token_key.pubkey(),
         spl_token::ui_amount_to_amount(500.0, decimals),
-        spl_token::ui_amount_to_amount(250.0, decimals),
-        oracles.len() as u8,
-        &oracles_pubkeys,
+        0,
+        1,
+        &vec![oracle.pubkey()],
         &synchronizer_key
     ).await.unwrap();
 
+    // Buys of 10 at 0.5 are 5 collateral tokens notional
+    let fee = spl_token::ui_amount_to_amount(0.001, decimals);
+    let prices = vec![spl_token::ui_amount_to_amount(0.5, decimals)];
+    let amount = spl_token::ui_amount_to_amount(10.0, decimals);
+    let notional = spl_token::ui_amount_to_amount(5.0, decimals);
+    process_signed(&mut banks_client, &payer, &recent_blockhash, synchronizer::instruction::set_asset_caps(&id(), &fiat_token_key.pubkey(), 2 * notional, notional, &synchronizer_key.pubkey(), &payer.pubkey()).unwrap(), &[&synchronizer_key]).await.unwrap();
+
+    for _ in 0..2 {
+        // Identical transactions need a new blockhash to not be deduplicated
+        recent_blockhash = banks_client.get_new_blockhash(&recent_blockhash).await.unwrap().0;
+        buy_for(
+            &mut banks_client,
+            &payer,
+            &recent_blockhash,
+            1,
+            amount,
+            fee,
+            &prices,
+            &oracles,
+            &fiat_token_key.pubkey(),
+            &user_collateral_account.pubkey(),
+            &user_fiat_account.pubkey(),
+            &synchronizer_collateral_account.pubkey(),
+            &user_key,
+            &synchronizer_key
+        ).await.unwrap();
+    }
+    let asset = get_asset_data(&mut banks_client, &synchronizer_key.pubkey(), &fiat_token_key.pubkey()).await;
+    assert_eq!(asset.outstanding_notional, 2 * notional);
+
+    // BadCase: outstanding notional cap exceeded
+    recent_blockhash = banks_client.get_new_blockhash(&recent_blockhash).await.unwrap().0;
     assert_eq!(
-        get_token_balance(&mut banks_client, &synchronizer_collateral_account.pubkey()).await,
-        500_000_000_000
-    );
-    assert_eq!(
-        get_token_balance(&mut banks_client, &recipient_collateral_account.pubkey()).await,
-        100_000_000_000
-    );
-    assert_eq!(
-        get_synchronizer_data(&mut banks_client, &synchronizer_key.pubkey()).await.withdrawable_fee_amount,
-        250_000_000_000
+        buy_for(
+            &mut banks_client,
+            &payer,
+            &recent_blockhash,
+            1,
+            amount,
+            fee,
+            &prices,
+            &oracles,
+            &fiat_token_key.pubkey(),
+            &user_collateral_account.pubkey(),
+            &user_fiat_account.pubkey(),
+            &synchronizer_collateral_account.pubkey(),
+            &user_key,
+            &synchronizer_key
+        ).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::AssetCapExceeded as u32))
     );
 
-    // Test withdraw_fee
-    let amount = spl_token::ui_amount_to_amount(50.0, decimals);
-    withdraw_fee(
+    // Sells restore the outstanding notional
+    sell_for(
         &mut banks_client,
         &payer,
         &recent_blockhash,
+        1,
         amount,
+        fee,
+        &prices,
+        &oracles,
+        &fiat_token_key.pubkey(),
+        &user_collateral_account.pubkey(),
+        &user_fiat_account.pubkey(),
         &synchronizer_collateral_account.pubkey(),
-        &recipient_collateral_account.pubkey(),
+        &user_key,
         &synchronizer_key
     ).await.unwrap();
+    let asset = get_asset_data(&mut banks_client, &synchronizer_key.pubkey(), &fiat_token_key.pubkey()).await;
+    assert_eq!(asset.outstanding_notional, notional);
 
+    // BadCase: transaction cap exceeded
     assert_eq!(
-        get_token_balance(&mut banks_client, &synchronizer_collateral_account.pubkey()).await,
-        450_000_000_000
-    );
-    assert_eq!(
-        get_token_balance(&mut banks_client, &recipient_collateral_account.pubkey()).await,
-        150_000_000_000
-    );
-    assert_eq!(
-        get_synchronizer_data(&mut banks_client, &synchronizer_key.pubkey()).await.withdrawable_fee_amount,
-        200_000_000_000
-    );
-
-    // Test withdraw_collateral
-    let amount = spl_token::ui_amount_to_amount(50.0, decimals);
+        buy_for(
+            &mut banks_client,
+            &payer,
+            &recent_blockhash,
+            1,
+            2 * amount,
+            fee,
+            &prices,
+            &oracles,
+            &fiat_token_key.pubkey(),
+            &user_collateral_account.pubkey(),
+            &user_fiat_account.pubkey(),
+            &synchronizer_collateral_account.pubkey(),
+            &user_key,
+            &synchronizer_key
+        ).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::TransactionCapExceeded as u32))
+    );
+}
+
+fn add_rate_limit_account(program_test: &mut ProgramTest, synchronizer_key: &Pubkey) {
+    let (address, _) = RateLimitData::find_address(synchronizer_key);
+    program_test.add_account(address, Account {
+        lamports: 1_000_000_000,
+        data: vec![0; RateLimitData::LEN],
+        owner: id(),
+        executable: false,
+        rent_epoch: 0,
+    });
+}
+
+#[tokio::test]
+async fn test_synchronizer_rate_limits() {
+    let mut program_test = ProgramTest::new(
+        "synchronizer",
+        id(),
+        processor!(Processor::process_instruction),
+    );
+    let synchronizer_key = Keypair::new();
+    add_rate_limit_account(&mut program_test, &synchronizer_key.pubkey());
+    let collateral_token_key = add_collateral_mint(&mut program_test, 9);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    let rent = banks_client.get_rent().await.unwrap();
+    let synchronizer_data_rent = rent.minimum_balance(SynchronizerData::LEN);
+    initialize_synchronizer_account(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        synchronizer_data_rent,
+        &collateral_token_key,
+        spl_token::ui_amount_to_amount(500.0, 9),
+        0,
+        2,
+        &vec![Pubkey::new_unique(), Pubkey::new_unique()],
+        &synchronizer_key
+    ).await.unwrap();
+
+    process_signed(&mut banks_client, &payer, &recent_blockhash, synchronizer::instruction::set_rate_limits(&id(), 1000, 300, 100, &synchronizer_key.pubkey(), &payer.pubkey()).unwrap(), &[&synchronizer_key]).await.unwrap();
+    let (_, bump_seed) = RateLimitData::find_address(&synchronizer_key.pubkey());
+    assert_eq!(get_rate_limit_data(&mut banks_client, &synchronizer_key.pubkey()).await, RateLimitData {
+        is_initialized: true,
+        synchronizer: synchronizer_key.pubkey(),
+        window_slots: 1000,
+        max_synchronizer_notional: 300,
+        max_user_notional: 100,
+        bump_seed,
+        ..RateLimitData::default()
+    });
+
+    process_signed(&mut banks_client, &payer, &recent_blockhash, synchronizer::instruction::set_rate_limits(&id(), 0, 0, 0, &synchronizer_key.pubkey(), &payer.pubkey()).unwrap(), &[&synchronizer_key]).await.unwrap();
+    let rate_limit = get_rate_limit_data(&mut banks_client, &synchronizer_key.pubkey()).await;
+    assert_eq!((rate_limit.window_slots, rate_limit.max_synchronizer_notional, rate_limit.max_user_notional), (0, 0, 0));
+
+    // BadCase: rate limit account of another Synchronizer
+    let mut instruction = synchronizer::instruction::set_rate_limits(
+        &id(), 1000, 300, 100, &synchronizer_key.pubkey(), &payer.pubkey()
+    ).unwrap();
+    instruction.accounts[1].pubkey = RateLimitData::find_address(&Pubkey::new_unique()).0;
+    assert_eq!(
+        process_signed(&mut banks_client, &payer, &recent_blockhash, instruction, &[&synchronizer_key]).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::InvalidRateLimitAccount as u32))
+    );
+
+    // BadCase: wrong Synchronizer signer
+    let fake_synchronizer_key = Keypair::new();
+    let mut instruction = synchronizer::instruction::set_rate_limits(
+        &id(), 1000, 300, 100, &synchronizer_key.pubkey(), &payer.pubkey()
+    ).unwrap();
+    instruction.accounts[0].pubkey = fake_synchronizer_key.pubkey();
+    assert_eq!(
+        process_signed(&mut banks_client, &payer, &recent_blockhash, instruction, &[&fake_synchronizer_key]).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::AccessDenied as u32))
+    );
+}
+
+#[tokio::test]
+async fn test_synchronizer_user_position() {
+    let mut program_test = ProgramTest::new(
+        "synchronizer",
+        id(),
+        processor!(Processor::process_instruction),
+    );
+    let synchronizer_key = Keypair::new();
+    let user_key = Keypair::new();
+    let fiat_asset_mint = Pubkey::new_unique();
+    let (address, bump_seed) = UserPositionData::find_address(&synchronizer_key.pubkey(), &user_key.pubkey(), &fiat_asset_mint);
+    // Native program tests can't create it with the system program
+    program_test.add_account(address, Account {
+        lamports: 1_000_000_000,
+        data: vec![0; UserPositionData::LEN],
+        owner: id(),
+        executable: false,
+        rent_epoch: 0,
+    });
+    let collateral_token_key = add_collateral_mint(&mut program_test, 9);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    let rent = banks_client.get_rent().await.unwrap();
+    initialize_synchronizer_account(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        rent.minimum_balance(SynchronizerData::LEN),
+        &collateral_token_key,
+        spl_token::ui_amount_to_amount(500.0, 9),
+        0,
+        2,
+        &vec![Pubkey::new_unique(), Pubkey::new_unique()],
+        &synchronizer_key
+    ).await.unwrap();
+
+    let create_instruction = || synchronizer::instruction::create_user_position(
+        &id(), &fiat_asset_mint, &user_key.pubkey(), &synchronizer_key.pubkey()
+    ).unwrap();
+    process_signed(&mut banks_client, &payer, &recent_blockhash, create_instruction(), &[&user_key]).await.unwrap();
+    let account = banks_client.get_account(address).await.unwrap().unwrap();
+    assert_eq!(UserPositionData::unpack(&account.data).unwrap(), UserPositionData {
+        is_initialized: true,
+        synchronizer: synchronizer_key.pubkey(),
+        user: user_key.pubkey(),
+        fiat_asset_mint,
+        bump_seed,
+        ..UserPositionData::default()
+    });
+
+    // BadCase: position account already created
+    let recent_blockhash = banks_client.get_new_blockhash(&recent_blockhash).await.unwrap().0;
+    assert_eq!(
+        process_signed(&mut banks_client, &payer, &recent_blockhash, create_instruction(), &[&user_key]).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::AlreadyInitialized as u32))
+    );
+
+    // BadCase: position account of another user
+    let recipient = Pubkey::new_unique();
+    let mut instruction = synchronizer::instruction::close_user_position(
+        &id(), &fiat_asset_mint, &recipient, &payer.pubkey(), &synchronizer_key.pubkey()
+    ).unwrap();
+    instruction.accounts[2].pubkey = address;
+    assert_eq!(
+        process_signed(&mut banks_client, &payer, &recent_blockhash, instruction, &[]).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::InvalidUserPositionAccount as u32))
+    );
+
+    // Closing returns the lamports to the recipient
+    let mut transaction = Transaction::new_with_payer(
+        &[synchronizer::instruction::close_user_position(
+            &id(), &fiat_asset_mint, &recipient, &user_key.pubkey(), &synchronizer_key.pubkey()
+        ).unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &user_key], recent_blockhash);
+    banks_client.process_transaction(transaction).await.unwrap();
+    assert_eq!(banks_client.get_account(address).await.unwrap(), None);
+    assert_eq!(banks_client.get_balance(recipient).await.unwrap(), 1_000_000_000);
+}
+
+#[tokio::test]
+async fn test_synchronizer_market_schedule() {
+    let mut program_test = ProgramTest::new(
+        "synchronizer",
+        id(),
+        processor!(Processor::process_instruction),
+    );
+    let synchronizer_key = Keypair::new();
+    let fiat_asset_mint = Pubkey::new_unique();
+    let (address, bump_seed) = MarketScheduleData::find_address(&synchronizer_key.pubkey(), &fiat_asset_mint);
+    // Native program tests can't create it with the system program
+    program_test.add_account(address, Account {
+        lamports: 1_000_000_000,
+        data: vec![0; MarketScheduleData::LEN],
+        owner: id(),
+        executable: false,
+        rent_epoch: 0,
+    });
+    let collateral_token_key = add_collateral_mint(&mut program_test, 9);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    let rent = banks_client.get_rent().await.unwrap();
+    initialize_synchronizer_account(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        rent.minimum_balance(SynchronizerData::LEN),
+        &collateral_token_key,
+        spl_token::ui_amount_to_amount(500.0, 9),
+        0,
+        2,
+        &vec![Pubkey::new_unique(), Pubkey::new_unique()],
+        &synchronizer_key
+    ).await.unwrap();
+
+    // Weekdays from 14:30 to 21:00 UTC, closed on 2021-12-24
+    let session = TradingHours { open: 52_200, close: 75_600 };
+    let weekdays = [session, session, session, session, session, TradingHours::default(), TradingHours::default()];
+    process_signed(&mut banks_client, &payer, &recent_blockhash, synchronizer::instruction::set_market_schedule(&id(), &fiat_asset_mint, weekdays, &[18_985], &synchronizer_key.pubkey(), &payer.pubkey()).unwrap(), &[&synchronizer_key]).await.unwrap();
+    let account = banks_client.get_account(address).await.unwrap().unwrap();
+    let mut schedule = MarketScheduleData {
+        is_initialized: true,
+        synchronizer: synchronizer_key.pubkey(),
+        fiat_asset_mint,
+        bump_seed,
+        ..MarketScheduleData::default()
+    };
+    schedule.set(weekdays, &[18_985]).unwrap();
+    assert_eq!(MarketScheduleData::unpack(&account.data).unwrap(), schedule);
+
+    // BadCase: invalid hours
+    assert_eq!(
+        process_signed(&mut banks_client, &payer, &recent_blockhash, synchronizer::instruction::set_market_schedule(&id(), &fiat_asset_mint, [TradingHours { open: 0, close: 86_401 }; 7], &[], &synchronizer_key.pubkey(), &payer.pubkey()).unwrap(), &[&synchronizer_key]).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::InvalidMarketSchedule as u32))
+    );
+
+    // BadCase: market schedule account of another fiat asset
+    let mut instruction = synchronizer::instruction::set_market_schedule(
+        &id(), &fiat_asset_mint, weekdays, &[], &synchronizer_key.pubkey(), &payer.pubkey()
+    ).unwrap();
+    instruction.accounts[1].pubkey = MarketScheduleData::find_address(&synchronizer_key.pubkey(), &Pubkey::new_unique()).0;
+    assert_eq!(
+        process_signed(&mut banks_client, &payer, &recent_blockhash, instruction, &[&synchronizer_key]).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::InvalidMarketScheduleAccount as u32))
+    );
+}
+
+#[tokio::test]
+async fn test_synchronizer_withdraw() {
+    let program_test = ProgramTest::new(
+        "synchronizer",
+        id(),
+        processor!(Processor::process_instruction),
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    let synchronizer_key = Keypair::new();
+    let recipient_key = Keypair::new();
+    let collateral_owner_key = Keypair::new();
+
+    let rent = banks_client.get_rent().await.unwrap();
+    let mint_rent = rent.minimum_balance(spl_token::state::Mint::LEN);
+    let account_rent = rent.minimum_balance(spl_token::state::Account::LEN);
+    let synchronizer_data_rent = rent.minimum_balance(SynchronizerData::LEN);
+
+    // Infrastructure preparing
+    // Create collateral token mint
+    let decimals = Processor::DEFAULT_DECIMALS;
+    let collateral_token_key = Keypair::new();
+    create_mint(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        &collateral_token_key,
+        mint_rent,
+        &collateral_owner_key.pubkey(),
+        decimals
+    ).await.unwrap();
+
+    // Create and init token associated accounts for synchronizer
+    let synchronizer_collateral_account = Keypair::new();
+    create_token_account(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        &synchronizer_collateral_account,
+        account_rent,
+        &collateral_token_key.pubkey(),
+        &synchronizer_key.pubkey()
+    ).await.unwrap();
+
+    // Create token associated accounts for recipient
+    let recipient_collateral_account = Keypair::new();
+    create_token_account(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        &recipient_collateral_account,
+        account_rent,
+        &collateral_token_key.pubkey(),
+        &recipient_key.pubkey()
+    ).await.unwrap();
+
+    // Mint some collateral asset to synchronizer account
+    mint_tokens_to(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        &collateral_token_key.pubkey(),
+        &synchronizer_collateral_account.pubkey(),
+        &collateral_owner_key,
+        spl_token::ui_amount_to_amount(500.0, decimals)
+    ).await.unwrap();
+
+    // Mint some collateral asset to recipient account
+    mint_tokens_to(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        &collateral_token_key.pubkey(),
+        &recipient_collateral_account.pubkey(),
+        &collateral_owner_key,
+        spl_token::ui_amount_to_amount(100.0, decimals)
+    ).await.unwrap();
+
+    // Initialize Synchronizer account
+    let oracles = vec![
+        Keypair::new(),
+        Keypair::new(),
+    ];
+    let oracles_pubkeys = oracles.iter().map(|k| k.pubkey()).collect();
+
+    initialize_synchronizer_account(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        synchronizer_data_rent,
+        &collateral_token_key.pubkey(),
+        spl_token::ui_amount_to_amount(500.0, decimals),
+        spl_token::ui_amount_to_amount(250.0, decimals),
+        oracles.len() as u8,
+        &oracles_pubkeys,
+        &synchronizer_key
+    ).await.unwrap();
+
+    assert_eq!(
+        get_token_balance(&mut banks_client, &synchronizer_collateral_account.pubkey()).await,
+        500_000_000_000
+    );
+    assert_eq!(
+        get_token_balance(&mut banks_client, &recipient_collateral_account.pubkey()).await,
+        100_000_000_000
+    );
+    assert_eq!(
+        get_synchronizer_data(&mut banks_client, &synchronizer_key.pubkey()).await.withdrawable_fee_amount,
+        250_000_000_000
+    );
+
+    // Test withdraw_fee
+    let amount = spl_token::ui_amount_to_amount(50.0, decimals);
+    withdraw_fee(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        amount,
+        &synchronizer_collateral_account.pubkey(),
+        &recipient_collateral_account.pubkey(),
+        &synchronizer_key
+    ).await.unwrap();
+
+    assert_eq!(
+        get_token_balance(&mut banks_client, &synchronizer_collateral_account.pubkey()).await,
+        450_000_000_000
+    );
+    assert_eq!(
+        get_token_balance(&mut banks_client, &recipient_collateral_account.pubkey()).await,
+        150_000_000_000
+    );
+    assert_eq!(
+        get_synchronizer_data(&mut banks_client, &synchronizer_key.pubkey()).await.withdrawable_fee_amount,
+        200_000_000_000
+    );
+
+    // Test withdraw_collateral
+    let amount = spl_token::ui_amount_to_amount(50.0, decimals);
     // Processor::process_withdraw_collateral(&accounts, amount).unwrap();
     withdraw_collateral(
         &mut banks_client,
@@ -1444,4 +2071,814 @@ async fn test_synchronizer_withdraw() {
         get_synchronizer_data(&mut banks_client, &synchronizer_key.pubkey()).await.withdrawable_fee_amount,
         200_000_000_000
     );
+
+    // Case: fake token program
+    let mut instruction = synchronizer::instruction::withdraw_fee(
+        &id(),
+        amount,
+        &synchronizer_collateral_account.pubkey(),
+        &recipient_collateral_account.pubkey(),
+        &synchronizer_key.pubkey(),
+        false,
+    ).unwrap();
+    instruction.accounts[3].pubkey = Keypair::new().pubkey();
+    let mut transaction = Transaction::new_with_payer(&[instruction], Some(&payer.pubkey()));
+    transaction.sign(&[&payer, &synchronizer_key], recent_blockhash);
+    assert_eq!(
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::InvalidTokenProgram as u32)),
+        banks_client.process_transaction(transaction).await.unwrap_err().unwrap(),
+    );
+
+    // Case: recipient account of other mint
+    let other_token_key = Keypair::new();
+    create_mint(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        &other_token_key,
+        mint_rent,
+        &collateral_owner_key.pubkey(),
+        decimals
+    ).await.unwrap();
+
+    let recipient_other_account = Keypair::new();
+    create_token_account(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        &recipient_other_account,
+        account_rent,
+        &other_token_key.pubkey(),
+        &recipient_key.pubkey()
+    ).await.unwrap();
+
+    assert_eq!(
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::BadCollateralMint as u32)),
+        withdraw_fee(
+            &mut banks_client,
+            &payer,
+            &recent_blockhash,
+            amount,
+            &synchronizer_collateral_account.pubkey(),
+            &recipient_other_account.pubkey(),
+            &synchronizer_key
+        ).await.unwrap_err().unwrap(),
+    );
+    assert_eq!(
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::BadCollateralMint as u32)),
+        withdraw_collateral(
+            &mut banks_client,
+            &payer,
+            &recent_blockhash,
+            amount,
+            &synchronizer_collateral_account.pubkey(),
+            &recipient_other_account.pubkey(),
+            &synchronizer_key
+        ).await.unwrap_err().unwrap(),
+    );
+}
+
+#[tokio::test]
+async fn test_synchronizer_migrate() {
+    let mut program_test = ProgramTest::new(
+        "synchronizer",
+        id(),
+        processor!(Processor::process_instruction),
+    );
+
+    // Version 1 Synchronizer account
+    let synchronizer_key = Keypair::new();
+    let collateral_token_key = Keypair::new();
+    let synchronizer = SynchronizerData {
+        is_initialized: true,
+        collateral_token_key: collateral_token_key.pubkey(),
+        remaining_dollar_cap: 500,
+        withdrawable_fee_amount: 20,
+        minimum_required_signature: 2,
+        oracles: [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::default()],
+        required_trade_accounts: 0,
+        collateral_decimals: None,
+    };
+    let v1_data = synchronizer.try_to_vec().unwrap()[..SYNCHRONIZER_DATA_V1_LEN].to_vec();
+    program_test.add_account(synchronizer_key.pubkey(), Account {
+        lamports: 1_000_000_000,
+        data: v1_data,
+        owner: id(),
+        executable: false,
+        rent_epoch: 0,
+    });
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let rent = banks_client.get_rent().await.unwrap();
+    let mint_rent = rent.minimum_balance(spl_token::state::Mint::LEN);
+    let account_rent = rent.minimum_balance(spl_token::state::Account::LEN);
+    let synchronizer_data_rent = rent.minimum_balance(SynchronizerData::LEN);
+
+    // Infrastructure preparing
+    let decimals = Processor::DEFAULT_DECIMALS;
+    create_mint(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        &collateral_token_key,
+        mint_rent,
+        &payer.pubkey(),
+        decimals
+    ).await.unwrap();
+
+    let fiat_asset_mint = Keypair::new();
+    create_mint(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        &fiat_asset_mint,
+        mint_rent,
+        &synchronizer_key.pubkey(),
+        decimals
+    ).await.unwrap();
+
+    let synchronizer_collateral_account = Keypair::new();
+    create_token_account(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        &synchronizer_collateral_account,
+        account_rent,
+        &collateral_token_key.pubkey(),
+        &synchronizer_key.pubkey()
+    ).await.unwrap();
+
+    // BadCase: version 1 account can't be used before migration
+    assert_eq!(
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::MigrationRequired as u32)),
+        set_remaining_dollar_cap(
+            &mut banks_client,
+            &payer,
+            &recent_blockhash,
+            100,
+            &synchronizer_key
+        ).await.unwrap_err().unwrap()
+    );
+
+    // Good case
+    let new_synchronizer_key = Keypair::new();
+    migrate_synchronizer_account(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        synchronizer_data_rent,
+        &[fiat_asset_mint.pubkey(), synchronizer_collateral_account.pubkey()],
+        &synchronizer_key,
+        &new_synchronizer_key,
+    ).await.unwrap();
+
+    assert_eq!(get_synchronizer_data(&mut banks_client, &new_synchronizer_key.pubkey()).await, synchronizer);
+    assert_eq!(banks_client.get_account(synchronizer_key.pubkey()).await.unwrap(), None);
+
+    let mint = banks_client.get_account(fiat_asset_mint.pubkey()).await.unwrap().unwrap();
+    let mint = spl_token::state::Mint::unpack(&mint.data).unwrap();
+    assert_eq!(mint.mint_authority, COption::Some(new_synchronizer_key.pubkey()));
+
+    let vault = banks_client.get_account(synchronizer_collateral_account.pubkey()).await.unwrap().unwrap();
+    let vault = spl_token::state::Account::unpack(&vault.data).unwrap();
+    assert_eq!(vault.owner, new_synchronizer_key.pubkey());
+
+    // Migrated account works with admin instructions
+    set_remaining_dollar_cap(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        100,
+        &new_synchronizer_key
+    ).await.unwrap();
+    assert_eq!(get_synchronizer_data(&mut banks_client, &new_synchronizer_key.pubkey()).await.remaining_dollar_cap, 100);
+}
+
+#[tokio::test]
+async fn test_synchronizer_fee_schedule() {
+    let mut program_test = ProgramTest::new(
+        "synchronizer",
+        id(),
+        processor!(Processor::process_instruction),
+    );
+    let synchronizer_key = Keypair::new();
+    let (address, bump_seed) = FeeScheduleData::find_address(&synchronizer_key.pubkey());
+    // Native program tests can't create it with the system program
+    program_test.add_account(address, Account {
+        lamports: 1_000_000_000,
+        data: vec![0; FeeScheduleData::LEN],
+        owner: id(),
+        executable: false,
+        rent_epoch: 0,
+    });
+    let collateral_token_key = add_collateral_mint(&mut program_test, 9);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    let rent = banks_client.get_rent().await.unwrap();
+    initialize_synchronizer_account(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        rent.minimum_balance(SynchronizerData::LEN),
+        &collateral_token_key,
+        spl_token::ui_amount_to_amount(500.0, 9),
+        0,
+        2,
+        &vec![Pubkey::new_unique(), Pubkey::new_unique()],
+        &synchronizer_key
+    ).await.unwrap();
+
+    let sync = synchronizer_key.pubkey();
+    let market_maker = Pubkey::new_unique();
+    let tiers = [FeeTier { min_volume: 1000, fee: 8 }, FeeTier { min_volume: 5000, fee: 5 }];
+    let set_tiers = |tiers: &[FeeTier]| synchronizer::instruction::set_fee_tiers(&id(), tiers, 216_000, &sync, &payer.pubkey()).unwrap();
+    let set_override = |fee| synchronizer::instruction::set_fee_override(&id(), &market_maker, fee, &sync, &payer.pubkey()).unwrap();
+    process_signed(&mut banks_client, &payer, &recent_blockhash, set_tiers(&tiers), &[&synchronizer_key]).await.unwrap();
+    process_signed(&mut banks_client, &payer, &recent_blockhash, set_override(Some(0)), &[&synchronizer_key]).await.unwrap();
+    let account = banks_client.get_account(address).await.unwrap().unwrap();
+    let mut schedule = FeeScheduleData {
+        is_initialized: true,
+        synchronizer: sync,
+        bump_seed,
+        volume_window_slots: 216_000,
+        ..FeeScheduleData::default()
+    };
+    schedule.set_tiers(&tiers).unwrap();
+    schedule.set_override(&market_maker, Some(0)).unwrap();
+    assert_eq!(FeeScheduleData::unpack(&account.data).unwrap(), schedule);
+
+    // Removing the override keeps the tiers
+    process_signed(&mut banks_client, &payer, &recent_blockhash, set_override(None), &[&synchronizer_key]).await.unwrap();
+    let account = banks_client.get_account(address).await.unwrap().unwrap();
+    let schedule = FeeScheduleData::unpack(&account.data).unwrap();
+    assert_eq!((schedule.tiers(), schedule.override_count), (&tiers[..], 0));
+
+    // The referral share is kept with the tiers
+    let set_referral_share = |bps| synchronizer::instruction::set_referral_share(&id(), bps, &sync, &payer.pubkey()).unwrap();
+    process_signed(&mut banks_client, &payer, &recent_blockhash, set_referral_share(2500), &[&synchronizer_key]).await.unwrap();
+    let account = banks_client.get_account(address).await.unwrap().unwrap();
+    let schedule = FeeScheduleData::unpack(&account.data).unwrap();
+    assert_eq!((schedule.tiers(), schedule.referral_share_bps), (&tiers[..], 2500));
+
+    // BadCase: referral share above 100%
+    assert_eq!(
+        process_signed(&mut banks_client, &payer, &recent_blockhash, set_referral_share(10_001), &[&synchronizer_key]).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::InvalidFeeSchedule as u32))
+    );
+
+    // BadCase: tiers out of order
+    assert_eq!(
+        process_signed(&mut banks_client, &payer, &recent_blockhash, set_tiers(&[tiers[1], tiers[0]]), &[&synchronizer_key]).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::InvalidFeeSchedule as u32))
+    );
+
+    // BadCase: fee schedule account of another Synchronizer
+    let mut instruction = set_tiers(&tiers);
+    instruction.accounts[1].pubkey = FeeScheduleData::find_address(&Pubkey::new_unique()).0;
+    assert_eq!(
+        process_signed(&mut banks_client, &payer, &recent_blockhash, instruction, &[&synchronizer_key]).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::InvalidFeeScheduleAccount as u32))
+    );
+}
+
+#[tokio::test]
+async fn test_synchronizer_referrals() {
+    let mut program_test = ProgramTest::new(
+        "synchronizer",
+        id(),
+        processor!(Processor::process_instruction),
+    );
+    let synchronizer_key = Keypair::new();
+    let fiat_token_key = Keypair::new();
+    // Native program tests can't create it with the system program
+    program_test.add_account(FeeScheduleData::find_address(&synchronizer_key.pubkey()).0, Account {
+        lamports: 1_000_000_000,
+        data: vec![0; FeeScheduleData::LEN],
+        owner: id(),
+        executable: false,
+        rent_epoch: 0,
+    });
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    let user_key = Keypair::new();
+    let referrer_key = Keypair::new();
+    let collateral_owner_key = Keypair::new();
+    let rent = banks_client.get_rent().await.unwrap();
+    let mint_rent = rent.minimum_balance(spl_token::state::Mint::LEN);
+    let account_rent = rent.minimum_balance(spl_token::state::Account::LEN);
+
+    let amount = |amount| spl_token::ui_amount_to_amount(amount, Processor::DEFAULT_DECIMALS);
+    let collateral_token_key = Keypair::new();
+    create_mint(&mut banks_client, &payer, &recent_blockhash, &collateral_token_key, mint_rent, &collateral_owner_key.pubkey(), Processor::DEFAULT_DECIMALS).await.unwrap();
+    create_mint(&mut banks_client, &payer, &recent_blockhash, &fiat_token_key, mint_rent, &synchronizer_key.pubkey(), Processor::DEFAULT_DECIMALS).await.unwrap();
+    let synchronizer_collateral_account = Keypair::new();
+    create_token_account(&mut banks_client, &payer, &recent_blockhash, &synchronizer_collateral_account, account_rent, &collateral_token_key.pubkey(), &synchronizer_key.pubkey()).await.unwrap();
+    let user_collateral_account = Keypair::new();
+    create_token_account(&mut banks_client, &payer, &recent_blockhash, &user_collateral_account, account_rent, &collateral_token_key.pubkey(), &user_key.pubkey()).await.unwrap();
+    let user_fiat_account = Keypair::new();
+    create_token_account(&mut banks_client, &payer, &recent_blockhash, &user_fiat_account, account_rent, &fiat_token_key.pubkey(), &user_key.pubkey()).await.unwrap();
+    let referrer_collateral_account = Keypair::new();
+    create_token_account(&mut banks_client, &payer, &recent_blockhash, &referrer_collateral_account, account_rent, &collateral_token_key.pubkey(), &referrer_key.pubkey()).await.unwrap();
+    let user_second_collateral_account = Keypair::new();
+    create_token_account(&mut banks_client, &payer, &recent_blockhash, &user_second_collateral_account, account_rent, &collateral_token_key.pubkey(), &user_key.pubkey()).await.unwrap();
+    mint_tokens_to(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        &collateral_token_key.pubkey(),
+        &user_collateral_account.pubkey(),
+        &collateral_owner_key,
+        amount(500.0)
+    ).await.unwrap();
+
+    let oracle = Keypair::new();
+    initialize_synchronizer_account(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        rent.minimum_balance(SynchronizerData::LEN),
+        &collateral_token_key.pubkey(),
+        amount(500.0),
+        0,
+        1,
+        &vec![oracle.pubkey()],
+        &synchronizer_key
+    ).await.unwrap();
+    let sync = synchronizer_key.pubkey();
+    let set_referral_share = synchronizer::instruction::set_referral_share(&id(), 2500, &sync, &payer.pubkey()).unwrap();
+    process_signed(&mut banks_client, &payer, &recent_blockhash, set_referral_share, &[&synchronizer_key]).await.unwrap();
+
+    // Buys at 5.5 with a 0.1% fee
+    let buy = |fiat_amount, referrer: &Pubkey| synchronizer::instruction::buy_for(
+        &id(),
+        1,
+        fiat_amount,
+        amount(0.001),
+        &vec![amount(5.5)],
+        &vec![oracle.pubkey()],
+        &fiat_token_key.pubkey(),
+        &user_collateral_account.pubkey(),
+        &user_fiat_account.pubkey(),
+        &synchronizer_collateral_account.pubkey(),
+        &user_key.pubkey(),
+        &sync,
+        TRADE_PROGRAM_ACCOUNTS,
+        Some(referrer),
+    ).unwrap();
+    process_signed(&mut banks_client, &payer, &recent_blockhash, buy(amount(10.0), &referrer_collateral_account.pubkey()), &[&user_key, &synchronizer_key, &oracle]).await.unwrap();
+
+    // The referrer gets a quarter of the fee
+    assert_eq!(get_token_balance(&mut banks_client, &referrer_collateral_account.pubkey()).await, 13_750_000);
+    assert_eq!(get_token_balance(&mut banks_client, &synchronizer_collateral_account.pubkey()).await, 55_055_000_000 - 13_750_000);
+    assert_eq!(get_synchronizer_data(&mut banks_client, &sync).await.withdrawable_fee_amount, 55_000_000 - 13_750_000);
+
+    // BadCase: the user refers itself with any of its collateral accounts
+    for referrer in [user_collateral_account.pubkey(), user_second_collateral_account.pubkey()].iter() {
+        assert_eq!(
+            process_signed(&mut banks_client, &payer, &recent_blockhash, buy(amount(1.0), referrer), &[&user_key, &synchronizer_key, &oracle]).await.unwrap_err().unwrap(),
+            TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::InvalidReferrerAccount as u32))
+        );
+    }
+    assert_eq!(get_token_balance(&mut banks_client, &user_second_collateral_account.pubkey()).await, 0);
+}
+
+#[tokio::test]
+async fn test_synchronizer_collateral_decimals() {
+    let mut program_test = ProgramTest::new(
+        "synchronizer",
+        id(),
+        processor!(Processor::process_instruction),
+    );
+    let usdc_key = add_collateral_mint(&mut program_test, 6);
+    let wrapped_key = add_collateral_mint(&mut program_test, 9);
+
+    let (mut banks_client, payer, mut recent_blockhash) = program_test.start().await;
+    let rent = banks_client.get_rent().await.unwrap();
+    let synchronizer_data_rent = rent.minimum_balance(SynchronizerData::LEN);
+    let synchronizer_key = Keypair::new();
+    let oracles_pubkeys = vec![Pubkey::new_unique()];
+
+    // Decimals are read from the collateral mint
+    initialize_synchronizer_account(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        synchronizer_data_rent,
+        &usdc_key,
+        spl_token::ui_amount_to_amount(500.0, 6),
+        0,
+        1,
+        &oracles_pubkeys,
+        &synchronizer_key
+    ).await.unwrap();
+    let synchronizer = get_synchronizer_data(&mut banks_client, &synchronizer_key.pubkey()).await;
+    assert_eq!(synchronizer.collateral_decimals, Some(6));
+    assert_eq!(synchronizer.collateral_token_decimals(), 6);
+
+    // BadCase: collateral token is not a mint
+    assert_eq!(
+        initialize_synchronizer_account(
+            &mut banks_client,
+            &payer,
+            &recent_blockhash,
+            synchronizer_data_rent,
+            &Pubkey::new_unique(),
+            0,
+            0,
+            1,
+            &oracles_pubkeys,
+            &Keypair::new()
+        ).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(1, InstructionError::Custom(SynchronizerError::InvalidAccountOwner as u32))
+    );
+
+    // BadCase: decimals change with a remaining dollar cap in the old decimals
+    assert_eq!(
+        set_collateral_token(&mut banks_client, &payer, &recent_blockhash, &wrapped_key, &synchronizer_key).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::BadDecimals as u32))
+    );
+
+    // Changing the collateral token updates its decimals
+    set_remaining_dollar_cap(&mut banks_client, &payer, &recent_blockhash, 0, &synchronizer_key).await.unwrap();
+    recent_blockhash = banks_client.get_new_blockhash(&recent_blockhash).await.unwrap().0;
+    set_collateral_token(&mut banks_client, &payer, &recent_blockhash, &wrapped_key, &synchronizer_key).await.unwrap();
+    let synchronizer = get_synchronizer_data(&mut banks_client, &synchronizer_key.pubkey()).await;
+    assert_eq!((synchronizer.collateral_token_key, synchronizer.collateral_decimals), (wrapped_key, Some(9)));
+
+    set_collateral_token(&mut banks_client, &payer, &recent_blockhash, &usdc_key, &synchronizer_key).await.unwrap();
+    let synchronizer = get_synchronizer_data(&mut banks_client, &synchronizer_key.pubkey()).await;
+    assert_eq!((synchronizer.collateral_token_key, synchronizer.collateral_decimals), (usdc_key, Some(6)));
+
+    // BadCase: mint of another token
+    let mut instruction = synchronizer::instruction::set_collateral_token(&id(), &wrapped_key, &synchronizer_key.pubkey()).unwrap();
+    instruction.accounts[1].pubkey = usdc_key;
+    assert_eq!(
+        process_signed(&mut banks_client, &payer, &recent_blockhash, instruction, &[&synchronizer_key]).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::BadCollateralMint as u32))
+    );
+}
+
+#[tokio::test]
+async fn test_synchronizer_collateral_decimals_trades() {
+    let mut program_test = ProgramTest::new(
+        "synchronizer",
+        id(),
+        processor!(Processor::process_instruction),
+    );
+    let synchronizer_key = Keypair::new();
+    let fiat_token_key = Keypair::new();
+    add_asset_account(&mut program_test, &synchronizer_key.pubkey(), &fiat_token_key.pubkey());
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    let user_key = Keypair::new();
+    let collateral_owner_key = Keypair::new();
+    let rent = banks_client.get_rent().await.unwrap();
+    let mint_rent = rent.minimum_balance(spl_token::state::Mint::LEN);
+    let account_rent = rent.minimum_balance(spl_token::state::Account::LEN);
+
+    // 6 decimals collateral, like USDC, for 9 decimals fiat assets
+    let usdc = |amount| spl_token::ui_amount_to_amount(amount, 6);
+    let fiat = |amount| spl_token::ui_amount_to_amount(amount, Processor::DEFAULT_DECIMALS);
+    let collateral_token_key = Keypair::new();
+    create_mint(&mut banks_client, &payer, &recent_blockhash, &collateral_token_key, mint_rent, &collateral_owner_key.pubkey(), 6).await.unwrap();
+    create_mint(&mut banks_client, &payer, &recent_blockhash, &fiat_token_key, mint_rent, &synchronizer_key.pubkey(), Processor::DEFAULT_DECIMALS).await.unwrap();
+    let synchronizer_collateral_account = Keypair::new();
+    create_token_account(&mut banks_client, &payer, &recent_blockhash, &synchronizer_collateral_account, account_rent, &collateral_token_key.pubkey(), &synchronizer_key.pubkey()).await.unwrap();
+    let user_collateral_account = Keypair::new();
+    create_token_account(&mut banks_client, &payer, &recent_blockhash, &user_collateral_account, account_rent, &collateral_token_key.pubkey(), &user_key.pubkey()).await.unwrap();
+    let user_fiat_account = Keypair::new();
+    create_token_account(&mut banks_client, &payer, &recent_blockhash, &user_fiat_account, account_rent, &fiat_token_key.pubkey(), &user_key.pubkey()).await.unwrap();
+    mint_tokens_to(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        &collateral_token_key.pubkey(),
+        &user_collateral_account.pubkey(),
+        &collateral_owner_key,
+        usdc(500.0)
+    ).await.unwrap();
+
+    let oracle = Keypair::new();
+    let oracles = vec![&oracle];
+    initialize_synchronizer_account(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        rent.minimum_balance(SynchronizerData::LEN),
+        &collateral_token_key.pubkey(),
+        usdc(500.0),
+        0,
+        1,
+        &vec![oracle.pubkey()],
+        &synchronizer_key
+    ).await.unwrap();
+
+    // Buy 10 at 5.5 USDC with a 0.1% fee
+    let fee = spl_token::ui_amount_to_amount(0.001, FEE_DECIMALS);
+    let prices = vec![usdc(5.5)];
+    buy_for(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        1,
+        fiat(10.0),
+        fee,
+        &prices,
+        &oracles,
+        &fiat_token_key.pubkey(),
+        &user_collateral_account.pubkey(),
+        &user_fiat_account.pubkey(),
+        &synchronizer_collateral_account.pubkey(),
+        &user_key,
+        &synchronizer_key
+    ).await.unwrap();
+    assert_eq!(get_token_balance(&mut banks_client, &user_fiat_account.pubkey()).await, fiat(10.0));
+    assert_eq!(get_token_balance(&mut banks_client, &user_collateral_account.pubkey()).await, 444_945_000);
+    assert_eq!(get_token_balance(&mut banks_client, &synchronizer_collateral_account.pubkey()).await, 55_055_000);
+    let synchronizer = get_synchronizer_data(&mut banks_client, &synchronizer_key.pubkey()).await;
+    assert_eq!(synchronizer.remaining_dollar_cap, 445_000_000);
+    assert_eq!(synchronizer.withdrawable_fee_amount, 55_000);
+
+    // Sell 4 back
+    sell_for(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        1,
+        fiat(4.0),
+        fee,
+        &prices,
+        &oracles,
+        &fiat_token_key.pubkey(),
+        &user_collateral_account.pubkey(),
+        &user_fiat_account.pubkey(),
+        &synchronizer_collateral_account.pubkey(),
+        &user_key,
+        &synchronizer_key
+    ).await.unwrap();
+    assert_eq!(get_token_balance(&mut banks_client, &user_fiat_account.pubkey()).await, fiat(6.0));
+    assert_eq!(get_token_balance(&mut banks_client, &user_collateral_account.pubkey()).await, 466_923_000);
+    assert_eq!(get_token_balance(&mut banks_client, &synchronizer_collateral_account.pubkey()).await, 33_077_000);
+    let synchronizer = get_synchronizer_data(&mut banks_client, &synchronizer_key.pubkey()).await;
+    assert_eq!(synchronizer.remaining_dollar_cap, 467_000_000);
+    assert_eq!(synchronizer.withdrawable_fee_amount, 77_000);
+}
+
+// Native program tests can't run the token program, vaults of accepted collaterals start initialized
+fn add_vault_account(program_test: &mut ProgramTest, mint: &Pubkey, owner: &Pubkey, amount: u64) -> Pubkey {
+    let address = Pubkey::new_unique();
+    let mut data = vec![0; spl_token::state::Account::LEN];
+    spl_token::state::Account {
+        mint: *mint,
+        owner: *owner,
+        amount,
+        state: spl_token::state::AccountState::Initialized,
+        ..Default::default()
+    }.pack_into_slice(&mut data);
+    program_test.add_account(address, Account {
+        lamports: 1_000_000_000,
+        data,
+        owner: spl_token::id(),
+        executable: false,
+        rent_epoch: 0,
+    });
+    address
+}
+
+#[tokio::test]
+async fn test_synchronizer_collateral_set() {
+    let mut program_test = ProgramTest::new(
+        "synchronizer",
+        id(),
+        processor!(Processor::process_instruction),
+    );
+    let synchronizer_key = Keypair::new();
+    let oracle = Keypair::new();
+    let (address, bump_seed) = CollateralSetData::find_address(&synchronizer_key.pubkey());
+    // Native program tests can't create it with the system program
+    program_test.add_account(address, Account {
+        lamports: 1_000_000_000,
+        data: vec![0; CollateralSetData::LEN],
+        owner: id(),
+        executable: false,
+        rent_epoch: 0,
+    });
+    let collateral_token_key = add_collateral_mint(&mut program_test, 9);
+    let usdc_key = add_collateral_mint(&mut program_test, 6);
+    let usdt_key = add_collateral_mint(&mut program_test, 6);
+    let usdc_vault = add_vault_account(&mut program_test, &usdc_key, &synchronizer_key.pubkey(), 0);
+    let usdt_vault = add_vault_account(&mut program_test, &usdt_key, &synchronizer_key.pubkey(), 1_000_000);
+    let foreign_vault = add_vault_account(&mut program_test, &usdc_key, &Pubkey::new_unique(), 0);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    let rent = banks_client.get_rent().await.unwrap();
+    initialize_synchronizer_account(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        rent.minimum_balance(SynchronizerData::LEN),
+        &collateral_token_key,
+        spl_token::ui_amount_to_amount(500.0, 9),
+        0,
+        1,
+        &vec![oracle.pubkey()],
+        &synchronizer_key
+    ).await.unwrap();
+
+    let sync = synchronizer_key.pubkey();
+    let rate_bounds = RateBounds { max_age: 3_600, max_deviation_bps: 100 };
+    let add = |mint: &Pubkey, vault: &Pubkey, rate| synchronizer::instruction::add_collateral(&id(), rate, rate_bounds, mint, vault, &sync, &payer.pubkey()).unwrap();
+    process_signed(&mut banks_client, &payer, &recent_blockhash, add(&usdc_key, &usdc_vault, 1_000_000_000), &[&synchronizer_key]).await.unwrap();
+    process_signed(&mut banks_client, &payer, &recent_blockhash, add(&usdt_key, &usdt_vault, 998_000_000), &[&synchronizer_key]).await.unwrap();
+
+    // Oracles attest the rates
+    let update_rate = synchronizer::instruction::update_collateral_rate(&id(), &usdt_key, 1_001_000_000, &sync, &[oracle.pubkey()]).unwrap();
+    process_signed(&mut banks_client, &payer, &recent_blockhash, update_rate, &[&oracle]).await.unwrap();
+    let account = banks_client.get_account(address).await.unwrap().unwrap();
+    let set = CollateralSetData::unpack(&account.data).unwrap();
+    assert_eq!((set.is_initialized, set.synchronizer, set.bump_seed), (true, sync, bump_seed));
+    assert_eq!(set.collaterals().iter().map(|collateral| (collateral.mint, collateral.vault, collateral.decimals, collateral.rate, collateral.rate_bounds)).collect::<Vec<_>>(), vec![
+        (usdc_key, usdc_vault, 6, 1_000_000_000, rate_bounds),
+        (usdt_key, usdt_vault, 6, 1_001_000_000, rate_bounds),
+    ]);
+
+    // Removing a collateral keeps the others
+    let remove = |mint: &Pubkey, vault: &Pubkey| synchronizer::instruction::remove_collateral(&id(), mint, vault, &sync).unwrap();
+    process_signed(&mut banks_client, &payer, &recent_blockhash, remove(&usdc_key, &usdc_vault), &[&synchronizer_key]).await.unwrap();
+    let account = banks_client.get_account(address).await.unwrap().unwrap();
+    let set = CollateralSetData::unpack(&account.data).unwrap();
+    assert_eq!(set.collaterals().iter().map(|collateral| collateral.mint).collect::<Vec<_>>(), vec![usdt_key]);
+    assert_eq!(set.collaterals[1], AcceptedCollateral::default());
+
+    // BadCase: collateral left in the vault, or another vault
+    for vault in [usdt_vault, usdc_vault].iter() {
+        assert_eq!(
+            process_signed(&mut banks_client, &payer, &recent_blockhash, remove(&usdt_key, vault), &[&synchronizer_key]).await.unwrap_err().unwrap(),
+            TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::InvalidCollateral as u32))
+        );
+    }
+
+    // BadCase: collateral already accepted
+    assert_eq!(
+        process_signed(&mut banks_client, &payer, &recent_blockhash, add(&usdt_key, &usdt_vault, 999_000_000), &[&synchronizer_key]).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::InvalidCollateral as u32))
+    );
+
+    // BadCase: the Synchronizer collateral token has no rate
+    let collateral_vault = Keypair::new().pubkey();
+    assert_eq!(
+        process_signed(&mut banks_client, &payer, &recent_blockhash, add(&collateral_token_key, &collateral_vault, 1), &[&synchronizer_key]).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::InvalidCollateral as u32))
+    );
+
+    // BadCase: vault not owned by the Synchronizer
+    assert_eq!(
+        process_signed(&mut banks_client, &payer, &recent_blockhash, add(&usdc_key, &foreign_vault, 1_000_000_000), &[&synchronizer_key]).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::OwnerMismatch as u32))
+    );
+
+    // BadCase: rate set by someone else
+    let outsider = Keypair::new();
+    let update_rate = synchronizer::instruction::update_collateral_rate(&id(), &usdt_key, 1, &sync, &[outsider.pubkey()]).unwrap();
+    assert_eq!(
+        process_signed(&mut banks_client, &payer, &recent_blockhash, update_rate, &[&outsider]).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::BadOracle as u32))
+    );
+
+    // BadCase: the oracle moves the rate by more than 1%
+    let update_rate = synchronizer::instruction::update_collateral_rate(&id(), &usdt_key, 1_012_000_000, &sync, &[oracle.pubkey()]).unwrap();
+    assert_eq!(
+        process_signed(&mut banks_client, &payer, &recent_blockhash, update_rate, &[&oracle]).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::CollateralRateDeviationExceeded as u32))
+    );
+
+    // The admin sets any rate but zero
+    let update_rate = synchronizer::instruction::update_collateral_rate(&id(), &usdt_key, 1_012_000_000, &sync, &[]).unwrap();
+    process_signed(&mut banks_client, &payer, &recent_blockhash, update_rate, &[&synchronizer_key]).await.unwrap();
+    let update_rate = synchronizer::instruction::update_collateral_rate(&id(), &usdt_key, 0, &sync, &[]).unwrap();
+    assert_eq!(
+        process_signed(&mut banks_client, &payer, &recent_blockhash, update_rate, &[&synchronizer_key]).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(SynchronizerError::InvalidCollateralRate as u32))
+    );
+}
+
+// Trades load every optional account and check every signer, they must fit the default compute budget
+#[cfg(feature = "test-bpf")]
+#[tokio::test]
+async fn test_synchronizer_trade_compute_budget() {
+    let mut program_test = ProgramTest::new(
+        "synchronizer",
+        id(),
+        processor!(Processor::process_instruction),
+    );
+    program_test.set_bpf_compute_max_units(200_000);
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    let synchronizer_key = Keypair::new();
+    let user_key = Keypair::new();
+    let collateral_owner_key = Keypair::new();
+    let rent = banks_client.get_rent().await.unwrap();
+    let mint_rent = rent.minimum_balance(spl_token::state::Mint::LEN);
+    let account_rent = rent.minimum_balance(spl_token::state::Account::LEN);
+
+    // Infrastructure preparing, trades are settled in an accepted collateral
+    let decimals = Processor::DEFAULT_DECIMALS;
+    let collateral_token_key = Keypair::new();
+    create_mint(&mut banks_client, &payer, &recent_blockhash, &collateral_token_key, mint_rent, &collateral_owner_key.pubkey(), decimals).await.unwrap();
+    let usdc_key = Keypair::new();
+    create_mint(&mut banks_client, &payer, &recent_blockhash, &usdc_key, mint_rent, &collateral_owner_key.pubkey(), 6).await.unwrap();
+    let fiat_token_key = Keypair::new();
+    create_mint(&mut banks_client, &payer, &recent_blockhash, &fiat_token_key, mint_rent, &synchronizer_key.pubkey(), decimals).await.unwrap();
+    let usdc_vault = Keypair::new();
+    create_token_account(&mut banks_client, &payer, &recent_blockhash, &usdc_vault, account_rent, &usdc_key.pubkey(), &synchronizer_key.pubkey()).await.unwrap();
+    let user_usdc_account = Keypair::new();
+    create_token_account(&mut banks_client, &payer, &recent_blockhash, &user_usdc_account, account_rent, &usdc_key.pubkey(), &user_key.pubkey()).await.unwrap();
+    let user_fiat_account = Keypair::new();
+    create_token_account(&mut banks_client, &payer, &recent_blockhash, &user_fiat_account, account_rent, &fiat_token_key.pubkey(), &user_key.pubkey()).await.unwrap();
+    mint_tokens_to(&mut banks_client, &payer, &recent_blockhash, &usdc_key.pubkey(), &user_usdc_account.pubkey(), &collateral_owner_key, 500_000_000).await.unwrap();
+    let mut transaction = Transaction::new_with_payer(
+        &[system_instruction::transfer(&payer.pubkey(), &user_key.pubkey(), 1_000_000_000)],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    let oracles: Vec<Keypair> = (0..synchronizer::instruction::MAX_ORACLES).map(|_| Keypair::new()).collect();
+    let oracles_pubkeys: Vec<Pubkey> = oracles.iter().map(|oracle| oracle.pubkey()).collect();
+    initialize_synchronizer_account(
+        &mut banks_client,
+        &payer,
+        &recent_blockhash,
+        rent.minimum_balance(SynchronizerData::LEN),
+        &collateral_token_key.pubkey(),
+        spl_token::ui_amount_to_amount(500.0, decimals),
+        0,
+        oracles.len() as u8,
+        &oracles_pubkeys,
+        &synchronizer_key
+    ).await.unwrap();
+
+    // Every optional account of a trade
+    let sync = synchronizer_key.pubkey();
+    let fiat_mint = fiat_token_key.pubkey();
+    let notional = spl_token::ui_amount_to_amount(100.0, decimals);
+    process_signed(&mut banks_client, &payer, &recent_blockhash, synchronizer::instruction::set_asset_caps(&id(), &fiat_mint, 10 * notional, notional, &synchronizer_key.pubkey(), &payer.pubkey()).unwrap(), &[&synchronizer_key]).await.unwrap();
+    process_signed(&mut banks_client, &payer, &recent_blockhash, synchronizer::instruction::set_asset_spreads(&id(), &fiat_mint, 10, 10, &synchronizer_key.pubkey(), &payer.pubkey()).unwrap(), &[&synchronizer_key]).await.unwrap();
+    process_signed(&mut banks_client, &payer, &recent_blockhash, synchronizer::instruction::set_rate_limits(&id(), 100, 10 * notional, 10 * notional, &synchronizer_key.pubkey(), &payer.pubkey()).unwrap(), &[&synchronizer_key]).await.unwrap();
+    process_signed(&mut banks_client, &payer, &recent_blockhash, synchronizer::instruction::set_market_schedule(&id(), &fiat_mint, [TradingHours::ALL_DAY; 7], &[], &synchronizer_key.pubkey(), &payer.pubkey()).unwrap(), &[&synchronizer_key]).await.unwrap();
+    let tiers = [FeeTier { min_volume: 0, fee: 1_000_000 }, FeeTier { min_volume: notional, fee: 500_000 }];
+    let set_tiers = synchronizer::instruction::set_fee_tiers(&id(), &tiers, 100, &sync, &payer.pubkey()).unwrap();
+    process_signed(&mut banks_client, &payer, &recent_blockhash, set_tiers, &[&synchronizer_key]).await.unwrap();
+    let add = synchronizer::instruction::add_collateral(&id(), 1_000_000_000, RateBounds { max_age: 3_600, max_deviation_bps: 100 }, &usdc_key.pubkey(), &usdc_vault.pubkey(), &sync, &payer.pubkey()).unwrap();
+    process_signed(&mut banks_client, &payer, &recent_blockhash, add, &[&synchronizer_key]).await.unwrap();
+    let create_position = synchronizer::instruction::create_user_position(&id(), &fiat_mint, &user_key.pubkey(), &sync).unwrap();
+    process_signed(&mut banks_client, &payer, &recent_blockhash, create_position, &[&user_key]).await.unwrap();
+
+    let fee = spl_token::ui_amount_to_amount(0.001, decimals);
+    let prices = vec![spl_token::ui_amount_to_amount(0.5, decimals); oracles.len()];
+    let amount = spl_token::ui_amount_to_amount(10.0, decimals);
+    let buy = synchronizer::instruction::buy_for(
+        &id(),
+        1,
+        amount,
+        fee,
+        &prices,
+        &oracles_pubkeys,
+        &fiat_mint,
+        &user_usdc_account.pubkey(),
+        &user_fiat_account.pubkey(),
+        &usdc_vault.pubkey(),
+        &user_key.pubkey(),
+        &sync,
+        TRADE_PROGRAM_ACCOUNTS,
+        None
+    ).unwrap();
+    let sell = synchronizer::instruction::sell_for(
+        &id(),
+        1,
+        amount,
+        fee,
+        &prices,
+        &oracles_pubkeys,
+        &fiat_mint,
+        &user_usdc_account.pubkey(),
+        &user_fiat_account.pubkey(),
+        &usdc_vault.pubkey(),
+        &user_key.pubkey(),
+        &sync,
+        TRADE_PROGRAM_ACCOUNTS,
+        None
+    ).unwrap();
+    let mut signers = vec![&payer, &user_key, &synchronizer_key];
+    signers.extend(oracles.iter());
+    for instruction in vec![buy, sell] {
+        let mut transaction = Transaction::new_with_payer(&[instruction], Some(&payer.pubkey()));
+        transaction.sign(&signers, recent_blockhash);
+        banks_client.process_transaction(transaction).await.unwrap();
+    }
+    let position = banks_client.get_account(UserPositionData::find_address(&sync, &user_key.pubkey(), &fiat_mint).0).await.unwrap().unwrap();
+    let position = UserPositionData::unpack(&position.data).unwrap();
+    assert_eq!((position.bought_amount, position.sold_amount), (amount, amount));
+    assert_eq!(position.collateral_mint, usdc_key.pubkey());
 }
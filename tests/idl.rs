@@ -0,0 +1,1371 @@
+//! JSON IDL generated from the program definitions.
+//!
+//! The IDL is checked in at `idl/synchronizer.json`. Regenerate it after changing instructions,
+//! state, events or errors with:
+//!
+//! ``` bash
+//! $ UPDATE_IDL=1 cargo test --test idl
+//! ```
+
+use num_traits::FromPrimitive;
+use serde_json::{json, Value};
+use solana_program::{instruction::Instruction, program_pack::Pack, pubkey::Pubkey};
+use synchronizer::{
+    error::SynchronizerError,
+    event::{SynchronizerEvent, EVENT_LOG_PREFIX, EVENT_VERSION},
+    instruction::{
+        self, SynchronizerInstruction, BORSH_INSTRUCTION_TAG, MAX_ORACLES, TRADE_ASSET_ACCOUNT, TRADE_COLLATERAL_SET_ACCOUNT, TRADE_FEE_SCHEDULE_ACCOUNT,
+        TRADE_MARKET_SCHEDULE_ACCOUNT, TRADE_PROGRAM_ACCOUNTS, TRADE_RATE_LIMIT_ACCOUNT, TRADE_REFERRER_ACCOUNT, TRADE_USER_POSITION_ACCOUNT,
+        TRADE_USER_VOLUME_ACCOUNT,
+    },
+    processor::id,
+    state::{
+        AcceptedCollateral, AccountType, AssetData, CollateralSetData, FeeOverride, FeeScheduleData, FeeTier, MarketScheduleData, RateBounds, RateLimitData, SynchronizerData, TradingHours,
+        UserPositionData, UserVolumeData, VolumeWindow, ACCOUNT_DISCRIMINATOR_LEN, ASSET_SEED, COLLATERAL_SET_SEED, FEE_SCHEDULE_SEED, MARKET_SCHEDULE_SEED,
+        MAX_COLLATERALS, MAX_FEE_OVERRIDES, MAX_FEE_TIERS, MAX_MARKET_HOLIDAYS, RATE_LIMIT_SEED,
+        SYNCHRONIZER_DATA_HEADER_LEN, SYNCHRONIZER_DATA_VERSION, USER_POSITION_SEED, USER_VOLUME_SEED, VOLUME_WINDOW_BUCKETS,
+    },
+};
+
+const IDL_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/idl/synchronizer.json");
+
+/// Instruction data field with a sample value
+enum Field {
+    Bool(bool),
+    U8(u8),
+    U16(u16),
+    U64(u64),
+    Pubkey(Pubkey),
+    VecU64(Vec<u64>),
+    VecPubkey(Vec<Pubkey>),
+    VecU32(Vec<u32>),
+    WeeklyHours([TradingHours; 7]),
+    VecFeeTier(Vec<FeeTier>),
+    OptionU64(Option<u64>),
+    RateBounds(RateBounds),
+}
+
+impl Field {
+    fn type_json(&self) -> Value {
+        match self {
+            Field::Bool(_) => json!("bool"),
+            Field::U8(_) => json!("u8"),
+            Field::U16(_) => json!("u16"),
+            Field::U64(_) => json!("u64"),
+            Field::Pubkey(_) => json!("publicKey"),
+            Field::VecU64(_) => json!({ "vec": "u64" }),
+            Field::VecPubkey(_) => json!({ "vec": "publicKey" }),
+            Field::VecU32(_) => json!({ "vec": "u32" }),
+            Field::WeeklyHours(_) => json!({ "array": [{ "struct": [["open", "u32"], ["close", "u32"]] }, 7] }),
+            Field::VecFeeTier(_) => json!({ "vec": { "struct": [["min_volume", "u64"], ["fee", "u64"]] } }),
+            Field::OptionU64(_) => json!({ "option": "u64" }),
+            Field::RateBounds(_) => json!({ "struct": [["max_age", "i64"], ["max_deviation_bps", "u16"]] }),
+        }
+    }
+
+    /// Encoded size, `None` for variable length fields
+    fn size(&self) -> Option<usize> {
+        match self {
+            Field::Bool(_) | Field::U8(_) => Some(1),
+            Field::U16(_) => Some(2),
+            Field::U64(_) => Some(8),
+            Field::Pubkey(_) => Some(32),
+            Field::WeeklyHours(_) => Some(7 * 8),
+            Field::RateBounds(_) => Some(8 + 2),
+            Field::VecU64(_) | Field::VecPubkey(_) | Field::VecU32(_) | Field::VecFeeTier(_) | Field::OptionU64(_) => None,
+        }
+    }
+
+    fn encode(&self, data: &mut Vec<u8>) {
+        match self {
+            Field::Bool(value) => data.push(*value as u8),
+            Field::U8(value) => data.push(*value),
+            Field::U16(value) => data.extend_from_slice(&value.to_le_bytes()),
+            Field::U64(value) => data.extend_from_slice(&value.to_le_bytes()),
+            Field::Pubkey(value) => data.extend_from_slice(value.as_ref()),
+            Field::VecU64(values) => {
+                data.extend_from_slice(&(values.len() as u32).to_le_bytes());
+                for value in values {
+                    data.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            Field::VecPubkey(values) => {
+                data.extend_from_slice(&(values.len() as u32).to_le_bytes());
+                for value in values {
+                    data.extend_from_slice(value.as_ref());
+                }
+            }
+            Field::VecU32(values) => {
+                data.extend_from_slice(&(values.len() as u32).to_le_bytes());
+                for value in values {
+                    data.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            Field::WeeklyHours(weekly_hours) => {
+                for hours in weekly_hours.iter() {
+                    data.extend_from_slice(&hours.open.to_le_bytes());
+                    data.extend_from_slice(&hours.close.to_le_bytes());
+                }
+            }
+            Field::VecFeeTier(tiers) => {
+                data.extend_from_slice(&(tiers.len() as u32).to_le_bytes());
+                for tier in tiers {
+                    data.extend_from_slice(&tier.min_volume.to_le_bytes());
+                    data.extend_from_slice(&tier.fee.to_le_bytes());
+                }
+            }
+            Field::OptionU64(value) => match value {
+                Some(value) => {
+                    data.push(1);
+                    data.extend_from_slice(&value.to_le_bytes());
+                }
+                None => data.push(0),
+            },
+            Field::RateBounds(bounds) => {
+                data.extend_from_slice(&bounds.max_age.to_le_bytes());
+                data.extend_from_slice(&bounds.max_deviation_bps.to_le_bytes());
+            }
+        }
+    }
+}
+
+/// Instruction description with a sample instruction built by the instruction builder
+struct InstructionIdl {
+    name: &'static str,
+    fields: Vec<(&'static str, Field)>,
+    /// Account names in order
+    accounts: Vec<&'static str>,
+    /// The last account is repeated N times
+    variadic: bool,
+    sample: Instruction,
+}
+
+/// `optional_accounts` flags of which any passes a `BuyFor`/`SellFor` account, `None` for accounts always passed
+fn trade_account_flags(name: &str) -> Option<u8> {
+    match name {
+        "clock_sysvar" => Some(u8::MAX),
+        "asset" => Some(TRADE_ASSET_ACCOUNT),
+        "rate_limit" => Some(TRADE_RATE_LIMIT_ACCOUNT),
+        "user_volume" | "system_program" => Some(TRADE_USER_VOLUME_ACCOUNT),
+        "user_position" => Some(TRADE_USER_POSITION_ACCOUNT),
+        "market_schedule" => Some(TRADE_MARKET_SCHEDULE_ACCOUNT),
+        "fee_schedule" => Some(TRADE_FEE_SCHEDULE_ACCOUNT),
+        "referrer" => Some(TRADE_REFERRER_ACCOUNT),
+        "collateral_set" => Some(TRADE_COLLATERAL_SET_ACCOUNT),
+        _ => None,
+    }
+}
+
+/// Trailing accounts an instruction can be sent without, the legacy layouts don't pass them
+fn optional_trailing_accounts(name: &str) -> &'static [&'static str] {
+    match name {
+        "InitializeSynchronizerAccount" => &["collateral_mint"],
+        "WithdrawFee" | "WithdrawCollateral" => &["collateral_set"],
+        _ => &[],
+    }
+}
+
+/// Exhaustive list of instruction names, adding a variant fails to compile until it is described below
+fn instruction_name(instruction: &SynchronizerInstruction) -> &'static str {
+    match instruction {
+        SynchronizerInstruction::BuyFor { .. } => "BuyFor",
+        SynchronizerInstruction::SellFor { .. } => "SellFor",
+        SynchronizerInstruction::InitializeSynchronizerAccount { .. } => "InitializeSynchronizerAccount",
+        SynchronizerInstruction::SetMinimumRequiredSignature { .. } => "SetMinimumRequiredSignature",
+        SynchronizerInstruction::SetCollateralToken { .. } => "SetCollateralToken",
+        SynchronizerInstruction::SetRemainingDollarCap { .. } => "SetRemainingDollarCap",
+        SynchronizerInstruction::WithdrawFee { .. } => "WithdrawFee",
+        SynchronizerInstruction::WithdrawCollateral { .. } => "WithdrawCollateral",
+        SynchronizerInstruction::SetOracles { .. } => "SetOracles",
+        SynchronizerInstruction::MigrateSynchronizerAccount => "MigrateSynchronizerAccount",
+        SynchronizerInstruction::SetAssetCaps { .. } => "SetAssetCaps",
+        SynchronizerInstruction::SetRateLimits { .. } => "SetRateLimits",
+        SynchronizerInstruction::CreateUserPosition { .. } => "CreateUserPosition",
+        SynchronizerInstruction::CloseUserPosition { .. } => "CloseUserPosition",
+        SynchronizerInstruction::SetMarketSchedule { .. } => "SetMarketSchedule",
+        SynchronizerInstruction::SetAssetSpreads { .. } => "SetAssetSpreads",
+        SynchronizerInstruction::SetFeeTiers { .. } => "SetFeeTiers",
+        SynchronizerInstruction::SetFeeOverride { .. } => "SetFeeOverride",
+        SynchronizerInstruction::SetReferralShare { .. } => "SetReferralShare",
+        SynchronizerInstruction::AddCollateral { .. } => "AddCollateral",
+        SynchronizerInstruction::RemoveCollateral { .. } => "RemoveCollateral",
+        SynchronizerInstruction::UpdateCollateralRate { .. } => "UpdateCollateralRate",
+    }
+}
+
+/// Fee rates of 0.08% from 1000 and 0.05% from 5000 collateral tokens traded
+fn sample_fee_tiers() -> Vec<FeeTier> {
+    vec![
+        FeeTier { min_volume: 1_000_000_000_000, fee: 800_000 },
+        FeeTier { min_volume: 5_000_000_000_000, fee: 500_000 },
+    ]
+}
+
+/// Rates expiring after an hour, which oracles move by at most 1%
+fn sample_rate_bounds() -> RateBounds {
+    RateBounds { max_age: 3_600, max_deviation_bps: 100 }
+}
+
+/// Weekdays from 14:30 to 21:00 UTC, closed on 2021-12-24 and 2021-12-31
+fn sample_market_schedule() -> ([TradingHours; 7], Vec<u32>) {
+    let session = TradingHours { open: 52_200, close: 75_600 };
+    let closed = TradingHours::default();
+    ([session, session, session, session, session, closed, closed], vec![18_985, 18_992])
+}
+
+fn instructions() -> Vec<InstructionIdl> {
+    let key = Pubkey::new_unique();
+    let keys = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+    let prices = vec![211, 123];
+    let (weekly_hours, holidays) = sample_market_schedule();
+    let trade_accounts = vec![
+        "fiat_asset_mint",
+        "user_collateral_token_account",
+        "user_fiat_token_account",
+        "synchronizer_collateral_token_account",
+        "user_authority",
+        "synchronizer_authority",
+        "token_program",
+        "clock_sysvar",
+        "asset",
+        "rate_limit",
+        "user_volume",
+        "system_program",
+        "user_position",
+        "market_schedule",
+        "fee_schedule",
+        "referrer",
+        "collateral_set",
+        "oracle",
+    ];
+    let withdraw_accounts = vec![
+        "synchronizer_collateral_token_account",
+        "recipient_collateral_token_account",
+        "synchronizer_authority",
+        "token_program",
+        "collateral_set",
+    ];
+
+    vec![
+        InstructionIdl {
+            name: "BuyFor",
+            fields: vec![
+                ("multiplier", Field::U64(5)),
+                ("amount", Field::U64(215)),
+                ("fee", Field::U64(100)),
+                ("prices", Field::VecU64(prices.clone())),
+                ("optional_accounts", Field::U8(TRADE_PROGRAM_ACCOUNTS | TRADE_REFERRER_ACCOUNT)),
+            ],
+            accounts: trade_accounts.clone(),
+            variadic: true,
+            // Sample with every optional account and a referrer, which is writable
+            sample: instruction::buy_for(&id(), 5, 215, 100, &prices, &keys, &key, &key, &key, &key, &key, &key, TRADE_PROGRAM_ACCOUNTS, Some(&key)).unwrap(),
+        },
+        InstructionIdl {
+            name: "SellFor",
+            fields: vec![
+                ("multiplier", Field::U64(5)),
+                ("amount", Field::U64(215)),
+                ("fee", Field::U64(100)),
+                ("prices", Field::VecU64(prices.clone())),
+                ("optional_accounts", Field::U8(TRADE_PROGRAM_ACCOUNTS | TRADE_REFERRER_ACCOUNT)),
+            ],
+            accounts: trade_accounts,
+            variadic: true,
+            sample: instruction::sell_for(&id(), 5, 215, 100, &prices, &keys, &key, &key, &key, &key, &key, &key, TRADE_PROGRAM_ACCOUNTS, Some(&key)).unwrap(),
+        },
+        InstructionIdl {
+            name: "InitializeSynchronizerAccount",
+            fields: vec![
+                ("collateral_token_key", Field::Pubkey(key)),
+                ("remaining_dollar_cap", Field::U64(300)),
+                ("withdrawable_fee_amount", Field::U64(200)),
+                ("minimum_required_signature", Field::U8(2)),
+                ("oracles", Field::VecPubkey(keys.clone())),
+            ],
+            accounts: vec!["synchronizer_authority", "rent_sysvar", "collateral_mint"],
+            variadic: false,
+            sample: instruction::initialize_synchronizer_account(&id(), &key, 300, 200, 2, &keys, &key).unwrap(),
+        },
+        InstructionIdl {
+            name: "SetMinimumRequiredSignature",
+            fields: vec![("minimum_required_signature", Field::U8(3))],
+            accounts: vec!["synchronizer_authority"],
+            variadic: false,
+            sample: instruction::set_minimum_required_signature(&id(), 3, &key).unwrap(),
+        },
+        InstructionIdl {
+            name: "SetCollateralToken",
+            fields: vec![("collateral_token_key", Field::Pubkey(key))],
+            accounts: vec!["synchronizer_authority", "collateral_mint"],
+            variadic: false,
+            sample: instruction::set_collateral_token(&id(), &key, &key).unwrap(),
+        },
+        InstructionIdl {
+            name: "SetRemainingDollarCap",
+            fields: vec![("remaining_dollar_cap", Field::U64(500))],
+            accounts: vec!["synchronizer_authority"],
+            variadic: false,
+            sample: instruction::set_remaining_dollar_cap(&id(), 500, &key).unwrap(),
+        },
+        InstructionIdl {
+            name: "WithdrawFee",
+            fields: vec![("amount", Field::U64(500))],
+            accounts: withdraw_accounts.clone(),
+            variadic: false,
+            sample: instruction::withdraw_fee(&id(), 500, &key, &key, &key, true).unwrap(),
+        },
+        InstructionIdl {
+            name: "WithdrawCollateral",
+            fields: vec![("amount", Field::U64(500))],
+            accounts: withdraw_accounts,
+            variadic: false,
+            sample: instruction::withdraw_collateral(&id(), 500, &key, &key, &key, true).unwrap(),
+        },
+        InstructionIdl {
+            name: "SetOracles",
+            fields: vec![("oracles", Field::VecPubkey(keys.clone()))],
+            accounts: vec!["synchronizer_authority"],
+            variadic: false,
+            sample: instruction::set_oracles(&id(), &keys, &key).unwrap(),
+        },
+        InstructionIdl {
+            name: "MigrateSynchronizerAccount",
+            fields: vec![],
+            accounts: vec![
+                "synchronizer_authority",
+                "new_synchronizer_authority",
+                "rent_sysvar",
+                "token_program",
+                "authority_account",
+            ],
+            variadic: true,
+            sample: instruction::migrate_synchronizer_account(&id(), &key, &key, &keys).unwrap(),
+        },
+        InstructionIdl {
+            name: "SetAssetCaps",
+            fields: vec![
+                ("fiat_asset_mint", Field::Pubkey(key)),
+                ("max_outstanding_notional", Field::U64(1000)),
+                ("max_transaction_notional", Field::U64(100)),
+            ],
+            accounts: vec!["synchronizer_authority", "asset", "payer", "system_program", "rent_sysvar"],
+            variadic: false,
+            sample: instruction::set_asset_caps(&id(), &key, 1000, 100, &key, &key).unwrap(),
+        },
+        InstructionIdl {
+            name: "SetRateLimits",
+            fields: vec![
+                ("window_slots", Field::U64(150)),
+                ("max_synchronizer_notional", Field::U64(1000)),
+                ("max_user_notional", Field::U64(100)),
+            ],
+            accounts: vec!["synchronizer_authority", "rate_limit", "payer", "system_program", "rent_sysvar"],
+            variadic: false,
+            sample: instruction::set_rate_limits(&id(), 150, 1000, 100, &key, &key).unwrap(),
+        },
+        InstructionIdl {
+            name: "CreateUserPosition",
+            fields: vec![("fiat_asset_mint", Field::Pubkey(key))],
+            accounts: vec!["user_authority", "synchronizer_authority", "user_position", "system_program", "rent_sysvar"],
+            variadic: false,
+            sample: instruction::create_user_position(&id(), &key, &key, &key).unwrap(),
+        },
+        InstructionIdl {
+            name: "CloseUserPosition",
+            fields: vec![("fiat_asset_mint", Field::Pubkey(key))],
+            accounts: vec!["user_authority", "synchronizer_authority", "user_position", "recipient"],
+            variadic: false,
+            sample: instruction::close_user_position(&id(), &key, &key, &key, &key).unwrap(),
+        },
+        InstructionIdl {
+            name: "SetMarketSchedule",
+            fields: vec![
+                ("fiat_asset_mint", Field::Pubkey(key)),
+                ("weekly_hours", Field::WeeklyHours(weekly_hours)),
+                ("holidays", Field::VecU32(holidays.clone())),
+            ],
+            accounts: vec!["synchronizer_authority", "market_schedule", "payer", "system_program", "rent_sysvar"],
+            variadic: false,
+            sample: instruction::set_market_schedule(&id(), &key, weekly_hours, &holidays, &key, &key).unwrap(),
+        },
+        InstructionIdl {
+            name: "SetAssetSpreads",
+            fields: vec![
+                ("fiat_asset_mint", Field::Pubkey(key)),
+                ("ask_spread_bps", Field::U16(150)),
+                ("bid_spread_bps", Field::U16(50)),
+            ],
+            accounts: vec!["synchronizer_authority", "asset", "payer", "system_program", "rent_sysvar"],
+            variadic: false,
+            sample: instruction::set_asset_spreads(&id(), &key, 150, 50, &key, &key).unwrap(),
+        },
+        InstructionIdl {
+            name: "SetFeeTiers",
+            fields: vec![("tiers", Field::VecFeeTier(sample_fee_tiers())), ("volume_window_slots", Field::U64(216_000))],
+            accounts: vec!["synchronizer_authority", "fee_schedule", "payer", "system_program", "rent_sysvar"],
+            variadic: false,
+            sample: instruction::set_fee_tiers(&id(), &sample_fee_tiers(), 216_000, &key, &key).unwrap(),
+        },
+        InstructionIdl {
+            name: "SetFeeOverride",
+            fields: vec![
+                ("user", Field::Pubkey(key)),
+                ("fee", Field::OptionU64(Some(0))),
+            ],
+            accounts: vec!["synchronizer_authority", "fee_schedule", "payer", "system_program", "rent_sysvar"],
+            variadic: false,
+            sample: instruction::set_fee_override(&id(), &key, Some(0), &key, &key).unwrap(),
+        },
+        InstructionIdl {
+            name: "SetReferralShare",
+            fields: vec![("referral_share_bps", Field::U16(2500))],
+            accounts: vec!["synchronizer_authority", "fee_schedule", "payer", "system_program", "rent_sysvar"],
+            variadic: false,
+            sample: instruction::set_referral_share(&id(), 2500, &key, &key).unwrap(),
+        },
+        InstructionIdl {
+            name: "AddCollateral",
+            fields: vec![("rate", Field::U64(998_000)), ("rate_bounds", Field::RateBounds(sample_rate_bounds()))],
+            accounts: vec![
+                "synchronizer_authority",
+                "collateral_set",
+                "payer",
+                "system_program",
+                "rent_sysvar",
+                "collateral_mint",
+                "vault",
+                "clock_sysvar",
+            ],
+            variadic: false,
+            sample: instruction::add_collateral(&id(), 998_000, sample_rate_bounds(), &key, &key, &key, &key).unwrap(),
+        },
+        InstructionIdl {
+            name: "RemoveCollateral",
+            fields: vec![("collateral_mint", Field::Pubkey(key))],
+            accounts: vec!["synchronizer_authority", "collateral_set", "vault"],
+            variadic: false,
+            sample: instruction::remove_collateral(&id(), &key, &key, &key).unwrap(),
+        },
+        InstructionIdl {
+            name: "UpdateCollateralRate",
+            fields: vec![
+                ("collateral_mint", Field::Pubkey(key)),
+                ("rate", Field::U64(1_001_000)),
+            ],
+            accounts: vec!["synchronizer", "collateral_set", "clock_sysvar", "oracles"],
+            variadic: true,
+            // Sample attested by the oracles, the Synchronizer account signs instead of them
+            sample: instruction::update_collateral_rate(&id(), &key, 1_001_000, &key, &keys).unwrap(),
+        },
+    ]
+}
+
+fn instruction_json(index: usize, instruction: &InstructionIdl) -> Value {
+    // Sample data must match the described layout
+    let mut data = vec![BORSH_INSTRUCTION_TAG, index as u8];
+    for (_, field) in instruction.fields.iter() {
+        field.encode(&mut data);
+    }
+    assert_eq!(data, instruction.sample.data, "{} data layout", instruction.name);
+    let unpacked = SynchronizerInstruction::unpack(&instruction.sample.data).unwrap();
+    assert_eq!(instruction_name(&unpacked), instruction.name);
+
+    let mut offset = Some(2);
+    let fields: Vec<Value> = instruction.fields.iter().map(|(name, field)| {
+        let field_json = json!({
+            "name": name,
+            "type": field.type_json(),
+            "offset": offset,
+        });
+        offset = offset.and_then(|offset| field.size().map(|size| offset + size));
+        field_json
+    }).collect();
+
+    // Account flags are taken from the instruction builder
+    let fixed_accounts = instruction.accounts.len() - instruction.variadic as usize;
+    let metas = &instruction.sample.accounts;
+    assert!(metas.len() >= instruction.accounts.len(), "{} accounts", instruction.name);
+    if !instruction.variadic {
+        assert_eq!(metas.len(), instruction.accounts.len(), "{} accounts", instruction.name);
+    }
+    let has_optional_accounts = instruction.fields.iter().any(|(name, _)| *name == "optional_accounts");
+    let accounts: Vec<Value> = instruction.accounts.iter().enumerate().map(|(i, name)| {
+        let variadic = i >= fixed_accounts;
+        if variadic {
+            assert!(metas[i..].iter().all(|meta| meta.is_writable == metas[i].is_writable && meta.is_signer == metas[i].is_signer));
+        }
+        let mut account_json = json!({
+            "name": name,
+            "isWritable": metas[i].is_writable,
+            "isSigner": metas[i].is_signer,
+            "variadic": variadic,
+        });
+        // Optional accounts are passed when any of their flags is set in `optional_accounts`
+        if let Some(flags) = trade_account_flags(name).filter(|_| has_optional_accounts) {
+            account_json["optionalFlags"] = json!(flags);
+        }
+        if optional_trailing_accounts(instruction.name).contains(name) {
+            account_json["optional"] = json!(true);
+        }
+        account_json
+    }).collect();
+
+    json!({
+        "name": instruction.name,
+        "index": index,
+        "fields": fields,
+        "accounts": accounts,
+    })
+}
+
+/// Event description with a sample event
+struct EventIdl {
+    name: &'static str,
+    fields: Vec<(&'static str, Field)>,
+    sample: SynchronizerEvent,
+}
+
+/// Exhaustive list of event names, adding a variant fails to compile until it is described below
+fn event_name(event: &SynchronizerEvent) -> &'static str {
+    match event {
+        SynchronizerEvent::BuyExecuted { .. } => "BuyExecuted",
+        SynchronizerEvent::SellExecuted { .. } => "SellExecuted",
+        SynchronizerEvent::SynchronizerInitialized { .. } => "SynchronizerInitialized",
+        SynchronizerEvent::MinimumRequiredSignatureChanged { .. } => "MinimumRequiredSignatureChanged",
+        SynchronizerEvent::CollateralTokenChanged { .. } => "CollateralTokenChanged",
+        SynchronizerEvent::CapChanged { .. } => "CapChanged",
+        SynchronizerEvent::FeeWithdrawn { .. } => "FeeWithdrawn",
+        SynchronizerEvent::CollateralWithdrawn { .. } => "CollateralWithdrawn",
+        SynchronizerEvent::OraclesChanged { .. } => "OraclesChanged",
+        SynchronizerEvent::SynchronizerMigrated { .. } => "SynchronizerMigrated",
+        SynchronizerEvent::AssetCapsChanged { .. } => "AssetCapsChanged",
+        SynchronizerEvent::RateLimitsChanged { .. } => "RateLimitsChanged",
+        SynchronizerEvent::UserPositionCreated { .. } => "UserPositionCreated",
+        SynchronizerEvent::UserPositionClosed { .. } => "UserPositionClosed",
+        SynchronizerEvent::MarketScheduleChanged { .. } => "MarketScheduleChanged",
+        SynchronizerEvent::AssetSpreadsChanged { .. } => "AssetSpreadsChanged",
+        SynchronizerEvent::FeeTiersChanged { .. } => "FeeTiersChanged",
+        SynchronizerEvent::FeeOverrideChanged { .. } => "FeeOverrideChanged",
+        SynchronizerEvent::ReferralShareChanged { .. } => "ReferralShareChanged",
+        SynchronizerEvent::ReferralFeePaid { .. } => "ReferralFeePaid",
+        SynchronizerEvent::CollateralAdded { .. } => "CollateralAdded",
+        SynchronizerEvent::CollateralRemoved { .. } => "CollateralRemoved",
+        SynchronizerEvent::CollateralRateUpdated { .. } => "CollateralRateUpdated",
+        SynchronizerEvent::CollateralSettled { .. } => "CollateralSettled",
+        SynchronizerEvent::CollateralFeeWithdrawn { .. } => "CollateralFeeWithdrawn",
+        SynchronizerEvent::AcceptedCollateralWithdrawn { .. } => "AcceptedCollateralWithdrawn",
+    }
+}
+
+fn events() -> Vec<EventIdl> {
+    let synchronizer = Pubkey::new_unique();
+    let user = Pubkey::new_unique();
+    let mint = Pubkey::new_unique();
+    let keys = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+    let referrer = Pubkey::new_unique();
+    let collateral_mint = Pubkey::new_unique();
+    let vault = Pubkey::new_unique();
+    let (weekly_hours, holidays) = sample_market_schedule();
+    let trade_fields = vec![
+        ("synchronizer", Field::Pubkey(synchronizer)),
+        ("user", Field::Pubkey(user)),
+        ("fiat_asset_mint", Field::Pubkey(mint)),
+        ("amount", Field::U64(10)),
+        ("price", Field::U64(5)),
+        ("collateral_amount", Field::U64(50)),
+        ("fee_amount", Field::U64(1)),
+        ("dollar_cap_amount", Field::U64(250)),
+        ("remaining_dollar_cap", Field::U64(1000)),
+    ];
+
+    vec![
+        EventIdl {
+            name: "BuyExecuted",
+            fields: trade_fields,
+            sample: SynchronizerEvent::BuyExecuted {
+                synchronizer,
+                user,
+                fiat_asset_mint: mint,
+                amount: 10,
+                price: 5,
+                collateral_amount: 50,
+                fee_amount: 1,
+                dollar_cap_amount: 250,
+                remaining_dollar_cap: 1000,
+            },
+        },
+        EventIdl {
+            name: "SellExecuted",
+            fields: vec![
+                ("synchronizer", Field::Pubkey(synchronizer)),
+                ("user", Field::Pubkey(user)),
+                ("fiat_asset_mint", Field::Pubkey(mint)),
+                ("amount", Field::U64(10)),
+                ("price", Field::U64(5)),
+                ("collateral_amount", Field::U64(50)),
+                ("fee_amount", Field::U64(1)),
+                ("dollar_cap_amount", Field::U64(250)),
+                ("remaining_dollar_cap", Field::U64(1000)),
+            ],
+            sample: SynchronizerEvent::SellExecuted {
+                synchronizer,
+                user,
+                fiat_asset_mint: mint,
+                amount: 10,
+                price: 5,
+                collateral_amount: 50,
+                fee_amount: 1,
+                dollar_cap_amount: 250,
+                remaining_dollar_cap: 1000,
+            },
+        },
+        EventIdl {
+            name: "SynchronizerInitialized",
+            fields: vec![
+                ("synchronizer", Field::Pubkey(synchronizer)),
+                ("collateral_token_key", Field::Pubkey(mint)),
+                ("remaining_dollar_cap", Field::U64(1000)),
+                ("withdrawable_fee_amount", Field::U64(0)),
+                ("minimum_required_signature", Field::U8(2)),
+                ("oracles", Field::VecPubkey(keys.clone())),
+            ],
+            sample: SynchronizerEvent::SynchronizerInitialized {
+                synchronizer,
+                collateral_token_key: mint,
+                remaining_dollar_cap: 1000,
+                withdrawable_fee_amount: 0,
+                minimum_required_signature: 2,
+                oracles: keys.clone(),
+            },
+        },
+        EventIdl {
+            name: "MinimumRequiredSignatureChanged",
+            fields: vec![
+                ("synchronizer", Field::Pubkey(synchronizer)),
+                ("minimum_required_signature", Field::U8(3)),
+            ],
+            sample: SynchronizerEvent::MinimumRequiredSignatureChanged { synchronizer, minimum_required_signature: 3 },
+        },
+        EventIdl {
+            name: "CollateralTokenChanged",
+            fields: vec![
+                ("synchronizer", Field::Pubkey(synchronizer)),
+                ("collateral_token_key", Field::Pubkey(mint)),
+            ],
+            sample: SynchronizerEvent::CollateralTokenChanged { synchronizer, collateral_token_key: mint },
+        },
+        EventIdl {
+            name: "CapChanged",
+            fields: vec![
+                ("synchronizer", Field::Pubkey(synchronizer)),
+                ("remaining_dollar_cap", Field::U64(1000)),
+            ],
+            sample: SynchronizerEvent::CapChanged { synchronizer, remaining_dollar_cap: 1000 },
+        },
+        EventIdl {
+            name: "FeeWithdrawn",
+            fields: vec![
+                ("synchronizer", Field::Pubkey(synchronizer)),
+                ("recipient", Field::Pubkey(user)),
+                ("amount", Field::U64(7)),
+                ("withdrawable_fee_amount", Field::U64(3)),
+            ],
+            sample: SynchronizerEvent::FeeWithdrawn { synchronizer, recipient: user, amount: 7, withdrawable_fee_amount: 3 },
+        },
+        EventIdl {
+            name: "CollateralWithdrawn",
+            fields: vec![
+                ("synchronizer", Field::Pubkey(synchronizer)),
+                ("recipient", Field::Pubkey(user)),
+                ("amount", Field::U64(7)),
+            ],
+            sample: SynchronizerEvent::CollateralWithdrawn { synchronizer, recipient: user, amount: 7 },
+        },
+        EventIdl {
+            name: "OraclesChanged",
+            fields: vec![
+                ("synchronizer", Field::Pubkey(synchronizer)),
+                ("oracles", Field::VecPubkey(keys.clone())),
+            ],
+            sample: SynchronizerEvent::OraclesChanged { synchronizer, oracles: keys.clone() },
+        },
+        EventIdl {
+            name: "SynchronizerMigrated",
+            fields: vec![
+                ("synchronizer", Field::Pubkey(synchronizer)),
+                ("new_synchronizer", Field::Pubkey(user)),
+                ("from_version", Field::U8(2)),
+            ],
+            sample: SynchronizerEvent::SynchronizerMigrated { synchronizer, new_synchronizer: user, from_version: 2 },
+        },
+        EventIdl {
+            name: "AssetCapsChanged",
+            fields: vec![
+                ("synchronizer", Field::Pubkey(synchronizer)),
+                ("fiat_asset_mint", Field::Pubkey(mint)),
+                ("max_outstanding_notional", Field::U64(1000)),
+                ("max_transaction_notional", Field::U64(100)),
+            ],
+            sample: SynchronizerEvent::AssetCapsChanged {
+                synchronizer,
+                fiat_asset_mint: mint,
+                max_outstanding_notional: 1000,
+                max_transaction_notional: 100,
+            },
+        },
+        EventIdl {
+            name: "RateLimitsChanged",
+            fields: vec![
+                ("synchronizer", Field::Pubkey(synchronizer)),
+                ("window_slots", Field::U64(150)),
+                ("max_synchronizer_notional", Field::U64(1000)),
+                ("max_user_notional", Field::U64(100)),
+            ],
+            sample: SynchronizerEvent::RateLimitsChanged {
+                synchronizer,
+                window_slots: 150,
+                max_synchronizer_notional: 1000,
+                max_user_notional: 100,
+            },
+        },
+        EventIdl {
+            name: "UserPositionCreated",
+            fields: vec![
+                ("synchronizer", Field::Pubkey(synchronizer)),
+                ("user", Field::Pubkey(user)),
+                ("fiat_asset_mint", Field::Pubkey(mint)),
+            ],
+            sample: SynchronizerEvent::UserPositionCreated {
+                synchronizer,
+                user,
+                fiat_asset_mint: mint,
+            },
+        },
+        EventIdl {
+            name: "UserPositionClosed",
+            fields: vec![
+                ("synchronizer", Field::Pubkey(synchronizer)),
+                ("user", Field::Pubkey(user)),
+                ("fiat_asset_mint", Field::Pubkey(mint)),
+                ("recipient", Field::Pubkey(user)),
+            ],
+            sample: SynchronizerEvent::UserPositionClosed {
+                synchronizer,
+                user,
+                fiat_asset_mint: mint,
+                recipient: user,
+            },
+        },
+        EventIdl {
+            name: "MarketScheduleChanged",
+            fields: vec![
+                ("synchronizer", Field::Pubkey(synchronizer)),
+                ("fiat_asset_mint", Field::Pubkey(mint)),
+                ("weekly_hours", Field::WeeklyHours(weekly_hours)),
+                ("holidays", Field::VecU32(holidays.clone())),
+            ],
+            sample: SynchronizerEvent::MarketScheduleChanged {
+                synchronizer,
+                fiat_asset_mint: mint,
+                weekly_hours,
+                holidays,
+            },
+        },
+        EventIdl {
+            name: "AssetSpreadsChanged",
+            fields: vec![
+                ("synchronizer", Field::Pubkey(synchronizer)),
+                ("fiat_asset_mint", Field::Pubkey(mint)),
+                ("ask_spread_bps", Field::U16(150)),
+                ("bid_spread_bps", Field::U16(50)),
+            ],
+            sample: SynchronizerEvent::AssetSpreadsChanged {
+                synchronizer,
+                fiat_asset_mint: mint,
+                ask_spread_bps: 150,
+                bid_spread_bps: 50,
+            },
+        },
+        EventIdl {
+            name: "FeeTiersChanged",
+            fields: vec![
+                ("synchronizer", Field::Pubkey(synchronizer)),
+                ("tiers", Field::VecFeeTier(sample_fee_tiers())),
+                ("volume_window_slots", Field::U64(216_000)),
+            ],
+            sample: SynchronizerEvent::FeeTiersChanged { synchronizer, tiers: sample_fee_tiers(), volume_window_slots: 216_000 },
+        },
+        EventIdl {
+            name: "FeeOverrideChanged",
+            fields: vec![
+                ("synchronizer", Field::Pubkey(synchronizer)),
+                ("user", Field::Pubkey(user)),
+                ("fee", Field::OptionU64(None)),
+            ],
+            sample: SynchronizerEvent::FeeOverrideChanged { synchronizer, user, fee: None },
+        },
+        EventIdl {
+            name: "ReferralShareChanged",
+            fields: vec![
+                ("synchronizer", Field::Pubkey(synchronizer)),
+                ("referral_share_bps", Field::U16(2500)),
+            ],
+            sample: SynchronizerEvent::ReferralShareChanged { synchronizer, referral_share_bps: 2500 },
+        },
+        EventIdl {
+            name: "ReferralFeePaid",
+            fields: vec![
+                ("synchronizer", Field::Pubkey(synchronizer)),
+                ("user", Field::Pubkey(user)),
+                ("referrer", Field::Pubkey(referrer)),
+                ("fiat_asset_mint", Field::Pubkey(mint)),
+                ("amount", Field::U64(3)),
+            ],
+            sample: SynchronizerEvent::ReferralFeePaid { synchronizer, user, referrer, fiat_asset_mint: mint, amount: 3 },
+        },
+        EventIdl {
+            name: "CollateralAdded",
+            fields: vec![
+                ("synchronizer", Field::Pubkey(synchronizer)),
+                ("collateral_mint", Field::Pubkey(collateral_mint)),
+                ("vault", Field::Pubkey(vault)),
+                ("decimals", Field::U8(6)),
+                ("rate", Field::U64(998_000)),
+                ("rate_bounds", Field::RateBounds(sample_rate_bounds())),
+            ],
+            sample: SynchronizerEvent::CollateralAdded { synchronizer, collateral_mint, vault, decimals: 6, rate: 998_000, rate_bounds: sample_rate_bounds() },
+        },
+        EventIdl {
+            name: "CollateralRemoved",
+            fields: vec![
+                ("synchronizer", Field::Pubkey(synchronizer)),
+                ("collateral_mint", Field::Pubkey(collateral_mint)),
+            ],
+            sample: SynchronizerEvent::CollateralRemoved { synchronizer, collateral_mint },
+        },
+        EventIdl {
+            name: "CollateralRateUpdated",
+            fields: vec![
+                ("synchronizer", Field::Pubkey(synchronizer)),
+                ("collateral_mint", Field::Pubkey(collateral_mint)),
+                ("rate", Field::U64(1_001_000)),
+                ("oracles", Field::VecPubkey(keys.clone())),
+            ],
+            sample: SynchronizerEvent::CollateralRateUpdated { synchronizer, collateral_mint, rate: 1_001_000, oracles: keys },
+        },
+        EventIdl {
+            name: "CollateralSettled",
+            fields: vec![
+                ("synchronizer", Field::Pubkey(synchronizer)),
+                ("user", Field::Pubkey(user)),
+                ("fiat_asset_mint", Field::Pubkey(mint)),
+                ("collateral_mint", Field::Pubkey(collateral_mint)),
+                ("buy", Field::Bool(true)),
+                ("user_collateral_amount", Field::U64(51)),
+                ("fee_amount", Field::U64(2)),
+                ("referral_amount", Field::U64(1)),
+                ("withdrawable_fee_amount", Field::U64(11)),
+            ],
+            sample: SynchronizerEvent::CollateralSettled {
+                synchronizer,
+                user,
+                fiat_asset_mint: mint,
+                collateral_mint,
+                buy: true,
+                user_collateral_amount: 51,
+                fee_amount: 2,
+                referral_amount: 1,
+                withdrawable_fee_amount: 11,
+            },
+        },
+        EventIdl {
+            name: "CollateralFeeWithdrawn",
+            fields: vec![
+                ("synchronizer", Field::Pubkey(synchronizer)),
+                ("collateral_mint", Field::Pubkey(collateral_mint)),
+                ("recipient", Field::Pubkey(user)),
+                ("amount", Field::U64(10)),
+                ("withdrawable_fee_amount", Field::U64(1)),
+            ],
+            sample: SynchronizerEvent::CollateralFeeWithdrawn {
+                synchronizer,
+                collateral_mint,
+                recipient: user,
+                amount: 10,
+                withdrawable_fee_amount: 1,
+            },
+        },
+        EventIdl {
+            name: "AcceptedCollateralWithdrawn",
+            fields: vec![
+                ("synchronizer", Field::Pubkey(synchronizer)),
+                ("collateral_mint", Field::Pubkey(collateral_mint)),
+                ("recipient", Field::Pubkey(user)),
+                ("amount", Field::U64(500)),
+            ],
+            sample: SynchronizerEvent::AcceptedCollateralWithdrawn { synchronizer, collateral_mint, recipient: user, amount: 500 },
+        },
+    ]
+}
+
+fn event_json(index: usize, event: &EventIdl) -> Value {
+    // Sample encoding must match the described layout
+    let mut data = vec![EVENT_VERSION, index as u8];
+    for (_, field) in event.fields.iter() {
+        field.encode(&mut data);
+    }
+    assert_eq!(base64::decode(event.sample.encode()).unwrap(), data, "{} data layout", event.name);
+    assert_eq!(event_name(&event.sample), event.name);
+
+    let mut offset = Some(2);
+    let fields: Vec<Value> = event.fields.iter().map(|(name, field)| {
+        let field_json = json!({
+            "name": name,
+            "type": field.type_json(),
+            "offset": offset,
+        });
+        offset = offset.and_then(|offset| field.size().map(|size| offset + size));
+        field_json
+    }).collect();
+
+    json!({
+        "name": event.name,
+        "index": index,
+        "fields": fields,
+    })
+}
+
+fn synchronizer_data_json() -> Value {
+    let synchronizer = SynchronizerData {
+        is_initialized: true,
+        collateral_token_key: Pubkey::new_unique(),
+        remaining_dollar_cap: 500,
+        withdrawable_fee_amount: 200,
+        minimum_required_signature: 2,
+        oracles: [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()],
+        required_trade_accounts: TRADE_PROGRAM_ACCOUNTS,
+        collateral_decimals: Some(6),
+    };
+    let mut oracles = Vec::new();
+    for oracle in synchronizer.oracles.iter() {
+        oracles.extend_from_slice(oracle.as_ref());
+    }
+    let fields = vec![
+        ("is_initialized", json!("bool"), vec![synchronizer.is_initialized as u8]),
+        ("collateral_token_key", json!("publicKey"), synchronizer.collateral_token_key.to_bytes().to_vec()),
+        ("remaining_dollar_cap", json!("u64"), synchronizer.remaining_dollar_cap.to_le_bytes().to_vec()),
+        ("withdrawable_fee_amount", json!("u64"), synchronizer.withdrawable_fee_amount.to_le_bytes().to_vec()),
+        ("minimum_required_signature", json!("u8"), vec![synchronizer.minimum_required_signature]),
+        ("oracles", json!({ "array": ["publicKey", MAX_ORACLES] }), oracles),
+        ("required_trade_accounts", json!("u8"), vec![synchronizer.required_trade_accounts]),
+        ("collateral_decimals", json!({ "option": "u8" }), vec![1, 6]),
+    ];
+
+    // Field offsets must match the packed data
+    let mut packed = vec![0; SynchronizerData::LEN];
+    SynchronizerData::pack(synchronizer, &mut packed).unwrap();
+    let mut offset = SYNCHRONIZER_DATA_HEADER_LEN;
+    let fields: Vec<Value> = fields.into_iter().map(|(name, field_type, bytes)| {
+        assert_eq!(&packed[offset..offset + bytes.len()], bytes.as_slice(), "SynchronizerData.{} layout", name);
+        let field_json = json!({
+            "name": name,
+            "type": field_type,
+            "offset": offset,
+            "size": bytes.len(),
+        });
+        offset += bytes.len();
+        field_json
+    }).collect();
+    assert!(packed[offset..].iter().all(|byte| *byte == 0));
+
+    json!({
+        "name": "SynchronizerData",
+        "discriminator": AccountType::Synchronizer.discriminator().to_vec(),
+        "version": SYNCHRONIZER_DATA_VERSION,
+        "versionOffset": ACCOUNT_DISCRIMINATOR_LEN,
+        "size": SynchronizerData::LEN,
+        "fields": fields,
+        "reserved": {
+            "offset": offset,
+            "size": SynchronizerData::LEN - offset,
+        },
+    })
+}
+
+/// Layout of a program derived account: type discriminator, fields, reserved bytes
+fn derived_account_json(
+    name: &str,
+    account_type: AccountType,
+    seeds: &[&str],
+    packed: &[u8],
+    fields: Vec<(&str, Value, Vec<u8>)>,
+) -> Value {
+    // Field offsets must match the packed data
+    let mut offset = ACCOUNT_DISCRIMINATOR_LEN;
+    assert_eq!(&packed[..offset], &account_type.discriminator()[..]);
+    let fields: Vec<Value> = fields.into_iter().map(|(field_name, field_type, bytes)| {
+        assert_eq!(&packed[offset..offset + bytes.len()], bytes.as_slice(), "{}.{} layout", name, field_name);
+        let field_json = json!({
+            "name": field_name,
+            "type": field_type,
+            "offset": offset,
+            "size": bytes.len(),
+        });
+        offset += bytes.len();
+        field_json
+    }).collect();
+    assert!(packed[offset..].iter().all(|byte| *byte == 0));
+
+    json!({
+        "name": name,
+        "discriminator": account_type.discriminator().to_vec(),
+        "seeds": seeds,
+        "size": packed.len(),
+        "fields": fields,
+        "reserved": {
+            "offset": offset,
+            "size": packed.len() - offset,
+        },
+    })
+}
+
+fn asset_data_json() -> Value {
+    let asset = AssetData {
+        is_initialized: true,
+        synchronizer: Pubkey::new_unique(),
+        fiat_asset_mint: Pubkey::new_unique(),
+        max_outstanding_notional: 1000,
+        max_transaction_notional: 100,
+        outstanding_notional: 400,
+        bump_seed: 254,
+        ask_spread_bps: 150,
+        bid_spread_bps: 50,
+    };
+    let mut packed = vec![0; AssetData::LEN];
+    AssetData::pack(asset, &mut packed).unwrap();
+    derived_account_json(
+        "AssetData",
+        AccountType::AssetRegistry,
+        &[std::str::from_utf8(ASSET_SEED).unwrap(), "synchronizer", "fiat_asset_mint"],
+        &packed,
+        vec![
+            ("is_initialized", json!("bool"), vec![asset.is_initialized as u8]),
+            ("synchronizer", json!("publicKey"), asset.synchronizer.to_bytes().to_vec()),
+            ("fiat_asset_mint", json!("publicKey"), asset.fiat_asset_mint.to_bytes().to_vec()),
+            ("max_outstanding_notional", json!("u64"), asset.max_outstanding_notional.to_le_bytes().to_vec()),
+            ("max_transaction_notional", json!("u64"), asset.max_transaction_notional.to_le_bytes().to_vec()),
+            ("outstanding_notional", json!("u64"), asset.outstanding_notional.to_le_bytes().to_vec()),
+            ("bump_seed", json!("u8"), vec![asset.bump_seed]),
+            ("ask_spread_bps", json!("u16"), asset.ask_spread_bps.to_le_bytes().to_vec()),
+            ("bid_spread_bps", json!("u16"), asset.bid_spread_bps.to_le_bytes().to_vec()),
+        ],
+    )
+}
+
+/// `VolumeWindow` fields: slots per bucket, then `index` and `notional` of every bucket
+// `names` of the bucket slots and buckets fields
+fn volume_window_fields(names: [&'static str; 2], window: &VolumeWindow) -> Vec<(&'static str, Value, Vec<u8>)> {
+    let mut buckets = Vec::new();
+    for bucket in window.buckets.iter() {
+        buckets.extend_from_slice(&bucket.index.to_le_bytes());
+        buckets.extend_from_slice(&bucket.notional.to_le_bytes());
+    }
+    vec![
+        (names[0], json!("u64"), window.bucket_slots.to_le_bytes().to_vec()),
+        (names[1], json!({ "array": [{ "struct": [["index", "u64"], ["notional", "u64"]] }, VOLUME_WINDOW_BUCKETS] }), buckets),
+    ]
+}
+
+fn rate_limit_data_json() -> Value {
+    let mut window = VolumeWindow::default();
+    window.record(1000, 160, 300, 500).unwrap();
+    let rate_limit = RateLimitData {
+        is_initialized: true,
+        synchronizer: Pubkey::new_unique(),
+        window_slots: 160,
+        max_synchronizer_notional: 500,
+        max_user_notional: 100,
+        bump_seed: 254,
+        window,
+    };
+    let mut packed = vec![0; RateLimitData::LEN];
+    RateLimitData::pack(rate_limit, &mut packed).unwrap();
+    let mut fields = vec![
+        ("is_initialized", json!("bool"), vec![rate_limit.is_initialized as u8]),
+        ("synchronizer", json!("publicKey"), rate_limit.synchronizer.to_bytes().to_vec()),
+        ("window_slots", json!("u64"), rate_limit.window_slots.to_le_bytes().to_vec()),
+        ("max_synchronizer_notional", json!("u64"), rate_limit.max_synchronizer_notional.to_le_bytes().to_vec()),
+        ("max_user_notional", json!("u64"), rate_limit.max_user_notional.to_le_bytes().to_vec()),
+        ("bump_seed", json!("u8"), vec![rate_limit.bump_seed]),
+    ];
+    fields.extend(volume_window_fields(["window.bucket_slots", "window.buckets"], &window));
+    derived_account_json(
+        "RateLimitData",
+        AccountType::RateLimit,
+        &[std::str::from_utf8(RATE_LIMIT_SEED).unwrap(), "synchronizer"],
+        &packed,
+        fields,
+    )
+}
+
+fn user_volume_data_json() -> Value {
+    let mut window = VolumeWindow::default();
+    window.record(1000, 160, 30, 100).unwrap();
+    let mut fee_window = VolumeWindow::default();
+    fee_window.add(1000, 1600, 40);
+    let user_volume = UserVolumeData {
+        is_initialized: true,
+        synchronizer: Pubkey::new_unique(),
+        user: Pubkey::new_unique(),
+        bump_seed: 254,
+        window,
+        fee_window,
+    };
+    let mut packed = vec![0; UserVolumeData::LEN];
+    UserVolumeData::pack(user_volume, &mut packed).unwrap();
+    let mut fields = vec![
+        ("is_initialized", json!("bool"), vec![user_volume.is_initialized as u8]),
+        ("synchronizer", json!("publicKey"), user_volume.synchronizer.to_bytes().to_vec()),
+        ("user", json!("publicKey"), user_volume.user.to_bytes().to_vec()),
+        ("bump_seed", json!("u8"), vec![user_volume.bump_seed]),
+    ];
+    fields.extend(volume_window_fields(["window.bucket_slots", "window.buckets"], &window));
+    fields.extend(volume_window_fields(["fee_window.bucket_slots", "fee_window.buckets"], &fee_window));
+    derived_account_json(
+        "UserVolumeData",
+        AccountType::UserVolume,
+        &[std::str::from_utf8(USER_VOLUME_SEED).unwrap(), "synchronizer", "user"],
+        &packed,
+        fields,
+    )
+}
+
+fn user_position_data_json() -> Value {
+    let position = UserPositionData {
+        is_initialized: true,
+        synchronizer: Pubkey::new_unique(),
+        user: Pubkey::new_unique(),
+        fiat_asset_mint: Pubkey::new_unique(),
+        collateral_mint: Pubkey::new_unique(),
+        bought_amount: 300,
+        sold_amount: 100,
+        collateral_paid: 153,
+        collateral_received: 49,
+        fee_paid: 4,
+        last_trade_slot: 1000,
+        bump_seed: 254,
+    };
+    let mut packed = vec![0; UserPositionData::LEN];
+    UserPositionData::pack(position, &mut packed).unwrap();
+    derived_account_json(
+        "UserPositionData",
+        AccountType::UserPosition,
+        &[std::str::from_utf8(USER_POSITION_SEED).unwrap(), "synchronizer", "user", "fiat_asset_mint"],
+        &packed,
+        vec![
+            ("is_initialized", json!("bool"), vec![position.is_initialized as u8]),
+            ("synchronizer", json!("publicKey"), position.synchronizer.to_bytes().to_vec()),
+            ("user", json!("publicKey"), position.user.to_bytes().to_vec()),
+            ("fiat_asset_mint", json!("publicKey"), position.fiat_asset_mint.to_bytes().to_vec()),
+            ("collateral_mint", json!("publicKey"), position.collateral_mint.to_bytes().to_vec()),
+            ("bought_amount", json!("u64"), position.bought_amount.to_le_bytes().to_vec()),
+            ("sold_amount", json!("u64"), position.sold_amount.to_le_bytes().to_vec()),
+            ("collateral_paid", json!("u64"), position.collateral_paid.to_le_bytes().to_vec()),
+            ("collateral_received", json!("u64"), position.collateral_received.to_le_bytes().to_vec()),
+            ("fee_paid", json!("u64"), position.fee_paid.to_le_bytes().to_vec()),
+            ("last_trade_slot", json!("u64"), position.last_trade_slot.to_le_bytes().to_vec()),
+            ("bump_seed", json!("u8"), vec![position.bump_seed]),
+        ],
+    )
+}
+
+fn market_schedule_data_json() -> Value {
+    let (weekly_hours, holidays) = sample_market_schedule();
+    let mut schedule = MarketScheduleData {
+        is_initialized: true,
+        synchronizer: Pubkey::new_unique(),
+        fiat_asset_mint: Pubkey::new_unique(),
+        bump_seed: 254,
+        ..MarketScheduleData::default()
+    };
+    schedule.set(weekly_hours, &holidays).unwrap();
+    let mut packed = vec![0; MarketScheduleData::LEN];
+    MarketScheduleData::pack(schedule, &mut packed).unwrap();
+    let mut weekly_hours_bytes = Vec::new();
+    Field::WeeklyHours(weekly_hours).encode(&mut weekly_hours_bytes);
+    let holidays_bytes: Vec<u8> = schedule.holidays.iter().flat_map(|holiday| holiday.to_le_bytes().to_vec()).collect();
+    derived_account_json(
+        "MarketScheduleData",
+        AccountType::MarketSchedule,
+        &[std::str::from_utf8(MARKET_SCHEDULE_SEED).unwrap(), "synchronizer", "fiat_asset_mint"],
+        &packed,
+        vec![
+            ("is_initialized", json!("bool"), vec![schedule.is_initialized as u8]),
+            ("synchronizer", json!("publicKey"), schedule.synchronizer.to_bytes().to_vec()),
+            ("fiat_asset_mint", json!("publicKey"), schedule.fiat_asset_mint.to_bytes().to_vec()),
+            ("bump_seed", json!("u8"), vec![schedule.bump_seed]),
+            ("weekly_hours", Field::WeeklyHours(weekly_hours).type_json(), weekly_hours_bytes),
+            ("holidays", json!({ "array": ["u32", MAX_MARKET_HOLIDAYS] }), holidays_bytes),
+        ],
+    )
+}
+
+fn fee_schedule_data_json() -> Value {
+    let mut schedule = FeeScheduleData {
+        is_initialized: true,
+        synchronizer: Pubkey::new_unique(),
+        bump_seed: 254,
+        volume_window_slots: 216_000,
+        ..FeeScheduleData::default()
+    };
+    schedule.set_tiers(&sample_fee_tiers()).unwrap();
+    schedule.set_override(&Pubkey::new_unique(), Some(0)).unwrap();
+    schedule.set_referral_share(2500).unwrap();
+    let mut packed = vec![0; FeeScheduleData::LEN];
+    FeeScheduleData::pack(schedule, &mut packed).unwrap();
+    let tiers_bytes: Vec<u8> = schedule.tiers.iter()
+        .flat_map(|tier| [tier.min_volume.to_le_bytes(), tier.fee.to_le_bytes()].concat())
+        .collect();
+    let overrides_bytes: Vec<u8> = schedule.overrides.iter()
+        .flat_map(|FeeOverride { user, fee }| [user.to_bytes().to_vec(), fee.to_le_bytes().to_vec()].concat())
+        .collect();
+    derived_account_json(
+        "FeeScheduleData",
+        AccountType::FeeSchedule,
+        &[std::str::from_utf8(FEE_SCHEDULE_SEED).unwrap(), "synchronizer"],
+        &packed,
+        vec![
+            ("is_initialized", json!("bool"), vec![schedule.is_initialized as u8]),
+            ("synchronizer", json!("publicKey"), schedule.synchronizer.to_bytes().to_vec()),
+            ("bump_seed", json!("u8"), vec![schedule.bump_seed]),
+            ("tier_count", json!("u8"), vec![schedule.tier_count]),
+            ("tiers", json!({ "array": [{ "struct": [["min_volume", "u64"], ["fee", "u64"]] }, MAX_FEE_TIERS] }), tiers_bytes),
+            ("override_count", json!("u8"), vec![schedule.override_count]),
+            ("overrides", json!({ "array": [{ "struct": [["user", "publicKey"], ["fee", "u64"]] }, MAX_FEE_OVERRIDES] }), overrides_bytes),
+            ("referral_share_bps", json!("u16"), schedule.referral_share_bps.to_le_bytes().to_vec()),
+            ("volume_window_slots", json!("u64"), schedule.volume_window_slots.to_le_bytes().to_vec()),
+        ],
+    )
+}
+
+fn collateral_set_data_json() -> Value {
+    let mut set = CollateralSetData {
+        is_initialized: true,
+        synchronizer: Pubkey::new_unique(),
+        bump_seed: 254,
+        ..CollateralSetData::default()
+    };
+    set.add(AcceptedCollateral {
+        mint: Pubkey::new_unique(),
+        vault: Pubkey::new_unique(),
+        decimals: 6,
+        rate: 998_000,
+        rate_updated_at: 1_640_000_000,
+        reference_rate: 998_000,
+        rate_bounds: sample_rate_bounds(),
+        withdrawable_fee_amount: 11,
+    }).unwrap();
+    let mut packed = vec![0; CollateralSetData::LEN];
+    CollateralSetData::pack(set, &mut packed).unwrap();
+    let collaterals_bytes: Vec<u8> = set.collaterals.iter()
+        .flat_map(|collateral| [
+            collateral.mint.to_bytes().to_vec(),
+            collateral.vault.to_bytes().to_vec(),
+            vec![collateral.decimals],
+            collateral.rate.to_le_bytes().to_vec(),
+            collateral.rate_updated_at.to_le_bytes().to_vec(),
+            collateral.reference_rate.to_le_bytes().to_vec(),
+            collateral.rate_bounds.max_age.to_le_bytes().to_vec(),
+            collateral.rate_bounds.max_deviation_bps.to_le_bytes().to_vec(),
+            collateral.withdrawable_fee_amount.to_le_bytes().to_vec(),
+        ].concat())
+        .collect();
+    derived_account_json(
+        "CollateralSetData",
+        AccountType::CollateralSet,
+        &[std::str::from_utf8(COLLATERAL_SET_SEED).unwrap(), "synchronizer"],
+        &packed,
+        vec![
+            ("is_initialized", json!("bool"), vec![set.is_initialized as u8]),
+            ("synchronizer", json!("publicKey"), set.synchronizer.to_bytes().to_vec()),
+            ("bump_seed", json!("u8"), vec![set.bump_seed]),
+            ("collateral_count", json!("u8"), vec![set.collateral_count]),
+            (
+                "collaterals",
+                json!({ "array": [{ "struct": [
+                    ["mint", "publicKey"],
+                    ["vault", "publicKey"],
+                    ["decimals", "u8"],
+                    ["rate", "u64"],
+                    ["rate_updated_at", "i64"],
+                    ["reference_rate", "u64"],
+                    ["rate_bounds", { "struct": [["max_age", "i64"], ["max_deviation_bps", "u16"]] }],
+                    ["withdrawable_fee_amount", "u64"],
+                ] }, MAX_COLLATERALS] }),
+                collaterals_bytes,
+            ),
+        ],
+    )
+}
+
+fn errors_json() -> Vec<Value> {
+    (0..)
+        .map_while(SynchronizerError::from_u32)
+        .map(|error| json!({
+            "code": error.clone() as u32,
+            "name": format!("{:?}", error),
+            "msg": error.to_string(),
+        }))
+        .collect()
+}
+
+fn generate_idl() -> Value {
+    let instructions: Vec<Value> = instructions().iter().enumerate()
+        .map(|(index, instruction)| instruction_json(index, instruction))
+        .collect();
+    let events: Vec<Value> = events().iter().enumerate()
+        .map(|(index, event)| event_json(index, event))
+        .collect();
+
+    json!({
+        "name": "synchronizer",
+        "version": env!("CARGO_PKG_VERSION"),
+        "programId": id().to_string(),
+        "instructionEncoding": {
+            "tag": BORSH_INSTRUCTION_TAG,
+            "variantOffset": 1,
+            "encoding": "borsh",
+        },
+        "instructions": instructions,
+        "accounts": [
+            synchronizer_data_json(),
+            asset_data_json(),
+            rate_limit_data_json(),
+            user_volume_data_json(),
+            user_position_data_json(),
+            market_schedule_data_json(),
+            fee_schedule_data_json(),
+            collateral_set_data_json(),
+        ],
+        "eventEncoding": {
+            "logPrefix": EVENT_LOG_PREFIX,
+            "version": EVENT_VERSION,
+            "variantOffset": 1,
+            "encoding": "base64 of version and borsh",
+        },
+        "events": events,
+        "errors": errors_json(),
+    })
+}
+
+#[test]
+fn test_idl_is_up_to_date() {
+    let idl = serde_json::to_string_pretty(&generate_idl()).unwrap() + "\n";
+    if std::env::var_os("UPDATE_IDL").is_some() {
+        std::fs::write(IDL_PATH, &idl).unwrap();
+    }
+
+    let checked_in = std::fs::read_to_string(IDL_PATH).unwrap_or_default();
+    assert!(checked_in == idl, "idl/synchronizer.json is out of date, run `UPDATE_IDL=1 cargo test --test idl`");
+}